@@ -78,8 +78,8 @@ fn into_pg_type(df_type: &DataType) -> PgWireResult<Type> {
         DataType::Boolean => Type::BOOL,
         DataType::Int8 | DataType::UInt8 => Type::CHAR,
         DataType::Int16 | DataType::UInt16 => Type::INT2,
-        DataType::Int32 | DataType::UInt32 => Type::INT4,
-        DataType::Int64 | DataType::UInt64 => Type::INT8,
+        DataType::Int32 => Type::INT4,
+        DataType::Int64 | DataType::UInt32 | DataType::UInt64 => Type::INT8,
         DataType::Timestamp(_, _) => Type::TIMESTAMP,
         DataType::Time32(_) | DataType::Time64(_) => Type::TIME,
         DataType::Date32 | DataType::Date64 => Type::DATE,
@@ -91,8 +91,8 @@ fn into_pg_type(df_type: &DataType) -> PgWireResult<Type> {
             DataType::Boolean => Type::BOOL_ARRAY,
             DataType::Int8 | DataType::UInt8 => Type::CHAR_ARRAY,
             DataType::Int16 | DataType::UInt16 => Type::INT2_ARRAY,
-            DataType::Int32 | DataType::UInt32 => Type::INT4_ARRAY,
-            DataType::Int64 | DataType::UInt64 => Type::INT8_ARRAY,
+            DataType::Int32 => Type::INT4_ARRAY,
+            DataType::Int64 | DataType::UInt32 | DataType::UInt64 => Type::INT8_ARRAY,
             DataType::Timestamp(_, _) => Type::TIMESTAMP_ARRAY,
             DataType::Time32(_) | DataType::Time64(_) => Type::TIME_ARRAY,
             DataType::Date32 | DataType::Date64 => Type::DATE_ARRAY,
@@ -245,7 +245,9 @@ get_primitive_list_value!(get_u8_list_value, UInt8Type, i8, |val: u8| { val as i
 get_primitive_list_value!(get_u16_list_value, UInt16Type, i16, |val: u16| {
     val as i16
 });
-get_primitive_list_value!(get_u32_list_value, UInt32Type, u32);
+get_primitive_list_value!(get_u32_list_value, UInt32Type, i64, |val: u32| {
+    val as i64
+});
 get_primitive_list_value!(get_u64_list_value, UInt64Type, i64, |val: u64| {
     val as i64
 });
@@ -283,7 +285,7 @@ fn encode_value(
         DataType::Int64 => encoder.encode_field(&get_i64_value(arr, idx))?,
         DataType::UInt8 => encoder.encode_field(&(get_u8_value(arr, idx) as i8))?,
         DataType::UInt16 => encoder.encode_field(&(get_u16_value(arr, idx) as i16))?,
-        DataType::UInt32 => encoder.encode_field(&get_u32_value(arr, idx))?,
+        DataType::UInt32 => encoder.encode_field(&(get_u32_value(arr, idx) as i64))?,
         DataType::UInt64 => encoder.encode_field(&(get_u64_value(arr, idx) as i64))?,
         DataType::Float32 => encoder.encode_field(&get_f32_value(arr, idx))?,
         DataType::Float64 => encoder.encode_field(&get_f64_value(arr, idx))?,
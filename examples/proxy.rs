@@ -0,0 +1,239 @@
+//! A read-through caching proxy in front of a real postgres server.
+//!
+//! `SELECT` results are cached by query string, other statements are forwarded
+//! to upstream and invalidate the cache. Errors from upstream are passed
+//! through with their SQLSTATE.
+//!
+//! Set upstream with `UPSTREAM` environment variable, in the connection string
+//! format of `tokio-postgres`:
+//!
+//! ```sh
+//! UPSTREAM="host=127.0.0.1 port=5433 user=postgres" cargo run --example proxy
+//! ```
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::{stream, Sink, StreamExt};
+use tokio::net::TcpListener;
+use tokio_postgres::{Client, NoTls, SimpleQueryMessage};
+
+use pgwire::api::auth::noop::NoopStartupHandler;
+use pgwire::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+use pgwire::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use pgwire::api::{ClientInfo, MakeHandler, StatelessMakeHandler, Type};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::PgWireBackendMessage;
+use pgwire::tokio::process_socket;
+use pgwire::types::RawText;
+
+/// Resultset of a `SELECT`, values are in text format as returned by upstream
+#[derive(Debug)]
+struct CachedResult {
+    fields: Vec<(String, Type)>,
+    rows: Vec<Vec<Option<String>>>,
+}
+
+pub struct CachingProxy {
+    upstream: Client,
+    cache: Mutex<HashMap<String, Arc<CachedResult>>>,
+}
+
+/// Pass upstream error to client, keeping its SQLSTATE and details
+fn upstream_error(e: tokio_postgres::Error) -> PgWireError {
+    if let Some(db_error) = e.as_db_error() {
+        let mut error_info = ErrorInfo::new(
+            db_error.severity().to_owned(),
+            db_error.code().code().to_owned(),
+            db_error.message().to_owned(),
+        );
+        error_info.detail = db_error.detail().map(str::to_owned);
+        error_info.hint = db_error.hint().map(str::to_owned);
+        PgWireError::UserError(Box::new(error_info))
+    } else {
+        PgWireError::ApiError(Box::new(e))
+    }
+}
+
+fn is_select(query: &str) -> bool {
+    query
+        .trim_start()
+        .get(..6)
+        .is_some_and(|s| s.eq_ignore_ascii_case("SELECT"))
+}
+
+impl CachingProxy {
+    async fn fetch(&self, query: &str) -> PgWireResult<Arc<CachedResult>> {
+        // simple query protocol returns values in text but no column types, so
+        // describe the query first to get them
+        let statement = self.upstream.prepare(query).await.map_err(upstream_error)?;
+        let fields = statement
+            .columns()
+            .iter()
+            .map(|c| (c.name().to_owned(), c.type_().clone()))
+            .collect();
+
+        let mut rows = Vec::new();
+        for message in self
+            .upstream
+            .simple_query(query)
+            .await
+            .map_err(upstream_error)?
+        {
+            if let SimpleQueryMessage::Row(row) = message {
+                rows.push(
+                    (0..row.len())
+                        .map(|i| row.get(i).map(str::to_owned))
+                        .collect(),
+                );
+            }
+        }
+
+        Ok(Arc::new(CachedResult { fields, rows }))
+    }
+
+    async fn forward(&self, query: &str) -> PgWireResult<Tag> {
+        let mut rows = None;
+        for message in self
+            .upstream
+            .simple_query(query)
+            .await
+            .map_err(upstream_error)?
+        {
+            if let SimpleQueryMessage::CommandComplete(n) = message {
+                rows = Some(n);
+            }
+        }
+
+        // tokio-postgres only reports the row count, rebuild the tag from the
+        // command name, e.g. `INSERT` or `CREATE TABLE`
+        let words = query
+            .split_whitespace()
+            .take(2)
+            .map(str::to_uppercase)
+            .collect::<Vec<_>>();
+        let command = match words[..] {
+            [ref verb, ref object] if ["CREATE", "DROP", "ALTER"].contains(&verb.as_str()) => {
+                format!("{verb} {object}")
+            }
+            [ref verb, ..] => verb.clone(),
+            [] => String::new(),
+        };
+        let tag = Tag::new(&command);
+        Ok(match (command.as_str(), rows) {
+            ("INSERT", Some(rows)) => tag.with_oid(0).with_row_count(rows),
+            ("UPDATE" | "DELETE" | "MERGE" | "COPY", Some(rows)) => tag.with_row_count(rows),
+            _ => tag,
+        })
+    }
+}
+
+fn into_response(result: Arc<CachedResult>) -> Response<'static> {
+    let schema = Arc::new(
+        result
+            .fields
+            .iter()
+            .map(|(name, datatype)| {
+                FieldInfo::new(
+                    name.clone(),
+                    None,
+                    None,
+                    datatype.clone(),
+                    FieldFormat::Text,
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    let schema_ref = schema.clone();
+    let rows = stream::iter(0..result.rows.len()).map(move |i| {
+        let mut encoder = DataRowEncoder::new(schema_ref.clone());
+        for value in &result.rows[i] {
+            // values are already in postgres text format
+            encoder.encode_field(&value.as_ref().map(|v| RawText(v.as_bytes())))?;
+        }
+        encoder.finish()
+    });
+
+    Response::Query(QueryResponse::new(schema, rows))
+}
+
+#[async_trait]
+impl SimpleQueryHandler for CachingProxy {
+    async fn do_query<'a, C>(
+        &self,
+        _client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if is_select(query) {
+            let cached = self.cache.lock().unwrap().get(query).cloned();
+            let result = match cached {
+                Some(result) => result,
+                None => {
+                    let result = self.fetch(query).await?;
+                    self.cache
+                        .lock()
+                        .unwrap()
+                        .insert(query.to_owned(), result.clone());
+                    result
+                }
+            };
+            Ok(vec![into_response(result)])
+        } else {
+            // any write may change cached results
+            self.cache.lock().unwrap().clear();
+            let tag = self.forward(query).await?;
+            Ok(vec![Response::Execution(tag)])
+        }
+    }
+}
+
+#[tokio::main]
+pub async fn main() {
+    let upstream_config = std::env::var("UPSTREAM")
+        .unwrap_or_else(|_| "host=127.0.0.1 port=5433 user=postgres".to_owned());
+    let (upstream, connection) = tokio_postgres::connect(&upstream_config, NoTls)
+        .await
+        .expect("failed to connect upstream");
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("upstream connection error: {}", e);
+        }
+    });
+
+    let processor = Arc::new(StatelessMakeHandler::new(Arc::new(CachingProxy {
+        upstream,
+        cache: Mutex::new(HashMap::new()),
+    })));
+    // We have not implemented extended query in this server, use placeholder instead
+    let placeholder = Arc::new(StatelessMakeHandler::new(Arc::new(
+        PlaceholderExtendedQueryHandler,
+    )));
+    let authenticator = Arc::new(StatelessMakeHandler::new(Arc::new(NoopStartupHandler)));
+
+    let server_addr = "127.0.0.1:5432";
+    let listener = TcpListener::bind(server_addr).await.unwrap();
+    println!("Listening to {}", server_addr);
+    loop {
+        let incoming_socket = listener.accept().await.unwrap();
+        let authenticator_ref = authenticator.make();
+        let processor_ref = processor.make();
+        let placeholder_ref = placeholder.make();
+        tokio::spawn(async move {
+            process_socket(
+                incoming_socket.0,
+                None,
+                authenticator_ref,
+                processor_ref,
+                placeholder_ref,
+            )
+            .await
+        });
+    }
+}
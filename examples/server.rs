@@ -50,7 +50,7 @@ impl SimpleQueryHandler for DummyProcessor {
                 (Some(2), None),
             ];
             let schema_ref = schema.clone();
-            let data_row_stream = stream::iter(data.into_iter()).map(move |r| {
+            let data_row_stream = stream::iter(data).map(move |r| {
                 let mut encoder = DataRowEncoder::new(schema_ref.clone());
                 encoder.encode_field(&r.0)?;
                 encoder.encode_field(&r.1)?;
@@ -62,8 +62,18 @@ impl SimpleQueryHandler for DummyProcessor {
                 schema,
                 data_row_stream,
             ))])
+        } else if let Some(command) = ["CREATE TABLE", "DROP TABLE", "ALTER TABLE"]
+            .into_iter()
+            .find(|command| query.starts_with(command))
+        {
+            // DDL replies the command name only, without row count
+            Ok(vec![Response::Execution(Tag::new(command))])
         } else {
-            Ok(vec![Response::Execution(Tag::new("OK").with_rows(1))])
+            Ok(vec![Response::Error(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "0A000".to_owned(),
+                format!("unsupported statement: {}", query),
+            )))])
         }
     }
 }
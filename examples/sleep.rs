@@ -0,0 +1,160 @@
+//! A server that implements `SELECT pg_sleep(seconds)` to demo cancellable
+//! handlers.
+//!
+//! Run a long query in psql and press `Ctrl-C` to cancel it:
+//!
+//! ```sql
+//! SELECT pg_sleep(30);
+//! ```
+//!
+//! or let it time out:
+//!
+//! ```sql
+//! SET statement_timeout = 1000;
+//! SELECT pg_sleep(30);
+//! ```
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{stream, Sink};
+use tokio::net::TcpListener;
+
+use pgwire::api::auth::noop::NoopStartupHandler;
+use pgwire::api::query::{PlaceholderExtendedQueryHandler, SimpleQueryHandler};
+use pgwire::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response};
+use pgwire::api::set::{DefaultSetHandler, SetStatement};
+use pgwire::api::{ClientInfo, MakeHandler, StatelessMakeHandler, Type};
+use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
+use pgwire::messages::PgWireBackendMessage;
+use pgwire::tokio::process_socket;
+use pgwire::types::RawText;
+
+#[derive(Default)]
+pub struct SleepProcessor {
+    set_handler: DefaultSetHandler,
+}
+
+fn query_canceled(reason: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "57014".to_owned(),
+        format!("canceling statement due to {reason}"),
+    )))
+}
+
+/// Parse `pg_sleep` argument in seconds from query
+fn parse_pg_sleep(query: &str) -> Option<f64> {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let rest = query
+        .strip_prefix("SELECT pg_sleep(")
+        .or_else(|| query.strip_prefix("select pg_sleep("))?;
+    rest.strip_suffix(')')?.trim().parse().ok()
+}
+
+#[async_trait]
+impl SimpleQueryHandler for SleepProcessor {
+    async fn do_query<'a, C>(
+        &self,
+        client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let Some(set) = SetStatement::parse(query) {
+            return Ok(vec![self.set_handler.on_set(client, &set).await?]);
+        }
+
+        let Some(seconds) = parse_pg_sleep(query) else {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "0A000".to_owned(),
+                "only SELECT pg_sleep(seconds) is supported".to_owned(),
+            ))));
+        };
+
+        // statement_timeout in milliseconds, 0 disables the timeout
+        let timeout = client
+            .metadata()
+            .get("statement_timeout")
+            .and_then(|v| v.parse::<u64>().ok())
+            .filter(|v| *v > 0)
+            .map(Duration::from_millis);
+
+        // take the token before doing any work, it's cancelled when a
+        // CancelRequest for this connection arrives
+        let cancel_token = client.cancel_token();
+        // too long for a `Duration`, like `inf` or `1e300`
+        let duration = Duration::try_from_secs_f64(seconds.max(0.0)).map_err(|_| {
+            PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "22003".to_owned(),
+                format!("pg_sleep argument {seconds} is out of range"),
+            )))
+        })?;
+        let sleep = tokio::time::sleep(duration);
+        let timeout = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => futures::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            _ = sleep => {}
+            _ = cancel_token.cancelled() => return Err(query_canceled("user request")),
+            _ = timeout => return Err(query_canceled("statement timeout")),
+        }
+
+        let schema = Arc::new(vec![FieldInfo::new(
+            "pg_sleep".into(),
+            None,
+            None,
+            Type::VOID,
+            FieldFormat::Text,
+        )]);
+        let mut encoder = DataRowEncoder::new(schema.clone());
+        // void is rendered as empty string, as postgres does
+        encoder.encode_field(&RawText(b""))?;
+        let row = encoder.finish();
+        Ok(vec![Response::Query(QueryResponse::new(
+            schema,
+            stream::iter(vec![row]),
+        ))])
+    }
+}
+
+#[tokio::main]
+pub async fn main() {
+    let processor = Arc::new(StatelessMakeHandler::new(Arc::new(
+        SleepProcessor::default(),
+    )));
+    let placeholder = Arc::new(StatelessMakeHandler::new(Arc::new(
+        PlaceholderExtendedQueryHandler,
+    )));
+    let authenticator = Arc::new(StatelessMakeHandler::new(Arc::new(NoopStartupHandler)));
+
+    let server_addr = "127.0.0.1:5432";
+    let listener = TcpListener::bind(server_addr).await.unwrap();
+    println!("Listening to {}", server_addr);
+    loop {
+        let incoming_socket = listener.accept().await.unwrap();
+        let authenticator_ref = authenticator.make();
+        let processor_ref = processor.make();
+        let placeholder_ref = placeholder.make();
+        tokio::spawn(async move {
+            process_socket(
+                incoming_socket.0,
+                None,
+                authenticator_ref,
+                processor_ref,
+                placeholder_ref,
+            )
+            .await
+        });
+    }
+}
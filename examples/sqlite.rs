@@ -8,15 +8,16 @@ use pgwire::api::auth::{AuthSource, DefaultServerParameterProvider, LoginInfo, P
 use pgwire::api::portal::{Format, Portal};
 use pgwire::api::query::{ExtendedQueryHandler, SimpleQueryHandler, StatementOrPortal};
 use pgwire::api::results::{
-    DataRowEncoder, DescribeResponse, FieldInfo, QueryResponse, Response, Tag,
+    blocking_row_stream, DataRowEncoder, DescribeResponse, FieldInfo, QueryResponse, Response, Tag,
 };
 use pgwire::api::stmt::NoopQueryParser;
 use pgwire::api::{ClientInfo, MakeHandler, Type};
 use pgwire::error::{ErrorInfo, PgWireError, PgWireResult};
 use pgwire::messages::data::DataRow;
 use pgwire::tokio::process_socket;
-use rusqlite::Rows;
+use pgwire::types::ByteaOutput;
 use rusqlite::{types::ValueRef, Connection, Statement, ToSql};
+use rusqlite::{Row, Rows};
 use tokio::net::TcpListener;
 
 pub struct SqliteBackend {
@@ -44,30 +45,48 @@ impl AuthSource for DummyAuthSource {
 impl SimpleQueryHandler for SqliteBackend {
     async fn do_query<'a, C>(
         &self,
-        _client: &mut C,
+        client: &mut C,
         query: &'a str,
     ) -> PgWireResult<Vec<Response<'a>>>
     where
         C: ClientInfo + Unpin + Send + Sync,
     {
-        let conn = self.conn.lock().unwrap();
         if query.to_uppercase().starts_with("SELECT") {
-            let mut stmt = conn
-                .prepare(query)
-                .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-            let header = Arc::new(row_desc_from_stmt(&stmt, &Format::UnifiedText)?);
-            stmt.query(())
-                .map(|rows| {
-                    let s = encode_row_data(rows, header.clone());
-                    vec![Response::Query(QueryResponse::new(header, s))]
-                })
-                .map_err(|e| PgWireError::ApiError(Box::new(e)))
+            let header = {
+                let conn = self.conn.lock().unwrap();
+                let stmt = conn
+                    .prepare_cached(query)
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                Arc::new(row_desc_from_stmt(&stmt, &Format::UnifiedText)?)
+            };
+
+            // sqlite calls are blocking, run them off the async runtime and
+            // stream rows back
+            let conn = self.conn.clone();
+            let query = query.to_owned();
+            let schema = header.clone();
+            let bytea_output = bytea_output(client);
+            let rows = blocking_row_stream(64, move |sender| {
+                let conn = conn.lock().unwrap();
+                let mut stmt = conn
+                    .prepare_cached(&query)
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                let mut rows = stmt
+                    .query(())
+                    .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+                while let Ok(Some(row)) = rows.next() {
+                    if !sender.send(encode_row(row, &schema, bytea_output)) {
+                        break;
+                    }
+                }
+                Ok(())
+            });
+            Ok(vec![Response::Query(QueryResponse::new(header, rows))])
         } else {
+            let conn = self.conn.lock().unwrap();
             conn.execute(query, ())
                 .map(|affected_rows| {
-                    vec![Response::Execution(
-                        Tag::new("OK").with_rows(affected_rows).into(),
-                    )]
+                    vec![Response::Execution(Tag::new("OK").with_rows(affected_rows))]
                 })
                 .map_err(|e| PgWireError::ApiError(Box::new(e)))
         }
@@ -77,6 +96,7 @@ impl SimpleQueryHandler for SqliteBackend {
 fn name_to_type(name: &str) -> PgWireResult<Type> {
     dbg!(name);
     match name.to_uppercase().as_ref() {
+        "SMALLINT" | "INT2" => Ok(Type::INT2),
         "INT" => Ok(Type::INT8),
         "VARCHAR" => Ok(Type::VARCHAR),
         "TEXT" => Ok(Type::TEXT),
@@ -107,82 +127,108 @@ fn row_desc_from_stmt(stmt: &Statement, format: &Format) -> PgWireResult<Vec<Fie
         .collect()
 }
 
+/// Text format of blobs requested by client with `bytea_output`.
+fn bytea_output<C: ClientInfo>(client: &C) -> ByteaOutput {
+    client
+        .metadata()
+        .get("bytea_output")
+        .and_then(|value| ByteaOutput::parse(value))
+        .unwrap_or_default()
+}
+
+fn encode_row(
+    row: &Row,
+    schema: &Arc<Vec<FieldInfo>>,
+    bytea_output: ByteaOutput,
+) -> PgWireResult<DataRow> {
+    let mut encoder = DataRowEncoder::new(schema.clone()).with_bytea_output(bytea_output);
+    for idx in 0..schema.len() {
+        let data = row.get_ref_unwrap::<usize>(idx);
+        match data {
+            ValueRef::Null => encoder.encode_field(&None::<i8>)?,
+            ValueRef::Integer(i) => {
+                if schema[idx].datatype() == &Type::INT2 {
+                    let i = i16::try_from(i).map_err(|_| {
+                        PgWireError::UserError(Box::new(ErrorInfo::new(
+                            "ERROR".to_owned(),
+                            "22003".to_owned(),
+                            "smallint out of range".to_owned(),
+                        )))
+                    })?;
+                    encoder.encode_field(&i)?;
+                } else {
+                    encoder.encode_field(&i)?;
+                }
+            }
+            ValueRef::Real(f) => {
+                encoder.encode_field(&f)?;
+            }
+            ValueRef::Text(t) => {
+                encoder.encode_field(&String::from_utf8_lossy(t).as_ref())?;
+            }
+            ValueRef::Blob(b) => {
+                encoder.encode_field(&b)?;
+            }
+        }
+    }
+
+    encoder.finish()
+}
+
 fn encode_row_data(
     mut rows: Rows,
     schema: Arc<Vec<FieldInfo>>,
+    bytea_output: ByteaOutput,
 ) -> impl Stream<Item = PgWireResult<DataRow>> {
     let mut results = Vec::new();
-    let ncols = schema.len();
     while let Ok(Some(row)) = rows.next() {
-        let mut encoder = DataRowEncoder::new(schema.clone());
-        for idx in 0..ncols {
-            let data = row.get_ref_unwrap::<usize>(idx);
-            match data {
-                ValueRef::Null => encoder.encode_field(&None::<i8>).unwrap(),
-                ValueRef::Integer(i) => {
-                    encoder.encode_field(&i).unwrap();
-                }
-                ValueRef::Real(f) => {
-                    encoder.encode_field(&f).unwrap();
-                }
-                ValueRef::Text(t) => {
-                    encoder
-                        .encode_field(&String::from_utf8_lossy(t).as_ref())
-                        .unwrap();
-                }
-                ValueRef::Blob(b) => {
-                    encoder.encode_field(&b).unwrap();
-                }
-            }
-        }
-
-        results.push(encoder.finish());
+        results.push(encode_row(row, &schema, bytea_output));
     }
 
-    stream::iter(results.into_iter())
+    stream::iter(results)
 }
 
-fn get_params(portal: &Portal<String>) -> Vec<Box<dyn ToSql>> {
+fn get_params(portal: &Portal<String>) -> PgWireResult<Vec<Box<dyn ToSql>>> {
     let mut results = Vec::with_capacity(portal.parameter_len());
     for i in 0..portal.parameter_len() {
         let param_type = portal.statement.parameter_types.get(i).unwrap();
         // we only support a small amount of types for demo
         match param_type {
             &Type::BOOL => {
-                let param = portal.parameter::<bool>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<bool>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::INT2 => {
-                let param = portal.parameter::<i16>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<i16>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::INT4 => {
-                let param = portal.parameter::<i32>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<i32>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::INT8 => {
-                let param = portal.parameter::<i64>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<i64>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::TEXT | &Type::VARCHAR => {
-                let param = portal.parameter::<String>(i, param_type).unwrap();
+                let param = portal
+                    .text_aware_parameter::<String>(i, param_type)
+                    .unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::FLOAT4 => {
-                let param = portal.parameter::<f32>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<f32>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
             &Type::FLOAT8 => {
-                let param = portal.parameter::<f64>(i, param_type).unwrap();
+                let param = portal.text_aware_parameter::<f64>(i, param_type).unwrap();
                 results.push(Box::new(param) as Box<dyn ToSql>);
             }
-            _ => {
-                unimplemented!("parameter type not supported")
-            }
+            _ => return Err(PgWireError::unsupported_type(param_type.oid())),
         }
     }
 
-    results
+    Ok(results)
 }
 
 #[async_trait]
@@ -196,7 +242,7 @@ impl ExtendedQueryHandler for SqliteBackend {
 
     async fn do_query<'a, C>(
         &self,
-        _client: &mut C,
+        client: &mut C,
         portal: &'a Portal<Self::Statement>,
         _max_rows: usize,
     ) -> PgWireResult<Response<'a>>
@@ -208,7 +254,7 @@ impl ExtendedQueryHandler for SqliteBackend {
         let mut stmt = conn
             .prepare_cached(query)
             .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
-        let params = get_params(portal);
+        let params = get_params(portal)?;
         let params_ref = params
             .iter()
             .map(|f| f.as_ref())
@@ -218,15 +264,13 @@ impl ExtendedQueryHandler for SqliteBackend {
             let header = Arc::new(row_desc_from_stmt(&stmt, &portal.result_column_format)?);
             stmt.query::<&[&dyn rusqlite::ToSql]>(params_ref.as_ref())
                 .map(|rows| {
-                    let s = encode_row_data(rows, header.clone());
+                    let s = encode_row_data(rows, header.clone(), bytea_output(client));
                     Response::Query(QueryResponse::new(header, s))
                 })
                 .map_err(|e| PgWireError::ApiError(Box::new(e)))
         } else {
             stmt.execute::<&[&dyn rusqlite::ToSql]>(params_ref.as_ref())
-                .map(|affected_rows| {
-                    Response::Execution(Tag::new("OK").with_rows(affected_rows).into())
-                })
+                .map(|affected_rows| Response::Execution(Tag::new("OK").with_rows(affected_rows)))
                 .map_err(|e| PgWireError::ApiError(Box::new(e)))
         }
     }
@@ -0,0 +1,65 @@
+//! Admission control, to shed load when the server is overloaded.
+
+use std::fmt;
+
+use super::ClientInfo;
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Decide whether a query may run, before it reaches the query handlers.
+///
+/// `admit` is called by the message loop of `process_socket_with_options` on
+/// every query start: a `Query` message of simple query, an `Execute`
+/// message of extended query, or a `FunctionCall`. Return an error to reject
+/// the query, usually `too_many_queries_error`. The error is sent to client
+/// as `ErrorResponse` followed by `ReadyForQuery`, or after the next `Sync`
+/// in extended query, and the connection is kept for client to retry.
+///
+/// It's implemented for closures taking `&dyn ClientInfo`, e.g. to allow a
+/// limited number of queries in flight:
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// use pgwire::api::admission::{too_many_queries_error, AdmissionControl};
+/// use pgwire::api::ClientInfo;
+///
+/// let running = Arc::new(AtomicUsize::new(0));
+/// let admission: Arc<dyn AdmissionControl> = Arc::new(move |_: &dyn ClientInfo| {
+///     if running.load(Ordering::Relaxed) >= 100 {
+///         Err(too_many_queries_error())
+///     } else {
+///         Ok(())
+///     }
+/// });
+/// ```
+pub trait AdmissionControl: Send + Sync {
+    fn admit(&self, client: &dyn ClientInfo) -> PgWireResult<()>;
+}
+
+impl<F> AdmissionControl for F
+where
+    F: Fn(&dyn ClientInfo) -> PgWireResult<()> + Send + Sync,
+{
+    fn admit(&self, client: &dyn ClientInfo) -> PgWireResult<()> {
+        self(client)
+    }
+}
+
+impl fmt::Debug for dyn AdmissionControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AdmissionControl")
+    }
+}
+
+/// `53300` error to reject a query when the server is overloaded.
+///
+/// Use `57P03 cannot_connect_now` instead when the server is temporarily
+/// unavailable, e.g. starting up or shutting down.
+pub fn too_many_queries_error() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "53300".to_owned(),
+        "server is overloaded, try again later".to_owned(),
+    )))
+}
@@ -2,13 +2,13 @@ use std::fmt::Debug;
 
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
+use log::warn;
 
 use super::{
-    AuthSource, ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider,
+    AsyncServerParameterProvider, AuthSource, ClientInfo, LoginInfo, PgWireConnectionState,
     StartupHandler,
 };
 use crate::error::{ErrorInfo, PgWireError, PgWireResult};
-use crate::messages::response::ErrorResponse;
 use crate::messages::startup::Authentication;
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
@@ -16,10 +16,28 @@ use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 pub struct CleartextPasswordAuthStartupHandler<A, P> {
     auth_source: A,
     parameter_provider: P,
+    /// refuse cleartext password over connections without TLS
+    #[new(default)]
+    require_tls: bool,
+}
+
+impl<A, P> CleartextPasswordAuthStartupHandler<A, P> {
+    /// Refuse authentication of clients not connected with TLS, so passwords
+    /// are never sent in plaintext over the network.
+    ///
+    /// When disabled (the default), a warning is logged for each cleartext
+    /// password authentication over an unencrypted connection.
+    pub fn set_require_tls(&mut self, require_tls: bool) {
+        self.require_tls = require_tls;
+    }
+
+    pub fn require_tls(&self) -> bool {
+        self.require_tls
+    }
 }
 
 #[async_trait]
-impl<V: AuthSource, P: ServerParameterProvider> StartupHandler
+impl<V: AuthSource, P: AsyncServerParameterProvider> StartupHandler
     for CleartextPasswordAuthStartupHandler<V, P>
 {
     async fn on_startup<C>(
@@ -35,6 +53,20 @@ impl<V: AuthSource, P: ServerParameterProvider> StartupHandler
         match message {
             PgWireFrontendMessage::Startup(ref startup) => {
                 super::save_startup_parameters_to_metadata(client, startup);
+                if !client.is_secure() {
+                    if self.require_tls {
+                        let error_info = ErrorInfo::new(
+                            "FATAL".to_owned(),
+                            "28000".to_owned(),
+                            "Cleartext password requires SSL, use sslmode=require".to_owned(),
+                        );
+                        return super::fail_authentication(client, error_info).await;
+                    }
+                    warn!(
+                        "cleartext password authentication over unencrypted connection from {}",
+                        client.socket_addr()
+                    );
+                }
                 client.set_state(PgWireConnectionState::AuthenticationInProgress);
                 client
                     .send(PgWireBackendMessage::Authentication(
@@ -43,23 +75,27 @@ impl<V: AuthSource, P: ServerParameterProvider> StartupHandler
                     .await?;
             }
             PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
-                let pwd = pwd.into_password()?;
+                let pwd = match pwd.into_password() {
+                    Ok(pwd) => pwd,
+                    Err(e) => {
+                        let error_info = super::into_malformed_message_error(e)?;
+                        return super::fail_authentication(client, error_info).await;
+                    }
+                };
+                if pwd.password.is_empty() {
+                    return super::fail_authentication(client, super::empty_password_returned())
+                        .await;
+                }
                 let login_info = LoginInfo::from_client_info(client);
-                let pass = self.auth_source.get_password(&login_info).await?;
-                if pass.password == pwd.password.as_bytes() {
-                    super::finish_authentication(client, &self.parameter_provider).await
+                let error_info = match self.auth_source.get_password(&login_info).await {
+                    Ok(pass) if pass.password == pwd.password.as_bytes() => None,
+                    Ok(_) => Some(super::password_authentication_failed(login_info.user())),
+                    Err(e) => Some(super::into_authentication_error(e, login_info.user())?),
+                };
+                if let Some(error_info) = error_info {
+                    super::fail_authentication(client, error_info).await?;
                 } else {
-                    let error_info = ErrorInfo::new(
-                        "FATAL".to_owned(),
-                        "28P01".to_owned(),
-                        "Password authentication failed".to_owned(),
-                    );
-                    let error = ErrorResponse::from(error_info);
-
-                    client
-                        .feed(PgWireBackendMessage::ErrorResponse(error))
-                        .await?;
-                    client.close().await?;
+                    super::finish_authentication(client, &self.parameter_provider).await?;
                 }
             }
             _ => {}
@@ -67,3 +103,148 @@ impl<V: AuthSource, P: ServerParameterProvider> StartupHandler
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::api::auth::{DefaultServerParameterProvider, Password};
+    use crate::api::testing::RecordingClient;
+    use crate::messages::startup::{Password as PasswordMessage, PasswordMessageFamily, Startup};
+    use crate::messages::Message;
+
+    struct TestAuthSource;
+
+    #[async_trait]
+    impl AuthSource for TestAuthSource {
+        async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
+            match login.user() {
+                Some("tomcat") => Ok(Password::new(None, b"pencil".to_vec())),
+                user => Err(PgWireError::UserNotFound(
+                    user.unwrap_or_default().to_owned(),
+                )),
+            }
+        }
+    }
+
+    async fn startup(is_secure: bool, require_tls: bool) -> RecordingClient {
+        let mut client = RecordingClient::new();
+        client.is_secure = is_secure;
+        client.state = PgWireConnectionState::AwaitingStartup;
+        let mut handler = CleartextPasswordAuthStartupHandler::new(
+            TestAuthSource,
+            DefaultServerParameterProvider::default(),
+        );
+        handler.set_require_tls(require_tls);
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "tomcat".to_owned());
+        handler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn test_require_tls() {
+        let mut client = startup(false, true).await;
+        assert!(client.is_closed());
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::ErrorResponse(error)] => {
+                let mut buf = BytesMut::new();
+                error.encode(&mut buf).unwrap();
+                assert!(String::from_utf8_lossy(&buf).contains("28000"));
+            }
+            _ => panic!("expect a single error response"),
+        }
+
+        for (is_secure, require_tls) in [(true, true), (false, false)] {
+            let mut client = startup(is_secure, require_tls).await;
+            assert!(!client.is_closed());
+            assert!(matches!(
+                &client.messages().unwrap()[..],
+                [PgWireBackendMessage::Authentication(
+                    Authentication::CleartextPassword
+                )]
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_password_authentication_failed() {
+        for (user, password) in [("tomcat", "tiger"), ("nobody", "pencil")] {
+            let mut client = startup(true, false).await;
+            client
+                .metadata_mut()
+                .insert("user".to_owned(), user.to_owned());
+            client.take_bytes();
+
+            let handler = CleartextPasswordAuthStartupHandler::new(
+                TestAuthSource,
+                DefaultServerParameterProvider::default(),
+            );
+            let mut buf = BytesMut::new();
+            PasswordMessage::new(password.to_owned())
+                .encode_body(&mut buf)
+                .unwrap();
+            handler
+                .on_startup(
+                    &mut client,
+                    PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(buf)),
+                )
+                .await
+                .unwrap();
+
+            assert!(client.is_closed());
+            match &client.messages().unwrap()[..] {
+                [PgWireBackendMessage::ErrorResponse(error)] => {
+                    assert!(error.fields.contains(&(b'C', "28P01".to_owned())));
+                    let message = format!("password authentication failed for user \"{user}\"");
+                    assert!(error.fields.contains(&(b'M', message)));
+                }
+                _ => panic!("expect a single error response"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_password() {
+        // an empty password, and malformed ones without a single terminator
+        for (body, code) in [
+            (&b"\0"[..], "28P01"),
+            (&b""[..], "28000"),
+            (&b"pencil"[..], "28000"),
+            (&b"pencil\0\0"[..], "28000"),
+        ] {
+            let mut client = startup(true, false).await;
+            client.take_bytes();
+
+            let handler = CleartextPasswordAuthStartupHandler::new(
+                TestAuthSource,
+                DefaultServerParameterProvider::default(),
+            );
+            handler
+                .on_startup(
+                    &mut client,
+                    PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(
+                        BytesMut::from(body),
+                    )),
+                )
+                .await
+                .unwrap();
+
+            assert!(client.is_closed());
+            match &client.messages().unwrap()[..] {
+                [PgWireBackendMessage::ErrorResponse(error)] => {
+                    assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                    assert!(error.fields.contains(&(b'C', code.to_owned())));
+                }
+                _ => panic!("expect a single error response"),
+            }
+        }
+    }
+}
@@ -6,23 +6,38 @@ use futures::sink::{Sink, SinkExt};
 use tokio::sync::Mutex;
 
 use super::{
-    AuthSource, ClientInfo, LoginInfo, PgWireConnectionState, ServerParameterProvider,
+    AsyncServerParameterProvider, AuthSource, ClientInfo, LoginInfo, PgWireConnectionState,
     StartupHandler,
 };
 use crate::api::MakeHandler;
-use crate::error::{ErrorInfo, PgWireError, PgWireResult};
-use crate::messages::response::ErrorResponse;
+use crate::error::{PgWireError, PgWireResult};
 use crate::messages::startup::Authentication;
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
 pub struct Md5PasswordAuthStartupHandler<A, P> {
     auth_source: Arc<A>,
     parameter_provider: Arc<P>,
-    cached_password: Mutex<Vec<u8>>,
+    /// expected password, `None` if the user doesn't exist
+    cached_password: Mutex<Option<Vec<u8>>>,
+    /// server secret for deriving mock salt of nonexistent users
+    mock_auth_secret: [u8; 32],
+}
+
+impl<A, P> Md5PasswordAuthStartupHandler<A, P> {
+    /// Create a salt for mock authentication of nonexistent user.
+    ///
+    /// Like the SCRAM handler, the salt is derived from username and a server
+    /// secret, so it stays the same across attempts as a real user's does.
+    fn mock_salt(&self, username: &str) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(username.len() + self.mock_auth_secret.len());
+        buf.extend_from_slice(username.as_bytes());
+        buf.extend_from_slice(&self.mock_auth_secret);
+        md5::compute(buf)[..4].to_vec()
+    }
 }
 
 #[async_trait]
-impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
+impl<A: AuthSource, P: AsyncServerParameterProvider> StartupHandler
     for Md5PasswordAuthStartupHandler<A, P>
 {
     async fn on_startup<C>(
@@ -41,39 +56,55 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
                 client.set_state(PgWireConnectionState::AuthenticationInProgress);
 
                 let login_info = LoginInfo::from_client_info(client);
-                let salt_and_pass = self.auth_source.get_password(&login_info).await?;
-
-                let salt = salt_and_pass
-                    .salt
-                    .as_ref()
-                    .expect("Salt is required for Md5Password authentication");
-
-                *self.cached_password.lock().await = salt_and_pass.password.clone();
+                let salt = match self.auth_source.get_password(&login_info).await {
+                    Ok(salt_and_pass) => {
+                        *self.cached_password.lock().await = Some(salt_and_pass.password);
+                        salt_and_pass
+                            .salt
+                            .expect("Salt is required for Md5Password authentication")
+                    }
+                    // like postgres, request password with a mock salt and
+                    // fail after, so the nonexistent user is indistinguishable
+                    // from a wrong password
+                    Err(PgWireError::UserNotFound(_)) => {
+                        *self.cached_password.lock().await = None;
+                        self.mock_salt(login_info.user().unwrap_or_default())
+                    }
+                    Err(e) => {
+                        let error_info = super::into_authentication_error(e, login_info.user())?;
+                        return super::fail_authentication(client, error_info).await;
+                    }
+                };
 
                 client
                     .send(PgWireBackendMessage::Authentication(
-                        Authentication::MD5Password(salt.clone()),
+                        Authentication::MD5Password(salt),
                     ))
                     .await?;
             }
             PgWireFrontendMessage::PasswordMessageFamily(pwd) => {
-                let pwd = pwd.into_password()?;
-                let cached_pass = self.cached_password.lock().await;
+                let pwd = match pwd.into_password() {
+                    Ok(pwd) => pwd,
+                    Err(e) => {
+                        let error_info = super::into_malformed_message_error(e)?;
+                        return super::fail_authentication(client, error_info).await;
+                    }
+                };
+                if pwd.password.is_empty() {
+                    return super::fail_authentication(client, super::empty_password_returned())
+                        .await;
+                }
+                let matched =
+                    self.cached_password.lock().await.as_deref() == Some(pwd.password.as_bytes());
 
-                if pwd.password.as_bytes() == *cached_pass {
-                    super::finish_authentication(client, self.parameter_provider.as_ref()).await
+                if matched {
+                    super::finish_authentication(client, self.parameter_provider.as_ref()).await?;
                 } else {
-                    let error_info = ErrorInfo::new(
-                        "FATAL".to_owned(),
-                        "28P01".to_owned(),
-                        "Password authentication failed".to_owned(),
-                    );
-                    let error = ErrorResponse::from(error_info);
-
-                    client
-                        .feed(PgWireBackendMessage::ErrorResponse(error))
-                        .await?;
-                    client.close().await?;
+                    let user = LoginInfo::from_client_info(client)
+                        .user()
+                        .map(str::to_owned);
+                    let error_info = super::password_authentication_failed(user.as_deref());
+                    super::fail_authentication(client, error_info).await?;
                 }
             }
             _ => {}
@@ -102,12 +133,27 @@ pub fn hash_md5_password(username: &str, password: &str, salt: &[u8]) -> String
 pub struct MakeMd5PasswordAuthStartupHandler<A, P> {
     auth_source: Arc<A>,
     parameter_provider: Arc<P>,
+    #[new(value = "rand::random()")]
+    mock_auth_secret: [u8; 32],
+}
+
+impl<A, P> MakeMd5PasswordAuthStartupHandler<A, P> {
+    /// Set the server secret used to derive salts for nonexistent users.
+    ///
+    /// When `AuthSource` returns `PgWireError::UserNotFound`, the handler
+    /// still asks for the password with a salt derived from username and
+    /// this secret, then rejects it. A random secret is generated by
+    /// default. Configure a fixed one if you run multiple instances or
+    /// restart servers, so the mock salt doesn't change.
+    pub fn set_mock_auth_secret(&mut self, secret: [u8; 32]) {
+        self.mock_auth_secret = secret;
+    }
 }
 
 impl<V, P> MakeHandler for MakeMd5PasswordAuthStartupHandler<V, P>
 where
     V: AuthSource,
-    P: ServerParameterProvider,
+    P: AsyncServerParameterProvider,
 {
     type Handler = Arc<Md5PasswordAuthStartupHandler<V, P>>;
 
@@ -115,13 +161,117 @@ where
         Arc::new(Md5PasswordAuthStartupHandler {
             auth_source: self.auth_source.clone(),
             parameter_provider: self.parameter_provider.clone(),
-            cached_password: Mutex::new(vec![]),
+            cached_password: Mutex::new(None),
+            mock_auth_secret: self.mock_auth_secret,
         })
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::api::auth::{DefaultServerParameterProvider, Password};
+    use crate::api::testing::RecordingClient;
+    use crate::messages::startup::{PasswordMessageFamily, Startup};
+
+    struct TestAuthSource;
+
+    #[async_trait]
+    impl AuthSource for TestAuthSource {
+        async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
+            match login.user() {
+                // a misconfigured source with empty password
+                Some("tomcat") => Ok(Password::new(Some(b"salt".to_vec()), Vec::new())),
+                user => Err(PgWireError::UserNotFound(
+                    user.unwrap_or_default().to_owned(),
+                )),
+            }
+        }
+    }
+
+    /// Start authentication of `user`, return the salt requested
+    async fn request_salt(
+        maker: &MakeMd5PasswordAuthStartupHandler<TestAuthSource, DefaultServerParameterProvider>,
+        user: &str,
+    ) -> Vec<u8> {
+        let handler = maker.make();
+        let mut client = RecordingClient::<String>::new();
+        client.state = PgWireConnectionState::AwaitingStartup;
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), user.to_owned());
+        handler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::Authentication(Authentication::MD5Password(salt))] => {
+                salt.clone()
+            }
+            _ => panic!("expect MD5Password"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_salt() {
+        let mut maker = MakeMd5PasswordAuthStartupHandler::new(
+            Arc::new(TestAuthSource),
+            Arc::new(DefaultServerParameterProvider::default()),
+        );
+        maker.set_mock_auth_secret([7; 32]);
+
+        // stable across attempts, like a real user's salt
+        let salt = request_salt(&maker, "nobody").await;
+        assert_eq!(4, salt.len());
+        assert_eq!(salt, request_salt(&maker, "nobody").await);
+        assert_ne!(salt, request_salt(&maker, "somebody").await);
+    }
+
+    #[tokio::test]
+    async fn test_empty_password() {
+        let maker = MakeMd5PasswordAuthStartupHandler::new(
+            Arc::new(TestAuthSource),
+            Arc::new(DefaultServerParameterProvider::default()),
+        );
+
+        // an empty password, and malformed ones without a single terminator
+        for (body, code) in [(&b"\0"[..], "28P01"), (&b""[..], "28000")] {
+            let handler = maker.make();
+            let mut client = RecordingClient::<String>::new();
+            client.state = PgWireConnectionState::AwaitingStartup;
+            let mut startup = Startup::new();
+            startup
+                .parameters
+                .insert("user".to_owned(), "tomcat".to_owned());
+            handler
+                .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+                .await
+                .unwrap();
+            client.take_bytes();
+
+            handler
+                .on_startup(
+                    &mut client,
+                    PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(
+                        BytesMut::from(body),
+                    )),
+                )
+                .await
+                .unwrap();
+
+            assert!(client.is_closed());
+            match &client.messages().unwrap()[..] {
+                [PgWireBackendMessage::ErrorResponse(error)] => {
+                    assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                    assert!(error.fields.contains(&(b'C', code.to_owned())));
+                }
+                _ => panic!("expect a single error response"),
+            }
+        }
+    }
 
     #[test]
     fn test_hash_md5_passwd() {
@@ -131,6 +281,6 @@ mod tests {
 
         let result = "md521fe459d77d3e3ea9c9fcd5c11030d30";
 
-        assert_eq!(result, super::hash_md5_password(username, password, &salt));
+        assert_eq!(result, hash_md5_password(username, password, &salt));
     }
 }
@@ -3,13 +3,18 @@ use std::fmt::Debug;
 
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
-use futures::stream;
 use rand;
 
-use super::{ClientInfo, PgWireConnectionState, METADATA_DATABASE, METADATA_USER};
-use crate::error::{PgWireError, PgWireResult};
+use super::batch::MessageBatch;
+use super::{
+    ClientInfo, PgWireConnectionState, METADATA_APPLICATION_NAME, METADATA_DATABASE,
+    METADATA_INTEGER_DATETIMES, METADATA_USER,
+};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
 use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
-use crate::messages::startup::{Authentication, BackendKeyData, ParameterStatus, Startup};
+use crate::messages::startup::{
+    Authentication, BackendKeyData, NegotiateProtocolVersion, ParameterStatus, Startup,
+};
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
 /// Handles startup process and frontend messages
@@ -33,16 +38,54 @@ pub trait ServerParameterProvider: Send + Sync {
         C: ClientInfo;
 }
 
+/// Async variant of `ServerParameterProvider`, for parameters that depend on
+/// external state, e.g. the default `search_path` of a user looked up in a
+/// database.
+///
+/// It's called once authentication succeeds, before `AuthenticationOk` is
+/// sent. `client` is mutable so the provider can keep what it loaded in
+/// metadata or extensions of the connection. An error fails the startup: it's
+/// sent to client as `ErrorResponse` and the connection is closed.
+///
+/// Every `ServerParameterProvider` is an `AsyncServerParameterProvider`,
+/// startup handlers accept either.
+#[async_trait]
+pub trait AsyncServerParameterProvider: Send + Sync {
+    async fn server_parameters<C>(
+        &self,
+        client: &mut C,
+    ) -> PgWireResult<Option<HashMap<String, String>>>
+    where
+        C: ClientInfo + Send;
+}
+
+#[async_trait]
+impl<P: ServerParameterProvider> AsyncServerParameterProvider for P {
+    async fn server_parameters<C>(
+        &self,
+        client: &mut C,
+    ) -> PgWireResult<Option<HashMap<String, String>>>
+    where
+        C: ClientInfo + Send,
+    {
+        Ok(ServerParameterProvider::server_parameters(self, client))
+    }
+}
+
 /// Default noop parameter provider.
 ///
 /// This provider responds frontend with default parameters:
 ///
 /// - `DateStyle: ISO YMD`: the default text serialization in this library is
-/// using `YMD` style date. If you override this, or use your own serialization
-/// for date types, remember to update this as well.
+///   using `YMD` style date. If you override this, or use your own serialization
+///   for date types, remember to update this as well.
 /// - `server_encoding: UTF8`
 /// - `client_encoding: UTF8`
-/// - `integer_datetimes: on`:
+/// - `integer_datetimes: on`: the only format of timestamps this library
+///   encodes, see `check_integer_datetimes`
+/// - `server_version_num`: derived from `server_version`, for clients that
+///   parse the numeric form
+/// - `application_name`: as given by client in startup message
 ///
 #[non_exhaustive]
 #[derive(Debug)]
@@ -51,10 +94,17 @@ pub struct DefaultServerParameterProvider {
     pub server_encoding: String,
     pub client_encoding: String,
     pub date_style: String,
+    #[deprecated(
+        note = "integer datetimes are always used, `integer_datetimes` is reported as on"
+    )]
     pub integer_datetimes: String,
+    /// Reported at startup, use `set::report_parameter` to report role changes
+    /// later in the session.
+    pub in_hot_standby: String,
 }
 
 impl Default for DefaultServerParameterProvider {
+    #[allow(deprecated)]
     fn default() -> Self {
         Self {
             server_version: env!("CARGO_PKG_VERSION").to_owned(),
@@ -62,29 +112,58 @@ impl Default for DefaultServerParameterProvider {
             client_encoding: "UTF8".to_owned(),
             date_style: "ISO YMD".to_owned(),
             integer_datetimes: "on".to_owned(),
+            in_hot_standby: "off".to_owned(),
         }
     }
 }
 
 impl ServerParameterProvider for DefaultServerParameterProvider {
-    fn server_parameters<C>(&self, _client: &C) -> Option<HashMap<String, String>>
+    fn server_parameters<C>(&self, client: &C) -> Option<HashMap<String, String>>
     where
         C: ClientInfo,
     {
-        let mut params = HashMap::with_capacity(5);
+        let mut params = HashMap::with_capacity(8);
         params.insert("server_version".to_owned(), self.server_version.clone());
+        if let Some(version_num) = server_version_num(&self.server_version) {
+            params.insert("server_version_num".to_owned(), version_num.to_string());
+        }
         params.insert("server_encoding".to_owned(), self.server_encoding.clone());
         params.insert("client_encoding".to_owned(), self.client_encoding.clone());
         params.insert("DateStyle".to_owned(), self.date_style.clone());
+        // timestamps are always encoded as integers
+        params.insert(METADATA_INTEGER_DATETIMES.to_owned(), "on".to_owned());
+        params.insert("in_hot_standby".to_owned(), self.in_hot_standby.clone());
         params.insert(
-            "integer_datetimes".to_owned(),
-            self.integer_datetimes.clone(),
+            METADATA_APPLICATION_NAME.to_owned(),
+            client.application_name().unwrap_or_default().to_owned(),
         );
 
         Some(params)
     }
 }
 
+/// Compute `server_version_num` from a version string like `15.3` or
+/// `9.6.24 (Debian)`.
+///
+/// Like postgres, it's `major * 10000 + minor` since 10, and
+/// `major * 10000 + minor * 100 + patch` for older versions. Return `None` if
+/// the string doesn't start with a numeric version.
+pub fn server_version_num(server_version: &str) -> Option<u32> {
+    let version = server_version
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()?;
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+    let major = parts.next().flatten()?;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+
+    if major >= 10 {
+        Some(major * 10000 + minor)
+    } else {
+        Some(major * 10000 + minor * 100 + patch)
+    }
+}
+
 #[derive(Debug, new, Clone)]
 pub struct Password {
     salt: Option<Vec<u8>>,
@@ -121,7 +200,7 @@ impl<'a> LoginInfo<'a> {
         &self.host
     }
 
-    pub fn from_client_info<C>(client: &'a C) -> LoginInfo
+    pub fn from_client_info<C>(client: &'a C) -> LoginInfo<'a>
     where
         C: ClientInfo,
     {
@@ -145,6 +224,11 @@ pub trait AuthSource: Send + Sync {
     /// Get password from the `AuthSource`.
     ///
     /// `Password` has a an optional salt field when it's hashed.
+    ///
+    /// Return `PgWireError::UserNotFound` if the user doesn't exist. Some
+    /// authentication mechanisms, like SCRAM, will then continue with a mock
+    /// exchange instead of failing early, so clients cannot tell whether a
+    /// user exists.
     async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password>;
 }
 
@@ -161,36 +245,358 @@ where
     );
 }
 
-pub async fn finish_authentication<C, P>(client: &mut C, server_parameter_provider: &P)
+/// Values of a parameter set in `options` of startup message, as
+/// `-c name=value` or `--name=value`.
+fn options_parameter<'a>(options: &'a str, name: &str) -> Option<&'a str> {
+    let mut value = None;
+    let mut words = options.split_whitespace();
+    while let Some(word) = words.next() {
+        let setting = match word {
+            "-c" => words.next(),
+            _ => word.strip_prefix("-c").or_else(|| word.strip_prefix("--")),
+        };
+        if let Some((key, v)) = setting.and_then(|setting| setting.split_once('=')) {
+            // postgres accepts `-` in place of `_` in names given this way
+            if key.replace('-', "_") == name {
+                value = Some(v);
+            }
+        }
+    }
+    value
+}
+
+/// Reject a startup message that asks for float datetimes.
+///
+/// Timestamps are always encoded as integer microseconds and
+/// `integer_datetimes` is reported as `on`. A client setting it to another
+/// value, as a startup parameter or in `options`, would misread binary
+/// timestamps, so it's refused with a `FATAL` error as postgres does. The
+/// message loop of `process_socket` calls this before the startup handler.
+pub fn check_integer_datetimes(startup: &Startup) -> Option<ErrorInfo> {
+    let value = startup
+        .parameters
+        .get(METADATA_INTEGER_DATETIMES)
+        .map(String::as_str)
+        .or_else(|| {
+            startup
+                .parameters
+                .get("options")
+                .and_then(|options| options_parameter(options, METADATA_INTEGER_DATETIMES))
+        })?;
+    if ["on", "true", "yes", "1"].contains(&value.to_lowercase().as_str()) {
+        return None;
+    }
+    let mut info = ErrorInfo::new(
+        "FATAL".to_owned(),
+        "55P02".to_owned(),
+        format!("parameter \"{METADATA_INTEGER_DATETIMES}\" cannot be changed"),
+    );
+    info.detail = Some("This server only supports integer datetimes.".to_owned());
+    Some(info)
+}
+
+/// Negotiate protocol version and extensions requested in startup message.
+///
+/// Like postgres, clients of a newer 3.x minor version and clients requesting
+/// protocol extensions with `_pq_.` parameters are not refused. No protocol
+/// extension is supported yet: the `_pq_.` parameters are removed from the
+/// startup message and, along with the supported minor version, returned in
+/// `NegotiateProtocolVersion`, which must be sent before authentication
+/// messages. `None` if no negotiation is needed.
+pub fn negotiate_protocol_version(startup: &mut Startup) -> Option<NegotiateProtocolVersion> {
+    let unsupported_options: Vec<String> = startup
+        .parameters
+        .keys()
+        .filter(|k| k.starts_with(Startup::PROTOCOL_EXTENSION_PREFIX))
+        .cloned()
+        .collect();
+    for option in &unsupported_options {
+        startup.parameters.remove(option);
+    }
+
+    if startup.protocol_number_minor > Startup::NEWEST_MINOR_VERSION
+        || !unsupported_options.is_empty()
+    {
+        Some(NegotiateProtocolVersion::new(
+            Startup::NEWEST_MINOR_VERSION as i32,
+            unsupported_options,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Error for failed password authentication, `28P01` with the same message as
+/// postgres, so clients like `psql` report it correctly.
+pub fn password_authentication_failed(user: Option<&str>) -> ErrorInfo {
+    ErrorInfo::new(
+        "FATAL".to_owned(),
+        "28P01".to_owned(),
+        format!(
+            "password authentication failed for user \"{}\"",
+            user.unwrap_or_default()
+        ),
+    )
+}
+
+/// Error for an empty password sent by client, identical to postgres'.
+///
+/// An empty password is never accepted, even if `AuthSource` returns one.
+pub fn empty_password_returned() -> ErrorInfo {
+    ErrorInfo::new(
+        "FATAL".to_owned(),
+        "28P01".to_owned(),
+        "empty password returned by client".to_owned(),
+    )
+}
+
+/// Turn errors of decoding a password or SASL message from client into
+/// authentication failure.
+///
+/// Empty and truncated messages, as well as malformed SCRAM data, are
+/// `28000`. Other errors are returned as is.
+pub(crate) fn into_malformed_message_error(error: PgWireError) -> PgWireResult<ErrorInfo> {
+    match error {
+        PgWireError::InvalidMessage(e) | PgWireError::InvalidScramMessage(e) => Ok(ErrorInfo::new(
+            "FATAL".to_owned(),
+            "28000".to_owned(),
+            format!("malformed authentication message: {e}"),
+        )),
+        e => Err(e),
+    }
+}
+
+/// Turn errors of `AuthSource::get_password` into authentication failure.
+///
+/// A nonexistent user fails as a wrong password does, so clients cannot tell
+/// whether a user exists, and a missing user name is `28000`. Other errors
+/// are returned as is.
+pub(crate) fn into_authentication_error(
+    error: PgWireError,
+    user: Option<&str>,
+) -> PgWireResult<ErrorInfo> {
+    match error {
+        PgWireError::UserNotFound(_) => Ok(password_authentication_failed(user)),
+        PgWireError::UserNameRequired => Ok(ErrorInfo::new(
+            "FATAL".to_owned(),
+            "28000".to_owned(),
+            "no PostgreSQL user name specified in startup packet".to_owned(),
+        )),
+        e => Err(e),
+    }
+}
+
+/// Send authentication failure to client and close the connection.
+pub async fn fail_authentication<C>(client: &mut C, error_info: ErrorInfo) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    client
+        .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
+        .await?;
+    client.close().await?;
+    Ok(())
+}
+
+/// Send `AuthenticationOk`, server parameters as `ParameterStatus`,
+/// `BackendKeyData` and `ReadyForQuery` to finish startup.
+///
+/// The messages are written in a single flush, so connection setup takes one
+/// round-trip. Parameters are sent ordered by name. If the provider fails,
+/// the error is sent instead, as `fail_authentication` does.
+pub async fn finish_authentication<C, P>(
+    client: &mut C,
+    server_parameter_provider: &P,
+) -> PgWireResult<()>
 where
     C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
     C::Error: Debug,
-    P: ServerParameterProvider,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    P: AsyncServerParameterProvider,
 {
-    let mut messages = vec![PgWireBackendMessage::Authentication(Authentication::Ok)];
+    let parameters =
+        match AsyncServerParameterProvider::server_parameters(server_parameter_provider, client)
+            .await
+        {
+            Ok(parameters) => parameters,
+            Err(PgWireError::UserError(error_info)) => {
+                return fail_authentication(client, *error_info).await
+            }
+            Err(e) => {
+                let error_info =
+                    ErrorInfo::new("FATAL".to_owned(), "XX000".to_owned(), e.to_string());
+                return fail_authentication(client, error_info).await;
+            }
+        };
 
-    if let Some(parameters) = server_parameter_provider.server_parameters(client) {
+    let mut batch = MessageBatch::new();
+    batch.push(PgWireBackendMessage::Authentication(Authentication::Ok))?;
+
+    if let Some(parameters) = parameters {
+        let mut parameters = parameters.into_iter().collect::<Vec<_>>();
+        parameters.sort();
         for (k, v) in parameters {
-            messages.push(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+            batch.push(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
                 k, v,
-            )));
+            )))?;
         }
     }
 
-    // TODO: store this backend key
-    messages.push(PgWireBackendMessage::BackendKeyData(BackendKeyData::new(
-        std::process::id() as i32,
-        rand::random::<i32>(),
-    )));
-    messages.push(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+    let (pid, secret_key) = client
+        .backend_key()
+        .unwrap_or_else(|| (std::process::id() as i32, rand::random::<i32>()));
+    batch.push(PgWireBackendMessage::BackendKeyData(BackendKeyData::new(
+        pid, secret_key,
+    )))?;
+    batch.push(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
         READY_STATUS_IDLE,
-    )));
-    let mut message_stream = stream::iter(messages.into_iter().map(Ok));
-    client.send_all(&mut message_stream).await.unwrap();
+    )))?;
+    batch.send(client).await?;
     client.set_state(PgWireConnectionState::ReadyForQuery);
+    Ok(())
 }
 
 pub mod cleartext;
 pub mod md5pass;
 pub mod noop;
 pub mod scram;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::testing::RecordingClient;
+
+    fn new_client() -> RecordingClient {
+        let mut client = RecordingClient::new();
+        client.state = PgWireConnectionState::AuthenticationInProgress;
+        client
+    }
+
+    #[tokio::test]
+    async fn test_finish_authentication() {
+        let mut client = new_client();
+        finish_authentication(&mut client, &DefaultServerParameterProvider::default())
+            .await
+            .unwrap();
+
+        let mut types = Vec::new();
+        let mut parameter_names = Vec::new();
+        for message in client.messages().unwrap() {
+            match message {
+                PgWireBackendMessage::Authentication(Authentication::Ok) => types.push('R'),
+                PgWireBackendMessage::ParameterStatus(status) => {
+                    types.push('S');
+                    parameter_names.push(status.name);
+                }
+                PgWireBackendMessage::BackendKeyData(_) => types.push('K'),
+                PgWireBackendMessage::ReadyForQuery(_) => types.push('Z'),
+                message => panic!("unexpected message {message:?}"),
+            }
+        }
+
+        let mut expected = vec!['R'];
+        expected.extend(std::iter::repeat('S').take(parameter_names.len()));
+        expected.extend(['K', 'Z']);
+        assert_eq!(expected, types);
+        // one flush after the whole burst
+        assert_eq!(1, client.flushes());
+
+        let mut sorted = parameter_names.clone();
+        sorted.sort();
+        assert_eq!(sorted, parameter_names);
+        assert!(matches!(
+            client.state(),
+            PgWireConnectionState::ReadyForQuery
+        ));
+    }
+
+    /// Look up `search_path` of the user, failing for unknown users
+    struct SearchPathProvider;
+
+    #[async_trait]
+    impl AsyncServerParameterProvider for SearchPathProvider {
+        async fn server_parameters<C>(
+            &self,
+            client: &mut C,
+        ) -> PgWireResult<Option<HashMap<String, String>>>
+        where
+            C: ClientInfo + Send,
+        {
+            tokio::task::yield_now().await;
+            match client.metadata().get(METADATA_USER).map(String::as_str) {
+                Some("tomcat") => Ok(Some(HashMap::from([(
+                    "search_path".to_owned(),
+                    "tomcat, public".to_owned(),
+                )]))),
+                _ => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "FATAL".to_owned(),
+                    "28000".to_owned(),
+                    "no search_path for user".to_owned(),
+                )))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_server_parameters() {
+        let mut client = new_client();
+        client
+            .metadata_mut()
+            .insert(METADATA_USER.to_owned(), "tomcat".to_owned());
+        finish_authentication(&mut client, &SearchPathProvider)
+            .await
+            .unwrap();
+        assert!(client.messages().unwrap().iter().any(|message| matches!(
+            message,
+            PgWireBackendMessage::ParameterStatus(status)
+                if status.name == "search_path" && status.value == "tomcat, public"
+        )));
+
+        let mut client = new_client();
+        finish_authentication(&mut client, &SearchPathProvider)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert!(matches!(
+            messages[0],
+            PgWireBackendMessage::ErrorResponse(_)
+        ));
+        assert!(!messages
+            .iter()
+            .any(|message| matches!(message, PgWireBackendMessage::ReadyForQuery(_))));
+    }
+
+    #[test]
+    fn test_server_version_num() {
+        assert_eq!(Some(150003), server_version_num("15.3"));
+        assert_eq!(Some(160000), server_version_num("16"));
+        assert_eq!(Some(90624), server_version_num("9.6.24 (Debian 9.6.24-1)"));
+        assert_eq!(Some(140010), server_version_num("14.10-pgwire"));
+        assert_eq!(None, server_version_num("pgwire"));
+    }
+
+    #[test]
+    fn test_check_integer_datetimes() {
+        let check = |key: &str, value: &str| {
+            let mut startup = Startup::new();
+            startup.parameters.insert(key.to_owned(), value.to_owned());
+            check_integer_datetimes(&startup).map(|info| info.code)
+        };
+
+        assert!(check_integer_datetimes(&Startup::new()).is_none());
+        assert_eq!(None, check("integer_datetimes", "on"));
+        assert_eq!(None, check("options", "-c search_path=public"));
+        assert_eq!(None, check("options", "-cinteger_datetimes=true"));
+        assert_eq!(Some("55P02".to_owned()), check("integer_datetimes", "off"));
+        assert_eq!(
+            Some("55P02".to_owned()),
+            check("options", "-c geqo=off -c integer_datetimes=off")
+        );
+        assert_eq!(
+            Some("55P02".to_owned()),
+            check("options", "--integer-datetimes=false")
+        );
+    }
+}
@@ -18,17 +18,18 @@ use x509_certificate::SignatureAlgorithm;
 
 use crate::api::auth::{AuthSource, LoginInfo, Password};
 use crate::api::{ClientInfo, MakeHandler, PgWireConnectionState};
-use crate::error::{PgWireError, PgWireResult};
-use crate::messages::startup::Authentication;
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::startup::{Authentication, PasswordMessageFamily};
 use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
 
-use super::{ServerParameterProvider, StartupHandler};
+use super::{AsyncServerParameterProvider, StartupHandler};
 
 #[derive(Debug)]
 pub enum ScramState {
     Initial,
-    // cached password, channel_binding and partial auth-message
-    ServerFirstSent(Password, String, String),
+    // cached password, channel_binding, partial auth-message and whether the
+    // exchange is a mock one for nonexistent user
+    ServerFirstSent(Password, String, String, bool),
 }
 
 #[derive(Debug)]
@@ -39,10 +40,18 @@ pub struct SASLScramAuthStartupHandler<A, P> {
     state: Mutex<ScramState>,
     /// base64 encoded certificate signature for tls-server-end-point channel binding
     server_cert_sig: Option<Arc<String>>,
+    /// advertised SASL mechanisms
+    mechanisms: Vec<String>,
     /// iterations
     iterations: usize,
+    /// server secret for deriving mock salt of nonexistent users
+    mock_auth_secret: [u8; 32],
 }
 
+/// Length of salt generated for mock authentication, identical to postgres'
+/// default
+const MOCK_SALT_LEN: usize = 16;
+
 /// Compute salted password from raw password as defined in
 /// [RFC5802](https://www.rfc-editor.org/rfc/rfc5802#section-3)
 ///
@@ -70,6 +79,20 @@ pub fn random_nonce() -> String {
 }
 
 impl<A, P> SASLScramAuthStartupHandler<A, P> {
+    /// Create a password for mock authentication of nonexistent user.
+    ///
+    /// Like postgres, the salt is derived from username and a server secret,
+    /// so it stays the same across attempts and looks like a real one.
+    fn mock_password(&self, username: &str) -> Password {
+        let mut buf = Vec::with_capacity(username.len() + self.mock_auth_secret.len());
+        buf.extend_from_slice(username.as_bytes());
+        buf.extend_from_slice(&self.mock_auth_secret);
+        let salt = h(&buf)[..MOCK_SALT_LEN].to_vec();
+
+        let password = (0..32).map(|_| rand::random::<u8>()).collect();
+        Password::new(Some(salt), password)
+    }
+
     fn compute_channel_binding(&self, client_channel_binding: &str) -> String {
         if client_channel_binding.starts_with("p=tls-server-end-point") {
             format!(
@@ -84,10 +107,142 @@ impl<A, P> SASLScramAuthStartupHandler<A, P> {
             STANDARD.encode(client_channel_binding.as_bytes())
         }
     }
+
+    /// SASL mechanisms to advertise. Like postgres, `-PLUS` mechanisms are
+    /// only offered over TLS, as there is no channel to bind otherwise.
+    fn advertised_mechanisms(&self, is_secure: bool) -> Vec<String> {
+        self.mechanisms
+            .iter()
+            .filter(|m| is_secure || !m.ends_with("-PLUS"))
+            .cloned()
+            .collect()
+    }
+
+    /// Process a client message and move to next state.
+    ///
+    /// Returns the message to send, or `None` when client proof is invalid.
+    /// Malformed client messages are returned as `InvalidMessage` or
+    /// `InvalidScramMessage`.
+    fn next_step(
+        &self,
+        state: &mut ScramState,
+        msg: PasswordMessageFamily,
+        salt_and_salted_pass: Password,
+        mock: bool,
+        is_secure: bool,
+    ) -> PgWireResult<Option<Authentication>> {
+        match *state {
+            ScramState::Initial => {
+                // initial response, client_first
+                let resp = msg.into_sasl_initial_response()?;
+                if !self
+                    .advertised_mechanisms(is_secure)
+                    .contains(&resp.auth_method)
+                {
+                    return Err(PgWireError::InvalidScramMessage(format!(
+                        "invalid SASL authentication mechanism \"{}\"",
+                        resp.auth_method
+                    )));
+                }
+                // parse into client_first
+                let client_first = resp
+                    .data
+                    .as_ref()
+                    .ok_or_else(|| {
+                        PgWireError::InvalidScramMessage("Empty client-first".to_owned())
+                    })
+                    .and_then(|data| {
+                        ClientFirst::try_new(String::from_utf8_lossy(data).as_ref())
+                    })?;
+                // like postgres, channel binding data is required by and only
+                // allowed with the -PLUS mechanism
+                let channel_binding_in_use = resp.auth_method.ends_with("-PLUS");
+                if channel_binding_in_use != client_first.cbind_flag.starts_with("p=") {
+                    return Err(PgWireError::InvalidScramMessage(format!(
+                        "channel binding data doesn't match mechanism \"{}\"",
+                        resp.auth_method
+                    )));
+                }
+                // like postgres, a client supporting channel binding but
+                // seeing no -PLUS mechanism is refused if we offered one, as
+                // the list may have been stripped by a man in the middle
+                if client_first.cbind_flag == "y"
+                    && self
+                        .advertised_mechanisms(is_secure)
+                        .iter()
+                        .any(|m| m.ends_with("-PLUS"))
+                {
+                    return Err(PgWireError::InvalidScramMessage(
+                        "client supports SCRAM channel binding, but thinks the server does not"
+                            .to_owned(),
+                    ));
+                }
+                // dbg!(&client_first);
+
+                // create server_first and send
+                let mut new_nonce = client_first.nonce.clone();
+                new_nonce.push_str(random_nonce().as_str());
+
+                let server_first = ServerFirst::new(
+                    new_nonce,
+                    STANDARD.encode(
+                        salt_and_salted_pass
+                            .salt
+                            .as_ref()
+                            .expect("Salt required for SCRAM auth source"),
+                    ),
+                    self.iterations,
+                );
+                let server_first_message = server_first.message();
+
+                *state = ScramState::ServerFirstSent(
+                    salt_and_salted_pass,
+                    client_first.channel_binding(),
+                    format!("{},{}", client_first.bare(), &server_first_message),
+                    mock,
+                );
+                Ok(Some(Authentication::SASLContinue(Bytes::from(
+                    server_first_message,
+                ))))
+            }
+            ScramState::ServerFirstSent(_, ref channel_binding_prefix, ref partial_auth_msg, _) => {
+                // second response, client_final
+                let resp = msg.into_sasl_response()?;
+                let client_final =
+                    ClientFinal::try_new(String::from_utf8_lossy(&resp.data).as_ref())?;
+                // dbg!(&client_final);
+
+                let channel_binding = self.compute_channel_binding(channel_binding_prefix);
+                client_final.validate_channel_binding(&channel_binding)?;
+
+                let salted_password = salt_and_salted_pass.password;
+                let client_key = hmac(salted_password.as_ref(), b"Client Key");
+                let stored_key = h(client_key.as_ref());
+                let auth_msg = format!("{},{}", partial_auth_msg, client_final.without_proof());
+                let client_signature = hmac(stored_key.as_ref(), auth_msg.as_bytes());
+
+                let computed_client_proof =
+                    STANDARD.encode(xor(client_key.as_ref(), client_signature.as_ref()).as_slice());
+
+                if !mock && computed_client_proof == client_final.proof {
+                    let server_key = hmac(salted_password.as_ref(), b"Server Key");
+                    let server_signature = hmac(server_key.as_ref(), auth_msg.as_bytes());
+                    let server_final = ServerFinalSuccess::new(STANDARD.encode(server_signature));
+                    Ok(Some(Authentication::SASLFinal(Bytes::from(
+                        server_final.message(),
+                    ))))
+                } else {
+                    // like postgres, no server-final is sent on
+                    // invalid proof, just the error
+                    Ok(None)
+                }
+            }
+        }
+    }
 }
 
 #[async_trait]
-impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
+impl<A: AuthSource, P: AsyncServerParameterProvider> StartupHandler
     for SASLScramAuthStartupHandler<A, P>
 {
     async fn on_startup<C>(
@@ -104,123 +259,82 @@ impl<A: AuthSource, P: ServerParameterProvider> StartupHandler
             PgWireFrontendMessage::Startup(ref startup) => {
                 super::save_startup_parameters_to_metadata(client, startup);
                 client.set_state(PgWireConnectionState::AuthenticationInProgress);
-                let supported_mechanisms = if self.server_cert_sig.is_some() {
-                    vec!["SCRAM-SHA-256".to_owned(), "SCRAM-SHA-256-PLUS".to_owned()]
-                } else {
-                    vec!["SCRAM-SHA-256".to_owned()]
-                };
+                let mechanisms = self.advertised_mechanisms(client.is_secure());
+                if mechanisms.is_empty() {
+                    let error_info = ErrorInfo::new(
+                        "FATAL".to_owned(),
+                        "28000".to_owned(),
+                        "SCRAM channel binding requires an SSL connection".to_owned(),
+                    );
+                    return super::fail_authentication(client, error_info).await;
+                }
                 client
                     .send(PgWireBackendMessage::Authentication(Authentication::SASL(
-                        supported_mechanisms,
+                        mechanisms,
                     )))
                     .await?;
             }
             PgWireFrontendMessage::PasswordMessageFamily(msg) => {
-                let salt_and_salted_pass = {
+                let (salt_and_salted_pass, mock) = {
                     let state = self.state.lock().await;
                     match *state {
                         ScramState::Initial => {
                             let login_info = LoginInfo::from_client_info(client);
-                            self.auth_db.get_password(&login_info).await?
+                            match self.auth_db.get_password(&login_info).await {
+                                Ok(pass) => (pass, false),
+                                // continue with a mock exchange and fail at
+                                // proof verification, so the nonexistent user
+                                // is indistinguishable from a wrong password
+                                Err(PgWireError::UserNotFound(_)) => (
+                                    self.mock_password(login_info.user().unwrap_or_default()),
+                                    true,
+                                ),
+                                Err(e) => {
+                                    let error_info =
+                                        super::into_authentication_error(e, login_info.user())?;
+                                    return super::fail_authentication(client, error_info).await;
+                                }
+                            }
                         }
-                        ScramState::ServerFirstSent(ref pass, _, _) => pass.clone(),
+                        ScramState::ServerFirstSent(ref pass, _, _, mock) => (pass.clone(), mock),
                     }
                 };
 
-                let mut success = false;
+                // `Ok(None)` when authentication failed
                 let resp = {
                     // this should never block
                     let mut state = self.state.lock().await;
-                    match *state {
-                        ScramState::Initial => {
-                            // initial response, client_first
-                            let resp = msg.into_sasl_initial_response()?;
-                            // parse into client_first
-                            let client_first = resp
-                                .data
-                                .as_ref()
-                                .ok_or_else(|| {
-                                    PgWireError::InvalidScramMessage(
-                                        "Empty client-first".to_owned(),
-                                    )
-                                })
-                                .and_then(|data| {
-                                    ClientFirst::try_new(String::from_utf8_lossy(data).as_ref())
-                                })?;
-                            // dbg!(&client_first);
-
-                            // create server_first and send
-                            let mut new_nonce = client_first.nonce.clone();
-                            new_nonce.push_str(random_nonce().as_str());
-
-                            let server_first = ServerFirst::new(
-                                new_nonce,
-                                STANDARD.encode(
-                                    salt_and_salted_pass
-                                        .salt
-                                        .as_ref()
-                                        .expect("Salt required for SCRAM auth source"),
-                                ),
-                                self.iterations,
-                            );
-                            let server_first_message = server_first.message();
-
-                            *state = ScramState::ServerFirstSent(
-                                salt_and_salted_pass,
-                                client_first.channel_binding(),
-                                format!("{},{}", client_first.bare(), &server_first_message),
-                            );
-                            Authentication::SASLContinue(Bytes::from(server_first_message))
-                        }
-                        ScramState::ServerFirstSent(
-                            _,
-                            ref channel_binding_prefix,
-                            ref partial_auth_msg,
-                        ) => {
-                            // second response, client_final
-                            let resp = msg.into_sasl_response()?;
-                            let client_final =
-                                ClientFinal::try_new(String::from_utf8_lossy(&resp.data).as_ref())?;
-                            // dbg!(&client_final);
-
-                            let channel_binding =
-                                self.compute_channel_binding(channel_binding_prefix);
-                            client_final.validate_channel_binding(&channel_binding)?;
-
-                            let salted_password = salt_and_salted_pass.password;
-                            let client_key = hmac(salted_password.as_ref(), b"Client Key");
-                            let stored_key = h(client_key.as_ref());
-                            let auth_msg =
-                                format!("{},{}", partial_auth_msg, client_final.without_proof());
-                            let client_signature = hmac(stored_key.as_ref(), auth_msg.as_bytes());
-
-                            let computed_client_proof = STANDARD.encode(
-                                xor(client_key.as_ref(), client_signature.as_ref()).as_slice(),
-                            );
-
-                            if computed_client_proof == client_final.proof {
-                                let server_key = hmac(salted_password.as_ref(), b"Server Key");
-                                let server_signature =
-                                    hmac(server_key.as_ref(), auth_msg.as_bytes());
-                                let server_final =
-                                    ServerFinalSuccess::new(STANDARD.encode(server_signature));
-                                success = true;
-                                Authentication::SASLFinal(Bytes::from(server_final.message()))
-                            } else {
-                                let server_final =
-                                    ServerFinalError::new("invalid-proof".to_owned());
-                                Authentication::SASLFinal(Bytes::from(server_final.message()))
-                            }
-                        }
-                    }
+                    self.next_step(
+                        &mut state,
+                        msg,
+                        salt_and_salted_pass,
+                        mock,
+                        client.is_secure(),
+                    )
                 };
 
-                client
-                    .send(PgWireBackendMessage::Authentication(resp))
-                    .await?;
-
-                if success {
-                    super::finish_authentication(client, self.parameter_provider.as_ref()).await
+                match resp {
+                    Ok(Some(resp)) => {
+                        let finished = matches!(resp, Authentication::SASLFinal(_));
+                        client
+                            .send(PgWireBackendMessage::Authentication(resp))
+                            .await?;
+                        if finished {
+                            super::finish_authentication(client, self.parameter_provider.as_ref())
+                                .await?;
+                        }
+                    }
+                    Ok(None) => {
+                        let user = LoginInfo::from_client_info(client)
+                            .user()
+                            .map(str::to_owned);
+                        let error_info = super::password_authentication_failed(user.as_deref());
+                        super::fail_authentication(client, error_info).await?;
+                    }
+                    Err(e) => {
+                        let error_info = super::into_malformed_message_error(e)?;
+                        super::fail_authentication(client, error_info).await?;
+                    }
                 }
             }
             _ => {}
@@ -238,9 +352,59 @@ pub struct MakeSASLScramAuthStartupHandler<A, P> {
     server_cert_sig: Option<Arc<String>>,
     #[new(value = "4096")]
     iterations: usize,
+    #[new(value = "rand::random()")]
+    mock_auth_secret: [u8; 32],
+    #[new(default)]
+    mechanisms: Option<Vec<String>>,
 }
 
+/// SASL mechanisms supported by `SASLScramAuthStartupHandler`
+pub const SCRAM_MECHANISMS: [&str; 2] = ["SCRAM-SHA-256", "SCRAM-SHA-256-PLUS"];
+
 impl<A, P> MakeSASLScramAuthStartupHandler<A, P> {
+    /// Create handler factory advertising given SASL mechanisms to client, in
+    /// order of preference.
+    ///
+    /// By default (`new`), `SCRAM-SHA-256` is advertised, along with
+    /// `SCRAM-SHA-256-PLUS` if certificate is configured. Offer only
+    /// `SCRAM-SHA-256-PLUS` to require channel binding. `-PLUS` mechanisms
+    /// are not offered to clients without TLS, and those clients fail
+    /// authentication if nothing else is left. A client selecting a mechanism
+    /// not offered fails authentication too.
+    ///
+    /// `certs_pem` is the server certificate as in `configure_certificate`.
+    /// Return error if `mechanisms` is empty, contains one not in
+    /// `SCRAM_MECHANISMS`, or contains a `-PLUS` mechanism without
+    /// certificate.
+    pub fn with_mechanisms(
+        auth_db: Arc<A>,
+        parameter_provider: Arc<P>,
+        mechanisms: Vec<String>,
+        certs_pem: Option<&[u8]>,
+    ) -> PgWireResult<Self> {
+        if mechanisms.is_empty() {
+            return Err(PgWireError::NoSaslMechanism);
+        }
+        if let Some(m) = mechanisms
+            .iter()
+            .find(|m| !SCRAM_MECHANISMS.contains(&m.as_str()))
+        {
+            return Err(PgWireError::UnsupportedSaslMechanism(m.clone()));
+        }
+        if certs_pem.is_none() {
+            if let Some(m) = mechanisms.iter().find(|m| m.ends_with("-PLUS")) {
+                return Err(PgWireError::CertificateRequiredForChannelBinding(m.clone()));
+            }
+        }
+
+        let mut maker = Self::new(auth_db, parameter_provider);
+        if let Some(certs_pem) = certs_pem {
+            maker.configure_certificate(certs_pem)?;
+        }
+        maker.mechanisms = Some(mechanisms);
+        Ok(maker)
+    }
+
     /// enable channel binding (SCRAM-SHA-256-PLUS) by configuring server
     /// certificate.
     ///
@@ -263,12 +427,23 @@ impl<A, P> MakeSASLScramAuthStartupHandler<A, P> {
     pub fn set_iterations(&mut self, iterations: usize) {
         self.iterations = iterations;
     }
+
+    /// Set the server secret used to derive salts for nonexistent users.
+    ///
+    /// When `AuthSource` returns `PgWireError::UserNotFound`, the handler
+    /// still runs a full SCRAM exchange with a salt derived from username and
+    /// this secret, then rejects the client proof. A random secret is
+    /// generated by default. Configure a fixed one if you run multiple
+    /// instances or restart servers, so the mock salt doesn't change.
+    pub fn set_mock_auth_secret(&mut self, secret: [u8; 32]) {
+        self.mock_auth_secret = secret;
+    }
 }
 
 impl<A, P> MakeHandler for MakeSASLScramAuthStartupHandler<A, P>
 where
     A: AuthSource,
-    P: ServerParameterProvider,
+    P: AsyncServerParameterProvider,
 {
     type Handler = Arc<SASLScramAuthStartupHandler<A, P>>;
 
@@ -278,7 +453,15 @@ where
             parameter_provider: self.parameter_provider.clone(),
             state: Mutex::new(ScramState::Initial),
             server_cert_sig: self.server_cert_sig.clone(),
+            mechanisms: self.mechanisms.clone().unwrap_or_else(|| {
+                if self.server_cert_sig.is_some() {
+                    vec!["SCRAM-SHA-256".to_owned(), "SCRAM-SHA-256-PLUS".to_owned()]
+                } else {
+                    vec!["SCRAM-SHA-256".to_owned()]
+                }
+            }),
             iterations: self.iterations,
+            mock_auth_secret: self.mock_auth_secret,
         })
     }
 }
@@ -408,17 +591,6 @@ impl ServerFinalSuccess {
     }
 }
 
-#[derive(Debug, new)]
-struct ServerFinalError {
-    error: String,
-}
-
-impl ServerFinalError {
-    fn message(&self) -> String {
-        format!("e={}", self.error)
-    }
-}
-
 fn hi(normalized_password: &[u8], salt: &[u8], iterations: usize) -> Vec<u8> {
     let mut buf = [0u8; 32];
 
@@ -457,7 +629,7 @@ fn xor(lhs: &[u8], rhs: &[u8]) -> Vec<u8> {
 /// 1. use sha-256 if the certificate's algorithm is md5 or sha-1
 /// 2. use the certificate's algorithm if it's neither md5 or sha-1
 /// 3. if the certificate has 0 or more than 1 signature algorithm, the
-/// behaviour is undefined at the time.
+///    behaviour is undefined at the time.
 fn compute_cert_signature(cert: &[u8]) -> PgWireResult<Vec<u8>> {
     let certs = CapturedX509Certificate::from_pem_multiple(cert)
         .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
@@ -478,3 +650,320 @@ fn compute_cert_signature(cert: &[u8]) -> PgWireResult<Vec<u8>> {
         _ => Err(PgWireError::UnsupportedCertificateSignatureAlgorithm),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::api::auth::DefaultServerParameterProvider;
+    use crate::api::testing::RecordingClient;
+    use crate::messages::startup::{
+        PasswordMessageFamily, SASLInitialResponse, SASLResponse, Startup,
+    };
+
+    const ITERATIONS: usize = 4096;
+
+    struct TestAuthSource;
+
+    #[async_trait]
+    impl AuthSource for TestAuthSource {
+        async fn get_password(&self, login: &LoginInfo) -> PgWireResult<Password> {
+            match login.user() {
+                Some("tomcat") => {
+                    let salt = b"0123456789abcdef".to_vec();
+                    let password = gen_salted_password("pencil", &salt, ITERATIONS);
+                    Ok(Password::new(Some(salt), password))
+                }
+                user => Err(PgWireError::UserNotFound(
+                    user.unwrap_or_default().to_owned(),
+                )),
+            }
+        }
+    }
+
+    fn new_client(is_secure: bool) -> RecordingClient {
+        let mut client = RecordingClient::new();
+        client.is_secure = is_secure;
+        client.state = PgWireConnectionState::AwaitingStartup;
+        client
+    }
+
+    fn sasl_data(msg: &PgWireBackendMessage) -> String {
+        match msg {
+            PgWireBackendMessage::Authentication(Authentication::SASLContinue(data))
+            | PgWireBackendMessage::Authentication(Authentication::SASLFinal(data)) => {
+                String::from_utf8_lossy(data).into_owned()
+            }
+            _ => panic!("expect SASL message, got {:?}", msg),
+        }
+    }
+
+    /// Run a full SCRAM exchange and return server-first message, along with
+    /// messages sent after client-final.
+    async fn authenticate(
+        maker: &MakeSASLScramAuthStartupHandler<TestAuthSource, DefaultServerParameterProvider>,
+        user: &str,
+        password: &str,
+    ) -> (String, Vec<PgWireBackendMessage>) {
+        let handler = maker.make();
+        let mut client = new_client(false);
+
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), user.to_owned());
+        handler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+
+        let client_first_bare = format!("n={},r=fyko+d2lbbFgONRv9qkxdawL", user);
+        let client_first = SASLInitialResponse::new(
+            "SCRAM-SHA-256".to_owned(),
+            Some(Bytes::from(format!("n,,{}", client_first_bare))),
+        );
+        handler
+            .on_startup(&mut client, password_message(client_first))
+            .await
+            .unwrap();
+        let server_first = sasl_data(&client.messages().unwrap()[1]);
+
+        // compute client proof as a client does
+        let mut attrs = server_first
+            .split(',')
+            .map(|kv| kv.split_at(2))
+            .collect::<HashMap<_, _>>();
+        let nonce = attrs.remove("r=").unwrap();
+        let salt = STANDARD.decode(attrs.remove("s=").unwrap()).unwrap();
+        let iterations = attrs.remove("i=").unwrap().parse().unwrap();
+
+        let salted_password = gen_salted_password(password, &salt, iterations);
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key = h(&client_key);
+        let without_proof = format!("c=biws,r={}", nonce);
+        let auth_msg = format!("{},{},{}", client_first_bare, server_first, without_proof);
+        let client_signature = hmac(&stored_key, auth_msg.as_bytes());
+        let proof = STANDARD.encode(xor(&client_key, &client_signature));
+
+        let client_final = SASLResponse::new(Bytes::from(format!("{},p={}", without_proof, proof)));
+        handler
+            .on_startup(&mut client, password_message(client_final))
+            .await
+            .unwrap();
+        let rest = client.messages().unwrap();
+
+        (server_first, rest)
+    }
+
+    fn password_message<M: crate::messages::Message>(msg: M) -> PgWireFrontendMessage {
+        let mut buf = bytes::BytesMut::new();
+        msg.encode_body(&mut buf).unwrap();
+        PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(buf))
+    }
+
+    fn strip_nonce(server_first: &str) -> String {
+        server_first
+            .split(',')
+            .filter(|kv| !kv.starts_with("r="))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    #[tokio::test]
+    async fn test_mock_authentication() {
+        let maker = MakeSASLScramAuthStartupHandler::new(
+            Arc::new(TestAuthSource),
+            Arc::new(DefaultServerParameterProvider::default()),
+        );
+
+        let (_, rest) = authenticate(&maker, "tomcat", "pencil").await;
+        assert!(sasl_data(&rest[0]).starts_with("v="));
+        assert!(matches!(
+            rest.last(),
+            Some(PgWireBackendMessage::ReadyForQuery(_))
+        ));
+
+        let (existent_first, existent_rest) = authenticate(&maker, "tomcat", "tiger").await;
+        let (nonexistent_first, nonexistent_rest) = authenticate(&maker, "nobody", "tiger").await;
+
+        // both attempts fail at the proof step with the same error
+        for (user, rest) in [("tomcat", existent_rest), ("nobody", nonexistent_rest)] {
+            match &rest[..] {
+                [PgWireBackendMessage::ErrorResponse(error)] => {
+                    assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                    assert!(error.fields.contains(&(b'C', "28P01".to_owned())));
+                    let message = format!("password authentication failed for user \"{user}\"");
+                    assert!(error.fields.contains(&(b'M', message)));
+                }
+                _ => panic!("expect a single error response"),
+            }
+        }
+
+        // the mock salt has the same shape as a real one, and is stable across
+        // attempts
+        let existent_first = strip_nonce(&existent_first);
+        let nonexistent_first = strip_nonce(&nonexistent_first);
+        assert_eq!(existent_first.len(), nonexistent_first.len());
+        assert_ne!(existent_first, nonexistent_first);
+        let (again_first, _) = authenticate(&maker, "nobody", "pencil").await;
+        assert_eq!(nonexistent_first, strip_nonce(&again_first));
+    }
+
+    fn assert_authentication_failed(sent: &[PgWireBackendMessage], code: &str) {
+        match sent {
+            [PgWireBackendMessage::ErrorResponse(error)] => {
+                assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                assert!(error.fields.contains(&(b'C', code.to_owned())));
+            }
+            _ => panic!("expect a single error response, got {:?}", sent),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_messages() {
+        let maker = MakeSASLScramAuthStartupHandler::new(
+            Arc::new(TestAuthSource),
+            Arc::new(DefaultServerParameterProvider::default()),
+        );
+        let startup = || {
+            let mut startup = Startup::new();
+            startup
+                .parameters
+                .insert("user".to_owned(), "tomcat".to_owned());
+            PgWireFrontendMessage::Startup(startup)
+        };
+        let raw = |body: &[u8]| {
+            PgWireFrontendMessage::PasswordMessageFamily(PasswordMessageFamily::Raw(
+                bytes::BytesMut::from(body),
+            ))
+        };
+
+        let client_first = b"n,,n=tomcat,r=fyko+d2lbbFgONRv9qkxdawL";
+        let mut wrong_mechanism = b"SCRAM-SHA-1\0".to_vec();
+        wrong_mechanism.extend_from_slice(&(client_first.len() as i32).to_be_bytes());
+        wrong_mechanism.extend_from_slice(client_first);
+
+        for body in [
+            &b""[..],
+            // empty password message
+            &b"\0"[..],
+            // truncated length
+            &b"SCRAM-SHA-256\0\0\0"[..],
+            // length beyond message
+            &b"SCRAM-SHA-256\0\0\0\0\x40n,,"[..],
+            // negative length
+            &b"SCRAM-SHA-256\0\xff\xff\xff\xfen,,"[..],
+            // null and empty client-first
+            &b"SCRAM-SHA-256\0\xff\xff\xff\xff"[..],
+            &b"SCRAM-SHA-256\0\0\0\0\0"[..],
+            &wrong_mechanism,
+        ] {
+            let handler = maker.make();
+            let mut client = new_client(false);
+            handler.on_startup(&mut client, startup()).await.unwrap();
+            client.take_bytes();
+
+            handler.on_startup(&mut client, raw(body)).await.unwrap();
+            assert_authentication_failed(&client.messages().unwrap(), "28000");
+        }
+
+        for body in [
+            &b""[..],
+            &b"c=biws"[..],
+            &b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL"[..],
+        ] {
+            let handler = maker.make();
+            let mut client = new_client(false);
+            handler.on_startup(&mut client, startup()).await.unwrap();
+            let client_first = SASLInitialResponse::new(
+                "SCRAM-SHA-256".to_owned(),
+                Some(Bytes::copy_from_slice(client_first)),
+            );
+            handler
+                .on_startup(&mut client, password_message(client_first))
+                .await
+                .unwrap();
+            client.take_bytes();
+
+            handler.on_startup(&mut client, raw(body)).await.unwrap();
+            assert_authentication_failed(&client.messages().unwrap(), "28000");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mechanisms() {
+        let with_mechanisms = |mechanisms: &[&str], certs_pem: Option<&[u8]>| {
+            MakeSASLScramAuthStartupHandler::with_mechanisms(
+                Arc::new(TestAuthSource),
+                Arc::new(DefaultServerParameterProvider::default()),
+                mechanisms.iter().map(|m| m.to_string()).collect(),
+                certs_pem,
+            )
+        };
+        let cert = include_bytes!("../../../examples/ssl/server.crt");
+        assert!(matches!(
+            with_mechanisms(&[], Some(cert)),
+            Err(PgWireError::NoSaslMechanism)
+        ));
+        assert!(matches!(
+            with_mechanisms(&["SCRAM-SHA-1"], Some(cert)),
+            Err(PgWireError::UnsupportedSaslMechanism(m)) if m == "SCRAM-SHA-1"
+        ));
+        assert!(matches!(
+            with_mechanisms(&["SCRAM-SHA-256", "SCRAM-SHA-256-PLUS"], None),
+            Err(PgWireError::CertificateRequiredForChannelBinding(_))
+        ));
+        let plus_only = ["SCRAM-SHA-256-PLUS"];
+        let both = ["SCRAM-SHA-256", "SCRAM-SHA-256-PLUS"];
+        // a mechanism not offered, -PLUS without channel binding data, and a
+        // client supporting channel binding but not seeing -PLUS offered
+        for (offered, mechanism, cbind_flag) in [
+            (&plus_only[..], "SCRAM-SHA-256", "n"),
+            (&plus_only[..], "SCRAM-SHA-256-PLUS", "n"),
+            (&both[..], "SCRAM-SHA-256", "y"),
+        ] {
+            let handler = with_mechanisms(offered, Some(cert)).unwrap().make();
+            let mut client = new_client(true);
+            let mut startup = Startup::new();
+            startup
+                .parameters
+                .insert("user".to_owned(), "tomcat".to_owned());
+            handler
+                .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+                .await
+                .unwrap();
+            assert!(matches!(
+                &client.messages().unwrap()[..],
+                [PgWireBackendMessage::Authentication(Authentication::SASL(mechanisms))]
+                    if mechanisms == offered
+            ));
+
+            let client_first = SASLInitialResponse::new(
+                mechanism.to_owned(),
+                Some(Bytes::from(format!(
+                    "{},,n=tomcat,r=fyko+d2lbbFgONRv9qkxdawL",
+                    cbind_flag
+                ))),
+            );
+            handler
+                .on_startup(&mut client, password_message(client_first))
+                .await
+                .unwrap();
+            assert_authentication_failed(&client.messages().unwrap(), "28000");
+        }
+
+        // nothing left to offer without TLS
+        let handler = with_mechanisms(&plus_only, Some(cert)).unwrap().make();
+        let mut client = new_client(false);
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("user".to_owned(), "tomcat".to_owned());
+        handler
+            .on_startup(&mut client, PgWireFrontendMessage::Startup(startup))
+            .await
+            .unwrap();
+        assert_authentication_failed(&client.messages().unwrap(), "28000");
+    }
+}
@@ -0,0 +1,333 @@
+//! Validated batches of backend messages, sent with a single flush.
+
+use std::fmt::Debug;
+
+use futures::{Sink, SinkExt};
+
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::PgWireBackendMessage;
+
+/// Where a batch is in the message flow of the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BatchState {
+    /// Between commands, any response may start
+    Idle,
+    /// After `ParameterDescription`, `RowDescription` or `NoData` must follow
+    Described,
+    /// After `RowDescription`, with the number of columns. Rows may follow, or
+    /// the command may end here, as in reply to a `Describe` without `Execute`
+    RowsDescribed(usize),
+    /// After a first `DataRow`, with the number of columns
+    Rows(usize),
+    /// In `COPY ... TO STDOUT`, until `CopyDone`
+    CopyOut,
+    /// After `ErrorResponse`, until `ReadyForQuery`
+    Failed,
+    /// After `CopyInResponse` or `CopyBothResponse`, it's the client's turn
+    AwaitingClient,
+}
+
+/// A sequence of backend messages, checked against the protocol as they are
+/// pushed and sent to client with a single flush.
+///
+/// For handlers and middleware that write several messages at once, like
+/// `RowDescription`, `DataRow`s and `CommandComplete`, or a `NoticeResponse`
+/// before a result. `push` rejects messages that can't follow the ones
+/// already in the batch with `PgWireError::InvalidBackendMessageSequence`, e.g. a
+/// `ReadyForQuery` before the `CommandComplete` of a result, or a `DataRow`
+/// with the wrong number of columns. `NoticeResponse`, `ParameterStatus` and
+/// `NotificationResponse` are accepted anywhere, as postgres sends them
+/// asynchronously.
+///
+/// `DataRow` may start a batch without `RowDescription`, for `Execute` of a
+/// portal described earlier, and a `RowDescription` may be followed by the
+/// next command or `ReadyForQuery`, as in reply to `Describe`. Messages of other kinds, like those of startup,
+/// are accepted between commands.
+#[derive(Debug)]
+pub struct MessageBatch {
+    messages: Vec<PgWireBackendMessage>,
+    state: BatchState,
+}
+
+impl Default for MessageBatch {
+    fn default() -> Self {
+        MessageBatch {
+            messages: Vec::new(),
+            state: BatchState::Idle,
+        }
+    }
+}
+
+fn message_name(message: &PgWireBackendMessage) -> &'static str {
+    match message {
+        PgWireBackendMessage::Authentication(_) => "Authentication",
+        PgWireBackendMessage::ParameterStatus(_) => "ParameterStatus",
+        PgWireBackendMessage::BackendKeyData(_) => "BackendKeyData",
+        PgWireBackendMessage::NegotiateProtocolVersion(_) => "NegotiateProtocolVersion",
+        PgWireBackendMessage::ParseComplete(_) => "ParseComplete",
+        PgWireBackendMessage::CloseComplete(_) => "CloseComplete",
+        PgWireBackendMessage::BindComplete(_) => "BindComplete",
+        PgWireBackendMessage::PortalSuspended(_) => "PortalSuspended",
+        PgWireBackendMessage::CommandComplete(_) => "CommandComplete",
+        PgWireBackendMessage::EmptyQueryResponse(_) => "EmptyQueryResponse",
+        PgWireBackendMessage::ReadyForQuery(_) => "ReadyForQuery",
+        PgWireBackendMessage::ErrorResponse(_) => "ErrorResponse",
+        PgWireBackendMessage::NoticeResponse(_) => "NoticeResponse",
+        PgWireBackendMessage::SslResponse(_) => "SslResponse",
+        PgWireBackendMessage::NotificationResponse(_) => "NotificationResponse",
+        PgWireBackendMessage::ParameterDescription(_) => "ParameterDescription",
+        PgWireBackendMessage::RowDescription(_) => "RowDescription",
+        PgWireBackendMessage::DataRow(_) => "DataRow",
+        PgWireBackendMessage::NoData(_) => "NoData",
+        PgWireBackendMessage::CopyData(_) => "CopyData",
+        PgWireBackendMessage::CopyFail(_) => "CopyFail",
+        PgWireBackendMessage::CopyDone(_) => "CopyDone",
+        PgWireBackendMessage::CopyInResponse(_) => "CopyInResponse",
+        PgWireBackendMessage::CopyOutResponse(_) => "CopyOutResponse",
+        PgWireBackendMessage::CopyBothResponse(_) => "CopyBothResponse",
+        PgWireBackendMessage::FunctionCallResponse(_) => "FunctionCallResponse",
+    }
+}
+
+impl MessageBatch {
+    pub fn new() -> MessageBatch {
+        MessageBatch::default()
+    }
+
+    /// Append `message` if it may follow the messages in the batch.
+    pub fn push(&mut self, message: PgWireBackendMessage) -> PgWireResult<&mut Self> {
+        self.state = self.next_state(&message)?;
+        self.messages.push(message);
+        Ok(self)
+    }
+
+    /// Like `push`, for chaining on an owned batch.
+    pub fn with(mut self, message: PgWireBackendMessage) -> PgWireResult<Self> {
+        self.push(message)?;
+        Ok(self)
+    }
+
+    fn next_state(&self, message: &PgWireBackendMessage) -> PgWireResult<BatchState> {
+        use BatchState::*;
+        use PgWireBackendMessage as M;
+
+        let state = match (self.state, message) {
+            (state, M::NoticeResponse(_) | M::ParameterStatus(_) | M::NotificationResponse(_))
+                if state != AwaitingClient =>
+            {
+                return Ok(state);
+            }
+            (
+                RowsDescribed(columns),
+                M::DataRow(_) | M::CommandComplete(_) | M::PortalSuspended(_),
+            ) => Rows(columns),
+            (RowsDescribed(_), _) => Idle,
+            (state, _) => state,
+        };
+
+        let state = match (state, message) {
+            (Idle | Rows(_) | CopyOut, M::ErrorResponse(_)) => Failed,
+            (Idle | Failed, M::ReadyForQuery(_)) => Idle,
+
+            (Idle, M::ParameterDescription(_)) => Described,
+            (Idle | Described, M::RowDescription(desc)) => RowsDescribed(desc.fields.len()),
+            (Idle | Described, M::NoData(_)) => Idle,
+            (Idle, M::DataRow(row)) => Rows(row.fields.len()),
+            (Rows(columns), M::DataRow(row)) if row.fields.len() == columns => Rows(columns),
+            (Rows(columns), M::DataRow(row)) => {
+                return Err(PgWireError::InvalidBackendMessageSequence(format!(
+                    "DataRow has {} columns, expected {columns}",
+                    row.fields.len()
+                )))
+            }
+            (Idle | Rows(_), M::CommandComplete(_) | M::PortalSuspended(_)) => Idle,
+
+            (Idle, M::CopyOutResponse(_)) => CopyOut,
+            (CopyOut, M::CopyData(_)) => CopyOut,
+            (CopyOut, M::CopyDone(_)) => Idle,
+            (Idle, M::CopyInResponse(_) | M::CopyBothResponse(_)) => AwaitingClient,
+
+            (
+                Idle,
+                M::Authentication(_)
+                | M::BackendKeyData(_)
+                | M::NegotiateProtocolVersion(_)
+                | M::ParseComplete(_)
+                | M::BindComplete(_)
+                | M::CloseComplete(_)
+                | M::EmptyQueryResponse(_)
+                | M::FunctionCallResponse(_)
+                | M::SslResponse(_),
+            ) => Idle,
+
+            (state, message) => {
+                let after = match state {
+                    Idle => "the start of a command".to_owned(),
+                    Described => "ParameterDescription".to_owned(),
+                    RowsDescribed(_) | Rows(_) => "rows without CommandComplete".to_owned(),
+                    CopyOut => "CopyOutResponse without CopyDone".to_owned(),
+                    Failed => "ErrorResponse".to_owned(),
+                    AwaitingClient => self
+                        .messages
+                        .last()
+                        .map(message_name)
+                        .unwrap_or_default()
+                        .to_owned(),
+                };
+                return Err(PgWireError::InvalidBackendMessageSequence(format!(
+                    "{} cannot follow {after}",
+                    message_name(message)
+                )));
+            }
+        };
+        Ok(state)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn messages(&self) -> &[PgWireBackendMessage] {
+        &self.messages
+    }
+
+    /// Feed all messages to `client` and flush once.
+    pub async fn send<C>(self, client: &mut C) -> PgWireResult<()>
+    where
+        C: Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        for message in self.messages {
+            client.feed(message).await?;
+        }
+        client.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::api::results::{into_row_description, FieldFormat, FieldInfo, Tag};
+    use crate::api::testing::RecordingClient;
+    use crate::api::Type;
+    use crate::error::ErrorInfo;
+    use crate::messages::data::{DataRow, ParameterDescription};
+    use crate::messages::extendedquery::{BindComplete, ParseComplete};
+    use crate::messages::response::{ReadyForQuery, READY_STATUS_IDLE};
+
+    fn row_description(columns: usize) -> PgWireBackendMessage {
+        let fields = (0..columns)
+            .map(|i| FieldInfo::new(format!("c{i}"), None, None, Type::INT4, FieldFormat::Text))
+            .collect::<Vec<_>>();
+        PgWireBackendMessage::RowDescription(into_row_description(&fields))
+    }
+
+    fn data_row(columns: usize) -> PgWireBackendMessage {
+        PgWireBackendMessage::DataRow(DataRow::new(vec![Some(Bytes::from("1")); columns]))
+    }
+
+    fn command_complete() -> PgWireBackendMessage {
+        PgWireBackendMessage::CommandComplete(Tag::new("SELECT").with_rows(2).into())
+    }
+
+    fn ready() -> PgWireBackendMessage {
+        PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(READY_STATUS_IDLE))
+    }
+
+    fn parameter_description() -> PgWireBackendMessage {
+        PgWireBackendMessage::ParameterDescription(ParameterDescription::new(vec![]))
+    }
+
+    fn notice() -> PgWireBackendMessage {
+        let info = ErrorInfo::new("NOTICE".to_owned(), "00000".to_owned(), "hi".to_owned());
+        PgWireBackendMessage::NoticeResponse(info.into())
+    }
+
+    #[tokio::test]
+    async fn test_message_batch() {
+        let batch = MessageBatch::new()
+            .with(notice())
+            .and_then(|b| b.with(row_description(2)))
+            .and_then(|b| b.with(data_row(2)))
+            .and_then(|b| b.with(notice()))
+            .and_then(|b| b.with(data_row(2)))
+            .and_then(|b| b.with(command_complete()))
+            .and_then(|b| b.with(ready()))
+            .unwrap();
+        assert_eq!(7, batch.len());
+
+        let mut client = RecordingClient::<String>::new();
+        batch.send(&mut client).await.unwrap();
+        assert_eq!(7, client.messages().unwrap().len());
+
+        // execute of a described portal
+        let mut batch = MessageBatch::new();
+        batch
+            .push(data_row(1))
+            .and_then(|b| b.push(command_complete()))
+            .unwrap();
+
+        // describe of a statement, then of a portal without execute
+        let mut batch = MessageBatch::new();
+        batch
+            .push(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
+            .and_then(|b| b.push(parameter_description()))
+            .and_then(|b| b.push(row_description(2)))
+            .and_then(|b| b.push(PgWireBackendMessage::BindComplete(BindComplete::new())))
+            .and_then(|b| b.push(row_description(2)))
+            .and_then(|b| b.push(ready()))
+            .unwrap();
+        let mut batch = MessageBatch::new();
+        batch
+            .push(parameter_description())
+            .and_then(|b| b.push(row_description(1)))
+            .and_then(|b| b.push(notice()))
+            .and_then(|b| b.push(ready()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_message_batch_invalid() {
+        let invalid = |messages: Vec<PgWireBackendMessage>| {
+            let mut batch = MessageBatch::new();
+            for message in messages {
+                if let Err(e) = batch.push(message) {
+                    assert!(matches!(e, PgWireError::InvalidBackendMessageSequence(_)));
+                    return true;
+                }
+            }
+            false
+        };
+
+        assert!(invalid(vec![row_description(2), data_row(1), ready()]));
+        assert!(invalid(vec![row_description(1), data_row(1), ready()]));
+        assert!(invalid(vec![row_description(2), data_row(3)]));
+        assert!(invalid(vec![
+            row_description(1),
+            data_row(1),
+            row_description(1)
+        ]));
+        assert!(invalid(vec![parameter_description(), command_complete()]));
+        let error = PgWireBackendMessage::ErrorResponse(
+            ErrorInfo::new("ERROR".to_owned(), "XX000".to_owned(), "boom".to_owned()).into(),
+        );
+        assert!(invalid(vec![error, command_complete()]));
+        assert!(!invalid(vec![
+            row_description(1),
+            data_row(1),
+            PgWireBackendMessage::ErrorResponse(
+                ErrorInfo::new("ERROR".to_owned(), "XX000".to_owned(), "boom".to_owned()).into(),
+            ),
+            notice(),
+            ready(),
+        ]));
+    }
+}
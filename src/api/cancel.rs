@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub use tokio_util::sync::CancellationToken;
+
+use crate::error::{ErrorInfo, PgWireError};
+
+/// Running connections that can be cancelled, keyed by `(pid, secret_key)`
+/// sent to client in `BackendKeyData`.
+static CANCEL_HANDLES: Mutex<BTreeMap<(i32, i32), CancelHandle>> = Mutex::new(BTreeMap::new());
+
+/// Cancellation state of a connection.
+///
+/// The handle is shared between the connection and `CancelRequest` received on
+/// other connections. Postgres only cancels the query running at the time the
+/// request arrives, so a fresh token is created for each query once the
+/// previous one is cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    token: Arc<Mutex<CancellationToken>>,
+}
+
+impl CancelHandle {
+    /// Get token for the current query.
+    pub fn token(&self) -> CancellationToken {
+        self.token.lock().unwrap().clone()
+    }
+
+    /// Cancel the current query.
+    pub fn cancel(&self) {
+        self.token.lock().unwrap().cancel();
+    }
+
+    /// Drop cancellation of previous query, so it doesn't affect next one.
+    pub fn reset(&self) {
+        let mut token = self.token.lock().unwrap();
+        if token.is_cancelled() {
+            *token = CancellationToken::new();
+        }
+    }
+}
+
+/// Make the connection cancellable by its backend key, until the returned
+/// registration is dropped.
+pub(crate) fn register(backend_key: (i32, i32), handle: CancelHandle) -> Registration {
+    CANCEL_HANDLES.lock().unwrap().insert(backend_key, handle);
+    Registration(backend_key)
+}
+
+#[derive(Debug)]
+pub(crate) struct Registration((i32, i32));
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        CANCEL_HANDLES.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// Cancel the query running on connection identified by `pid` and
+/// `secret_key`.
+///
+/// Return `false` if no such connection. As in postgres, the client is never
+/// told whether the cancellation succeeded.
+pub fn cancel(pid: i32, secret_key: i32) -> bool {
+    if let Some(handle) = CANCEL_HANDLES.lock().unwrap().get(&(pid, secret_key)) {
+        handle.cancel();
+        true
+    } else {
+        false
+    }
+}
+
+/// `57014` error of a query cancelled by `CancelRequest`, the same as postgres
+/// sends.
+pub fn query_canceled_error() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "57014".to_owned(),
+        "canceling statement due to user request".to_owned(),
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let handle = CancelHandle::default();
+        let registration = register((1, 42), handle.clone());
+
+        let token = handle.token();
+        assert!(!cancel(1, 43));
+        assert!(!token.is_cancelled());
+
+        assert!(cancel(1, 42));
+        assert!(token.is_cancelled());
+
+        handle.reset();
+        assert!(!handle.token().is_cancelled());
+
+        drop(registration);
+        assert!(!cancel(1, 42));
+    }
+}
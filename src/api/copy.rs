@@ -0,0 +1,574 @@
+use std::fmt::{self, Debug};
+use std::io::{Error as IOError, ErrorKind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use std::future::Future;
+
+use bytes::{Bytes, BytesMut};
+use futures::future::{self, BoxFuture, Either};
+use futures::{ready, FutureExt, Sink, SinkExt, Stream, StreamExt};
+use tokio_util::io::StreamReader;
+
+use super::cancel::{query_canceled_error, CancellationToken};
+use super::results::{CopyResponse, Tag};
+use super::ClientInfo;
+use crate::error::{protocol_violation, ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::copy::{CopyData, CopyDone, CopyFail};
+use crate::messages::{PgWireBackendMessage, PgWireFrontendMessage};
+
+/// Payloads of `CopyData` messages received from client, until `CopyDone`.
+///
+/// `CopyFail` and messages not allowed during copy end the stream with an
+/// error, so does the connection being closed before `CopyDone`. `Flush` and
+/// `Sync` are ignored as postgres does.
+pub struct CopyDataStream<S> {
+    messages: S,
+    done: bool,
+    cancelled: Option<BoxFuture<'static, ()>>,
+}
+
+impl<S: Debug> Debug for CopyDataStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CopyDataStream")
+            .field("messages", &self.messages)
+            .field("done", &self.done)
+            .field("cancellable", &self.cancelled.is_some())
+            .finish()
+    }
+}
+
+impl<S> CopyDataStream<S> {
+    pub fn new(messages: S) -> CopyDataStream<S> {
+        CopyDataStream {
+            messages,
+            done: false,
+            cancelled: None,
+        }
+    }
+
+    /// Abort the copy once `token` is cancelled, usually
+    /// `ClientInfo::cancel_token` of the connection, which `CancelRequest`
+    /// cancels.
+    ///
+    /// The stream then ends with `57014 canceling statement due to user
+    /// request` error, without reading further messages. Send it to client
+    /// as `ErrorResponse` followed by `ReadyForQuery`, the client stops
+    /// copying on the error. As in postgres, `CopyData`, `CopyDone` and
+    /// `CopyFail` the client already sent are dropped by the message loop of
+    /// `process_socket`, custom loops should skip them too.
+    pub fn with_cancel_token(mut self, token: CancellationToken) -> CopyDataStream<S> {
+        self.cancelled = Some(async move { token.cancelled().await }.boxed());
+        self
+    }
+
+    /// Unwrap the message stream, to continue reading messages after
+    /// `CopyDone`.
+    pub fn into_inner(self) -> S {
+        self.messages
+    }
+}
+
+/// Error of a copy aborted by client with `CopyFail`, as in postgres.
+pub(crate) fn copy_failed_error(fail: &CopyFail) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "57014".to_owned(),
+        format!("COPY from stdin failed: {}", fail.message),
+    )))
+}
+
+/// Error of a message other than `CopyData`, `CopyDone`, `CopyFail`, `Flush`
+/// and `Sync` received during copy.
+pub(crate) fn unexpected_copy_message_error(message: &PgWireFrontendMessage) -> PgWireError {
+    protocol_violation(format!(
+        "unexpected message during COPY from stdin: {message:?}"
+    ))
+}
+
+impl<S> Stream for CopyDataStream<S>
+where
+    S: Stream<Item = PgWireResult<PgWireFrontendMessage>> + Unpin,
+{
+    type Item = Result<Bytes, IOError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Some(cancelled) = self.cancelled.as_mut() {
+            if cancelled.poll_unpin(cx).is_ready() {
+                self.done = true;
+                return Poll::Ready(Some(Err(query_canceled_error().into())));
+            }
+        }
+        while !self.done {
+            let item = match ready!(Pin::new(&mut self.messages).poll_next(cx)) {
+                Some(Ok(PgWireFrontendMessage::CopyData(data))) => Ok(data.data),
+                Some(Ok(PgWireFrontendMessage::Flush(_) | PgWireFrontendMessage::Sync(_))) => {
+                    continue
+                }
+                Some(Ok(PgWireFrontendMessage::CopyDone(_))) => {
+                    self.done = true;
+                    break;
+                }
+                Some(Ok(PgWireFrontendMessage::CopyFail(fail))) => {
+                    Err(copy_failed_error(&fail).into())
+                }
+                Some(Ok(message)) => Err(unexpected_copy_message_error(&message).into()),
+                Some(Err(e)) => Err(e.into()),
+                None => Err(IOError::new(
+                    ErrorKind::UnexpectedEof,
+                    "connection closed during COPY from stdin",
+                )),
+            };
+            // no more data after an error
+            self.done = item.is_err();
+            return Poll::Ready(Some(item));
+        }
+        Poll::Ready(None)
+    }
+}
+
+/// Buffered reader over the concatenated payloads of `CopyData` messages.
+///
+/// Clients split the copy data into `CopyData` messages at arbitrary
+/// boundaries, a row of `COPY FROM STDIN` or a logical record may span several
+/// messages, or a message may contain several of them. The reader hides these
+/// boundaries: use `AsyncBufReadExt::read_line` for text format rows or
+/// `AsyncReadExt::read_exact` for binary records. Messages are pulled from the
+/// stream only when the buffered data is consumed, so a slow consumer applies
+/// backpressure to the client.
+///
+/// Reaching the end of the reader means `CopyDone` was received. Errors
+/// caused by the client, e.g. `CopyFail`, wrap a `PgWireError::UserError`
+/// that can be sent back to client.
+pub type CopyReader<S> = StreamReader<CopyDataStream<S>, Bytes>;
+
+/// Create a `CopyReader` from frontend messages received after
+/// `CopyInResponse` or `CopyBothResponse`, e.g. a `Framed` connection.
+///
+/// The message loop of `process_socket` passes copy messages to
+/// `SimpleQueryHandler::on_copy_data`, `on_copy_done` and `on_copy_fail`
+/// instead. To use the reader there, forward them to a channel, e.g.
+/// `futures::channel::mpsc`, and read its receiver from a task spawned when
+/// the copy starts.
+pub fn copy_reader<S>(messages: S) -> CopyReader<S>
+where
+    S: Stream<Item = PgWireResult<PgWireFrontendMessage>> + Unpin,
+{
+    StreamReader::new(CopyDataStream::new(messages))
+}
+
+/// Feed data received from client to `callback` in chunks of `chunk_size`
+/// bytes, until `CopyDone`. Returns total number of bytes copied.
+///
+/// Every chunk is `chunk_size` long except the last one, regardless of how the
+/// client split data into `CopyData` messages. The next message is read only
+/// after the future returned by `callback` completes, so at most one chunk is
+/// buffered and a handler can commit a batch or report progress before taking
+/// more data. An error from `callback` stops the copy and is returned as is,
+/// data already passed to it is up to the handler to keep or roll back.
+///
+/// Like `copy_reader`, `messages` come from a custom loop, or from a channel
+/// fed by the copy methods of `SimpleQueryHandler`.
+pub async fn copy_in_chunks<S, F, Fut>(
+    messages: S,
+    chunk_size: usize,
+    callback: F,
+) -> PgWireResult<u64>
+where
+    S: Stream<Item = PgWireResult<PgWireFrontendMessage>> + Unpin,
+    F: FnMut(Bytes) -> Fut,
+    Fut: Future<Output = PgWireResult<()>>,
+{
+    copy_data_in_chunks(CopyDataStream::new(messages), chunk_size, callback).await
+}
+
+/// Like `copy_in_chunks`, but abort the copy with `57014` error once `token`
+/// is cancelled, see `CopyDataStream::with_cancel_token`. A chunk already
+/// passed to `callback` is completed before the error is returned.
+pub async fn copy_in_chunks_with_cancel<S, F, Fut>(
+    messages: S,
+    chunk_size: usize,
+    token: CancellationToken,
+    callback: F,
+) -> PgWireResult<u64>
+where
+    S: Stream<Item = PgWireResult<PgWireFrontendMessage>> + Unpin,
+    F: FnMut(Bytes) -> Fut,
+    Fut: Future<Output = PgWireResult<()>>,
+{
+    let data = CopyDataStream::new(messages).with_cancel_token(token);
+    copy_data_in_chunks(data, chunk_size, callback).await
+}
+
+async fn copy_data_in_chunks<S, F, Fut>(
+    mut data: CopyDataStream<S>,
+    chunk_size: usize,
+    mut callback: F,
+) -> PgWireResult<u64>
+where
+    S: Stream<Item = PgWireResult<PgWireFrontendMessage>> + Unpin,
+    F: FnMut(Bytes) -> Fut,
+    Fut: Future<Output = PgWireResult<()>>,
+{
+    assert!(chunk_size > 0, "chunk size must be positive");
+
+    let mut buf = BytesMut::new();
+    let mut total = 0u64;
+    while let Some(item) = data.next().await {
+        let bytes = item.map_err(into_pgwire_error)?;
+        total += bytes.len() as u64;
+        buf.extend_from_slice(&bytes);
+        while buf.len() >= chunk_size {
+            callback(buf.split_to(chunk_size).freeze()).await?;
+        }
+    }
+    if !buf.is_empty() {
+        callback(buf.freeze()).await?;
+    }
+    Ok(total)
+}
+
+/// Send `CopyInResponse` to start `COPY ... FROM STDIN`, for handlers
+/// overriding `on_query` or `on_execute`. The default ones send it for
+/// `Response::CopyIn`.
+///
+/// Put the connection in `PgWireConnectionState::CopyInProgress` afterwards,
+/// so the message loop of `process_socket` routes copy messages to the
+/// handler.
+pub async fn send_copy_in_response<C>(client: &mut C, copy: CopyResponse) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    client
+        .send(PgWireBackendMessage::CopyInResponse(copy.into()))
+        .await?;
+    Ok(())
+}
+
+/// Send `data` to client as `CopyData` messages followed by `CopyDone`, for
+/// `COPY ... TO STDOUT` after `CopyOutResponse`. Returns total number of bytes
+/// sent.
+///
+/// Once `token` is cancelled, usually `ClientInfo::cancel_token` of the
+/// connection, no more data is sent and `57014 canceling statement due to
+/// user request` error is returned without `CopyDone`. An error from `data`
+/// stops the copy the same way. Send the error to client as `ErrorResponse`
+/// followed by `ReadyForQuery`, which ends copy mode on the client.
+pub async fn copy_out<C, S>(client: &mut C, data: S, token: CancellationToken) -> PgWireResult<u64>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    S: Stream<Item = PgWireResult<Bytes>>,
+{
+    send_copy_data(client, data, token)
+        .await
+        .map(|(_, bytes)| bytes)
+}
+
+/// Like `copy_out`, but each item of `rows` is a single row, sent in its own
+/// `CopyData` message as postgres does. Returns number of rows sent, to
+/// complete the copy with `copy_tag`.
+///
+/// In binary format, send the file header with the first row and the trailer
+/// with the last one, so they are not counted as rows.
+pub async fn copy_out_rows<C, S>(
+    client: &mut C,
+    rows: S,
+    token: CancellationToken,
+) -> PgWireResult<u64>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    S: Stream<Item = PgWireResult<Bytes>>,
+{
+    send_copy_data(client, rows, token)
+        .await
+        .map(|(rows, _)| rows)
+}
+
+/// Send `data` as `CopyData` messages, return number of messages and bytes.
+async fn send_copy_data<C, S>(
+    client: &mut C,
+    data: S,
+    token: CancellationToken,
+) -> PgWireResult<(u64, u64)>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    S: Stream<Item = PgWireResult<Bytes>>,
+{
+    futures::pin_mut!(data);
+    let mut cancelled = Box::pin(token.cancelled());
+    let mut messages = 0u64;
+    let mut total = 0u64;
+    loop {
+        let bytes = match future::select(cancelled.as_mut(), data.next()).await {
+            Either::Left(_) => return Err(query_canceled_error()),
+            Either::Right((None, _)) => break,
+            Either::Right((Some(bytes), _)) => bytes?,
+        };
+        messages += 1;
+        total += bytes.len() as u64;
+        client
+            .feed(PgWireBackendMessage::CopyData(CopyData::new(bytes)))
+            .await?;
+    }
+    client
+        .send(PgWireBackendMessage::CopyDone(CopyDone::new()))
+        .await?;
+    Ok((messages, total))
+}
+
+/// Tag of `CommandComplete` ending a `COPY`, as `COPY n` with the number of
+/// rows copied.
+///
+/// Clients like psql display the count and drivers return it, e.g.
+/// `copy_in` of tokio-postgres. Send it with `send_execution_response`, or
+/// return it as `Response::Execution`, once `CopyDone` of `COPY ... FROM
+/// STDIN` is received and the rows are stored, or after `copy_out_rows`.
+pub fn copy_tag(rows: u64) -> Tag {
+    Tag::new("COPY").with_row_count(rows)
+}
+
+fn into_pgwire_error(e: IOError) -> PgWireError {
+    if e.get_ref().is_some_and(|inner| inner.is::<PgWireError>()) {
+        *e.into_inner().unwrap().downcast::<PgWireError>().unwrap()
+    } else {
+        PgWireError::IoError(e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    use super::*;
+    use crate::api::query::send_execution_response;
+    use crate::api::results::Response;
+    use crate::api::testing::{encode_response, RecordingClient};
+    use crate::messages::copy::CopyFail;
+    use crate::messages::extendedquery::Flush;
+    use crate::messages::simplequery::Query;
+
+    fn data(s: &str) -> PgWireResult<PgWireFrontendMessage> {
+        Ok(PgWireFrontendMessage::CopyData(CopyData::new(
+            Bytes::copy_from_slice(s.as_bytes()),
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_copy_reader() {
+        let messages = stream::iter(vec![
+            data("1\tfoo\n2\t"),
+            Ok(PgWireFrontendMessage::Flush(Flush::new())),
+            data("bar"),
+            data("\n3\tbaz\n"),
+            Ok(PgWireFrontendMessage::CopyDone(CopyDone::new())),
+            Ok(PgWireFrontendMessage::Query(Query::new(
+                "SELECT 1".to_owned(),
+            ))),
+        ]);
+        let mut reader = copy_reader(messages);
+
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        while reader.read_line(&mut line).await.unwrap() > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+        assert_eq!(vec!["1\tfoo\n", "2\tbar\n", "3\tbaz\n"], lines);
+
+        // messages after CopyDone are left in the stream
+        let mut messages = reader.into_inner().into_inner();
+        assert!(matches!(
+            futures::StreamExt::next(&mut messages).await,
+            Some(Ok(PgWireFrontendMessage::Query(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_copy_reader_fail() {
+        let messages = stream::iter(vec![
+            data("abc"),
+            Ok(PgWireFrontendMessage::CopyFail(CopyFail::new(
+                "canceled".to_owned(),
+            ))),
+        ]);
+        let mut buf = Vec::new();
+        let error = copy_reader(messages)
+            .read_to_end(&mut buf)
+            .await
+            .unwrap_err();
+        assert_eq!(b"abc", &buf[..]);
+        let error = error
+            .into_inner()
+            .unwrap()
+            .downcast::<PgWireError>()
+            .unwrap();
+        match *error {
+            PgWireError::UserError(info) => {
+                assert_eq!("57014", info.code);
+                assert_eq!("COPY from stdin failed: canceled", info.message);
+            }
+            _ => panic!("expect user error"),
+        }
+
+        let messages = stream::iter(vec![data("abc")]);
+        let error = copy_reader(messages)
+            .read_to_end(&mut Vec::new())
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, error.kind());
+    }
+
+    #[tokio::test]
+    async fn test_copy_in_cancel() {
+        // the client keeps sending data, never reaching CopyDone
+        let messages = stream::iter(vec![data("abcd")]).chain(stream::pending());
+        let token = CancellationToken::new();
+        let mut chunks = Vec::new();
+        let error = copy_in_chunks_with_cancel(messages, 3, token.clone(), |chunk| {
+            chunks.push(chunk);
+            // cancel request arrives while processing the first chunk
+            token.cancel();
+            async { Ok(()) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(vec!["abc"], chunks);
+        match error {
+            PgWireError::UserError(info) => {
+                assert_eq!("57014", info.code);
+                assert_eq!("canceling statement due to user request", info.message);
+            }
+            _ => panic!("expect user error"),
+        }
+
+        // cancelled before data is read
+        let messages = stream::iter(vec![data("abc")]);
+        let token = CancellationToken::new();
+        token.cancel();
+        let mut reader = StreamReader::new(CopyDataStream::new(messages).with_cancel_token(token));
+        let mut buf = Vec::new();
+        assert!(reader.read_to_end(&mut buf).await.is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copy_out() {
+        let mut client = RecordingClient::<String>::new();
+        let chunks = stream::iter(vec![Ok(Bytes::from("1\ta\n")), Ok(Bytes::from("2\tb\n"))]);
+        let total = copy_out(&mut client, chunks, CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(8, total);
+        let messages = client.messages().unwrap();
+        assert_eq!(3, messages.len());
+        assert!(
+            matches!(&messages[1], PgWireBackendMessage::CopyData(data) if data.data == "2\tb\n")
+        );
+        assert!(matches!(messages[2], PgWireBackendMessage::CopyDone(_)));
+
+        // cancel request arrives while waiting for more data
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let chunks =
+            stream::iter(vec![Ok(Bytes::from("1\ta\n"))]).chain(stream::once(async move {
+                cancel.cancel();
+                future::pending().await
+            }));
+        let error = copy_out(&mut client, chunks, token).await.unwrap_err();
+        assert!(matches!(error, PgWireError::UserError(info) if info.code == "57014"));
+        // no CopyDone after the data sent
+        let messages = client.messages().unwrap();
+        assert_eq!(1, messages.len());
+        assert!(matches!(messages[0], PgWireBackendMessage::CopyData(_)));
+    }
+
+    #[tokio::test]
+    async fn test_copy_out_rows() {
+        let mut client = RecordingClient::<String>::new();
+        let rows = stream::iter(vec![
+            Ok(Bytes::from("1\ta\n")),
+            Ok(Bytes::from("2\tb\n")),
+            Ok(Bytes::from("3\tc\n")),
+        ]);
+        let count = copy_out_rows(&mut client, rows, CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(3, count);
+        send_execution_response(&mut client, copy_tag(count))
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(5, messages.len());
+        assert!(matches!(messages[3], PgWireBackendMessage::CopyDone(_)));
+        assert!(
+            matches!(&messages[4], PgWireBackendMessage::CommandComplete(cc) if cc.tag == "COPY 3")
+        );
+
+        assert_eq!(
+            b"C\0\0\0\x0bCOPY 0\0".as_ref(),
+            encode_response(Response::Execution(copy_tag(0)))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_in_chunks() {
+        let messages = stream::iter(vec![
+            data("ab"),
+            data("cdefg"),
+            data("h"),
+            data("ij"),
+            Ok(PgWireFrontendMessage::CopyDone(CopyDone::new())),
+        ]);
+        let mut chunks = Vec::new();
+        let total = copy_in_chunks(messages, 3, |chunk| {
+            chunks.push(chunk);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(10, total);
+        assert_eq!(vec!["abc", "def", "ghi", "j"], chunks);
+
+        let messages = stream::iter(vec![
+            data("abcd"),
+            Ok(PgWireFrontendMessage::CopyFail(CopyFail::new(
+                "canceled".to_owned(),
+            ))),
+        ]);
+        let mut chunks = Vec::new();
+        let error = copy_in_chunks(messages, 3, |chunk| {
+            chunks.push(chunk);
+            async { Ok(()) }
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(vec!["abc"], chunks);
+        assert!(matches!(error, PgWireError::UserError(info) if info.code == "57014"));
+
+        // callback error stops the copy
+        let messages = stream::iter(vec![data("abcdef"), data("ghi")]);
+        let error = copy_in_chunks(messages, 2, |chunk| async move {
+            if chunk == "cd" {
+                Err(PgWireError::IoError(IOError::other("full")))
+            } else {
+                Ok(())
+            }
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(error, PgWireError::IoError(_)));
+    }
+}
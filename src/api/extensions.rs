@@ -0,0 +1,110 @@
+//! Per-connection data of the application, like `http::Extensions`.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A map of values keyed by their type, to attach application data to a
+/// connection: tenant id, auth claims, cache handles and so on.
+///
+/// Available from handlers with `ClientInfo::extensions`, e.g. set by a
+/// `StartupHandler` after authentication and read by query handlers. It
+/// lives as long as the connection, one value per type, so wrap values in a
+/// newtype to tell apart two of the same type.
+///
+/// ```
+/// use pgwire::api::extensions::Extensions;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct TenantId(u64);
+///
+/// let mut extensions = Extensions::new();
+/// extensions.insert(TenantId(42));
+/// assert_eq!(Some(&TenantId(42)), extensions.get::<TenantId>());
+/// ```
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions::default()
+    }
+
+    /// Insert a value, returning the previous value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok().map(|v| *v))
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref())
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_mut())
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|v| v.downcast().ok().map(|v| *v))
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct TenantId(u64);
+
+    #[test]
+    fn test_extensions() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.is_empty());
+        assert_eq!(None, extensions.insert(TenantId(1)));
+        extensions.insert("claims".to_owned());
+        assert_eq!(2, extensions.len());
+
+        assert_eq!(Some(TenantId(1)), extensions.insert(TenantId(2)));
+        extensions.get_mut::<TenantId>().unwrap().0 += 1;
+        assert_eq!(Some(&TenantId(3)), extensions.get::<TenantId>());
+        assert_eq!(
+            Some("claims"),
+            extensions.get::<String>().map(String::as_str)
+        );
+        assert_eq!(None, extensions.get::<u64>());
+
+        assert_eq!(Some(TenantId(3)), extensions.remove::<TenantId>());
+        assert_eq!(None, extensions.get::<TenantId>());
+        extensions.clear();
+        assert!(extensions.is_empty());
+    }
+}
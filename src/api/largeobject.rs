@@ -0,0 +1,712 @@
+//! Large objects through the function call protocol, for libpq's `lo_*` API.
+
+use std::fmt::Debug;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::sink::{Sink, SinkExt};
+use futures::stream;
+use postgres_types::{FromSqlOwned, IsNull, Oid, ToSql};
+
+use super::portal::Format;
+use super::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response};
+use super::{ClientInfo, Type};
+use crate::error::{protocol_violation, ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::function::{FunctionCall, FunctionCallResponse};
+use crate::messages::response::ReadyForQuery;
+use crate::messages::PgWireBackendMessage;
+use crate::types::ToSqlText;
+
+/// Mode flag of `lo_creat` and `lo_open` to open for writing.
+pub const INV_WRITE: i32 = 0x0002_0000;
+/// Mode flag of `lo_creat` and `lo_open` to open for reading.
+pub const INV_READ: i32 = 0x0004_0000;
+
+/// `whence` of `lo_lseek`, from the start of the object.
+pub const SEEK_SET: i32 = 0;
+/// `whence` of `lo_lseek`, from the current position.
+pub const SEEK_CUR: i32 = 1;
+/// `whence` of `lo_lseek`, from the end of the object.
+pub const SEEK_END: i32 = 2;
+
+// OIDs of large object functions in `pg_catalog`, the same in all postgres
+// versions.
+pub const LO_CREATE_OID: Oid = 715;
+pub const LO_OPEN_OID: Oid = 952;
+pub const LO_CLOSE_OID: Oid = 953;
+pub const LOREAD_OID: Oid = 954;
+pub const LOWRITE_OID: Oid = 955;
+pub const LO_LSEEK_OID: Oid = 956;
+pub const LO_CREAT_OID: Oid = 957;
+pub const LO_TELL_OID: Oid = 958;
+pub const LO_UNLINK_OID: Oid = 964;
+pub const LO_LSEEK64_OID: Oid = 3170;
+pub const LO_TELL64_OID: Oid = 3171;
+
+/// Functions dispatched by `LargeObjectHandler`, with their OIDs.
+pub const LARGE_OBJECT_FUNCTIONS: &[(&str, Oid)] = &[
+    ("lo_create", LO_CREATE_OID),
+    ("lo_open", LO_OPEN_OID),
+    ("lo_close", LO_CLOSE_OID),
+    ("loread", LOREAD_OID),
+    ("lowrite", LOWRITE_OID),
+    ("lo_lseek", LO_LSEEK_OID),
+    ("lo_creat", LO_CREAT_OID),
+    ("lo_tell", LO_TELL_OID),
+    ("lo_unlink", LO_UNLINK_OID),
+    ("lo_lseek64", LO_LSEEK64_OID),
+    ("lo_tell64", LO_TELL64_OID),
+];
+
+/// Test if `query` is the lookup of large object function OIDs, sent by
+/// libpq before its first `lo_*` call.
+pub fn is_large_object_functions_query(query: &str) -> bool {
+    let query = query.to_lowercase();
+    query.contains("from pg_catalog.pg_proc") && query.contains("'lo_open'")
+}
+
+/// Answer the lookup of `is_large_object_functions_query`, with a row of
+/// `proname` and `oid` for each function of `LARGE_OBJECT_FUNCTIONS`.
+pub fn large_object_functions_response() -> PgWireResult<Response<'static>> {
+    let schema = Arc::new(vec![
+        FieldInfo::new("proname".into(), None, None, Type::NAME, FieldFormat::Text),
+        FieldInfo::new("oid".into(), None, None, Type::OID, FieldFormat::Text),
+    ]);
+    let rows = LARGE_OBJECT_FUNCTIONS
+        .iter()
+        .map(|(name, oid)| {
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            encoder.encode_field(name)?;
+            encoder.encode_field(oid)?;
+            encoder.finish()
+        })
+        .collect::<Vec<_>>();
+    Ok(Response::Query(QueryResponse::new(
+        schema,
+        stream::iter(rows),
+    )))
+}
+
+/// Large object operations, called through `FunctionCall` messages.
+///
+/// libpq implements `lo_import`, `lo_export` and the other large object
+/// functions with the function call protocol. To serve them, answer its OID
+/// lookup query with `large_object_functions_response` in the simple query
+/// handler, and forward `SimpleQueryHandler::on_function_call` to
+/// `LargeObjectHandler::on_function_call`, which decodes the arguments by the
+/// function OID and calls the methods below.
+///
+/// Descriptors returned by `lo_open` belong to the connection, keep them in
+/// `ClientInfo::extensions` for example. libpq opens large objects inside a
+/// transaction, it's up to the handler whether descriptors outlive it.
+/// Errors are sent to client as `ErrorResponse`, and all functions are
+/// strict: a call with a `NULL` argument returns `NULL` without calling the
+/// handler.
+#[async_trait]
+pub trait LargeObjectHandler: Send + Sync {
+    /// Create a large object, return its OID.
+    async fn lo_creat<C>(&self, client: &mut C, mode: i32) -> PgWireResult<Oid>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Create a large object with the given OID, or a new one if `lobj_id` is
+    /// 0. The default implementation only supports the latter, with
+    /// `lo_creat`.
+    async fn lo_create<C>(&self, client: &mut C, lobj_id: Oid) -> PgWireResult<Oid>
+    where
+        C: ClientInfo + Unpin + Send + Sync,
+    {
+        if lobj_id == 0 {
+            self.lo_creat(client, INV_READ | INV_WRITE).await
+        } else {
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "0A000".to_owned(),
+                "creating a large object with a given OID is not supported".to_owned(),
+            ))))
+        }
+    }
+
+    /// Open a large object, return a descriptor for other operations.
+    async fn lo_open<C>(&self, client: &mut C, lobj_id: Oid, mode: i32) -> PgWireResult<i32>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Read at most `len` bytes from the current position.
+    async fn lo_read<C>(&self, client: &mut C, fd: i32, len: i32) -> PgWireResult<Bytes>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Write `data` at the current position, return the number of bytes
+    /// written.
+    async fn lo_write<C>(&self, client: &mut C, fd: i32, data: Bytes) -> PgWireResult<i32>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Move the current position, `whence` is one of `SEEK_SET`, `SEEK_CUR`
+    /// and `SEEK_END`. Return the new position. `lo_tell` is this with offset
+    /// 0 from `SEEK_CUR`.
+    async fn lo_lseek<C>(
+        &self,
+        client: &mut C,
+        fd: i32,
+        offset: i64,
+        whence: i32,
+    ) -> PgWireResult<i64>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Close a descriptor.
+    async fn lo_close<C>(&self, client: &mut C, fd: i32) -> PgWireResult<()>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Delete a large object.
+    async fn lo_unlink<C>(&self, client: &mut C, lobj_id: Oid) -> PgWireResult<()>
+    where
+        C: ClientInfo + Unpin + Send + Sync;
+
+    /// Call the large object function of `call`, then send its result as
+    /// `FunctionCallResponse` followed by `ReadyForQuery`, with the status of
+    /// `ClientInfo::transaction_status` as libpq calls these functions in a
+    /// transaction.
+    ///
+    /// Calls of other functions are rejected with `42883`, malformed
+    /// arguments with `22P02` for text and `08P01` for binary format.
+    async fn on_function_call<C>(&self, client: &mut C, call: FunctionCall) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let args = Arguments::new(&call)?;
+        let value = if call.arguments.iter().any(Option::is_none) {
+            None
+        } else {
+            let format = FieldFormat::from(call.result_format_code);
+            let value = match call.function_oid {
+                LO_CREAT_OID => {
+                    args.check_len(1)?;
+                    let oid = self.lo_creat(client, args.int(0, &Type::INT4)?).await?;
+                    encode_result(&oid, &Type::OID, format)?
+                }
+                LO_CREATE_OID => {
+                    args.check_len(1)?;
+                    let oid = self.lo_create(client, args.int(0, &Type::OID)?).await?;
+                    encode_result(&oid, &Type::OID, format)?
+                }
+                LO_OPEN_OID => {
+                    args.check_len(2)?;
+                    let (lobj_id, mode) = (args.int(0, &Type::OID)?, args.int(1, &Type::INT4)?);
+                    let fd = self.lo_open(client, lobj_id, mode).await?;
+                    encode_result(&fd, &Type::INT4, format)?
+                }
+                LOREAD_OID => {
+                    args.check_len(2)?;
+                    let (fd, len) = (args.int(0, &Type::INT4)?, args.int(1, &Type::INT4)?);
+                    let data = self.lo_read(client, fd, len).await?;
+                    encode_result(&data.as_ref(), &Type::BYTEA, format)?
+                }
+                LOWRITE_OID => {
+                    args.check_len(2)?;
+                    let (fd, data) = (args.int(0, &Type::INT4)?, args.bytes(1)?);
+                    let written = self.lo_write(client, fd, data).await?;
+                    encode_result(&written, &Type::INT4, format)?
+                }
+                LO_LSEEK_OID | LO_LSEEK64_OID => {
+                    args.check_len(3)?;
+                    let fd = args.int(0, &Type::INT4)?;
+                    let offset = if call.function_oid == LO_LSEEK_OID {
+                        args.int::<i32>(1, &Type::INT4)? as i64
+                    } else {
+                        args.int(1, &Type::INT8)?
+                    };
+                    let whence = args.int(2, &Type::INT4)?;
+                    let position = self.lo_lseek(client, fd, offset, whence).await?;
+                    encode_position(call.function_oid == LO_LSEEK_OID, position, format)?
+                }
+                LO_TELL_OID | LO_TELL64_OID => {
+                    args.check_len(1)?;
+                    let fd = args.int(0, &Type::INT4)?;
+                    let position = self.lo_lseek(client, fd, 0, SEEK_CUR).await?;
+                    encode_position(call.function_oid == LO_TELL_OID, position, format)?
+                }
+                LO_CLOSE_OID => {
+                    args.check_len(1)?;
+                    self.lo_close(client, args.int(0, &Type::INT4)?).await?;
+                    encode_result(&0i32, &Type::INT4, format)?
+                }
+                LO_UNLINK_OID => {
+                    args.check_len(1)?;
+                    self.lo_unlink(client, args.int(0, &Type::OID)?).await?;
+                    encode_result(&1i32, &Type::INT4, format)?
+                }
+                oid => {
+                    return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                        "ERROR".to_owned(),
+                        "42883".to_owned(),
+                        format!("function with OID {oid} does not exist"),
+                    ))))
+                }
+            };
+            Some(value)
+        };
+
+        client
+            .feed(PgWireBackendMessage::FunctionCallResponse(
+                FunctionCallResponse::new(value),
+            ))
+            .await?;
+        client
+            .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                client.transaction_status().ready_status(),
+            )))
+            .await?;
+        client.flush().await?;
+        Ok(())
+    }
+}
+
+fn invalid_text_representation(ty: &Type, value: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "22P02".to_owned(),
+        format!("invalid input syntax for type {}: \"{value}\"", ty.name()),
+    )))
+}
+
+/// Arguments of a `FunctionCall`, decoded by their format codes.
+struct Arguments<'a> {
+    values: &'a [Option<Bytes>],
+    format: Format,
+}
+
+impl<'a> Arguments<'a> {
+    fn new(call: &'a FunctionCall) -> PgWireResult<Arguments<'a>> {
+        let nargs = call.arguments.len();
+        let nformats = call.argument_format_codes.len();
+        if nformats > 1 && nformats != nargs {
+            return Err(protocol_violation(format!(
+                "function call message has {nformats} argument formats but {nargs} arguments"
+            )));
+        }
+        Ok(Arguments {
+            values: &call.arguments,
+            format: Format::from_codes(&call.argument_format_codes),
+        })
+    }
+
+    fn check_len(&self, expected: usize) -> PgWireResult<()> {
+        if self.values.len() != expected {
+            return Err(protocol_violation(format!(
+                "function call message contains {} arguments but function requires {expected}",
+                self.values.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn value(&self, idx: usize) -> &Bytes {
+        // null arguments are handled before decoding
+        self.values[idx].as_ref().expect("non-null argument")
+    }
+
+    fn int<T>(&self, idx: usize, ty: &Type) -> PgWireResult<T>
+    where
+        T: FromSqlOwned + FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let value = self.value(idx);
+        if self.format.is_binary(idx) {
+            T::from_sql(ty, value).map_err(|_| {
+                protocol_violation(format!(
+                    "incorrect binary data format in function argument {}",
+                    idx + 1
+                ))
+            })
+        } else {
+            let text = String::from_utf8_lossy(value);
+            text.trim()
+                .parse()
+                .map_err(|_| invalid_text_representation(ty, &text))
+        }
+    }
+
+    fn bytes(&self, idx: usize) -> PgWireResult<Bytes> {
+        let value = self.value(idx);
+        if self.format.is_binary(idx) {
+            return Ok(value.clone());
+        }
+        match value.strip_prefix(b"\\x") {
+            Some(digits) => hex::decode(digits).map(Bytes::from).map_err(|_| {
+                invalid_text_representation(&Type::BYTEA, &String::from_utf8_lossy(value))
+            }),
+            None => Ok(value.clone()),
+        }
+    }
+}
+
+fn encode_result<T>(value: &T, ty: &Type, format: FieldFormat) -> PgWireResult<Bytes>
+where
+    T: ToSql + ToSqlText,
+{
+    let mut buf = BytesMut::new();
+    let is_null = if format == FieldFormat::Binary {
+        value.to_sql(ty, &mut buf)
+    } else {
+        value.to_sql_text(ty, &mut buf)
+    }
+    .map_err(PgWireError::ApiError)?;
+    debug_assert!(matches!(is_null, IsNull::No));
+    Ok(buf.freeze())
+}
+
+/// Encode position of `lo_lseek` or `lo_tell`, as `integer` for the 32-bit
+/// variants or `bigint`.
+fn encode_position(int4: bool, position: i64, format: FieldFormat) -> PgWireResult<Bytes> {
+    if !int4 {
+        return encode_result(&position, &Type::INT8, format);
+    }
+    let position = i32::try_from(position).map_err(|_| {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "22003".to_owned(),
+            format!("lo_lseek result out of range for large-object descriptor: {position}"),
+        )))
+    })?;
+    encode_result(&position, &Type::INT4, format)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::api::testing::RecordingClient;
+    use crate::api::TransactionStatus;
+
+    /// Large objects in memory, shared by all connections.
+    #[derive(Default)]
+    struct BlobStore {
+        objects: Mutex<HashMap<Oid, Vec<u8>>>,
+    }
+
+    /// Open descriptors of a connection: object and position.
+    #[derive(Default)]
+    struct Descriptors(Vec<Option<(Oid, usize)>>);
+
+    fn descriptor<C: ClientInfo>(client: &mut C, fd: i32) -> PgWireResult<&mut (Oid, usize)> {
+        client
+            .extensions_mut()
+            .and_then(|ext| ext.get_mut::<Descriptors>())
+            .and_then(|descriptors| descriptors.0.get_mut(fd as usize))
+            .and_then(Option::as_mut)
+            .ok_or_else(|| {
+                PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42704".to_owned(),
+                    format!("invalid large-object descriptor: {fd}"),
+                )))
+            })
+    }
+
+    #[async_trait]
+    impl LargeObjectHandler for BlobStore {
+        async fn lo_creat<C>(&self, _client: &mut C, _mode: i32) -> PgWireResult<Oid>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let mut objects = self.objects.lock().unwrap();
+            let oid = 16384 + objects.len() as Oid;
+            objects.insert(oid, Vec::new());
+            Ok(oid)
+        }
+
+        async fn lo_open<C>(&self, client: &mut C, lobj_id: Oid, _mode: i32) -> PgWireResult<i32>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let extensions = client.extensions_mut().unwrap();
+            if extensions.get::<Descriptors>().is_none() {
+                extensions.insert(Descriptors::default());
+            }
+            let descriptors = extensions.get_mut::<Descriptors>().unwrap();
+            descriptors.0.push(Some((lobj_id, 0)));
+            Ok(descriptors.0.len() as i32 - 1)
+        }
+
+        async fn lo_read<C>(&self, client: &mut C, fd: i32, len: i32) -> PgWireResult<Bytes>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let (oid, position) = descriptor(client, fd)?;
+            let objects = self.objects.lock().unwrap();
+            let object = &objects[oid];
+            let start = (*position).min(object.len());
+            let end = (start + len as usize).min(object.len());
+            *position = end;
+            Ok(Bytes::copy_from_slice(&object[start..end]))
+        }
+
+        async fn lo_write<C>(&self, client: &mut C, fd: i32, data: Bytes) -> PgWireResult<i32>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let (oid, position) = descriptor(client, fd)?;
+            let mut objects = self.objects.lock().unwrap();
+            let object = objects.get_mut(oid).unwrap();
+            let end = *position + data.len();
+            if object.len() < end {
+                object.resize(end, 0);
+            }
+            object[*position..end].copy_from_slice(&data);
+            *position = end;
+            Ok(data.len() as i32)
+        }
+
+        async fn lo_lseek<C>(
+            &self,
+            client: &mut C,
+            fd: i32,
+            offset: i64,
+            whence: i32,
+        ) -> PgWireResult<i64>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let (oid, position) = descriptor(client, fd)?;
+            let base = match whence {
+                SEEK_SET => 0,
+                SEEK_CUR => *position as i64,
+                _ => self.objects.lock().unwrap()[oid].len() as i64,
+            };
+            *position = (base + offset) as usize;
+            Ok(*position as i64)
+        }
+
+        async fn lo_close<C>(&self, client: &mut C, fd: i32) -> PgWireResult<()>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            descriptor(client, fd)?;
+            let descriptors = client.extensions_mut().unwrap();
+            descriptors.get_mut::<Descriptors>().unwrap().0[fd as usize] = None;
+            Ok(())
+        }
+
+        async fn lo_unlink<C>(&self, _client: &mut C, lobj_id: Oid) -> PgWireResult<()>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            self.objects.lock().unwrap().remove(&lobj_id);
+            Ok(())
+        }
+    }
+
+    fn int4(v: i32) -> Option<Bytes> {
+        Some(Bytes::copy_from_slice(&v.to_be_bytes()))
+    }
+
+    /// Call like libpq, with binary arguments and result, and return the
+    /// result.
+    async fn call(
+        store: &BlobStore,
+        client: &mut RecordingClient,
+        function_oid: Oid,
+        arguments: Vec<Option<Bytes>>,
+    ) -> PgWireResult<Option<Bytes>> {
+        let call = FunctionCall::new(function_oid, vec![1], arguments, 1);
+        store.on_function_call(client, call).await?;
+        let messages = client.messages()?;
+        assert_eq!(2, messages.len());
+        assert!(matches!(
+            messages[1],
+            PgWireBackendMessage::ReadyForQuery(_)
+        ));
+        match messages.into_iter().next() {
+            Some(PgWireBackendMessage::FunctionCallResponse(response)) => Ok(response.value),
+            message => panic!("expect FunctionCallResponse, got {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_large_object_functions() {
+        let store = BlobStore::default();
+        let mut client = RecordingClient::new();
+
+        let oid = call(&store, &mut client, LO_CREAT_OID, vec![int4(INV_WRITE)])
+            .await
+            .unwrap();
+        assert_eq!(int4(16384), oid);
+
+        let fd = call(
+            &store,
+            &mut client,
+            LO_OPEN_OID,
+            vec![oid.clone(), int4(INV_READ | INV_WRITE)],
+        )
+        .await
+        .unwrap();
+        let written = call(
+            &store,
+            &mut client,
+            LOWRITE_OID,
+            vec![fd.clone(), Some(Bytes::from_static(b"hello world"))],
+        )
+        .await
+        .unwrap();
+        assert_eq!(int4(11), written);
+        let position = call(&store, &mut client, LO_TELL_OID, vec![fd.clone()])
+            .await
+            .unwrap();
+        assert_eq!(int4(11), position);
+
+        let position = call(
+            &store,
+            &mut client,
+            LO_LSEEK64_OID,
+            vec![
+                fd.clone(),
+                Some(Bytes::copy_from_slice(&6i64.to_be_bytes())),
+                int4(SEEK_SET),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(Some(Bytes::copy_from_slice(&6i64.to_be_bytes())), position);
+        let data = call(&store, &mut client, LOREAD_OID, vec![fd.clone(), int4(100)])
+            .await
+            .unwrap();
+        assert_eq!(Some(Bytes::from_static(b"world")), data);
+
+        // text result and arguments
+        store
+            .on_function_call(
+                &mut client,
+                FunctionCall::new(
+                    LO_LSEEK_OID,
+                    vec![],
+                    vec![
+                        Some(Bytes::from("0")),
+                        Some(Bytes::from("-5")),
+                        Some(Bytes::from("2")),
+                    ],
+                    0,
+                ),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            &client.messages().unwrap()[0],
+            PgWireBackendMessage::FunctionCallResponse(response)
+                if response.value == Some(Bytes::from("6"))
+        ));
+
+        // strict functions return null for null arguments
+        assert_eq!(
+            None,
+            call(&store, &mut client, LOREAD_OID, vec![None, int4(1)])
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            int4(0),
+            call(&store, &mut client, LO_CLOSE_OID, vec![fd.clone()])
+                .await
+                .unwrap()
+        );
+        match call(&store, &mut client, LOREAD_OID, vec![fd, int4(1)]).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("42704", e.code),
+            r => panic!("expect invalid descriptor error, got {r:?}"),
+        }
+        assert_eq!(
+            int4(1),
+            call(&store, &mut client, LO_UNLINK_OID, vec![oid])
+                .await
+                .unwrap()
+        );
+        assert!(store.objects.lock().unwrap().is_empty());
+
+        match call(&store, &mut client, 1004, vec![int4(0), int4(0)]).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("42883", e.code),
+            r => panic!("expect undefined function error, got {r:?}"),
+        }
+        match call(&store, &mut client, LO_CLOSE_OID, vec![int4(0), int4(0)]).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("08P01", e.code),
+            r => panic!("expect protocol violation, got {r:?}"),
+        }
+
+        // malformed arguments fail the call only
+        let short = Some(Bytes::from_static(b"\0\0"));
+        match call(&store, &mut client, LO_CLOSE_OID, vec![short]).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("08P01", e.code),
+            r => panic!("expect protocol violation, got {r:?}"),
+        }
+        for (function_oid, arguments) in [
+            (LO_CLOSE_OID, vec![Some(Bytes::from("one"))]),
+            (
+                LOWRITE_OID,
+                vec![Some(Bytes::from("0")), Some(Bytes::from("\\xzz"))],
+            ),
+        ] {
+            let call = FunctionCall::new(function_oid, vec![0], arguments, 0);
+            match store.on_function_call(&mut client, call).await {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+                r => panic!("expect invalid text representation, got {r:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_function_call_transaction_status() {
+        let store = BlobStore::default();
+        let mut client = RecordingClient::new();
+        client.set_transaction_status(TransactionStatus::Transaction);
+        call(&store, &mut client, LO_CREAT_OID, vec![int4(INV_WRITE)])
+            .await
+            .unwrap();
+        store
+            .on_function_call(
+                &mut client,
+                FunctionCall::new(LO_CREAT_OID, vec![], vec![Some(Bytes::from("0"))], 0),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            &client.messages().unwrap()[1],
+            PgWireBackendMessage::ReadyForQuery(ready)
+                if ready.status == TransactionStatus::Transaction.ready_status()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_large_object_functions_query() {
+        // sent by lo_initialize of libpq
+        let query = "select proname, oid from pg_catalog.pg_proc where proname in ('lo_open', \
+                     'lo_close', 'lo_creat', 'lo_create', 'lo_unlink', 'lo_lseek', 'lo_lseek64', \
+                     'lo_tell', 'lo_tell64', 'lo_truncate', 'lo_truncate64', 'loread', \
+                     'lowrite') and pronamespace = (select oid from pg_catalog.pg_namespace \
+                     where nspname = 'pg_catalog')";
+        assert!(is_large_object_functions_query(query));
+        assert!(!is_large_object_functions_query("SELECT 1"));
+
+        let Response::Query(response) = large_object_functions_response().unwrap() else {
+            panic!("expect query response");
+        };
+        let mut client = RecordingClient::<String>::new();
+        crate::api::query::send_query_response(&mut client, response, true)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(LARGE_OBJECT_FUNCTIONS.len() + 2, messages.len());
+        assert!(matches!(
+            &messages[2],
+            PgWireBackendMessage::DataRow(row)
+                if row.fields == vec![Some(Bytes::from("lo_open")), Some(Bytes::from("952"))]
+        ));
+    }
+}
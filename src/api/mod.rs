@@ -6,12 +6,30 @@ use std::sync::Arc;
 
 pub use postgres_types::Type;
 
+use self::cancel::{CancelHandle, CancellationToken};
+use self::extensions::Extensions;
+use self::replication::ReplicationMode;
+use self::results::RowLimit;
+use crate::messages::response::{
+    READY_STATUS_FAILED_TRANSACTION_BLOCK, READY_STATUS_IDLE, READY_STATUS_TRANSACTION_BLOCK,
+};
+
+pub mod admission;
 pub mod auth;
+pub mod batch;
+pub mod cancel;
+pub mod copy;
+pub mod extensions;
+pub mod largeobject;
 pub mod portal;
 pub mod query;
+pub mod replication;
 pub mod results;
+pub mod session;
+pub mod set;
 pub mod stmt;
 pub mod store;
+pub mod testing;
 
 pub const DEFAULT_NAME: &str = "POSTGRESQL_DEFAULT_NAME";
 
@@ -23,6 +41,33 @@ pub enum PgWireConnectionState {
     ReadyForQuery,
     QueryInProgress,
     AwaitingSync,
+    /// Receiving data of `COPY ... FROM STDIN`, started by `Response::CopyIn`
+    /// from extended query if true, or simple query otherwise.
+    CopyInProgress(bool),
+}
+
+/// Transaction status of a session, reported to client in `ReadyForQuery`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionStatus {
+    /// Not in a transaction block
+    #[default]
+    Idle,
+    /// In a transaction block
+    Transaction,
+    /// In a failed transaction block, queries are rejected until the block
+    /// ends
+    Error,
+}
+
+impl TransactionStatus {
+    /// Status byte of `ReadyForQuery`
+    pub fn ready_status(&self) -> u8 {
+        match self {
+            TransactionStatus::Idle => READY_STATUS_IDLE,
+            TransactionStatus::Transaction => READY_STATUS_TRANSACTION_BLOCK,
+            TransactionStatus::Error => READY_STATUS_FAILED_TRANSACTION_BLOCK,
+        }
+    }
 }
 
 /// Describe a client information holder
@@ -38,6 +83,129 @@ pub trait ClientInfo {
     fn metadata(&self) -> &HashMap<String, String>;
 
     fn metadata_mut(&mut self) -> &mut HashMap<String, String>;
+
+    /// Transaction status of the session, sent to client in `ReadyForQuery`
+    /// by the default `on_query` and `on_sync`.
+    ///
+    /// Handlers running transaction control statements keep it up to date
+    /// with `set_transaction_status`, e.g. `Transaction` after `BEGIN` and
+    /// `Idle` after `COMMIT`. An error sent to client in a transaction block
+    /// moves it to `Error`, as postgres does. Always `Idle` by default.
+    fn transaction_status(&self) -> TransactionStatus {
+        TransactionStatus::Idle
+    }
+
+    /// Update transaction status of the session. Ignored by default, for
+    /// clients that don't keep it.
+    fn set_transaction_status(&mut self, _status: TransactionStatus) {}
+
+    /// `(pid, secret_key)` sent to client in `BackendKeyData`, which client
+    /// uses to cancel running query.
+    ///
+    /// `None` if the client doesn't support cancellation, a random key will be
+    /// sent in that case.
+    fn backend_key(&self) -> Option<(i32, i32)> {
+        None
+    }
+
+    /// Token cancelled when client requests to cancel the running query.
+    ///
+    /// Long running handlers should `select!` on `CancellationToken::cancelled`
+    /// and return `57014 canceling statement due to user request` error, i.e.
+    /// `cancel::query_canceled_error`. Copy helpers in `copy` take the token
+    /// as well. The default token is never cancelled.
+    fn cancel_token(&self) -> CancellationToken {
+        CancellationToken::new()
+    }
+
+    /// Application data attached to this connection, see `Extensions`.
+    ///
+    /// `None` if the client doesn't keep any, which is the default.
+    fn extensions(&self) -> Option<&Extensions> {
+        None
+    }
+
+    fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        None
+    }
+
+    /// Replication mode requested in startup message, `None` for a normal
+    /// connection.
+    ///
+    /// Recorded once when the startup message is accepted, later changes of
+    /// the `replication` metadata don't affect it. `None` by default, for
+    /// clients that don't keep it.
+    fn replication_mode(&self) -> Option<ReplicationMode> {
+        None
+    }
+
+    /// Test if the client requested a replication connection in startup
+    /// message.
+    fn is_replication_connection(&self) -> bool {
+        self.replication_mode().is_some()
+    }
+
+    /// Server-wide cap on rows of each query response, e.g. from
+    /// `SocketOptions::max_result_rows`.
+    ///
+    /// `send_query_response` enforces it along with the limit of the
+    /// response itself, the lower one wins. `None` by default.
+    fn max_result_rows(&self) -> Option<RowLimit> {
+        None
+    }
+
+    /// Current `application_name` of the client, set in startup message and
+    /// updated by `SET application_name`.
+    ///
+    /// `DefaultSetHandler` keeps the metadata up to date and reports the new
+    /// value to client with `ParameterStatus`.
+    fn application_name(&self) -> Option<&str> {
+        self.metadata()
+            .get(METADATA_APPLICATION_NAME)
+            .map(String::as_str)
+    }
+
+    /// OS credentials of the peer process, available on unix domain socket
+    /// connections regardless of the authentication method.
+    ///
+    /// Useful for audit logging of local clients. `None` for TCP connections.
+    fn peer_credentials(&self) -> Option<PeerCred> {
+        None
+    }
+
+    /// Format identity of this client as `user@host:port/database`, useful
+    /// for audit logging.
+    ///
+    /// The host is taken from peer socket address as is, no DNS lookup is
+    /// performed. User is reported as `-` before authentication is finished
+    /// because it's not verified yet, so is a missing database name.
+    fn identity_string(&self) -> String {
+        let user = match self.state() {
+            PgWireConnectionState::AwaitingStartup
+            | PgWireConnectionState::AuthenticationInProgress => None,
+            _ => self.metadata().get(METADATA_USER),
+        };
+        format!(
+            "{}@{}/{}",
+            user.map(String::as_str).unwrap_or("-"),
+            self.socket_addr(),
+            self.metadata()
+                .get(METADATA_DATABASE)
+                .map(String::as_str)
+                .unwrap_or("-")
+        )
+    }
+}
+
+/// Credentials of the process connected to a unix domain socket, as returned
+/// by `SO_PEERCRED` or its platform equivalent.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct PeerCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// Not available on all platforms
+    pub pid: Option<i32>,
 }
 
 /// Client Portal Store
@@ -49,6 +217,8 @@ pub trait ClientPortalStore {
 
 pub const METADATA_USER: &str = "user";
 pub const METADATA_DATABASE: &str = "database";
+pub const METADATA_APPLICATION_NAME: &str = "application_name";
+pub const METADATA_INTEGER_DATETIMES: &str = "integer_datetimes";
 
 #[non_exhaustive]
 #[derive(Debug)]
@@ -56,8 +226,15 @@ pub struct DefaultClient<S> {
     pub socket_addr: SocketAddr,
     pub is_secure: bool,
     pub state: PgWireConnectionState,
+    pub transaction_status: TransactionStatus,
     pub metadata: HashMap<String, String>,
     pub portal_store: store::MemPortalStore<S>,
+    pub backend_key: (i32, i32),
+    pub cancel_handle: CancelHandle,
+    pub peer_credentials: Option<PeerCred>,
+    pub replication_mode: Option<ReplicationMode>,
+    pub max_result_rows: Option<RowLimit>,
+    pub extensions: Extensions,
 }
 
 impl<S> ClientInfo for DefaultClient<S> {
@@ -77,6 +254,14 @@ impl<S> ClientInfo for DefaultClient<S> {
         self.state = new_state;
     }
 
+    fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.transaction_status = status;
+    }
+
     fn metadata(&self) -> &HashMap<String, String> {
         &self.metadata
     }
@@ -84,6 +269,34 @@ impl<S> ClientInfo for DefaultClient<S> {
     fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
         &mut self.metadata
     }
+
+    fn backend_key(&self) -> Option<(i32, i32)> {
+        Some(self.backend_key)
+    }
+
+    fn cancel_token(&self) -> CancellationToken {
+        self.cancel_handle.token()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCred> {
+        self.peer_credentials
+    }
+
+    fn replication_mode(&self) -> Option<ReplicationMode> {
+        self.replication_mode
+    }
+
+    fn max_result_rows(&self) -> Option<RowLimit> {
+        self.max_result_rows
+    }
+
+    fn extensions(&self) -> Option<&Extensions> {
+        Some(&self.extensions)
+    }
+
+    fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        Some(&mut self.extensions)
+    }
 }
 
 impl<S> DefaultClient<S> {
@@ -92,8 +305,15 @@ impl<S> DefaultClient<S> {
             socket_addr,
             is_secure,
             state: PgWireConnectionState::default(),
+            transaction_status: TransactionStatus::default(),
             metadata: HashMap::new(),
             portal_store: store::MemPortalStore::new(),
+            backend_key: (std::process::id() as i32, rand::random::<i32>()),
+            cancel_handle: CancelHandle::default(),
+            peer_credentials: None,
+            replication_mode: None,
+            max_result_rows: None,
+            extensions: Extensions::new(),
         }
     }
 }
@@ -122,3 +342,70 @@ impl<H> MakeHandler for StatelessMakeHandler<H> {
         self.0.clone()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::replication::METADATA_REPLICATION;
+    use super::*;
+
+    #[test]
+    fn test_identity_string() {
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        client
+            .metadata_mut()
+            .insert(METADATA_USER.to_owned(), "tomcat".to_owned());
+        client
+            .metadata_mut()
+            .insert(METADATA_DATABASE.to_owned(), "pgwire".to_owned());
+        client.set_state(PgWireConnectionState::AuthenticationInProgress);
+        assert_eq!("-@127.0.0.1:5432/pgwire", client.identity_string());
+
+        client.set_state(PgWireConnectionState::ReadyForQuery);
+        assert_eq!("tomcat@127.0.0.1:5432/pgwire", client.identity_string());
+
+        let client = DefaultClient::<String>::new("[::1]:5432".parse().unwrap(), false);
+        assert_eq!("-@[::1]:5432/-", client.identity_string());
+    }
+
+    #[test]
+    fn test_client_extensions() {
+        #[derive(Debug, PartialEq)]
+        struct TenantId(u64);
+
+        fn tenant(client: &dyn ClientInfo) -> Option<&TenantId> {
+            client.extensions()?.get()
+        }
+
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        assert_eq!(None, tenant(&client));
+        client.extensions_mut().unwrap().insert(TenantId(7));
+        assert_eq!(Some(&TenantId(7)), tenant(&client));
+    }
+
+    #[test]
+    fn test_replication_mode() {
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        assert!(!client.is_replication_connection());
+
+        client.replication_mode = Some(ReplicationMode::Logical);
+        assert_eq!(Some(ReplicationMode::Logical), client.replication_mode());
+        assert!(client.is_replication_connection());
+
+        // unaffected by metadata, e.g. after `SET replication`
+        client
+            .metadata_mut()
+            .insert(METADATA_REPLICATION.to_owned(), "false".to_owned());
+        assert!(client.is_replication_connection());
+    }
+
+    #[test]
+    fn test_application_name() {
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        assert_eq!(None, client.application_name());
+
+        client
+            .metadata_mut()
+            .insert(METADATA_APPLICATION_NAME.to_owned(), "psql".to_owned());
+        assert_eq!(Some("psql"), client.application_name());
+    }
+}
@@ -1,15 +1,22 @@
+use std::str::FromStr;
 use std::sync::Arc;
 
 use bytes::Bytes;
-use postgres_types::FromSqlOwned;
+use chrono::{NaiveDate, NaiveDateTime};
+use postgres_types::{FromSqlOwned, Kind};
 
 use crate::{
     api::Type,
-    error::{PgWireError, PgWireResult},
+    error::{protocol_violation, ErrorInfo, PgWireError, PgWireResult},
     messages::{data::FORMAT_CODE_BINARY, extendedquery::Bind},
+    types::{parse_array, DateStyle, FromSqlText, Money, Numeric, PgLsn},
 };
 
-use super::{results::FieldFormat, stmt::StoredStatement, DEFAULT_NAME};
+use super::{
+    results::{FieldFormat, FieldInfo},
+    stmt::StoredStatement,
+    DEFAULT_NAME,
+};
 
 /// Represent a prepared sql statement and its parameters bound by a `Bind`
 /// request.
@@ -60,7 +67,40 @@ impl Format {
         self.format_for(idx) == FieldFormat::Binary
     }
 
-    fn from_codes(codes: &[i16]) -> Self {
+    /// Get format of each column for a resultset of `ncols` columns
+    ///
+    /// Bind message carries zero, one or one-per-column format codes. Zero
+    /// codes means all columns are text, one code applies to all columns, and
+    /// otherwise the codes are returned as is. Like postgres, any other
+    /// number of codes is a protocol violation.
+    pub fn formats(&self, ncols: usize) -> PgWireResult<Vec<FieldFormat>> {
+        match self {
+            Format::UnifiedText => Ok(vec![FieldFormat::Text; ncols]),
+            Format::UnifiedBinary => Ok(vec![FieldFormat::Binary; ncols]),
+            Format::Individual(ref fv) if fv.len() == ncols => {
+                Ok(fv.iter().map(|c| FieldFormat::from(*c)).collect())
+            }
+            Format::Individual(ref fv) => Err(protocol_violation(format!(
+                "bind message has {} result formats but query has {ncols} columns",
+                fv.len()
+            ))),
+        }
+    }
+
+    /// Set formats of `fields` to the ones requested by client.
+    ///
+    /// Fail with a protocol violation if the number of format codes doesn't
+    /// fit the fields, see `formats`.
+    pub fn apply(&self, fields: Vec<FieldInfo>) -> PgWireResult<Vec<FieldInfo>> {
+        let formats = self.formats(fields.len())?;
+        Ok(fields
+            .into_iter()
+            .zip(formats)
+            .map(|(field, format)| field.with_format(format))
+            .collect())
+    }
+
+    pub(crate) fn from_codes(codes: &[i16]) -> Self {
         if codes.is_empty() {
             Format::UnifiedText
         } else if codes.len() == 1 {
@@ -71,6 +111,22 @@ impl Format {
     }
 }
 
+/// `22P02` error for a parameter that can't be decoded. Errors of the
+/// parsers in `types`, which carry their own code, are kept as is.
+fn invalid_parameter_error(
+    error: Box<dyn std::error::Error + Sync + Send>,
+    message: impl FnOnce() -> String,
+) -> PgWireError {
+    match error.downcast::<PgWireError>() {
+        Ok(error) if matches!(*error, PgWireError::UserError(_)) => *error,
+        _ => PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "22P02".to_owned(),
+            message(),
+        ))),
+    }
+}
+
 impl<S: Clone> Portal<S> {
     /// Try to create portal from bind command and current client state
     pub fn try_new(bind: &Bind, statement: Arc<StoredStatement<S>>) -> PgWireResult<Self> {
@@ -79,6 +135,23 @@ impl<S: Clone> Portal<S> {
             .clone()
             .unwrap_or_else(|| DEFAULT_NAME.to_owned());
 
+        let nparams = bind.parameters.len();
+        let nformats = bind.parameter_format_codes.len();
+        if nformats > 1 && nformats != nparams {
+            return Err(protocol_violation(format!(
+                "bind message has {nformats} parameter formats but {nparams} parameters"
+            )));
+        }
+        // the statement has at least as many parameters as types declared in
+        // `Parse`, clients may leave trailing ones unspecified
+        let declared = statement.parameter_types.len();
+        if nparams < declared {
+            return Err(protocol_violation(format!(
+                "bind message supplies {nparams} parameters, but prepared statement \"{}\" requires {declared}",
+                statement.id
+            )));
+        }
+
         // param format
         let param_format = Format::from_codes(&bind.parameter_format_codes);
 
@@ -94,14 +167,95 @@ impl<S: Clone> Portal<S> {
         })
     }
 
-    /// Get number of parameters
+    /// Get name of this portal, as assigned by client in `Bind`.
+    ///
+    /// The unnamed portal is named `DEFAULT_NAME`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get name of the prepared statement this portal is bound to, as
+    /// assigned by client in `Parse`.
+    ///
+    /// The unnamed statement is named `DEFAULT_NAME`.
+    pub fn statement_name(&self) -> &str {
+        &self.statement.id
+    }
+
+    /// Get result format of each column, with format codes from `Bind`
+    /// expanded to `ncols` columns.
+    ///
+    /// Return a protocol violation error if `Bind` carries a number of format
+    /// codes other than 0, 1 or `ncols`.
+    pub fn result_formats(&self, ncols: usize) -> PgWireResult<Vec<FieldFormat>> {
+        self.result_column_format.formats(ncols)
+    }
+
+    /// Get number of parameters bound by `Bind`.
     pub fn parameter_len(&self) -> usize {
         self.parameters.len()
     }
 
+    /// Get number of parameter types declared by `Parse`.
+    ///
+    /// Clients may declare fewer types than the parameters of the statement,
+    /// so this is a lower bound. `Bind` supplying fewer parameters is
+    /// rejected when creating the portal, check the number of parameters your
+    /// parsed statement actually uses against `parameter_len`.
+    pub fn declared_parameter_len(&self) -> usize {
+        self.statement.parameter_types.len()
+    }
+
     /// Attempt to get parameter at given index as type `T`.
     ///
+    /// The parameter is decoded with `FromSql` of `T` regardless of its format
+    /// code. That's the binary format, which is the same as text format only
+    /// for text types like `String`. Use `text_aware_parameter` to decode text
+    /// format parameters of other types.
     pub fn parameter<T>(&self, idx: usize, pg_type: &Type) -> PgWireResult<Option<T>>
+    where
+        T: FromSqlOwned,
+    {
+        self.binary_parameter(idx, pg_type)
+    }
+
+    /// Attempt to get parameter at given index as type `T`, in the format set
+    /// by format codes of `Bind`.
+    ///
+    /// The parameter is decoded with `FromSql` of `T` in binary format, and
+    /// with `FromSqlText` in text format. So `Vec<Option<i32>>` decodes an
+    /// `int4[]` parameter in either format, with `NULL` elements as `None`.
+    /// Return `22P02` error on malformed input.
+    pub fn text_aware_parameter<T>(&self, idx: usize, pg_type: &Type) -> PgWireResult<Option<T>>
+    where
+        T: FromSqlOwned + FromSqlText,
+    {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, pg_type);
+        }
+        if !T::accepts(pg_type) {
+            return Err(PgWireError::InvalidRustTypeForParameter(
+                pg_type.name().to_owned(),
+            ));
+        }
+        self.raw_parameter(idx)?
+            .map(|param| {
+                T::from_sql_text(pg_type, param).map_err(|e| {
+                    invalid_parameter_error(e, || {
+                        format!(
+                            "invalid input syntax for type {}: \"{}\"",
+                            pg_type.name(),
+                            String::from_utf8_lossy(param)
+                        )
+                    })
+                })
+            })
+            .transpose()
+    }
+
+    /// Decode parameter at given index with `FromSql` of `T`, regardless of
+    /// its format code.
+    fn binary_parameter<T>(&self, idx: usize, pg_type: &Type) -> PgWireResult<Option<T>>
     where
         T: FromSqlOwned,
     {
@@ -110,24 +264,166 @@ impl<S: Clone> Portal<S> {
                 pg_type.name().to_owned(),
             ));
         }
+        self.raw_parameter(idx)?
+            .map(|param| {
+                T::from_sql(pg_type, param).map_err(|e| {
+                    invalid_parameter_error(e, || {
+                        format!("incorrect binary data format in bind parameter {}", idx + 1)
+                    })
+                })
+            })
+            .transpose()
+    }
 
-        let param = self
-            .parameters
+    fn raw_parameter(&self, idx: usize) -> PgWireResult<Option<&[u8]>> {
+        self.parameters
             .get(idx)
-            .ok_or_else(|| PgWireError::ParameterIndexOutOfBound(idx))?;
+            .map(|param| param.as_deref())
+            .ok_or_else(|| PgWireError::ParameterIndexOutOfBound(idx))
+    }
 
-        let _format = self.parameter_format.format_for(idx);
+    /// Get `date` parameter at given index.
+    ///
+    /// Text format parameter is parsed with `date_style`, usually the
+    /// session's `DateStyle`, to resolve ambiguous input like `01/02/2023`.
+    pub fn date_parameter(
+        &self,
+        idx: usize,
+        date_style: &DateStyle,
+    ) -> PgWireResult<Option<NaiveDate>> {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, &Type::DATE);
+        }
+        self.text_parameter(idx)?
+            .map(|v| date_style.parse_date(v))
+            .transpose()
+    }
 
-        if let Some(ref param) = param {
-            // TODO: from_sql only works with binary format
-            // here we need to check format code first and seek to support text
-            T::from_sql(pg_type, param)
-                .map(|v| Some(v))
-                .map_err(PgWireError::FailedToParseParameter)
-        } else {
-            // Null
-            Ok(None)
+    /// Get `timestamp` parameter at given index.
+    ///
+    /// Text format parameter is parsed with `date_style`, usually the
+    /// session's `DateStyle`, to resolve ambiguous input like `01/02/2023`.
+    pub fn timestamp_parameter(
+        &self,
+        idx: usize,
+        date_style: &DateStyle,
+    ) -> PgWireResult<Option<NaiveDateTime>> {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, &Type::TIMESTAMP);
+        }
+        self.text_parameter(idx)?
+            .map(|v| date_style.parse_timestamp(v))
+            .transpose()
+    }
+
+    /// Get `numeric` parameter at given index, in text or binary format.
+    ///
+    /// Text format parameter is parsed as a plain decimal like `-12.34`, or
+    /// one of the special values, returning `22P02` error on invalid input.
+    pub fn numeric_parameter(&self, idx: usize) -> PgWireResult<Option<Numeric>> {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, &Type::NUMERIC);
+        }
+        self.text_parameter(idx)?
+            .map(|v| {
+                v.parse()
+                    .map_err(|e: Box<dyn std::error::Error + Sync + Send>| {
+                        PgWireError::UserError(Box::new(ErrorInfo::new(
+                            "ERROR".to_owned(),
+                            "22P02".to_owned(),
+                            e.to_string(),
+                        )))
+                    })
+            })
+            .transpose()
+    }
+
+    /// Get `money` parameter at given index, in text or binary format.
+    ///
+    /// Text format parameter is parsed with `Money::parse`, so `$1,234.50`,
+    /// `1234.5` and `(12.34)` are accepted regardless of server locale.
+    pub fn money_parameter(&self, idx: usize) -> PgWireResult<Option<Money>> {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, &Type::MONEY);
+        }
+        self.text_parameter(idx)?.map(Money::parse).transpose()
+    }
+
+    /// Get `pg_lsn` parameter at given index, in text or binary format.
+    ///
+    /// Text format parameter is parsed with `PgLsn::parse`, like
+    /// `16/B374D848`.
+    pub fn pg_lsn_parameter(&self, idx: usize) -> PgWireResult<Option<PgLsn>> {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, &Type::PG_LSN);
+        }
+        self.text_parameter(idx)?.map(PgLsn::parse).transpose()
+    }
+
+    /// Get one-dimensional array parameter at given index, in text or binary
+    /// format, with `NULL` elements as `None`.
+    ///
+    /// `pg_type` is the array type like `Type::INT4_ARRAY`. Binary format is
+    /// decoded with `FromSql` of `T`, while text format like `{1,NULL,3}` is
+    /// parsed with `FromStr` of `T`. This works for `i32`, `i64`, `String`
+    /// and other types implementing both, e.g. `uuid::Uuid` with the
+    /// `with-uuid-1` feature of `postgres-types`. Return `22P02` error on
+    /// malformed text input.
+    pub fn array_parameter<T>(
+        &self,
+        idx: usize,
+        pg_type: &Type,
+    ) -> PgWireResult<Option<Vec<Option<T>>>>
+    where
+        T: FromSqlOwned + FromStr,
+    {
+        if self.parameter_format.is_binary(idx) {
+            return self.binary_parameter(idx, pg_type);
         }
+
+        let element_type = match pg_type.kind() {
+            Kind::Array(element_type) if T::accepts(element_type) => element_type,
+            _ => {
+                return Err(PgWireError::InvalidRustTypeForParameter(
+                    pg_type.name().to_owned(),
+                ))
+            }
+        };
+        self.text_parameter(idx)?
+            .map(|v| {
+                parse_array(v)?
+                    .into_iter()
+                    .map(|element| {
+                        element
+                            .map(|element| {
+                                element.parse().map_err(|_| {
+                                    PgWireError::UserError(Box::new(ErrorInfo::new(
+                                        "ERROR".to_owned(),
+                                        "22P02".to_owned(),
+                                        format!(
+                                            "invalid input syntax for type {}: \"{element}\"",
+                                            element_type.name()
+                                        ),
+                                    )))
+                                })
+                            })
+                            .transpose()
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+
+    fn text_parameter(&self, idx: usize) -> PgWireResult<Option<&str>> {
+        self.raw_parameter(idx)?
+            .map(|v| {
+                std::str::from_utf8(v).map_err(|e| {
+                    invalid_parameter_error(e.into(), || {
+                        format!("invalid UTF-8 in bind parameter {}", idx + 1)
+                    })
+                })
+            })
+            .transpose()
     }
 }
 
@@ -137,6 +433,107 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_result_formats() {
+        let bind = Bind::new(None, None, vec![], vec![], vec![]);
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+        assert_eq!(
+            vec![FieldFormat::Text; 3],
+            portal.result_formats(3).unwrap()
+        );
+
+        let bind = Bind::new(None, None, vec![], vec![], vec![FORMAT_CODE_BINARY]);
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+        assert_eq!(
+            vec![FieldFormat::Binary; 2],
+            portal.result_formats(2).unwrap()
+        );
+
+        let bind = Bind::new(None, None, vec![], vec![], vec![0, 1]);
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+        assert_eq!(
+            vec![FieldFormat::Text, FieldFormat::Binary],
+            portal.result_formats(2).unwrap()
+        );
+        for ncols in [1, 3] {
+            match portal.result_formats(ncols) {
+                Err(PgWireError::UserError(e)) => assert_eq!("08P01", e.code),
+                _ => panic!("expect protocol violation"),
+            }
+        }
+
+        let fields = vec![
+            FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Binary),
+            FieldInfo::new("name".into(), None, None, Type::TEXT, FieldFormat::Text),
+        ];
+        let formats = portal
+            .result_column_format
+            .apply(fields.clone())
+            .unwrap()
+            .iter()
+            .map(FieldInfo::format)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![FieldFormat::Text, FieldFormat::Binary], formats);
+        assert!(portal
+            .result_column_format
+            .apply(fields[..1].to_vec())
+            .is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        let statement = StoredStatement::new("find-user".to_owned(), "".to_owned(), vec![]);
+        let bind = Bind::new(
+            Some("find-user-0".to_owned()),
+            Some("find-user".to_owned()),
+            vec![],
+            vec![],
+            vec![],
+        );
+        let portal = Portal::try_new(&bind, Arc::new(statement)).unwrap();
+        assert_eq!("find-user-0", portal.name());
+        assert_eq!("find-user", portal.statement_name());
+
+        let bind = Bind::new(None, None, vec![], vec![], vec![]);
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+        assert_eq!(DEFAULT_NAME, portal.name());
+    }
+
+    #[test]
+    fn test_parameter_count() {
+        let statement = Arc::new(StoredStatement::new(
+            "find-user".to_owned(),
+            "".to_owned(),
+            vec![Type::INT4],
+        ));
+        let param = Some(Bytes::from_static(&[0, 0, 0, 1]));
+
+        let bind = Bind::new(None, None, vec![], vec![], vec![]);
+        match Portal::try_new(&bind, statement.clone()) {
+            Err(PgWireError::UserError(info)) => {
+                assert_eq!("08P01", info.code);
+                assert_eq!(
+                    "bind message supplies 0 parameters, but prepared statement \"find-user\" requires 1",
+                    info.message
+                );
+            }
+            _ => panic!("expect protocol violation"),
+        }
+
+        let bind = Bind::new(None, None, vec![1, 1, 1], vec![param.clone()], vec![]);
+        assert!(Portal::try_new(&bind, statement.clone()).is_err());
+
+        // a parameter with undeclared type
+        let bind = Bind::new(None, None, vec![1], vec![param.clone(), param], vec![]);
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        assert_eq!(1, portal.declared_parameter_len());
+        assert_eq!(2, portal.parameter_len());
+    }
+
     #[test]
     fn test_from_sql() {
         assert_eq!(
@@ -144,4 +541,362 @@ mod tests {
             String::from_sql(&Type::UNKNOWN, "helloworld".as_bytes()).unwrap()
         )
     }
+
+    #[test]
+    fn test_date_parameter() {
+        let date = NaiveDate::from_ymd_opt(2023, 2, 1).unwrap();
+        let mut binary_date = bytes::BytesMut::new();
+        postgres_types::ToSql::to_sql(&date, &Type::DATE, &mut binary_date).unwrap();
+
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 1, 0],
+            vec![
+                Some(Bytes::from_static(b"01/02/2023")),
+                Some(binary_date.freeze()),
+                Some(Bytes::from_static(b"01/02/2023 10:00:00")),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        let dmy = DateStyle::parse("ISO, DMY").unwrap();
+        assert_eq!(Some(date), portal.date_parameter(0, &dmy).unwrap());
+        assert_eq!(Some(date), portal.date_parameter(1, &dmy).unwrap());
+        assert_eq!(
+            Some(date.and_hms_opt(10, 0, 0).unwrap()),
+            portal.timestamp_parameter(2, &dmy).unwrap()
+        );
+
+        let mdy = DateStyle::default();
+        assert_eq!(
+            Some(NaiveDate::from_ymd_opt(2023, 1, 2).unwrap()),
+            portal.date_parameter(0, &mdy).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_numeric_and_money_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 1, 0, 1, 0],
+            vec![
+                Some(Bytes::from_static(b"12.340")),
+                // 0.5::numeric(10,4)
+                Some(Bytes::from_static(&[
+                    0, 1, 0xff, 0xff, 0, 0, 0, 4, 0x13, 0x88,
+                ])),
+                Some(Bytes::from_static(b"$1,234.5")),
+                // -12.34::money
+                Some(Bytes::from_static(&[
+                    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfb, 0x2e,
+                ])),
+                Some(Bytes::from_static(b"1,2.3x")),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(
+            Some(Numeric::Finite("12.340".to_owned())),
+            portal.numeric_parameter(0).unwrap()
+        );
+        assert_eq!(
+            Some(Numeric::Finite("0.5000".to_owned())),
+            portal.numeric_parameter(1).unwrap()
+        );
+        assert_eq!(Some(Money(123450)), portal.money_parameter(2).unwrap());
+        assert_eq!(Some(Money(-1234)), portal.money_parameter(3).unwrap());
+        assert_eq!(Some(Money(1234)), portal.money_parameter(0).unwrap());
+
+        for result in [
+            portal.numeric_parameter(2).map(|_| ()),
+            portal.money_parameter(4).map(|_| ()),
+            portal.money_parameter(1).map(|_| ()),
+        ] {
+            match result {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+                _ => panic!("expect invalid input error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pg_lsn_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 1, 0],
+            vec![
+                Some(Bytes::from_static(b"16/B374D848")),
+                Some(Bytes::from_static(&[0, 0, 0, 0x16, 0xb3, 0x74, 0xd8, 0x48])),
+                Some(Bytes::from_static(b"16")),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(
+            Some(PgLsn(0x16_b374_d848)),
+            portal.pg_lsn_parameter(0).unwrap()
+        );
+        assert_eq!(
+            Some(PgLsn(0x16_b374_d848)),
+            portal.pg_lsn_parameter(1).unwrap()
+        );
+        match portal.pg_lsn_parameter(2) {
+            Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+            _ => panic!("expect invalid input error"),
+        }
+    }
+
+    #[test]
+    fn test_array_parameter() {
+        let mut binary_array = bytes::BytesMut::new();
+        postgres_types::ToSql::to_sql(
+            &vec![Some(1i64), None, Some(3)],
+            &Type::INT8_ARRAY,
+            &mut binary_array,
+        )
+        .unwrap();
+        let binary_array = binary_array.freeze();
+        let mut binary_empty_array = bytes::BytesMut::new();
+        postgres_types::ToSql::to_sql(
+            &Vec::<i64>::new(),
+            &Type::INT8_ARRAY,
+            &mut binary_empty_array,
+        )
+        .unwrap();
+
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 0, 1, 1, 0, 0, 0, 1],
+            vec![
+                Some(Bytes::from_static(b"{1, NULL ,3}")),
+                Some(Bytes::from_static(br#"{"a,b",NULL,"NULL",c\"d}"#)),
+                Some(binary_array.clone()),
+                Some(binary_empty_array.freeze()),
+                Some(Bytes::from_static(b"{}")),
+                Some(Bytes::from_static(b"{1,2")),
+                Some(Bytes::from_static(b"{1,x}")),
+                Some(binary_array.slice(..binary_array.len() - 4)),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(
+            Some(vec![Some(1), None, Some(3)]),
+            portal.array_parameter::<i32>(0, &Type::INT4_ARRAY).unwrap()
+        );
+        assert_eq!(
+            Some(vec![
+                Some("a,b".to_owned()),
+                None,
+                Some("NULL".to_owned()),
+                Some("c\"d".to_owned())
+            ]),
+            portal
+                .array_parameter::<String>(1, &Type::TEXT_ARRAY)
+                .unwrap()
+        );
+        assert_eq!(
+            Some(vec![Some(1), None, Some(3)]),
+            portal.array_parameter::<i64>(2, &Type::INT8_ARRAY).unwrap()
+        );
+        assert_eq!(
+            Some(vec![]),
+            portal.array_parameter::<i64>(3, &Type::INT8_ARRAY).unwrap()
+        );
+        assert_eq!(
+            Some(vec![]),
+            portal.array_parameter::<i64>(4, &Type::INT8_ARRAY).unwrap()
+        );
+
+        for idx in [5, 6] {
+            match portal.array_parameter::<i32>(idx, &Type::INT4_ARRAY) {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+                _ => panic!("expect invalid input error"),
+            }
+        }
+        for idx in [5, 6, 7] {
+            match portal.text_aware_parameter::<Vec<Option<i64>>>(idx, &Type::INT8_ARRAY) {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+                _ => panic!("expect invalid input error"),
+            }
+        }
+        match portal.array_parameter::<i64>(7, &Type::INT8_ARRAY) {
+            Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+            _ => panic!("expect invalid input error"),
+        }
+
+        // decoded by format code with `text_aware_parameter` as well
+        for idx in [0, 2] {
+            assert_eq!(
+                Some(vec![Some(1), None, Some(3)]),
+                portal
+                    .text_aware_parameter::<Vec<Option<i64>>>(idx, &Type::INT8_ARRAY)
+                    .unwrap()
+            );
+        }
+        assert_eq!(
+            Some(vec![
+                Some("a,b".to_owned()),
+                None,
+                Some("NULL".to_owned()),
+                Some("c\"d".to_owned())
+            ]),
+            portal
+                .text_aware_parameter::<Vec<Option<String>>>(1, &Type::TEXT_ARRAY)
+                .unwrap()
+        );
+        assert_eq!(
+            Some(vec![]),
+            portal
+                .text_aware_parameter::<Vec<i64>>(4, &Type::INT8_ARRAY)
+                .unwrap()
+        );
+        // NULL elements need `Option`
+        match portal.text_aware_parameter::<Vec<i64>>(0, &Type::INT8_ARRAY) {
+            Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code),
+            _ => panic!("expect invalid input error"),
+        }
+
+        for idx in [0, 2] {
+            assert!(matches!(
+                portal.array_parameter::<i32>(idx, &Type::INT8_ARRAY),
+                Err(PgWireError::InvalidRustTypeForParameter(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_int2_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![1],
+            vec![
+                Some(Bytes::from_static(&[0xff, 0xfe])),
+                Some(Bytes::from_static(&[0, 0, 0, 1])),
+                None,
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(Some(-2), portal.parameter::<i16>(0, &Type::INT2).unwrap());
+        assert_eq!(None, portal.parameter::<i16>(2, &Type::INT2).unwrap());
+        // binary int2 must be exactly 2 bytes
+        match portal.parameter::<i16>(1, &Type::INT2) {
+            Err(PgWireError::UserError(e)) => {
+                assert_eq!("22P02", e.code);
+                assert_eq!(
+                    "incorrect binary data format in bind parameter 2",
+                    e.message
+                );
+            }
+            _ => panic!("expect invalid input error"),
+        }
+        assert!(matches!(
+            portal.parameter::<i32>(0, &Type::INT2),
+            Err(PgWireError::InvalidRustTypeForParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_text_parameter() {
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0],
+            vec![
+                Some(Bytes::from_static(b"42")),
+                Some(Bytes::from_static(b"forty-two")),
+                Some(Bytes::from_static(b"on")),
+                Some(Bytes::from_static(br"\x0aff")),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(
+            Some(42),
+            portal.text_aware_parameter::<i32>(0, &Type::INT4).unwrap()
+        );
+        match portal.text_aware_parameter::<i32>(1, &Type::INT4) {
+            Err(PgWireError::UserError(e)) => {
+                assert_eq!("22P02", e.code);
+                assert_eq!(
+                    "invalid input syntax for type int4: \"forty-two\"",
+                    e.message
+                );
+            }
+            _ => panic!("expect invalid input error"),
+        }
+        assert_eq!(
+            Some(true),
+            portal.text_aware_parameter::<bool>(2, &Type::BOOL).unwrap()
+        );
+        assert_eq!(
+            Some(vec![0x0a, 0xff]),
+            portal
+                .text_aware_parameter::<Vec<u8>>(3, &Type::BYTEA)
+                .unwrap()
+        );
+        // text types are the same in both formats
+        assert_eq!(
+            Some("forty-two".to_owned()),
+            portal.parameter::<String>(1, &Type::TEXT).unwrap()
+        );
+    }
+
+    #[cfg(feature = "with-uuid-1")]
+    #[test]
+    fn test_uuid_array_parameter() {
+        let ids = vec![
+            uuid::Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8),
+            uuid::Uuid::nil(),
+        ];
+        let mut binary = bytes::BytesMut::new();
+        postgres_types::ToSql::to_sql(&ids, &Type::UUID_ARRAY, &mut binary).unwrap();
+        let bind = Bind::new(
+            None,
+            None,
+            vec![1, 0],
+            vec![
+                Some(binary.freeze()),
+                Some(Bytes::from_static(
+                    b"{67e55044-10b1-426f-9247-bb680e5fe0c8,00000000-0000-0000-0000-000000000000}",
+                )),
+            ],
+            vec![],
+        );
+        let portal =
+            Portal::try_new(&bind, Arc::new(StoredStatement::<String>::default())).unwrap();
+
+        assert_eq!(
+            Some(ids.clone()),
+            portal
+                .parameter::<Vec<uuid::Uuid>>(0, &Type::UUID_ARRAY)
+                .unwrap()
+        );
+        for idx in [0, 1] {
+            assert_eq!(
+                Some(ids.clone()),
+                portal
+                    .text_aware_parameter::<Vec<uuid::Uuid>>(idx, &Type::UUID_ARRAY)
+                    .unwrap()
+            );
+        }
+    }
 }
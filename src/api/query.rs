@@ -1,23 +1,33 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::sink::{Sink, SinkExt};
 use futures::stream::StreamExt;
+use log::warn;
 
-use super::portal::Portal;
-use super::results::{into_row_description, Tag};
+use super::copy::{copy_failed_error, send_copy_in_response};
+use super::portal::{Format, Portal};
+use super::results::{into_row_description, FieldInfo, Tag};
+use super::session::{on_reset, ResetStatement};
+use super::set::reset_local_parameters;
 use super::stmt::{NoopQueryParser, QueryParser, StoredStatement};
 use super::store::PortalStore;
-use super::{ClientInfo, ClientPortalStore, DEFAULT_NAME};
-use crate::api::results::{DescribeResponse, QueryResponse, Response};
-use crate::error::{PgWireError, PgWireResult};
+use super::{
+    ClientInfo, ClientPortalStore, PgWireConnectionState, TransactionStatus, DEFAULT_NAME,
+};
+use crate::api::results::{DescribeResponse, QueryResponse, Response, RowLimitPolicy, RowOrNotice};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::copy::{CopyData, CopyDone, CopyFail};
 use crate::messages::data::{NoData, ParameterDescription};
 use crate::messages::extendedquery::{
     Bind, BindComplete, Close, CloseComplete, Describe, Execute, Parse, ParseComplete,
     Sync as PgSync, TARGET_TYPE_BYTE_PORTAL, TARGET_TYPE_BYTE_STATEMENT,
 };
-use crate::messages::response::{EmptyQueryResponse, ReadyForQuery, READY_STATUS_IDLE};
+use crate::messages::function::FunctionCall;
+use crate::messages::response::{EmptyQueryResponse, ReadyForQuery};
 use crate::messages::simplequery::Query;
 use crate::messages::PgWireBackendMessage;
 
@@ -26,6 +36,24 @@ fn is_empty_query(q: &str) -> bool {
     trimmed_query == ";" || trimmed_query.is_empty()
 }
 
+fn copy_not_supported_error() -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "0A000".to_owned(),
+        "COPY FROM STDIN is not supported".to_owned(),
+    )))
+}
+
+fn rewrite_notice(original: &str, rewritten: &str) -> PgWireBackendMessage {
+    let mut info = ErrorInfo::new(
+        "NOTICE".to_owned(),
+        "00000".to_owned(),
+        format!("query rewritten to: {rewritten}"),
+    );
+    info.detail = Some(format!("original query: {original}"));
+    PgWireBackendMessage::NoticeResponse(info.into())
+}
+
 /// handler for processing simple query.
 #[async_trait]
 pub trait SimpleQueryHandler: Send + Sync {
@@ -35,20 +63,65 @@ pub trait SimpleQueryHandler: Send + Sync {
     ///
     /// This handle checks empty query by default, if the query string is empty
     /// or `;`, it returns `EmptyQueryResponse` and does not call `self.do_query`.
-    async fn on_query<C>(&self, client: &mut C, query: Query) -> PgWireResult<()>
+    ///
+    /// Simple query has no `Sync`, the messages sent for each `Query` are
+    /// guaranteed to be, for each `Response` in order:
+    ///
+    /// - `Response::Query`: `RowDescription`, zero or more `DataRow`, then
+    ///   `CommandComplete` with the row count
+    /// - `Response::Execution`: `CommandComplete`
+    /// - `Response::EmptyQuery`: `EmptyQueryResponse`
+    /// - `Response::Error`: `ErrorResponse`, remaining responses are discarded
+    /// - `Response::CopyIn`: `CopyInResponse`, remaining responses are
+    ///   discarded and the connection enters copy mode, see `on_copy_data`
+    ///
+    /// and terminated by a single `ReadyForQuery`, sent after the copy ends
+    /// for `Response::CopyIn`, with the status of
+    /// `ClientInfo::transaction_status`. Parameters changed by `SET LOCAL` are
+    /// restored before it if the transaction has ended, see
+    /// `set::reset_local_parameters`. When `do_query` returns an `Err`, the
+    /// error is sent by the server loop as an `ErrorResponse` followed by
+    /// `ReadyForQuery`.
+    ///
+    /// Rows of `Response::Query` are streamed as in extended query: the row
+    /// stream is polled one row at a time while rows are written to client,
+    /// with the same `FlushPolicy` and `RowLimit`, so a large result, e.g. of
+    /// `SELECT * FROM huge_table` from `psql`, is sent with bounded memory.
+    /// Simple query has no result formats, so its rows should be encoded with
+    /// `FieldFormat::Text`.
+    ///
+    /// With `handle_reset_statements` enabled, a `DEALLOCATE` or `DISCARD
+    /// ALL` query, after `rewrite_query`, is passed to `on_reset` instead of
+    /// `do_query`.
+    async fn on_query<C>(&self, client: &mut C, mut query: Query) -> PgWireResult<()>
     where
-        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore,
         C::Error: Debug,
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
         client.set_state(super::PgWireConnectionState::QueryInProgress);
+        if let Cow::Owned(query_string) = self.rewrite_query(&query.query) {
+            if self.notice_rewrite() {
+                client
+                    .feed(rewrite_notice(&query.query, &query_string))
+                    .await?;
+            }
+            query.query = query_string;
+        }
         let query_string = query.query;
         if is_empty_query(&query_string) {
             client
                 .feed(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
                 .await?;
         } else {
-            let resp = self.do_query(client, &query_string).await?;
+            let started = Instant::now();
+            let reset_statement =
+                ResetStatement::parse(&query_string).filter(|_| self.handle_reset_statements());
+            let resp = match reset_statement {
+                Some(statement) => vec![self.on_reset(client, statement).await?],
+                None => self.do_query(client, &query_string).await?,
+            };
             for r in resp {
                 match r {
                     Response::EmptyQuery => {
@@ -66,14 +139,33 @@ pub trait SimpleQueryHandler: Send + Sync {
                         client
                             .feed(PgWireBackendMessage::ErrorResponse((*e).into()))
                             .await?;
+                        if client.transaction_status() == TransactionStatus::Transaction {
+                            client.set_transaction_status(TransactionStatus::Error);
+                        }
+                        // postgres aborts remaining statements of the query
+                        // string on error
+                        break;
                     }
+                    Response::CopyIn(copy) => {
+                        // the query completes with the copy, in `on_copy_done`
+                        send_copy_in_response(client, copy).await?;
+                        client.set_state(super::PgWireConnectionState::CopyInProgress(false));
+                        return Ok(());
+                    }
+                }
+            }
+            if let Some(threshold) = self.slow_query_threshold() {
+                let elapsed = started.elapsed();
+                if elapsed >= threshold {
+                    self.on_slow_query(&query_string, elapsed);
                 }
             }
         }
 
+        reset_local_parameters(client).await?;
         client
             .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
+                client.transaction_status().ready_status(),
             )))
             .await?;
         client.flush().await?;
@@ -81,6 +173,145 @@ pub trait SimpleQueryHandler: Send + Sync {
         Ok(())
     }
 
+    /// Rewrite the incoming query string before it's checked and passed to
+    /// `do_query`.
+    ///
+    /// This is a general-purpose hook for compatibility layers, for example,
+    /// to translate `SHOW` into a query or strip unsupported clauses. The
+    /// default implementation returns the query as is.
+    fn rewrite_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        Cow::Borrowed(query)
+    }
+
+    /// Return true to send a `NoticeResponse` with the original and the
+    /// rewritten query, whenever `rewrite_query` changes a query.
+    ///
+    /// This is a debugging aid to see what the server actually executes,
+    /// disabled by default.
+    fn notice_rewrite(&self) -> bool {
+        false
+    }
+
+    /// Report queries taking at least this long to `on_slow_query`, disabled
+    /// by default.
+    ///
+    /// The time is measured from calling `do_query` until all of its
+    /// responses are sent, including streaming the rows. So a slow client
+    /// can make a query slow as well, just like `log_min_duration_statement`
+    /// of postgres.
+    fn slow_query_threshold(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called with the query string and elapsed time of a query slower than
+    /// `slow_query_threshold`. The default implementation logs a warning.
+    fn on_slow_query(&self, query: &str, elapsed: Duration) {
+        warn!("slow query, took {elapsed:?}: {query}");
+    }
+
+    /// Executed on `FunctionCall` request, the legacy fast-path interface
+    /// libpq uses for its large object functions.
+    ///
+    /// The implementation should respond with `FunctionCallResponse`
+    /// followed by `ReadyForQuery`, e.g. with `LargeObjectHandler` from
+    /// `largeobject` module. An `Err` is sent by the server loop as an
+    /// `ErrorResponse` followed by `ReadyForQuery`. The default
+    /// implementation rejects all calls.
+    async fn on_function_call<C>(&self, _client: &mut C, call: FunctionCall) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            format!(
+                "function call of OID {} is not supported",
+                call.function_oid
+            ),
+        ))))
+    }
+
+    /// Executed on `CopyData` of `COPY ... FROM STDIN`, started by
+    /// `Response::CopyIn` from `do_query` of this handler or of
+    /// `ExtendedQueryHandler`.
+    ///
+    /// Data is split into messages at arbitrary boundaries, see `copy_reader`
+    /// to read rows across them. An `Err` ends the copy: the server loop
+    /// sends it as `ErrorResponse` and drops the rest of the copy data, as
+    /// postgres does. A copy cancelled by `CancelRequest` is ended by the
+    /// server loop with `57014` error, before this is called. The default
+    /// implementation rejects the copy.
+    async fn on_copy_data<C>(&self, _client: &mut C, _copy_data: CopyData) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(copy_not_supported_error())
+    }
+
+    /// Executed on `CopyDone`, once all data of the copy is received.
+    ///
+    /// The implementation should store the data and send `CommandComplete`
+    /// with `copy_tag`. The server loop then sends `ReadyForQuery` for a copy
+    /// started by simple query, extended query waits for `Sync` as usual. The
+    /// default implementation rejects the copy.
+    async fn on_copy_done<C>(&self, _client: &mut C, _copy_done: CopyDone) -> PgWireResult<()>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(copy_not_supported_error())
+    }
+
+    /// Executed on `CopyFail`, when client aborts the copy. Return the error
+    /// sent to client, data already received should be discarded.
+    ///
+    /// The default implementation returns `57014 COPY from stdin failed`
+    /// with the message of client, as postgres does.
+    async fn on_copy_fail<C>(&self, _client: &mut C, copy_fail: CopyFail) -> PgWireError
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        copy_failed_error(&copy_fail)
+    }
+
+    /// Return true to have `on_query` apply simple queries of `DEALLOCATE`
+    /// and `DISCARD ALL` to the portal store of the connection with
+    /// `on_reset`, instead of passing them to `do_query`.
+    ///
+    /// Disabled by default, as handlers keeping prepared statements of their
+    /// own, or forwarding queries like a proxy, need to see these statements
+    /// in `do_query`.
+    fn handle_reset_statements(&self) -> bool {
+        false
+    }
+
+    /// Executed instead of `do_query` on a simple query of `DEALLOCATE` or
+    /// `DISCARD ALL`, when `handle_reset_statements` is enabled.
+    ///
+    /// The default implementation applies the statement with
+    /// `session::on_reset`. Override it to drop session state of your own as
+    /// well, e.g. on `DISCARD ALL`.
+    async fn on_reset<C>(
+        &self,
+        client: &mut C,
+        statement: ResetStatement,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::PortalStore: PortalStore,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        on_reset(client, &statement)
+    }
+
     /// Provide your query implementation using the incoming query string.
     async fn do_query<'a, 'b: 'a, C>(
         &'b self,
@@ -101,19 +332,80 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// Get a reference to associated `QueryParser` implementation
     fn query_parser(&self) -> Arc<Self::QueryParser>;
 
+    /// Rewrite the query string of `Parse` before it's passed to
+    /// `Self::QueryParser`.
+    ///
+    /// See `SimpleQueryHandler::rewrite_query`. The default implementation
+    /// returns the query as is.
+    fn rewrite_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+        Cow::Borrowed(query)
+    }
+
+    /// See `SimpleQueryHandler::notice_rewrite`, the notice is sent ahead of
+    /// `ParseComplete`. Disabled by default.
+    fn notice_rewrite(&self) -> bool {
+        false
+    }
+
+    /// See `SimpleQueryHandler::slow_query_threshold`, the time is measured
+    /// for each `Execute`. Disabled by default.
+    fn slow_query_threshold(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called with the executed portal and elapsed time of an `Execute`
+    /// slower than `slow_query_threshold`.
+    ///
+    /// The default implementation logs a warning with names of the statement
+    /// and portal, as `Self::Statement` isn't printable. Override it to log
+    /// the statement itself.
+    fn on_slow_query(&self, portal: &Portal<Self::Statement>, elapsed: Duration) {
+        warn!(
+            "slow query, took {elapsed:?}: statement {:?}, portal {:?}",
+            portal.statement.id, portal.name
+        );
+    }
+
+    /// Inspect a statement parsed from `Parse` before it's stored, return an
+    /// error to reject it.
+    ///
+    /// This is a policy hook, for example to enforce an allowlist of
+    /// statements or to restrict what tenants can run. The error is sent to
+    /// client as `ErrorResponse`, return `PgWireError::UserError` for a custom
+    /// error like `42501 permission denied`. The default implementation
+    /// accepts all statements.
+    async fn check_statement<C>(
+        &self,
+        _client: &C,
+        _statement: &StoredStatement<Self::Statement>,
+    ) -> PgWireResult<()>
+    where
+        C: ClientInfo + Send + Sync,
+    {
+        Ok(())
+    }
+
     /// Called when client sends `parse` command.
     ///
-    /// The default implementation parsed query with `Self::QueryParser` and
-    /// stores it in `Self::PortalStore`.
-    async fn on_parse<C>(&self, client: &mut C, message: Parse) -> PgWireResult<()>
+    /// The default implementation rewrites the query with
+    /// `self::rewrite_query`, parses it with `Self::QueryParser`, checks it
+    /// with `self::check_statement` and stores it in `Self::PortalStore`.
+    async fn on_parse<C>(&self, client: &mut C, mut message: Parse) -> PgWireResult<()>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
         C::PortalStore: PortalStore<Statement = Self::Statement>,
         C::Error: Debug,
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
+        if let Cow::Owned(query) = self.rewrite_query(&message.query) {
+            if self.notice_rewrite() {
+                client.feed(rewrite_notice(&message.query, &query)).await?;
+            }
+            message.query = query;
+        }
         let parser = self.query_parser();
         let stmt = StoredStatement::parse(&message, parser).await?;
+        self.check_statement(client, &stmt).await?;
         client.portal_store().put_statement(Arc::new(stmt));
         client
             .send(PgWireBackendMessage::ParseComplete(ParseComplete::new()))
@@ -155,6 +447,17 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// Note that, different from `SimpleQueryHandler`, this implementation
     /// won't check empty query because it cannot understand parsed
     /// `Self::Statement`.
+    ///
+    /// `Response::Error`, and errors from `do_query` or the row stream, are
+    /// returned as `Err`. The server loop then sends `ErrorResponse` and
+    /// discards messages until `Sync`. If the error happens after some rows
+    /// were sent, the `ErrorResponse` follows the partial rows and no
+    /// `CommandComplete` is sent, so client discards the result.
+    ///
+    /// `RowDescription` is never sent on `Execute`, it's the response of
+    /// `Describe`, which clients needing it send first. A handler overriding
+    /// `on_execute` must not send it either, or binary clients lose track of
+    /// the message flow.
     async fn on_execute<C>(&self, client: &mut C, message: Execute) -> PgWireResult<()>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
@@ -164,6 +467,7 @@ pub trait ExtendedQueryHandler: Send + Sync {
     {
         let portal_name = message.name.as_deref().unwrap_or(DEFAULT_NAME);
         if let Some(portal) = client.portal_store().get_portal(portal_name) {
+            let started = Instant::now();
             match self
                 .do_query(client, portal.as_ref(), message.max_rows as usize)
                 .await?
@@ -174,15 +478,24 @@ pub trait ExtendedQueryHandler: Send + Sync {
                         .await?;
                 }
                 Response::Query(results) => {
+                    // `RowDescription` belongs to `Describe`
                     send_query_response(client, results, false).await?;
                 }
                 Response::Execution(tag) => {
                     send_execution_response(client, tag).await?;
                 }
                 Response::Error(err) => {
-                    client
-                        .send(PgWireBackendMessage::ErrorResponse((*err).into()))
-                        .await?;
+                    return Err(PgWireError::UserError(err));
+                }
+                Response::CopyIn(copy) => {
+                    send_copy_in_response(client, copy).await?;
+                    client.set_state(PgWireConnectionState::CopyInProgress(true));
+                }
+            }
+            if let Some(threshold) = self.slow_query_threshold() {
+                let elapsed = started.elapsed();
+                if elapsed >= threshold {
+                    self.on_slow_query(&portal, elapsed);
                 }
             }
 
@@ -195,6 +508,9 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// Called when client sends `describe` command.
     ///
     /// The default implementation delegates the call to `self::do_describe`.
+    /// Format codes of the described fields are overridden to match what
+    /// will be sent: the result formats requested in `Bind` for a portal, text
+    /// for a statement.
     async fn on_describe<C>(&self, client: &mut C, message: Describe) -> PgWireResult<()>
     where
         C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
@@ -206,9 +522,13 @@ pub trait ExtendedQueryHandler: Send + Sync {
         match message.target_type {
             TARGET_TYPE_BYTE_STATEMENT => {
                 if let Some(stmt) = client.portal_store().get_statement(name) {
-                    let describe_response = self
+                    let mut describe_response = self
                         .do_describe(client, StatementOrPortal::Statement(&stmt))
                         .await?;
+                    // result formats are not known until `Bind`, postgres
+                    // always reports text for statements
+                    describe_response.fields =
+                        Format::UnifiedText.apply(describe_response.fields)?;
                     send_describe_response(client, &describe_response).await?;
                 } else {
                     return Err(PgWireError::StatementNotFound(name.to_owned()));
@@ -216,9 +536,13 @@ pub trait ExtendedQueryHandler: Send + Sync {
             }
             TARGET_TYPE_BYTE_PORTAL => {
                 if let Some(portal) = client.portal_store().get_portal(name) {
-                    let describe_response = self
+                    let mut describe_response = self
                         .do_describe(client, StatementOrPortal::Portal(&portal))
                         .await?;
+                    // declare the formats rows of this portal are encoded in
+                    describe_response.fields = portal
+                        .result_column_format
+                        .apply(describe_response.fields)?;
                     send_describe_response(client, &describe_response).await?;
                 } else {
                     return Err(PgWireError::PortalNotFound(name.to_owned()));
@@ -233,16 +557,20 @@ pub trait ExtendedQueryHandler: Send + Sync {
     /// Called when client sends `sync` command.
     ///
     /// The default implementation flushes client buffer and sends
-    /// `READY_FOR_QUERY` response to client
+    /// `READY_FOR_QUERY` response to client, with the transaction status of
+    /// `ClientInfo::transaction_status`. Parameters changed by `SET LOCAL` are
+    /// restored first if the transaction has ended, see
+    /// `set::reset_local_parameters`.
     async fn on_sync<C>(&self, client: &mut C, _message: PgSync) -> PgWireResult<()>
     where
         C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
         C::Error: Debug,
         PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
     {
+        reset_local_parameters(client).await?;
         client
             .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
+                client.transaction_status().ready_status(),
             )))
             .await?;
         client.flush().await?;
@@ -321,8 +649,32 @@ where
     C::Error: Debug,
     PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
 {
+    send_query_response_counted(client, results, send_describe, 0).await
+}
+
+/// `send_query_response`, with the row counter of the command tag starting
+/// at `rows`.
+async fn send_query_response_counted<'a, C>(
+    client: &mut C,
+    results: QueryResponse<'a>,
+    send_describe: bool,
+    mut rows: u64,
+) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    let results = results.resolve().await?;
     let row_schema = results.row_schema();
-    let mut data_rows = results.data_rows();
+    let flush_policy = results.flush_policy();
+    // the lower of server-wide and response's own limit
+    let row_limit = match (client.max_result_rows(), results.row_limit()) {
+        (Some(server), Some(response)) if server.max_rows < response.max_rows => Some(server),
+        (server, response) => response.or(server),
+    };
+    let command_tag = results.command_tag().clone();
+    let mut items = results.items();
 
     // Simple query has row_schema in query response. For extended query,
     // row_schema is returned as response of `Describe`.
@@ -333,14 +685,81 @@ where
             .await?;
     }
 
-    let mut rows = 0;
-    while let Some(row) = data_rows.next().await {
-        let row = row?;
-        rows += 1;
-        client.feed(PgWireBackendMessage::DataRow(row)).await?;
+    // `rows` is u64 so the count doesn't overflow on 32-bit targets for huge
+    // scans
+    // rows fed but not yet flushed, and when they must be flushed
+    let mut buffered_rows = 0;
+    let mut flush_deadline = None;
+    loop {
+        let item = if let Some(deadline) = flush_deadline {
+            match tokio::time::timeout_at(deadline, items.next()).await {
+                Ok(item) => item,
+                Err(_) => {
+                    client.flush().await?;
+                    buffered_rows = 0;
+                    flush_deadline = None;
+                    continue;
+                }
+            }
+        } else {
+            items.next().await
+        };
+        let Some(item) = item else {
+            break;
+        };
+
+        match item? {
+            RowOrNotice::Row(row) => {
+                if let Some(limit) = row_limit.filter(|limit| rows >= limit.max_rows as u64) {
+                    let message = format!("query returned more than {} rows", limit.max_rows);
+                    match limit.policy {
+                        RowLimitPolicy::Error => {
+                            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                                "ERROR".to_owned(),
+                                "54000".to_owned(),
+                                message,
+                            ))));
+                        }
+                        RowLimitPolicy::Truncate => {
+                            let notice = ErrorInfo::new(
+                                "WARNING".to_owned(),
+                                "01000".to_owned(),
+                                format!("{message}, result truncated"),
+                            );
+                            client
+                                .feed(PgWireBackendMessage::NoticeResponse(notice.into()))
+                                .await?;
+                            break;
+                        }
+                    }
+                }
+
+                rows += 1;
+                client.feed(PgWireBackendMessage::DataRow(row)).await?;
+
+                buffered_rows += 1;
+                if flush_policy
+                    .max_rows
+                    .is_some_and(|max| buffered_rows >= max)
+                {
+                    client.flush().await?;
+                    buffered_rows = 0;
+                    flush_deadline = None;
+                } else if buffered_rows == 1 {
+                    flush_deadline = flush_policy
+                        .max_delay
+                        .map(|delay| tokio::time::Instant::now() + delay);
+                }
+            }
+            RowOrNotice::Notice(notice) => {
+                client
+                    .feed(PgWireBackendMessage::NoticeResponse(notice))
+                    .await?;
+            }
+        }
     }
 
-    let tag = Tag::new("SELECT").with_rows(rows);
+    let tag = command_tag.with_default_rows(rows);
     client
         .send(PgWireBackendMessage::CommandComplete(tag.into()))
         .await?;
@@ -392,6 +811,76 @@ where
     Ok(())
 }
 
+/// Helper function to send a standalone `RowDescription`.
+///
+/// This is for handlers that describe a result set ahead of its rows, like
+/// server-side cursors that send `RowDescription` on `Describe` and stream
+/// rows later on `Execute` with `send_query_response(client, results, false)`.
+///
+/// Return `PgWireError::InvalidConnectionState` if the connection is not
+/// processing queries, i.e. during startup or while waiting for `Sync` after
+/// an error.
+pub async fn send_row_description<C>(client: &mut C, fields: &[FieldInfo]) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    match client.state() {
+        PgWireConnectionState::ReadyForQuery | PgWireConnectionState::QueryInProgress => {
+            client
+                .send(PgWireBackendMessage::RowDescription(into_row_description(
+                    fields,
+                )))
+                .await?;
+            Ok(())
+        }
+        _ => Err(PgWireError::InvalidConnectionState(
+            "RowDescription".to_owned(),
+        )),
+    }
+}
+
+/// Send `ReadyForQuery` with transaction `status` out of the normal message
+/// flow, to resynchronize a client that is out of step with the server.
+///
+/// **Only use it for recovery**, e.g. in a proxy when an upstream failed in
+/// the middle of a response. `ReadyForQuery` is otherwise sent by `on_query`
+/// and `on_sync`, an extra one makes a well-behaved client treat the
+/// responses of its next query as those of the current one. The connection
+/// is put back to `ReadyForQuery` state, dropping a pending wait for `Sync`,
+/// and its transaction status is set to `status`.
+///
+/// Return `PgWireError::InvalidConnectionState` during startup, before the
+/// client expects any `ReadyForQuery`.
+pub async fn force_ready_for_query<C>(client: &mut C, status: TransactionStatus) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    match client.state() {
+        PgWireConnectionState::AwaitingStartup
+        | PgWireConnectionState::AuthenticationInProgress => Err(
+            PgWireError::InvalidConnectionState("ReadyForQuery".to_owned()),
+        ),
+        state => {
+            warn!(
+                "forcing ReadyForQuery to {} in state {state:?}",
+                client.socket_addr()
+            );
+            client
+                .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                    status.ready_status(),
+                )))
+                .await?;
+            client.set_state(PgWireConnectionState::ReadyForQuery);
+            client.set_transaction_status(status);
+            Ok(())
+        }
+    }
+}
+
 /// An enum holds borrowed statement or portal
 #[derive(Debug)]
 pub enum StatementOrPortal<'a, S> {
@@ -437,3 +926,1163 @@ impl ExtendedQueryHandler for PlaceholderExtendedQueryHandler {
         unimplemented!("Extended Query is not implemented on this server.")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use bytes::{Bytes, BytesMut};
+    use futures::stream;
+    use postgres_types::{Kind, Oid};
+
+    use super::*;
+    use crate::api::results::{DataRowEncoder, FieldFormat, FieldInfo, FlushPolicy, RowLimit, Tag};
+    use crate::api::testing::RecordingClient;
+    use crate::api::{PgWireConnectionState, Type};
+    use crate::messages::data::DataRow;
+    use crate::messages::response::{
+        NoticeResponse, READY_STATUS_FAILED_TRANSACTION_BLOCK, READY_STATUS_TRANSACTION_BLOCK,
+    };
+
+    struct SimpleHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for SimpleHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "a".into(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let resp = match query {
+                "SELECT 1 AS a" => {
+                    let mut encoder = DataRowEncoder::new(schema.clone());
+                    encoder.encode_field(&1i32)?;
+                    let rows = vec![encoder.finish()];
+                    Response::Query(QueryResponse::new(schema, stream::iter(rows)))
+                }
+                "SELECT 1 AS a WHERE false" => {
+                    Response::Query(QueryResponse::new(schema, stream::empty()))
+                }
+                "SET a = 1" => Response::Execution(Tag::new("SET")),
+                "SELEC 1" => {
+                    // as reported by postgres
+                    let mut info = ErrorInfo::new(
+                        "ERROR".to_owned(),
+                        "42601".to_owned(),
+                        "syntax error at or near \"SELEC\"".to_owned(),
+                    );
+                    info.position = Some("1".to_owned());
+                    info.file_name = Some("scan.l".to_owned());
+                    info.line = Some(1188);
+                    info.routine = Some("scanner_yyerror".to_owned());
+                    Response::Error(Box::new(info))
+                }
+                _ => Response::Error(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42601".to_owned(),
+                    "syntax error".to_owned(),
+                ))),
+            };
+            // mimic a multi-statement query string
+            Ok(vec![resp, Response::Execution(Tag::new("SET"))])
+        }
+
+        fn rewrite_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+            if query.eq_ignore_ascii_case("SHOW a") {
+                Cow::Owned("SELECT 1 AS a".to_owned())
+            } else {
+                Cow::Borrowed(query)
+            }
+        }
+    }
+
+    struct NoticeRewriteHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for NoticeRewriteHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            SimpleHandler.do_query(client, query).await
+        }
+
+        fn rewrite_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+            SimpleHandler.rewrite_query(query)
+        }
+
+        fn notice_rewrite(&self) -> bool {
+            true
+        }
+    }
+
+    struct ResetHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for ResetHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            SimpleHandler.do_query(client, query).await
+        }
+
+        fn rewrite_query<'q>(&self, query: &'q str) -> Cow<'q, str> {
+            match query {
+                "RESET SESSION" => Cow::Owned("DISCARD ALL".to_owned()),
+                _ => Cow::Borrowed(query),
+            }
+        }
+
+        fn handle_reset_statements(&self) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_statements() {
+        // passed to do_query unless enabled
+        let mut client = RecordingClient::<String>::new();
+        SimpleHandler
+            .on_query(&mut client, Query::new("DISCARD ALL".to_owned()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.messages().unwrap()[0],
+            PgWireBackendMessage::ErrorResponse(_)
+        ));
+
+        // applied after rewrite_query
+        let mut client = RecordingClient::<String>::new();
+        ResetHandler
+            .on_query(&mut client, Query::new("RESET SESSION".to_owned()))
+            .await
+            .unwrap();
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::CommandComplete(cc), PgWireBackendMessage::ReadyForQuery(_)] => {
+                assert_eq!("DISCARD ALL", cc.tag)
+            }
+            messages => panic!("unexpected messages {messages:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notice_rewrite() {
+        let mut client = RecordingClient::<String>::new();
+        NoticeRewriteHandler
+            .on_query(&mut client, Query::new("SHOW a".to_owned()))
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        match &messages[0] {
+            PgWireBackendMessage::NoticeResponse(notice) => {
+                assert!(notice
+                    .fields
+                    .contains(&(b'M', "query rewritten to: SELECT 1 AS a".to_owned())));
+                assert!(notice
+                    .fields
+                    .contains(&(b'D', "original query: SHOW a".to_owned())));
+            }
+            _ => panic!("expect NoticeResponse"),
+        }
+        assert!(matches!(
+            messages[1],
+            PgWireBackendMessage::RowDescription(_)
+        ));
+
+        // no notice if the query is kept as is
+        let mut client = RecordingClient::<String>::new();
+        NoticeRewriteHandler
+            .on_query(&mut client, Query::new("SELECT 1 AS a".to_owned()))
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.messages().unwrap()[0],
+            PgWireBackendMessage::RowDescription(_)
+        ));
+    }
+
+    struct SlowQueryHandler {
+        threshold: Duration,
+        slow_queries: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for SlowQueryHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            SimpleHandler.do_query(client, query).await
+        }
+
+        fn slow_query_threshold(&self) -> Option<Duration> {
+            Some(self.threshold)
+        }
+
+        fn on_slow_query(&self, query: &str, _elapsed: Duration) {
+            self.slow_queries.lock().unwrap().push(query.to_owned());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_query() {
+        let handler = SlowQueryHandler {
+            threshold: Duration::ZERO,
+            slow_queries: Default::default(),
+        };
+        let mut client = RecordingClient::<String>::new();
+        handler
+            .on_query(&mut client, Query::new("SELECT 1 AS a".to_owned()))
+            .await
+            .unwrap();
+        // empty query isn't dispatched to `do_query`
+        handler
+            .on_query(&mut client, Query::new(";".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(
+            vec!["SELECT 1 AS a".to_owned()],
+            *handler.slow_queries.lock().unwrap()
+        );
+
+        let handler = SlowQueryHandler {
+            threshold: Duration::from_secs(60),
+            slow_queries: Default::default(),
+        };
+        handler
+            .on_query(&mut client, Query::new("SELECT 1 AS a".to_owned()))
+            .await
+            .unwrap();
+        assert!(handler.slow_queries.lock().unwrap().is_empty());
+    }
+
+    async fn simple_query_bytes(query: &str) -> BytesMut {
+        let mut client = RecordingClient::<String>::new();
+        SimpleHandler
+            .on_query(&mut client, Query::new(query.to_owned()))
+            .await
+            .unwrap();
+        client.take_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_simple_query_message_sequence() {
+        // bytes sent by postgres 15 for the same query strings, followed by
+        // `; SET search_path = public` as the second statement
+        let row_desc: &[u8] =
+            b"T\0\0\0\x1a\0\x01a\0\0\0\0\0\0\0\0\0\0\x17\0\x04\xff\xff\xff\xff\0\0";
+        let set_complete: &[u8] = b"C\0\0\0\x08SET\0";
+        let ready: &[u8] = b"Z\0\0\0\x05I";
+
+        let expected = [
+            row_desc,
+            b"D\0\0\0\x0b\0\x01\0\0\0\x011",
+            b"C\0\0\0\x0dSELECT 1\0",
+            set_complete,
+            ready,
+        ]
+        .concat();
+        assert_eq!(expected, simple_query_bytes("SELECT 1 AS a").await.as_ref());
+
+        let expected = [row_desc, b"C\0\0\0\x0dSELECT 0\0", set_complete, ready].concat();
+        assert_eq!(
+            expected,
+            simple_query_bytes("SELECT 1 AS a WHERE false")
+                .await
+                .as_ref()
+        );
+
+        assert_eq!(
+            simple_query_bytes("SELECT 1 AS a").await,
+            simple_query_bytes("show a").await
+        );
+
+        let expected = [set_complete, set_complete, ready].concat();
+        assert_eq!(expected, simple_query_bytes("SET a = 1").await.as_ref());
+
+        let expected = [b"I\0\0\0\x04".as_ref(), ready].concat();
+        assert_eq!(expected, simple_query_bytes(" ; ").await.as_ref());
+
+        // statements after an error are not executed
+        let expected = [
+            b"E\0\0\0]SERROR\0VERROR\0C42601\0Msyntax error at or near \"SELEC\"\0P1\0Fscan.l\0L1188\0Rscanner_yyerror\0\0".as_ref(),
+            ready,
+        ]
+        .concat();
+        assert_eq!(expected, simple_query_bytes("SELEC 1").await.as_ref());
+    }
+
+    /// Streams `rows` text rows, checking that each row is generated only
+    /// after the previous one was sent to client.
+    struct StreamingHandler {
+        rows: usize,
+        sent_rows: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for StreamingHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "name".into(),
+                None,
+                None,
+                Type::VARCHAR,
+                FieldFormat::Text,
+            )]);
+            let row_schema = schema.clone();
+            let sent_rows = self.sent_rows.clone();
+            let rows = stream::iter(0..self.rows).map(move |i| {
+                // RowDescription and the previous rows are sent already
+                assert_eq!(i + 1, sent_rows.load(Ordering::SeqCst));
+                let mut encoder = DataRowEncoder::new(row_schema.clone());
+                encoder.encode_field(&format!("row {i}"))?;
+                encoder.finish()
+            });
+            Ok(vec![Response::Query(QueryResponse::new(schema, rows))])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simple_query_streaming() {
+        let mut client = RecordingClient::<String>::new();
+        let handler = StreamingHandler {
+            rows: 100_000,
+            sent_rows: client.sent_count(),
+        };
+        handler
+            .on_query(
+                &mut client,
+                Query::new("SELECT * FROM huge_table".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let messages = client.messages().unwrap();
+        assert_eq!(100_003, messages.len());
+        assert!(matches!(
+            messages[0],
+            PgWireBackendMessage::RowDescription(ref desc) if desc.fields[0].format_code == 0
+        ));
+        if let PgWireBackendMessage::DataRow(ref row) = messages[100_000] {
+            assert_eq!(Some(Bytes::from("row 99999")), row.fields[0]);
+        } else {
+            panic!("expect DataRow, got {:?}", messages[100_000]);
+        }
+        if let PgWireBackendMessage::CommandComplete(ref cc) = messages[100_001] {
+            assert_eq!("SELECT 100000", cc.tag);
+        } else {
+            panic!("expect CommandComplete, got {:?}", messages[100_001]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notice_between_data_rows() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        let row = |v: i32| {
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            encoder.encode_field(&v).unwrap();
+            RowOrNotice::Row(encoder.finish().unwrap())
+        };
+        let notice = NoticeResponse::from(ErrorInfo::new(
+            "NOTICE".to_owned(),
+            "01000".to_owned(),
+            "half way".to_owned(),
+        ));
+        let items = vec![Ok(row(1)), Ok(RowOrNotice::Notice(notice)), Ok(row(2))];
+
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new_with_notices(schema.clone(), stream::iter(items));
+        send_query_response(&mut client, response, true)
+            .await
+            .unwrap();
+
+        let messages = client.messages().unwrap();
+        assert_eq!(5, messages.len());
+        assert!(matches!(
+            messages[0],
+            PgWireBackendMessage::RowDescription(_)
+        ));
+        assert!(matches!(messages[1], PgWireBackendMessage::DataRow(_)));
+        assert!(matches!(
+            messages[2],
+            PgWireBackendMessage::NoticeResponse(_)
+        ));
+        assert!(matches!(messages[3], PgWireBackendMessage::DataRow(_)));
+        if let PgWireBackendMessage::CommandComplete(ref cc) = messages[4] {
+            assert_eq!("SELECT 2", cc.tag);
+        } else {
+            panic!("expect CommandComplete, got {:?}", messages[4]);
+        }
+    }
+
+    fn int_rows(schema: &Arc<Vec<FieldInfo>>, n: i32) -> Vec<PgWireResult<DataRow>> {
+        (0..n)
+            .map(|i| {
+                let mut encoder = DataRowEncoder::new(schema.clone());
+                encoder.encode_field(&i)?;
+                encoder.finish()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_large_row_count() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+        // a scan past the 32-bit boundary, without producing all the rows
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 3)));
+        send_query_response_counted(&mut client, response, false, u32::MAX as u64)
+            .await
+            .unwrap();
+        match client.messages().unwrap().last() {
+            Some(PgWireBackendMessage::CommandComplete(cc)) => {
+                assert_eq!("SELECT 4294967298", cc.tag)
+            }
+            _ => panic!("expect CommandComplete"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_policy() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        // no policy: rows are only flushed with CommandComplete
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        assert_eq!(1, client.flushes());
+
+        // flush every 2 rows, then with CommandComplete
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)))
+            .with_flush_policy(FlushPolicy::new(Some(2), None));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        assert_eq!(3, client.flushes());
+
+        // slow stream: buffered row is flushed before the next one arrives
+        let mut client = RecordingClient::<String>::new();
+        let slow_rows = stream::iter(int_rows(&schema, 3))
+            .then(|row| async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                row
+            })
+            .boxed();
+        let response = QueryResponse::new(schema.clone(), slow_rows)
+            .with_flush_policy(FlushPolicy::new(Some(100), Some(Duration::from_millis(1))));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        assert_eq!(3, client.flushes());
+        assert_eq!(4, client.messages().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_row_limit() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        // within the limit
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 3)))
+            .with_row_limit(RowLimit::new(3, RowLimitPolicy::Error));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        assert_eq!(4, client.messages().unwrap().len());
+
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)))
+            .with_row_limit(RowLimit::new(3, RowLimitPolicy::Error));
+        match send_query_response(&mut client, response, false).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("54000", e.code),
+            _ => panic!("expect program_limit_exceeded error"),
+        }
+
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)))
+            .with_row_limit(RowLimit::new(3, RowLimitPolicy::Truncate));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(5, messages.len());
+        assert!(matches!(
+            messages[3],
+            PgWireBackendMessage::NoticeResponse(_)
+        ));
+        match &messages[4] {
+            PgWireBackendMessage::CommandComplete(cc) => assert_eq!("SELECT 3", cc.tag),
+            _ => panic!("expect CommandComplete"),
+        }
+
+        // server-wide limit, applied when lower than the response's
+        let mut client = RecordingClient::<String>::new();
+        client.max_result_rows = Some(RowLimit::new(2, RowLimitPolicy::Error));
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)))
+            .with_row_limit(RowLimit::new(3, RowLimitPolicy::Truncate));
+        match send_query_response(&mut client, response, false).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("54000", e.code),
+            _ => panic!("expect program_limit_exceeded error"),
+        }
+
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)))
+            .with_row_limit(RowLimit::new(1, RowLimitPolicy::Truncate));
+        client.take_bytes();
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        assert_eq!(3, client.messages().unwrap().len());
+
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 5)));
+        assert!(send_query_response(&mut client, response, false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_tag() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        // row count is computed from a lazy stream of unknown length
+        let row_schema = schema.clone();
+        let rows = stream::unfold(0, |i| async move { (i < 42).then_some((i, i + 1)) })
+            .map(move |i| {
+                let mut encoder = DataRowEncoder::new(row_schema.clone());
+                encoder.encode_field(&i)?;
+                encoder.finish()
+            })
+            .boxed();
+        let mut client = RecordingClient::<String>::new();
+        send_query_response(&mut client, QueryResponse::new(schema.clone(), rows), false)
+            .await
+            .unwrap();
+        match client.messages().unwrap().last() {
+            Some(PgWireBackendMessage::CommandComplete(cc)) => assert_eq!("SELECT 42", cc.tag),
+            _ => panic!("expect CommandComplete"),
+        }
+
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 3)))
+            .with_command_tag(Tag::new("UPDATE"));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        match client.messages().unwrap().last() {
+            Some(PgWireBackendMessage::CommandComplete(cc)) => assert_eq!("UPDATE 3", cc.tag),
+            _ => panic!("expect CommandComplete"),
+        }
+
+        // explicit row count wins over rows sent
+        let mut client = RecordingClient::<String>::new();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 0)))
+            .with_command_tag(Tag::new("SELECT").with_rows(7));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+        match client.messages().unwrap().last() {
+            Some(PgWireBackendMessage::CommandComplete(cc)) => assert_eq!("SELECT 7", cc.tag),
+            _ => panic!("expect CommandComplete"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deferred_schema() {
+        // documents of a schemaless source, columns are inferred from the
+        // first one
+        let documents = stream::iter(vec![Ok(vec![("id", 1)]), Ok(vec![("id", 2)])]);
+        let response = QueryResponse::new_deferred(async move {
+            let mut documents = documents.peekable();
+            let fields = match Pin::new(&mut documents).peek().await {
+                Some(Ok(document)) => document
+                    .iter()
+                    .map(|(name, _)| {
+                        FieldInfo::new((*name).into(), None, None, Type::INT4, FieldFormat::Text)
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            let schema = Arc::new(fields);
+            let row_schema = schema.clone();
+            let rows = documents.map(move |document: PgWireResult<Vec<(&str, i32)>>| {
+                let mut encoder = DataRowEncoder::new(row_schema.clone());
+                for (_, value) in document? {
+                    encoder.encode_field(&value)?;
+                }
+                encoder.finish()
+            });
+            Ok((schema, rows))
+        });
+        assert!(response.row_schema().is_empty());
+
+        let mut client = RecordingClient::<String>::new();
+        send_query_response(&mut client, response, true)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(4, messages.len());
+        match &messages[0] {
+            PgWireBackendMessage::RowDescription(desc) => {
+                assert_eq!("id", desc.fields[0].name);
+            }
+            _ => panic!("expect RowDescription"),
+        }
+        assert!(matches!(messages[1], PgWireBackendMessage::DataRow(_)));
+        match &messages[3] {
+            PgWireBackendMessage::CommandComplete(cc) => assert_eq!("SELECT 2", cc.tag),
+            _ => panic!("expect CommandComplete"),
+        }
+
+        // nothing is sent if the schema fails
+        let response = QueryResponse::new_deferred(async {
+            Err::<(_, stream::Empty<_>), _>(PgWireError::UserNameRequired)
+        });
+        let mut client = RecordingClient::<String>::new();
+        assert!(send_query_response(&mut client, response, true)
+            .await
+            .is_err());
+        assert!(client.messages().unwrap().is_empty());
+
+        // rows are read through `data_rows` as well
+        let response = QueryResponse::new_deferred(async {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "id".into(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let rows = stream::iter(int_rows(&schema, 3));
+            Ok((schema, rows))
+        });
+        assert_eq!(3, response.data_rows().count().await);
+    }
+
+    #[tokio::test]
+    async fn test_send_describe_response() {
+        // INSERT with parameters and without RETURNING
+        let mut client = RecordingClient::<String>::new();
+        let response = DescribeResponse::new(Some(vec![Type::INT4, Type::TEXT]), vec![]);
+        send_describe_response(&mut client, &response)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(2, messages.len());
+        match &messages[0] {
+            PgWireBackendMessage::ParameterDescription(desc) => {
+                assert_eq!(vec![Type::INT4.oid(), Type::TEXT.oid()], desc.types);
+            }
+            _ => panic!("expect ParameterDescription"),
+        }
+        assert!(matches!(messages[1], PgWireBackendMessage::NoData(_)));
+
+        // columns are described even if the query returns no rows
+        let mut client = RecordingClient::<String>::new();
+        let fields = vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )];
+        let response = DescribeResponse::new(None, fields);
+        send_describe_response(&mut client, &response)
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        assert_eq!(1, messages.len());
+        assert!(matches!(
+            messages[0],
+            PgWireBackendMessage::RowDescription(_)
+        ));
+    }
+
+    struct DescribeHandler;
+
+    #[async_trait]
+    impl ExtendedQueryHandler for DescribeHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser::new())
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            let fields = Arc::new(
+                portal
+                    .result_column_format
+                    .apply(describe_fields())
+                    .unwrap(),
+            );
+            let mut encoder = DataRowEncoder::new(fields.clone());
+            for i in 1..=3 {
+                encoder.encode_field(&i)?;
+            }
+            let rows = vec![encoder.finish()];
+            Ok(Response::Query(QueryResponse::new(
+                fields,
+                stream::iter(rows),
+            )))
+        }
+
+        async fn do_describe<C>(
+            &self,
+            _client: &mut C,
+            _target: StatementOrPortal<'_, Self::Statement>,
+        ) -> PgWireResult<DescribeResponse>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            Ok(DescribeResponse::new(None, describe_fields()))
+        }
+    }
+
+    fn describe_fields() -> Vec<FieldInfo> {
+        ["a", "b", "c"]
+            .into_iter()
+            .map(|name| FieldInfo::new(name.into(), None, None, Type::INT4, FieldFormat::Binary))
+            .collect()
+    }
+
+    fn described_formats(client: &mut RecordingClient) -> Vec<i16> {
+        match client.messages().unwrap().pop() {
+            Some(PgWireBackendMessage::RowDescription(desc)) => {
+                desc.fields.iter().map(|field| field.format_code).collect()
+            }
+            _ => panic!("expect RowDescription"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_describe_formats() {
+        let mut client = RecordingClient::<String>::new();
+        let statement = Arc::new(StoredStatement::new(
+            "s".to_owned(),
+            "SELECT 1, 2, 3".to_owned(),
+            vec![],
+        ));
+        client.portal_store.put_statement(statement.clone());
+        let bind = Bind::new(
+            Some("p".to_owned()),
+            Some("s".to_owned()),
+            vec![],
+            vec![],
+            vec![0, 1, 0],
+        );
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        client.portal_store.put_portal(Arc::new(portal));
+
+        DescribeHandler
+            .on_describe(
+                &mut client,
+                Describe::new(TARGET_TYPE_BYTE_STATEMENT, Some("s".to_owned())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 0, 0], described_formats(&mut client));
+
+        DescribeHandler
+            .on_describe(
+                &mut client,
+                Describe::new(TARGET_TYPE_BYTE_PORTAL, Some("p".to_owned())),
+            )
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 1, 0], described_formats(&mut client));
+    }
+
+    #[tokio::test]
+    async fn test_describe_then_execute() {
+        let mut client = RecordingClient::<String>::new();
+        let statement = Arc::new(StoredStatement::new(
+            "s".to_owned(),
+            "SELECT 1, 2, 3".to_owned(),
+            vec![],
+        ));
+        client.portal_store.put_statement(statement.clone());
+        let bind = Bind::new(None, Some("s".to_owned()), vec![], vec![], vec![1]);
+        let portal = Portal::try_new(&bind, statement).unwrap();
+        client.portal_store.put_portal(Arc::new(portal));
+
+        DescribeHandler
+            .on_describe(&mut client, Describe::new(TARGET_TYPE_BYTE_PORTAL, None))
+            .await
+            .unwrap();
+
+        DescribeHandler
+            .on_execute(&mut client, Execute::new(None, 0))
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        let row_descriptions = messages
+            .iter()
+            .filter(|message| matches!(message, PgWireBackendMessage::RowDescription(_)))
+            .count();
+        assert_eq!(1, row_descriptions);
+        assert!(matches!(
+            messages[..],
+            [
+                PgWireBackendMessage::RowDescription(_),
+                PgWireBackendMessage::DataRow(_),
+                PgWireBackendMessage::CommandComplete(_)
+            ]
+        ));
+    }
+
+    /// OID of a custom type known to `CastParser`
+    const VECTOR_OID: Oid = 90000;
+
+    /// Parser inferring types of parameters from casts like `$1::uuid`
+    struct CastParser;
+
+    #[async_trait]
+    impl QueryParser for CastParser {
+        type Statement = String;
+
+        async fn parse_sql(&self, sql: &str, _types: &[Type]) -> PgWireResult<Self::Statement> {
+            Ok(sql.to_owned())
+        }
+
+        fn lookup_type(&self, oid: Oid) -> Option<Type> {
+            if oid == VECTOR_OID {
+                Some(Type::new(
+                    "vector".to_owned(),
+                    VECTOR_OID,
+                    Kind::Simple,
+                    "public".to_owned(),
+                ))
+            } else {
+                Type::from_oid(oid)
+            }
+        }
+
+        fn infer_parameter_types(
+            &self,
+            sql: &str,
+            _statement: &Self::Statement,
+            types: &[Type],
+        ) -> PgWireResult<Vec<Type>> {
+            let mut inferred = types.to_vec();
+            for param in sql.split('$').skip(1) {
+                let (idx, cast) = param.split_at(param.find("::").unwrap());
+                let idx = idx.parse::<usize>().unwrap() - 1;
+                let ty = match cast[2..].split(|c: char| !c.is_alphanumeric()).next() {
+                    Some("uuid") => Type::UUID,
+                    Some("timestamptz") => Type::TIMESTAMPTZ,
+                    _ => Type::UNKNOWN,
+                };
+                if inferred.len() <= idx {
+                    inferred.resize(idx + 1, Type::UNKNOWN);
+                }
+                if inferred[idx] == Type::UNKNOWN {
+                    inferred[idx] = ty;
+                }
+            }
+            Ok(inferred)
+        }
+    }
+
+    struct CastHandler;
+
+    #[async_trait]
+    impl ExtendedQueryHandler for CastHandler {
+        type Statement = String;
+        type QueryParser = CastParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(CastParser)
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            // only describe is tested
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "0A000".to_owned(),
+                "execute not supported by test handler".to_owned(),
+            ))))
+        }
+
+        async fn do_describe<C>(
+            &self,
+            _client: &mut C,
+            target: StatementOrPortal<'_, Self::Statement>,
+        ) -> PgWireResult<DescribeResponse>
+        where
+            C: ClientInfo + Unpin + Send + Sync,
+        {
+            match target {
+                StatementOrPortal::Statement(stmt) => Ok(DescribeResponse::new(
+                    Some(stmt.parameter_types.clone()),
+                    vec![],
+                )),
+                StatementOrPortal::Portal(_) => Ok(DescribeResponse::no_data()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_describe_inferred_parameter_types() {
+        let mut client = RecordingClient::<String>::new();
+        for (name, type_oids) in [("s1", vec![]), ("s2", vec![0, Type::TEXT.oid()])] {
+            CastHandler
+                .on_parse(
+                    &mut client,
+                    Parse::new(
+                        Some(name.to_owned()),
+                        "SELECT $1::uuid, $2::timestamptz".to_owned(),
+                        type_oids,
+                    ),
+                )
+                .await
+                .unwrap();
+            CastHandler
+                .on_describe(
+                    &mut client,
+                    Describe::new(TARGET_TYPE_BYTE_STATEMENT, Some(name.to_owned())),
+                )
+                .await
+                .unwrap();
+        }
+
+        let described = client
+            .messages()
+            .unwrap()
+            .into_iter()
+            .filter_map(|message| match message {
+                PgWireBackendMessage::ParameterDescription(desc) => Some(desc.types),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec![Type::UUID.oid(), Type::TIMESTAMPTZ.oid()],
+                // types declared in `Parse` are kept
+                vec![Type::UUID.oid(), Type::TEXT.oid()],
+            ],
+            described
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_unknown_parameter_type() {
+        let mut client = RecordingClient::<String>::new();
+        match CastHandler
+            .on_parse(
+                &mut client,
+                Parse::new(None, "SELECT $1::uuid, $2::x".to_owned(), vec![0, 99999]),
+            )
+            .await
+        {
+            Err(PgWireError::UserError(e)) => {
+                assert_eq!("42704", e.code);
+                assert_eq!("type with OID 99999 does not exist", e.message);
+            }
+            _ => panic!("expect type does not exist error"),
+        }
+        assert!(client.messages().unwrap().is_empty());
+
+        CastHandler
+            .on_parse(
+                &mut client,
+                Parse::new(None, "SELECT $1::vector".to_owned(), vec![VECTOR_OID]),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            &client.messages().unwrap()[..],
+            [PgWireBackendMessage::ParseComplete(_)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_send_row_description() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        let mut client = RecordingClient::<String>::new();
+        client.set_state(PgWireConnectionState::AwaitingSync);
+        assert!(matches!(
+            send_row_description(&mut client, &schema).await,
+            Err(PgWireError::InvalidConnectionState(_))
+        ));
+        assert!(client.messages().unwrap().is_empty());
+
+        // describe first, then stream rows without another RowDescription
+        client.set_state(PgWireConnectionState::ReadyForQuery);
+        send_row_description(&mut client, &schema).await.unwrap();
+        let response = QueryResponse::new(schema.clone(), stream::iter(int_rows(&schema, 2)));
+        send_query_response(&mut client, response, false)
+            .await
+            .unwrap();
+
+        let messages = client.messages().unwrap();
+        assert_eq!(4, messages.len());
+        match &messages[0] {
+            PgWireBackendMessage::RowDescription(desc) => {
+                assert_eq!(1, desc.fields.len());
+                assert_eq!("id", desc.fields[0].name);
+            }
+            _ => panic!("expect RowDescription"),
+        }
+        assert!(matches!(messages[1], PgWireBackendMessage::DataRow(_)));
+        assert!(matches!(messages[2], PgWireBackendMessage::DataRow(_)));
+        assert!(matches!(
+            messages[3],
+            PgWireBackendMessage::CommandComplete(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ready_for_query_transaction_status() {
+        let ready_status = |client: &mut RecordingClient| match client.messages().unwrap().last() {
+            Some(PgWireBackendMessage::ReadyForQuery(ready)) => ready.status,
+            _ => panic!("expect ReadyForQuery"),
+        };
+
+        let mut client = RecordingClient::<String>::new();
+        client.set_transaction_status(TransactionStatus::Transaction);
+        SimpleHandler
+            .on_query(&mut client, Query::new("SET a = 1".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(READY_STATUS_TRANSACTION_BLOCK, ready_status(&mut client));
+        PlaceholderExtendedQueryHandler
+            .on_sync(&mut client, PgSync::new())
+            .await
+            .unwrap();
+        assert_eq!(READY_STATUS_TRANSACTION_BLOCK, ready_status(&mut client));
+
+        // error aborts the transaction
+        SimpleHandler
+            .on_query(&mut client, Query::new("SELEC 1".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(TransactionStatus::Error, client.transaction_status());
+        assert_eq!(
+            READY_STATUS_FAILED_TRANSACTION_BLOCK,
+            ready_status(&mut client)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_force_ready_for_query() {
+        let mut client = RecordingClient::<String>::new();
+        client.set_state(PgWireConnectionState::AwaitingStartup);
+        assert!(matches!(
+            force_ready_for_query(&mut client, TransactionStatus::Idle).await,
+            Err(PgWireError::InvalidConnectionState(_))
+        ));
+        assert!(client.messages().unwrap().is_empty());
+
+        client.set_state(PgWireConnectionState::AwaitingSync);
+        force_ready_for_query(&mut client, TransactionStatus::Error)
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.state(),
+            PgWireConnectionState::ReadyForQuery
+        ));
+        assert_eq!(TransactionStatus::Error, client.transaction_status());
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::ReadyForQuery(ready)] => assert_eq!(b'E', ready.status),
+            messages => panic!("unexpected messages {messages:?}"),
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::{stream, Sink};
+
+use super::query::SimpleQueryHandler;
+use super::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use super::{ClientInfo, Type};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::PgWireBackendMessage;
+
+/// Startup parameter to request a replication connection
+pub const METADATA_REPLICATION: &str = "replication";
+
+/// Mode of a replication connection, requested by `replication` startup
+/// parameter.
+///
+/// Replication connections only accept a restricted set of commands through
+/// simple query protocol, like `IDENTIFY_SYSTEM` and `START_REPLICATION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationMode {
+    /// `replication=true`, for physical replication. The connection is not
+    /// bound to a database.
+    Physical,
+    /// `replication=database`, for logical replication from the database in
+    /// `database` parameter. SQL statements are accepted too.
+    Logical,
+}
+
+impl ReplicationMode {
+    /// Parse value of `replication` startup parameter.
+    ///
+    /// Return `None` for a normal connection, like `replication=false`, and
+    /// `22023` error for values postgres doesn't accept.
+    pub fn parse(value: &str) -> PgWireResult<Option<ReplicationMode>> {
+        match value.to_ascii_lowercase().as_str() {
+            "database" => Ok(Some(ReplicationMode::Logical)),
+            "true" | "on" | "yes" | "1" => Ok(Some(ReplicationMode::Physical)),
+            "false" | "off" | "no" | "0" => Ok(None),
+            _ => Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "FATAL".to_owned(),
+                "22023".to_owned(),
+                format!("invalid value for parameter \"{METADATA_REPLICATION}\": \"{value}\""),
+            )))),
+        }
+    }
+}
+
+/// `CREATE_REPLICATION_SLOT` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreateReplicationSlot {
+    pub slot_name: String,
+    pub temporary: bool,
+    /// Output plugin of a `LOGICAL` slot, `None` for a `PHYSICAL` slot
+    pub output_plugin: Option<String>,
+    /// Remaining options as written by client, e.g. `RESERVE_WAL` or
+    /// `(SNAPSHOT 'export')`, empty if none
+    pub options: String,
+}
+
+/// `START_REPLICATION` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartReplication {
+    pub slot_name: Option<String>,
+    pub logical: bool,
+    /// Start position, as `XXX/XXX`
+    pub start_lsn: String,
+    pub timeline: Option<u32>,
+    /// Plugin options of logical replication as written by client, e.g.
+    /// `("proto_version" '1')`, empty if none
+    pub options: String,
+}
+
+/// Command accepted by a replication connection, besides SQL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplicationCommand {
+    IdentifySystem,
+    CreateReplicationSlot(CreateReplicationSlot),
+    StartReplication(StartReplication),
+    TimelineHistory(u32),
+    /// `BASE_BACKUP` with its options as written by client
+    BaseBackup(String),
+}
+
+fn replication_syntax_error(query: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "42601".to_owned(),
+        format!("syntax error in replication command: \"{query}\""),
+    )))
+}
+
+fn not_supported(command: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "0A000".to_owned(),
+        format!("{command} is not supported"),
+    )))
+}
+
+/// Words of a replication command, split by whitespace.
+struct Words<'a>(&'a str);
+
+impl<'a> Words<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.0.split_whitespace().next()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let word = self.peek()?;
+        let start = self.0.find(word).unwrap_or(0);
+        self.0 = &self.0[start + word.len()..];
+        Some(word)
+    }
+
+    /// Consume next word if it's `keyword`, case insensitive.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_some_and(|w| w.eq_ignore_ascii_case(keyword)) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Next word as identifier: unquoted as is in double quotes, folded to
+    /// lower case otherwise.
+    fn ident(&mut self) -> Option<String> {
+        let word = self.next()?;
+        match word.strip_prefix('"').and_then(|w| w.strip_suffix('"')) {
+            Some(quoted) => Some(quoted.to_owned()),
+            None => Some(word.to_ascii_lowercase()),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        self.0.trim()
+    }
+}
+
+impl ReplicationCommand {
+    /// Parse `query` as a replication command.
+    ///
+    /// Return `None` if it's not one, e.g. a SQL statement sent to a logical
+    /// replication connection, and `42601` error if it starts with a command
+    /// name but doesn't follow its syntax. Options are not validated, they are
+    /// kept as written by client.
+    pub fn parse(query: &str) -> Option<PgWireResult<ReplicationCommand>> {
+        let trimmed = query.trim().trim_end_matches(';');
+        let mut words = Words(trimmed);
+        let command = words.next()?.to_ascii_uppercase();
+        let parsed = match command.as_str() {
+            "IDENTIFY_SYSTEM" => Some(ReplicationCommand::IdentifySystem),
+            "CREATE_REPLICATION_SLOT" => Self::parse_create_slot(&mut words),
+            "START_REPLICATION" => Self::parse_start_replication(&mut words),
+            "TIMELINE_HISTORY" => words
+                .next()
+                .and_then(|tli| tli.parse().ok())
+                .map(ReplicationCommand::TimelineHistory),
+            "BASE_BACKUP" => {
+                let options = words.rest().to_owned();
+                words = Words("");
+                Some(ReplicationCommand::BaseBackup(options))
+            }
+            _ => return None,
+        };
+        Some(
+            parsed
+                .filter(|_| words.rest().is_empty())
+                .ok_or_else(|| replication_syntax_error(trimmed)),
+        )
+    }
+
+    fn parse_create_slot(words: &mut Words) -> Option<ReplicationCommand> {
+        let slot_name = words.ident()?;
+        let temporary = words.keyword("TEMPORARY");
+        let output_plugin = if words.keyword("LOGICAL") {
+            Some(words.ident()?)
+        } else if words.keyword("PHYSICAL") {
+            None
+        } else {
+            return None;
+        };
+        let options = words.rest().to_owned();
+        *words = Words("");
+        Some(ReplicationCommand::CreateReplicationSlot(
+            CreateReplicationSlot {
+                slot_name,
+                temporary,
+                output_plugin,
+                options,
+            },
+        ))
+    }
+
+    fn parse_start_replication(words: &mut Words) -> Option<ReplicationCommand> {
+        let slot_name = if words.keyword("SLOT") {
+            Some(words.ident()?)
+        } else {
+            None
+        };
+        let logical = words.keyword("LOGICAL");
+        if !logical {
+            words.keyword("PHYSICAL");
+        }
+        // a logical slot is required for logical replication
+        if logical && slot_name.is_none() {
+            return None;
+        }
+        let start_lsn = words.next().filter(|lsn| lsn.contains('/'))?.to_owned();
+        let timeline = if !logical && words.keyword("TIMELINE") {
+            Some(words.next()?.parse().ok()?)
+        } else {
+            None
+        };
+        let options = if logical {
+            let options = words.rest().to_owned();
+            *words = Words("");
+            options
+        } else {
+            String::new()
+        };
+        Some(ReplicationCommand::StartReplication(StartReplication {
+            slot_name,
+            logical,
+            start_lsn,
+            timeline,
+            options,
+        }))
+    }
+}
+
+/// Build the response of `IDENTIFY_SYSTEM`.
+///
+/// `systemid` is the unique system identifier, `xlogpos` the current WAL
+/// flush location as `XXX/XXX`, and `dbname` the database of a logical
+/// replication connection. The response has the same columns and command tag
+/// as postgres, so a stub is enough for clients like `pg_receivewal` to
+/// complete their handshake.
+pub fn identify_system_response(
+    systemid: &str,
+    timeline: i32,
+    xlogpos: &str,
+    dbname: Option<&str>,
+) -> PgWireResult<Response<'static>> {
+    let schema = Arc::new(
+        [
+            ("systemid", Type::TEXT),
+            ("timeline", Type::INT4),
+            ("xlogpos", Type::TEXT),
+            ("dbname", Type::TEXT),
+        ]
+        .into_iter()
+        .map(|(name, datatype)| {
+            FieldInfo::new(name.to_owned(), None, None, datatype, FieldFormat::Text)
+        })
+        .collect::<Vec<_>>(),
+    );
+    let mut encoder = DataRowEncoder::new(schema.clone());
+    encoder.encode_field(&systemid)?;
+    encoder.encode_field(&timeline)?;
+    encoder.encode_field(&xlogpos)?;
+    encoder.encode_field(&dbname)?;
+    let row = encoder.finish();
+    Ok(Response::Query(
+        QueryResponse::new(schema, stream::iter(vec![row]))
+            .with_command_tag(Tag::new("IDENTIFY_SYSTEM").without_row_count()),
+    ))
+}
+
+/// Handler of commands in a replication connection.
+///
+/// Wrap it in `ReplicationQueryHandler` and pass that to
+/// `process_socket_with_replication`. Each method handles a command parsed by
+/// `ReplicationCommand::parse`, and by default responds with a `0A000` error.
+/// Other queries go to `do_sql_query`, which rejects them as postgres does
+/// for physical replication.
+///
+/// `START_REPLICATION` and `BASE_BACKUP` stream data with `CopyBothResponse`
+/// and `CopyOutResponse`, send them to `client` in the handler, then return
+/// the final response.
+#[async_trait]
+pub trait ReplicationCommandHandler: Send + Sync {
+    async fn identify_system<C>(&self, _client: &mut C) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(not_supported("IDENTIFY_SYSTEM"))
+    }
+
+    async fn create_replication_slot<C>(
+        &self,
+        _client: &mut C,
+        _command: CreateReplicationSlot,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(not_supported("CREATE_REPLICATION_SLOT"))
+    }
+
+    async fn start_replication<C>(
+        &self,
+        _client: &mut C,
+        _command: StartReplication,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(not_supported("START_REPLICATION"))
+    }
+
+    async fn timeline_history<C>(
+        &self,
+        _client: &mut C,
+        _timeline: u32,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(not_supported("TIMELINE_HISTORY"))
+    }
+
+    async fn base_backup<C>(
+        &self,
+        _client: &mut C,
+        _options: String,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(not_supported("BASE_BACKUP"))
+    }
+
+    /// Handle a query that isn't a replication command.
+    ///
+    /// Postgres runs SQL in a logical replication connection, override this
+    /// to do so. The default rejects it with `0A000` error.
+    async fn do_sql_query<C>(
+        &self,
+        _client: &mut C,
+        _query: &str,
+    ) -> PgWireResult<Vec<Response<'static>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            "cannot execute SQL commands in WAL sender for physical replication".to_owned(),
+        ))))
+    }
+}
+
+/// `SimpleQueryHandler` of a replication connection, dispatching commands to
+/// a `ReplicationCommandHandler`.
+#[derive(Debug)]
+pub struct ReplicationQueryHandler<H>(pub H);
+
+#[async_trait]
+impl<H: ReplicationCommandHandler> SimpleQueryHandler for ReplicationQueryHandler<H> {
+    async fn do_query<'a, 'b: 'a, C>(
+        &'b self,
+        client: &mut C,
+        query: &'a str,
+    ) -> PgWireResult<Vec<Response<'a>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        let handler = &self.0;
+        let response = match ReplicationCommand::parse(query) {
+            None => return handler.do_sql_query(client, query).await,
+            Some(command) => match command? {
+                ReplicationCommand::IdentifySystem => handler.identify_system(client).await?,
+                ReplicationCommand::CreateReplicationSlot(command) => {
+                    handler.create_replication_slot(client, command).await?
+                }
+                ReplicationCommand::StartReplication(command) => {
+                    handler.start_replication(client, command).await?
+                }
+                ReplicationCommand::TimelineHistory(timeline) => {
+                    handler.timeline_history(client, timeline).await?
+                }
+                ReplicationCommand::BaseBackup(options) => {
+                    handler.base_backup(client, options).await?
+                }
+            },
+        };
+        Ok(vec![response])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::testing::RecordingClient;
+    use crate::messages::simplequery::Query;
+
+    #[test]
+    fn test_parse_replication_mode() {
+        assert_eq!(
+            Some(ReplicationMode::Logical),
+            ReplicationMode::parse("database").unwrap()
+        );
+        assert_eq!(
+            Some(ReplicationMode::Physical),
+            ReplicationMode::parse("on").unwrap()
+        );
+        assert_eq!(None, ReplicationMode::parse("false").unwrap());
+        assert!(ReplicationMode::parse("maybe").is_err());
+    }
+
+    #[test]
+    fn test_parse_replication_command() {
+        let parse = |query| ReplicationCommand::parse(query).map(|c| c.unwrap());
+        assert_eq!(
+            Some(ReplicationCommand::IdentifySystem),
+            parse("identify_system;")
+        );
+        assert_eq!(None, parse("SELECT 1"));
+        assert_eq!(
+            Some(ReplicationCommand::CreateReplicationSlot(
+                CreateReplicationSlot {
+                    slot_name: "Sub".to_owned(),
+                    temporary: true,
+                    output_plugin: Some("pgoutput".to_owned()),
+                    options: "(SNAPSHOT 'nothing')".to_owned(),
+                }
+            )),
+            parse(
+                "CREATE_REPLICATION_SLOT \"Sub\" TEMPORARY LOGICAL pgoutput (SNAPSHOT 'nothing')"
+            )
+        );
+        assert_eq!(
+            Some(ReplicationCommand::StartReplication(StartReplication {
+                slot_name: Some("s1".to_owned()),
+                logical: false,
+                start_lsn: "0/1000000".to_owned(),
+                timeline: Some(1),
+                options: String::new(),
+            })),
+            parse("START_REPLICATION SLOT S1 PHYSICAL 0/1000000 TIMELINE 1")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::StartReplication(StartReplication {
+                slot_name: Some("sub".to_owned()),
+                logical: true,
+                start_lsn: "0/0".to_owned(),
+                timeline: None,
+                options: "(proto_version '1', publication_names 'pub')".to_owned(),
+            })),
+            parse("START_REPLICATION SLOT sub LOGICAL 0/0 (proto_version '1', publication_names 'pub')")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::TimelineHistory(2)),
+            parse("TIMELINE_HISTORY 2")
+        );
+        assert_eq!(
+            Some(ReplicationCommand::BaseBackup("(LABEL 'b')".to_owned())),
+            parse("BASE_BACKUP (LABEL 'b')")
+        );
+
+        for query in [
+            "IDENTIFY_SYSTEM now",
+            "CREATE_REPLICATION_SLOT s1",
+            "START_REPLICATION LOGICAL 0/0",
+            "START_REPLICATION 0/0 TIMELINE x",
+            "TIMELINE_HISTORY",
+        ] {
+            match ReplicationCommand::parse(query) {
+                Some(Err(PgWireError::UserError(e))) => assert_eq!("42601", e.code, "{query}"),
+                _ => panic!("expect syntax error for {query}"),
+            }
+        }
+    }
+
+    struct IdentifyOnly;
+
+    #[async_trait]
+    impl ReplicationCommandHandler for IdentifyOnly {
+        async fn identify_system<C>(&self, _client: &mut C) -> PgWireResult<Response<'static>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            identify_system_response("7697044085768173274", 1, "0/1555A60", None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replication_query_handler() {
+        let handler = ReplicationQueryHandler(IdentifyOnly);
+        let mut client = RecordingClient::<String>::new();
+        client
+            .metadata
+            .insert(METADATA_REPLICATION.to_owned(), "true".to_owned());
+
+        handler
+            .on_query(&mut client, Query::new("IDENTIFY_SYSTEM".to_owned()))
+            .await
+            .unwrap();
+        let messages = client.messages().unwrap();
+        match &messages[..] {
+            [PgWireBackendMessage::RowDescription(desc), PgWireBackendMessage::DataRow(row), PgWireBackendMessage::CommandComplete(cc), PgWireBackendMessage::ReadyForQuery(_)] =>
+            {
+                assert_eq!(4, desc.fields.len());
+                assert_eq!(4, row.fields.len());
+                assert_eq!(None, row.fields[3]);
+                assert_eq!("IDENTIFY_SYSTEM", cc.tag);
+            }
+            _ => panic!("unexpected messages {messages:?}"),
+        }
+
+        for query in ["TIMELINE_HISTORY 1", "SELECT 1"] {
+            match handler.do_query(&mut client, query).await {
+                Err(PgWireError::UserError(e)) => assert_eq!("0A000", e.code, "{query}"),
+                _ => panic!("expect error for {query}"),
+            }
+        }
+    }
+}
@@ -1,26 +1,39 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use bytes::BytesMut;
 use futures::{
-    stream::{BoxStream, StreamExt},
-    Stream,
+    stream::{self, BoxStream, StreamExt},
+    Future, Stream,
 };
-use postgres_types::{IsNull, Oid, ToSql, Type};
+use postgres_types::{IsNull, Kind, Oid, ToSql, Type};
+use tokio::sync::mpsc;
 
 use crate::{
-    error::{ErrorInfo, PgWireResult},
+    error::{ErrorInfo, PgWireError, PgWireResult},
     messages::{
+        copy::CopyInResponse,
         data::{DataRow, FieldDescription, RowDescription, FORMAT_CODE_BINARY, FORMAT_CODE_TEXT},
-        response::CommandComplete,
+        response::{CommandComplete, NoticeResponse},
     },
-    types::ToSqlText,
+    types::{ByteaOutput, ToSqlText},
 };
 
-#[derive(Debug, Eq, PartialEq)]
+/// Command tag sent in `CommandComplete`.
+///
+/// The tag is the command name, followed by the number of rows affected for
+/// DML and `SELECT`, e.g. `UPDATE 10`. DDL statements reply their command name
+/// only, e.g. `Tag::new("CREATE TABLE")` or `Tag::new("DROP INDEX")`. Return
+/// it as `Response::Execution` from handler, so no `RowDescription` is sent.
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Tag {
     command: String,
     oid: Option<Oid>,
-    rows: Option<usize>,
+    rows: Option<u64>,
+    row_count: bool,
 }
 
 impl Tag {
@@ -29,11 +42,28 @@ impl Tag {
             command: command.to_owned(),
             oid: None,
             rows: None,
+            row_count: true,
         }
     }
 
-    pub fn with_rows(mut self, rows: usize) -> Tag {
+    pub fn with_rows(self, rows: usize) -> Tag {
+        self.with_row_count(rows as u64)
+    }
+
+    /// Like `with_rows`, for a count that may not fit in `usize` on 32-bit
+    /// targets, e.g. from a streaming counter.
+    pub fn with_row_count(mut self, rows: u64) -> Tag {
         self.rows = Some(rows);
+        self.row_count = true;
+        self
+    }
+
+    /// Send the command name only, even after rows of a `QueryResponse`, as
+    /// postgres does for `SHOW` and replication commands like
+    /// `IDENTIFY_SYSTEM`.
+    pub fn without_row_count(mut self) -> Tag {
+        self.rows = None;
+        self.row_count = false;
         self
     }
 
@@ -41,14 +71,23 @@ impl Tag {
         self.oid = Some(oid);
         self
     }
+
+    /// Set row count unless it was given by `with_rows` or `with_row_count`
+    pub(crate) fn with_default_rows(mut self, rows: u64) -> Tag {
+        if self.row_count {
+            self.rows.get_or_insert(rows);
+        }
+        self
+    }
 }
 
 impl From<Tag> for CommandComplete {
     fn from(tag: Tag) -> CommandComplete {
-        let tag_string = if let Some(rows) = tag.rows {
-            format!("{} {rows}", tag.command)
-        } else {
-            tag.command
+        let tag_string = match (tag.oid, tag.rows) {
+            // only `INSERT` carries oid, as `INSERT 0 1`
+            (Some(oid), Some(rows)) => format!("{} {oid} {rows}", tag.command),
+            (None, Some(rows)) => format!("{} {rows}", tag.command),
+            _ => tag.command,
         };
         CommandComplete::new(tag_string)
     }
@@ -93,6 +132,67 @@ pub struct FieldInfo {
 }
 
 impl FieldInfo {
+    /// Create a text format field for a type that `postgres-types` doesn't
+    /// know, typically from an extension, by its name and OID.
+    ///
+    /// Values of this field can be encoded with `types::RawText`. Types
+    /// implementing `ToSql` for it, like `types::Vector` for pgvector's
+    /// `vector`, can also be sent in binary format with `with_format`.
+    pub fn new_with_type_oid(
+        name: String,
+        table_id: Option<i32>,
+        column_id: Option<i16>,
+        type_name: &str,
+        type_oid: Oid,
+    ) -> FieldInfo {
+        let datatype = Type::from_oid(type_oid).unwrap_or_else(|| {
+            Type::new(
+                type_name.to_owned(),
+                type_oid,
+                Kind::Simple,
+                "public".to_owned(),
+            )
+        });
+        FieldInfo::new(name, table_id, column_id, datatype, FieldFormat::Text)
+    }
+
+    /// Declare this field as a domain over its type, by the domain's schema,
+    /// name and OID.
+    ///
+    /// Values of a domain are sent exactly as values of its base type, only
+    /// `RowDescription` carries the domain's OID, which tools introspecting
+    /// domains rely on. So values are encoded as the base type in both text
+    /// and binary formats, e.g. `String` for a domain over `TEXT`.
+    pub fn with_domain(mut self, schema: &str, domain_name: &str, domain_oid: Oid) -> FieldInfo {
+        self.datatype = Type::new(
+            domain_name.to_owned(),
+            domain_oid,
+            Kind::Domain(self.datatype),
+            schema.to_owned(),
+        );
+        self
+    }
+
+    /// Attach source table and column of this field, sent as `table_oid` and
+    /// `column_attnum` in `RowDescription`.
+    ///
+    /// Both must be real catalog values, the OID of the table in `pg_class`
+    /// and the column's `attnum` in `pg_attribute`, for updatable result sets
+    /// to work. JDBC's `ResultSet.updateRow()`, for example, uses them to
+    /// generate `UPDATE` statements and fails if they are missing.
+    pub fn with_table_column(mut self, table_id: i32, column_id: i16) -> FieldInfo {
+        self.table_id = Some(table_id);
+        self.column_id = Some(column_id);
+        self
+    }
+
+    /// Set format of this field, usually the format requested by client for
+    /// this column in `Bind`.
+    pub fn with_format(mut self, format: FieldFormat) -> FieldInfo {
+        self.format = format;
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -121,51 +221,459 @@ impl From<&FieldInfo> for FieldDescription {
             fi.table_id.unwrap_or(0),  // table_id
             fi.column_id.unwrap_or(0), // column_id
             fi.datatype.oid(),         // type_id
-            // TODO: type size and modifier
-            0,
-            0,
+            type_size(&fi.datatype),   // type_size
+            -1,                        // type_modifier, none
             fi.format.value(),
         )
     }
 }
 
+/// Get `typlen` of `pg_type` for `data_type`, as postgres sends it in
+/// `RowDescription`: the size of fixed-size types, `-1` for variable length
+/// types like `text`, arrays or unknown extension types, and `-2` for
+/// null-terminated C strings.
+fn type_size(data_type: &Type) -> i16 {
+    match data_type.kind() {
+        Kind::Domain(base_type) => return type_size(base_type),
+        Kind::Enum(_) => return 4,
+        _ => {}
+    }
+    match *data_type {
+        Type::BOOL | Type::CHAR => 1,
+        Type::INT2 => 2,
+        Type::INT4
+        | Type::OID
+        | Type::XID
+        | Type::CID
+        | Type::FLOAT4
+        | Type::DATE
+        | Type::REGPROC
+        | Type::REGPROCEDURE
+        | Type::REGOPER
+        | Type::REGOPERATOR
+        | Type::REGCLASS
+        | Type::REGTYPE
+        | Type::REGCONFIG
+        | Type::REGDICTIONARY
+        | Type::REGNAMESPACE
+        | Type::REGROLE
+        | Type::REGCOLLATION
+        | Type::VOID => 4,
+        Type::TID | Type::MACADDR => 6,
+        Type::INT8
+        | Type::XID8
+        | Type::FLOAT8
+        | Type::MONEY
+        | Type::TIME
+        | Type::TIMESTAMP
+        | Type::TIMESTAMPTZ
+        | Type::MACADDR8
+        | Type::PG_LSN => 8,
+        Type::TIMETZ | Type::ACLITEM => 12,
+        Type::INTERVAL | Type::UUID | Type::POINT => 16,
+        Type::LINE | Type::CIRCLE => 24,
+        Type::LSEG | Type::BOX => 32,
+        Type::NAME => 64,
+        Type::UNKNOWN | Type::CSTRING => -2,
+        _ => -1,
+    }
+}
+
 pub(crate) fn into_row_description(fields: &[FieldInfo]) -> RowDescription {
     RowDescription::new(fields.iter().map(Into::into).collect())
 }
 
+/// An item in the stream of `QueryResponse`.
+///
+/// Postgres allows `NoticeResponse` to be sent at any time, including in the
+/// middle of a resultset. Each item is written to the client as a complete
+/// message so a notice is always placed between two `DataRow`s.
+#[derive(Debug)]
+pub enum RowOrNotice {
+    Row(DataRow),
+    Notice(NoticeResponse),
+}
+
+impl From<DataRow> for RowOrNotice {
+    fn from(row: DataRow) -> Self {
+        RowOrNotice::Row(row)
+    }
+}
+
+impl From<NoticeResponse> for RowOrNotice {
+    fn from(notice: NoticeResponse) -> Self {
+        RowOrNotice::Notice(notice)
+    }
+}
+
+/// Controls how rows of a `QueryResponse` are flushed to client.
+///
+/// Rows are buffered and written in batches to reduce write syscalls. The
+/// buffer is flushed once `max_rows` rows are buffered, or `max_delay` has
+/// elapsed since the first buffered row while waiting for the next one,
+/// whichever comes first. The delay keeps latency bounded for interactive
+/// clients that receive rows one at a time. `None` disables the limit. Note
+/// that the transport may also flush when its own write buffer is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, new)]
+pub struct FlushPolicy {
+    pub max_rows: Option<usize>,
+    pub max_delay: Option<Duration>,
+}
+
+/// What to do when a `QueryResponse` produces more rows than its limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RowLimitPolicy {
+    /// Abort the query with `54000 program_limit_exceeded` error.
+    #[default]
+    Error,
+    /// Stop at the limit, and tell client with a warning `NoticeResponse`.
+    Truncate,
+}
+
+/// A cap on total rows of a `QueryResponse`, independent of the client's fetch
+/// size. It's a guardrail against runaway queries.
+///
+/// Set per response with `QueryResponse::with_row_limit`, or for every
+/// response of a connection with `SocketOptions::max_result_rows`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct RowLimit {
+    pub max_rows: usize,
+    pub policy: RowLimitPolicy,
+}
+
+/// Rows returned by a query, as a stream.
+///
+/// The stream is consumed lazily while sending to client, and the row count
+/// of `CommandComplete` tag, e.g. `SELECT 42`, is computed from rows actually
+/// sent. So the handler doesn't need to know the count upfront.
 pub struct QueryResponse<'a> {
     row_schema: Arc<Vec<FieldInfo>>,
-    data_rows: BoxStream<'a, PgWireResult<DataRow>>,
+    data_rows: BoxStream<'a, PgWireResult<RowOrNotice>>,
+    deferred_schema: Option<DeferredSchema>,
+    command_tag: Tag,
+    flush_policy: FlushPolicy,
+    row_limit: Option<RowLimit>,
 }
 
+/// Schema of a `new_deferred` response, set by its row stream once known.
+type DeferredSchema = Arc<Mutex<Option<Arc<Vec<FieldInfo>>>>>;
+
 impl<'a> QueryResponse<'a> {
     /// Create `QueryResponse` from column schemas and stream of data row
+    ///
+    /// The stream is consumed lazily as rows are sent to client, and needn't
+    /// be `Unpin`, so a row stream of an async driver can be passed directly,
+    /// see [`encode_row_stream`].
     pub fn new<S>(field_defs: Arc<Vec<FieldInfo>>, row_stream: S) -> QueryResponse<'a>
     where
-        S: Stream<Item = PgWireResult<DataRow>> + Send + Unpin + 'a,
+        S: Stream<Item = PgWireResult<DataRow>> + Send + 'a,
     {
         QueryResponse {
             row_schema: field_defs,
-            data_rows: row_stream.boxed(),
+            data_rows: row_stream.map(|r| r.map(RowOrNotice::Row)).boxed(),
+            deferred_schema: None,
+            command_tag: Tag::new("SELECT"),
+            flush_policy: FlushPolicy::default(),
+            row_limit: None,
         }
     }
 
-    /// Get schema of columns
+    /// Create `QueryResponse` from column schemas and stream of data rows
+    /// interleaved with notices
+    pub fn new_with_notices<S>(field_defs: Arc<Vec<FieldInfo>>, stream: S) -> QueryResponse<'a>
+    where
+        S: Stream<Item = PgWireResult<RowOrNotice>> + Send + 'a,
+    {
+        QueryResponse {
+            row_schema: field_defs,
+            data_rows: stream.boxed(),
+            deferred_schema: None,
+            command_tag: Tag::new("SELECT"),
+            flush_policy: FlushPolicy::default(),
+            row_limit: None,
+        }
+    }
+
+    /// Create `QueryResponse` whose columns are not known until rows are
+    /// read, for schema-on-read sources like document stores.
+    ///
+    /// `response` resolves to the schema and the row stream, typically by
+    /// reading the first row to infer columns, then chaining it back in front
+    /// of the remaining rows. It's awaited when the response is sent, so
+    /// `RowDescription` goes out right before the first `DataRow`. Until then
+    /// `row_schema` is empty, use `resolve` to await it earlier.
+    ///
+    /// Extended query sends `RowDescription` on `Describe`, ahead of
+    /// `Execute`, so this is for simple query, or for portals described with
+    /// `NoData`.
+    pub fn new_deferred<F, S>(response: F) -> QueryResponse<'a>
+    where
+        F: Future<Output = PgWireResult<(Arc<Vec<FieldInfo>>, S)>> + Send + 'a,
+        S: Stream<Item = PgWireResult<DataRow>> + Send + 'a,
+    {
+        let deferred_schema = Arc::new(Mutex::new(None));
+        let schema_slot = deferred_schema.clone();
+        let data_rows = stream::once(response)
+            .flat_map(move |response| match response {
+                Ok((schema, rows)) => {
+                    *schema_slot.lock().unwrap() = Some(schema);
+                    rows.map(|r| r.map(RowOrNotice::Row)).boxed()
+                }
+                Err(e) => stream::once(async { Err(e) }).boxed(),
+            })
+            .boxed();
+        QueryResponse {
+            row_schema: Arc::new(Vec::new()),
+            data_rows,
+            deferred_schema: Some(deferred_schema),
+            command_tag: Tag::new("SELECT"),
+            flush_policy: FlushPolicy::default(),
+            row_limit: None,
+        }
+    }
+
+    /// Await schema of a response created with `new_deferred`, so
+    /// `row_schema` is available. Other responses are returned as is.
+    pub async fn resolve(mut self) -> PgWireResult<QueryResponse<'a>> {
+        if let Some(deferred_schema) = self.deferred_schema.take() {
+            // the schema is set once the stream yields its first item
+            let first = self.data_rows.next().await;
+            let row_schema = deferred_schema.lock().unwrap().take();
+            match row_schema {
+                Some(row_schema) => self.row_schema = row_schema,
+                None => {
+                    return Err(first.and_then(Result::err).unwrap_or_else(|| {
+                        PgWireError::ApiError("deferred schema is not resolved".into())
+                    }))
+                }
+            }
+            self.data_rows = stream::iter(first).chain(self.data_rows).boxed();
+        }
+        Ok(self)
+    }
+
+    /// Create `QueryResponse` of a single row with a single column, like
+    /// `SELECT nextval('seq')` or `SELECT oid FROM pg_class WHERE ...`.
+    ///
+    /// The value is encoded with type and format of `field`, so the rust type
+    /// has to match: `i64` for `INT8` of `nextval` and `lastval`, `u32` for
+    /// `OID`, which is unsigned on the wire and in text, e.g. `4294967295`.
+    pub fn single_value<T>(field: FieldInfo, value: &T) -> PgWireResult<QueryResponse<'a>>
+    where
+        T: ToSql + ToSqlText + Sized,
+    {
+        let schema = Arc::new(vec![field]);
+        let mut encoder = DataRowEncoder::new(schema.clone());
+        encoder.encode_field(value)?;
+        let row = encoder.finish();
+        Ok(QueryResponse::new(schema, stream::iter(vec![row])))
+    }
+
+    /// Set tag of the `CommandComplete` sent after rows, `SELECT` by default.
+    ///
+    /// Use this for statements other than `SELECT` that return rows, for
+    /// example, `Tag::new("INSERT").with_oid(0)` for `INSERT ... RETURNING`.
+    ///
+    /// Row count of the tag is the number of rows sent, unless the tag carries
+    /// its own count from `Tag::with_rows`. Set it when the count clients
+    /// should see differs from rows returned, e.g. for `SELECT INTO`.
+    pub fn with_command_tag(mut self, command_tag: Tag) -> QueryResponse<'a> {
+        self.command_tag = command_tag;
+        self
+    }
+
+    /// Get tag of the `CommandComplete` sent after rows, row count is only
+    /// present when set explicitly
+    pub fn command_tag(&self) -> &Tag {
+        &self.command_tag
+    }
+
+    /// Set how rows of this response are flushed to client
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> QueryResponse<'a> {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Get flush policy of this response
+    pub fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    /// Limit total rows sent to client for this response
+    pub fn with_row_limit(mut self, row_limit: RowLimit) -> QueryResponse<'a> {
+        self.row_limit = Some(row_limit);
+        self
+    }
+
+    /// Get row limit of this response
+    pub fn row_limit(&self) -> Option<RowLimit> {
+        self.row_limit
+    }
+
+    /// Get schema of columns, empty for a `new_deferred` response that is not
+    /// resolved yet
     pub fn row_schema(&self) -> Arc<Vec<FieldInfo>> {
         self.row_schema.clone()
     }
 
     /// Get owned `BoxStream` of data rows
+    ///
+    /// Notices in the stream are dropped, use `items` to keep them.
     pub fn data_rows(self) -> BoxStream<'a, PgWireResult<DataRow>> {
+        self.data_rows
+            .filter_map(|item| async move {
+                match item {
+                    Ok(RowOrNotice::Row(row)) => Some(Ok(row)),
+                    Ok(RowOrNotice::Notice(_)) => None,
+                    Err(e) => Some(Err(e)),
+                }
+            })
+            .boxed()
+    }
+
+    /// Get owned `BoxStream` of data rows and notices
+    pub fn items(self) -> BoxStream<'a, PgWireResult<RowOrNotice>> {
         self.data_rows
     }
 }
 
+/// Sending half of [`blocking_row_stream`], used from the blocking producer.
+pub struct BlockingRowSender {
+    tx: mpsc::Sender<PgWireResult<DataRow>>,
+}
+
+impl BlockingRowSender {
+    /// Send a row, waiting while the channel is full.
+    ///
+    /// Returns `false` once the response is dropped, for example the client
+    /// disconnected or the query was cancelled. The producer should stop then.
+    pub fn send(&self, row: PgWireResult<DataRow>) -> bool {
+        self.tx.blocking_send(row).is_ok()
+    }
+}
+
+/// Create a row stream for `QueryResponse` from blocking code, like `rusqlite`
+/// queries or FFI calls, without stalling the async runtime.
+///
+/// `producer` runs on tokio's blocking thread pool with `spawn_blocking`, and
+/// passes rows through a channel of `buffer` rows, at least 1 as a channel
+/// can't be unbuffered. A slow client holds the producer back once the
+/// channel is full. An error returned by `producer`,
+/// or its panic, ends the stream as an error. This must be called within a
+/// tokio runtime.
+///
+/// ```no_run
+/// # use std::sync::{Arc, Mutex};
+/// # use pgwire::api::results::{blocking_row_stream, DataRowEncoder, FieldInfo, QueryResponse};
+/// # use pgwire::error::{PgWireError, PgWireResult};
+/// # fn example(conn: Arc<Mutex<rusqlite::Connection>>, schema: Arc<Vec<FieldInfo>>) {
+/// let row_schema = schema.clone();
+/// let rows = blocking_row_stream(64, move |sender| {
+///     let conn = conn.lock().unwrap();
+///     let mut stmt = conn
+///         .prepare("SELECT id FROM users")
+///         .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+///     let mut rows = stmt
+///         .query(())
+///         .map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+///     while let Some(row) = rows.next().map_err(|e| PgWireError::ApiError(Box::new(e)))? {
+///         let mut encoder = DataRowEncoder::new(row_schema.clone());
+///         encoder.encode_field(&row.get::<_, i64>(0).unwrap())?;
+///         if !sender.send(encoder.finish()) {
+///             break;
+///         }
+///     }
+///     Ok(())
+/// });
+/// let response = QueryResponse::new(schema, rows);
+/// # }
+/// ```
+pub fn blocking_row_stream<F>(
+    buffer: usize,
+    producer: F,
+) -> BoxStream<'static, PgWireResult<DataRow>>
+where
+    F: FnOnce(&BlockingRowSender) -> PgWireResult<()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(buffer.max(1));
+    let handle = tokio::task::spawn_blocking(move || {
+        let sender = BlockingRowSender { tx };
+        if let Err(e) = producer(&sender) {
+            sender.send(Err(e));
+        }
+    });
+
+    stream::unfold((rx, Some(handle)), |(mut rx, handle)| async move {
+        if let Some(row) = rx.recv().await {
+            return Some((row, (rx, handle)));
+        }
+        // channel is closed, report the producer if it panicked
+        match handle?.await {
+            Ok(()) => None,
+            Err(e) => Some((Err(PgWireError::ApiError(Box::new(e))), (rx, None))),
+        }
+    })
+    .boxed()
+}
+
+/// Create a row stream for `QueryResponse` from an async driver, like `sqlx`
+/// or `tokio-postgres` to a real database, without collecting rows first.
+///
+/// This is the async counterpart of [`blocking_row_stream`]. Each driver row
+/// is passed to `encode` with a fresh `DataRowEncoder` of `schema`, to encode
+/// its fields in order. Driver errors end up as `PgWireError::ApiError`. Rows
+/// are pulled from the driver only as fast as they are sent to client.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use pgwire::api::results::{encode_row_stream, FieldInfo, QueryResponse};
+/// # async fn example(
+/// #     client: &tokio_postgres::Client,
+/// #     schema: Arc<Vec<FieldInfo>>,
+/// # ) -> Result<(), tokio_postgres::Error> {
+/// let rows = client
+///     .query_raw("SELECT id, name FROM users", std::iter::empty::<i32>())
+///     .await?;
+/// let rows = encode_row_stream(schema.clone(), rows, |encoder, row| {
+///     encoder.encode_field(&row.get::<_, i32>(0))?;
+///     encoder.encode_field(&row.get::<_, String>(1))
+/// });
+/// let response = QueryResponse::new(schema, rows);
+/// # Ok(())
+/// # }
+/// ```
+pub fn encode_row_stream<'a, S, R, E, F>(
+    schema: Arc<Vec<FieldInfo>>,
+    rows: S,
+    mut encode: F,
+) -> BoxStream<'a, PgWireResult<DataRow>>
+where
+    S: Stream<Item = Result<R, E>> + Send + 'a,
+    E: std::error::Error + Send + Sync + 'static,
+    F: FnMut(&mut DataRowEncoder, R) -> PgWireResult<()> + Send + 'a,
+{
+    rows.map(move |row| {
+        let row = row.map_err(|e| PgWireError::ApiError(Box::new(e)))?;
+        let mut encoder = DataRowEncoder::new(schema.clone());
+        encode(&mut encoder, row)?;
+        encoder.finish()
+    })
+    .boxed()
+}
+
+/// Encoder of `DataRow`, for both text and binary formats.
+///
+/// Each value is encoded in the format of its field in schema, so one encoder
+/// serves simple query, which is text only, as well as extended query with any
+/// mix of formats. For extended query, set formats requested in `Bind` with
+/// `Format::apply` before creating the encoder, the same schema is used for
+/// `RowDescription`.
 pub struct DataRowEncoder {
     buffer: DataRow,
     field_buffer: BytesMut,
     schema: Arc<Vec<FieldInfo>>,
     col_index: usize,
+    bytea_output: ByteaOutput,
 }
 
 impl DataRowEncoder {
@@ -177,13 +685,28 @@ impl DataRowEncoder {
             field_buffer: BytesMut::with_capacity(8),
             schema: fields,
             col_index: 0,
+            bytea_output: ByteaOutput::default(),
         }
     }
 
+    /// Set text output format of `BYTEA` and `BYTEA[]` fields, usually the
+    /// session's `bytea_output` parameter, parsed from client metadata with
+    /// `ByteaOutput::parse`. Binary format is not affected.
+    pub fn with_bytea_output(mut self, bytea_output: ByteaOutput) -> DataRowEncoder {
+        self.bytea_output = bytea_output;
+        self
+    }
+
     /// Encode value with custom type and format
     ///
     /// This encode function ignores data type and format information from
     /// schema of this encoder.
+    ///
+    /// In debug builds, it panics if the value doesn't match `data_type`: in
+    /// binary format any value not accepted by the type, in text format only
+    /// a string appended to a numeric or bool field like `INT8`. Other text
+    /// values, like a string for `TIMESTAMP` or `NUMERIC`, an `i32` for
+    /// `INT8`, or `RawText` for any type, are sent as is.
     pub fn encode_field_with_type_and_format<T>(
         &mut self,
         value: &T,
@@ -193,13 +716,32 @@ impl DataRowEncoder {
     where
         T: ToSql + ToSqlText + Sized,
     {
+        let data_type = domain_base_type(data_type);
         let is_null = if format == FieldFormat::Text {
-            value.to_sql_text(data_type, &mut self.field_buffer)?
+            match self
+                .bytea_output
+                .encode_value(value, data_type, &mut self.field_buffer)
+            {
+                Some(is_null) => is_null,
+                None => value.to_sql_text(data_type, &mut self.field_buffer)?,
+            }
         } else {
-            value.to_sql(data_type, &mut self.field_buffer)?
+            let is_null = value.to_sql(data_type, &mut self.field_buffer)?;
+            if let Kind::Array(_) = data_type.kind() {
+                normalize_empty_array(&mut self.field_buffer);
+            }
+            is_null
         };
 
         if let IsNull::No = is_null {
+            debug_assert!(
+                value_matches_type::<T>(data_type, format),
+                "value of type {} doesn't match field {} of type {} in {:?} format",
+                std::any::type_name::<T>(),
+                self.col_index,
+                data_type,
+                format
+            );
             let buf = self.field_buffer.split().freeze();
             self.buffer.fields.push(Some(buf));
         } else {
@@ -213,30 +755,16 @@ impl DataRowEncoder {
 
     /// Encode value using type and format, defined by schema
     ///
-    /// Panic when encoding more columns than provided as schema.
+    /// Panic when encoding more columns than provided as schema. Like
+    /// `encode_field_with_type_and_format`, panic in debug builds if the value
+    /// doesn't match the field type.
     pub fn encode_field<T>(&mut self, value: &T) -> PgWireResult<()>
     where
         T: ToSql + ToSqlText + Sized,
     {
-        let data_type = self.schema[self.col_index].datatype();
-        let format = self.schema[self.col_index].format();
-
-        let is_null = if format == FieldFormat::Text {
-            value.to_sql_text(data_type, &mut self.field_buffer)?
-        } else {
-            value.to_sql(data_type, &mut self.field_buffer)?
-        };
-
-        if let IsNull::No = is_null {
-            let buf = self.field_buffer.split().freeze();
-            self.buffer.fields.push(Some(buf));
-        } else {
-            self.buffer.fields.push(None);
-        }
-
-        self.col_index += 1;
-        self.field_buffer.clear();
-        Ok(())
+        let schema = self.schema.clone();
+        let field = &schema[self.col_index];
+        self.encode_field_with_type_and_format(value, field.datatype(), field.format())
     }
 
     pub fn finish(mut self) -> PgWireResult<DataRow> {
@@ -245,12 +773,73 @@ impl DataRowEncoder {
     }
 }
 
+/// Resolve a domain, possibly over another domain, to its base type, whose
+/// format values of the domain are sent in.
+fn domain_base_type(data_type: &Type) -> &Type {
+    match data_type.kind() {
+        Kind::Domain(base_type) => domain_base_type(base_type),
+        _ => data_type,
+    }
+}
+
+/// Rewrite a binary array without elements as zero dimensional, as postgres
+/// sends it. `postgres-types` encodes an empty `Vec` as one dimension of
+/// length 0, which some clients fail to decode.
+fn normalize_empty_array(buf: &mut BytesMut) {
+    // header: ndim, has-nulls flag and element oid, then length and lower
+    // bound of each dimension, then elements
+    if buf.len() < 12 {
+        return;
+    }
+    let ndim = i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    if ndim <= 0 || buf.len() != 12 + 8 * ndim as usize {
+        return;
+    }
+    let empty = buf[12..].chunks(8).any(|dim| dim[..4] == [0, 0, 0, 0]);
+    if empty {
+        buf.truncate(12);
+        buf[..8].fill(0);
+    }
+}
+
+/// Test if values of `T` can be encoded as `data_type`.
+///
+/// A binary value not accepted by the type corrupts the field. A text value
+/// is valid as long as its text is, so only strings for numeric and bool
+/// types, most likely encoded into the wrong column, are rejected.
+fn value_matches_type<T: ToSql>(data_type: &Type, format: FieldFormat) -> bool {
+    if T::accepts(data_type) {
+        return true;
+    }
+    match format {
+        FieldFormat::Binary => false,
+        FieldFormat::Text => {
+            let numeric_or_bool = matches!(
+                *data_type,
+                Type::BOOL
+                    | Type::INT2
+                    | Type::INT4
+                    | Type::INT8
+                    | Type::FLOAT4
+                    | Type::FLOAT8
+                    | Type::OID
+            );
+            !(numeric_or_bool && T::accepts(&Type::TEXT))
+        }
+    }
+}
+
 /// Response for frontend describe requests.
 ///
 /// There are two types of describe: statement and portal. When describing
 /// statement, frontend expects parameter types inferenced by server. And both
 /// describe messages will require column definitions for resultset being
 /// returned.
+///
+/// Empty `fields` means the statement returns no rows at all, like `INSERT`
+/// without `RETURNING`, and `NoData` is sent instead of `RowDescription`. A
+/// query that returns columns but happens to have zero rows still has its
+/// fields here.
 #[non_exhaustive]
 #[derive(Debug, new)]
 pub struct DescribeResponse {
@@ -276,36 +865,88 @@ impl DescribeResponse {
         }
     }
 
-    /// Return true if the `DescribeResponse` is empty/nodata
+    /// Return true if the statement returns no rows, and `NoData` is sent.
+    ///
+    /// Parameters of a statement don't matter here, they are described by
+    /// `ParameterDescription` ahead of `NoData`.
     pub fn is_no_data(&self) -> bool {
-        self.parameters.is_none() && self.fields.is_empty()
+        self.fields.is_empty()
+    }
+}
+
+/// Response starting `COPY ... FROM STDIN`, sent to client as
+/// `CopyInResponse`.
+///
+/// The connection then enters copy mode, where `CopyData`, `CopyDone` and
+/// `CopyFail` from client are passed to `SimpleQueryHandler::on_copy_data`,
+/// `on_copy_done` and `on_copy_fail`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, new)]
+pub struct CopyResponse {
+    /// format of the copy data, binary for `COPY ... (FORMAT binary)`, all
+    /// columns share it as in postgres
+    pub format: FieldFormat,
+    /// number of columns of the data
+    pub columns: usize,
+}
+
+impl From<CopyResponse> for CopyInResponse {
+    fn from(copy: CopyResponse) -> CopyInResponse {
+        let format = copy.format.value();
+        CopyInResponse::new(
+            format as i8,
+            copy.columns as i16,
+            vec![format; copy.columns],
+        )
     }
 }
 
 /// Query response types:
 ///
 /// * Query: the response contains data rows
-/// * Execution: response for ddl/dml execution
+/// * Execution: response for ddl/dml execution, only `CommandComplete` with
+///   the tag is sent
 /// * Error: error response
+/// * CopyIn: start of `COPY ... FROM STDIN`, `CommandComplete` is sent once
+///   the copy completes, see `CopyResponse`
 pub enum Response<'a> {
     EmptyQuery,
     Query(QueryResponse<'a>),
     Execution(Tag),
     Error(Box<ErrorInfo>),
+    CopyIn(CopyResponse),
 }
 
 #[cfg(test)]
 mod test {
     use std::time::SystemTime;
 
+    use bytes::Buf;
+
     use super::*;
+    use crate::types::{RawText, Vector};
 
     #[test]
     fn test_command_complete() {
         let tag = Tag::new("INSERT").with_oid(0).with_rows(100);
         let cc = CommandComplete::from(tag);
 
-        assert_eq!(cc.tag, "INSERT 100");
+        assert_eq!(cc.tag, "INSERT 0 100");
+
+        let cc = CommandComplete::from(Tag::new("CREATE TABLE"));
+        assert_eq!(cc.tag, "CREATE TABLE");
+    }
+
+    #[test]
+    fn test_command_complete_large_count() {
+        // row count of a huge scan, past the 32-bit boundary
+        let tag = Tag::new("SELECT").with_default_rows(u32::MAX as u64 + 3);
+        assert_eq!("SELECT 4294967298", CommandComplete::from(tag).tag);
+
+        let tag = Tag::new("UPDATE").with_row_count(u64::MAX);
+        assert_eq!(
+            "UPDATE 18446744073709551615",
+            CommandComplete::from(tag).tag
+        );
     }
 
     #[test]
@@ -327,4 +968,465 @@ mod test {
         assert_eq!(row.fields[1].as_ref().unwrap().len(), 4);
         assert_eq!(row.fields[2].as_ref().unwrap().len(), 26);
     }
+
+    #[test]
+    fn test_field_table_column() {
+        let field = FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Text);
+        let desc = FieldDescription::from(&field);
+        assert_eq!((0, 0), (desc.table_id, desc.column_id));
+
+        let field = field.with_table_column(16384, 2);
+        assert_eq!(Some(16384), field.table_id());
+        assert_eq!(Some(2), field.column_id());
+        let desc = FieldDescription::from(&field);
+        assert_eq!((16384, 2), (desc.table_id, desc.column_id));
+    }
+
+    #[test]
+    fn test_encode_int2() {
+        let schema = Arc::new(vec![
+            FieldInfo::new("text".into(), None, None, Type::INT2, FieldFormat::Text),
+            FieldInfo::new("binary".into(), None, None, Type::INT2, FieldFormat::Binary),
+            FieldInfo::new("null".into(), None, None, Type::INT2, FieldFormat::Binary),
+        ]);
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&Some(-32768i16)).unwrap();
+        encoder.encode_field(&Some(513i16)).unwrap();
+        encoder.encode_field(&None::<i16>).unwrap();
+        let row = encoder.finish().unwrap();
+
+        assert_eq!(b"-32768", row.fields[0].as_ref().unwrap().as_ref());
+        assert_eq!(&[2u8, 1], row.fields[1].as_ref().unwrap().as_ref());
+        assert!(row.fields[2].is_none());
+    }
+
+    #[test]
+    fn test_encode_bytea_output() {
+        let schema = Arc::new(vec![
+            FieldInfo::new("text".into(), None, None, Type::BYTEA, FieldFormat::Text),
+            FieldInfo::new(
+                "binary".into(),
+                None,
+                None,
+                Type::BYTEA,
+                FieldFormat::Binary,
+            ),
+            FieldInfo::new(
+                "array".into(),
+                None,
+                None,
+                Type::BYTEA_ARRAY,
+                FieldFormat::Text,
+            ),
+        ]);
+        let mut encoder = DataRowEncoder::new(schema).with_bytea_output(ByteaOutput::Escape);
+        encoder.encode_field(&b"\x00A\\".as_ref()).unwrap();
+        encoder.encode_field(&b"\x00A".as_ref()).unwrap();
+        encoder
+            .encode_field(&vec![b"\x00".as_ref(), b"A".as_ref()])
+            .unwrap();
+        let row = encoder.finish().unwrap();
+
+        assert_eq!(b"\\000A\\\\", row.fields[0].as_ref().unwrap().as_ref());
+        assert_eq!(b"\x00A", row.fields[1].as_ref().unwrap().as_ref());
+        assert_eq!(b"{\"\\\\000\",A}", row.fields[2].as_ref().unwrap().as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_single_value_oid() {
+        for format in [FieldFormat::Text, FieldFormat::Binary] {
+            let field = FieldInfo::new("oid".into(), None, None, Type::OID, format);
+            let response = QueryResponse::single_value(field, &u32::MAX).unwrap();
+            assert_eq!(Type::OID, *response.row_schema()[0].datatype());
+
+            let rows = response.data_rows().collect::<Vec<_>>().await;
+            assert_eq!(1, rows.len());
+            let row = rows.into_iter().next().unwrap().unwrap();
+            let value = row.fields[0].as_ref().unwrap();
+            let decoded = match format {
+                FieldFormat::Text => {
+                    assert_eq!(b"4294967295", value.as_ref());
+                    std::str::from_utf8(value).unwrap().parse::<u32>().unwrap()
+                }
+                FieldFormat::Binary => {
+                    assert_eq!(&[0xff, 0xff, 0xff, 0xff], value.as_ref());
+                    <u32 as postgres_types::FromSql>::from_sql(&Type::OID, value).unwrap()
+                }
+            };
+            assert_eq!(u32::MAX, decoded);
+        }
+
+        let field = FieldInfo::new(
+            "nextval".into(),
+            None,
+            None,
+            Type::INT8,
+            FieldFormat::Binary,
+        );
+        let response = QueryResponse::single_value(field, &42i64).unwrap();
+        let row = response.data_rows().next().await.unwrap().unwrap();
+        assert_eq!(
+            &42i64.to_be_bytes(),
+            row.fields[0].as_ref().unwrap().as_ref()
+        );
+    }
+
+    #[test]
+    fn test_encode_runtime_types() {
+        // types chosen at runtime, like a generic query executor does
+        let schema = Arc::new(
+            [("amount", Type::NUMERIC), ("age", Type::INTERVAL)]
+                .into_iter()
+                .map(|(name, datatype)| {
+                    FieldInfo::new(name.into(), None, None, datatype, FieldFormat::Text)
+                })
+                .collect::<Vec<_>>(),
+        );
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&"1234.5600").unwrap();
+        encoder.encode_field(&RawText(b"1 day 02:03:04")).unwrap();
+        let row = encoder.finish().unwrap();
+        assert_eq!(b"1234.5600", row.fields[0].as_ref().unwrap().as_ref());
+        assert_eq!(b"1 day 02:03:04", row.fields[1].as_ref().unwrap().as_ref());
+
+        assert!(value_matches_type::<i64>(&Type::INT8, FieldFormat::Binary));
+        assert!(!value_matches_type::<&str>(&Type::INT8, FieldFormat::Text));
+        assert!(!value_matches_type::<&str>(
+            &Type::NUMERIC,
+            FieldFormat::Binary
+        ));
+        assert!(value_matches_type::<i32>(&Type::INT8, FieldFormat::Text));
+        assert!(!value_matches_type::<i32>(&Type::INT8, FieldFormat::Binary));
+        assert!(value_matches_type::<&str>(&Type::VOID, FieldFormat::Text));
+        assert!(value_matches_type::<&str>(
+            &Type::TIMESTAMP,
+            FieldFormat::Text
+        ));
+        assert!(!value_matches_type::<&str>(&Type::BOOL, FieldFormat::Text));
+    }
+
+    /// Values the examples encode, which must not trip the type check.
+    #[test]
+    fn test_encode_example_values() {
+        let field =
+            |datatype: Type| FieldInfo::new("c".into(), None, None, datatype, FieldFormat::Text);
+        let schema = Arc::new(vec![
+            // bench, server and secure_server
+            field(Type::INT4),
+            field(Type::VARCHAR),
+            // sleep
+            field(Type::VOID),
+            // proxy, forwarding text of any type
+            field(Type::TIMESTAMP),
+            // sqlite
+            field(Type::INT8),
+            field(Type::INT2),
+            field(Type::FLOAT8),
+            field(Type::TEXT),
+            field(Type::BYTEA),
+            field(Type::INT8),
+        ]);
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&Some(1i32)).unwrap();
+        encoder.encode_field(&Some("Tom")).unwrap();
+        encoder.encode_field(&RawText(b"")).unwrap();
+        encoder
+            .encode_field(&Some(RawText(b"2024-01-02 03:04:05")))
+            .unwrap();
+        encoder.encode_field(&42i64).unwrap();
+        encoder.encode_field(&7i16).unwrap();
+        encoder.encode_field(&1.5f64).unwrap();
+        encoder.encode_field(&"text").unwrap();
+        encoder.encode_field(&vec![0xdeu8, 0xad]).unwrap();
+        encoder.encode_field(&None::<i8>).unwrap();
+        let row = encoder.finish().unwrap();
+
+        let fields = row
+            .fields
+            .iter()
+            .map(|f| f.as_ref().map(|b| b.as_ref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                Some(b"1".as_ref()),
+                Some(b"Tom"),
+                Some(b""),
+                Some(b"2024-01-02 03:04:05"),
+                Some(b"42"),
+                Some(b"7"),
+                Some(b"1.5"),
+                Some(b"text"),
+                Some(b"\\xdead"),
+                None,
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't match field 0 of type int8")]
+    fn test_encode_mismatched_type() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT8,
+            FieldFormat::Text,
+        )]);
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&"42").unwrap();
+    }
+
+    #[test]
+    fn test_encode_raw_text() {
+        let schema = Arc::new(vec![FieldInfo::new_with_type_oid(
+            "path".into(),
+            None,
+            None,
+            "ltree",
+            90001,
+        )]);
+        assert_eq!(90001, schema[0].datatype().oid());
+        assert_eq!("ltree", schema[0].datatype().name());
+
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&RawText(b"Top.Science")).unwrap();
+        let row = encoder.finish().unwrap();
+        assert_eq!(b"Top.Science", row.fields[0].as_ref().unwrap().as_ref());
+    }
+
+    #[test]
+    fn test_encode_vector() {
+        let field = FieldInfo::new_with_type_oid("embedding".into(), None, None, "vector", 90002);
+        let schema = Arc::new(vec![field.clone(), field.with_format(FieldFormat::Binary)]);
+
+        let mut encoder = DataRowEncoder::new(schema);
+        let value = Vector(vec![1.0, 2.5]);
+        encoder.encode_field(&value).unwrap();
+        encoder.encode_field(&value).unwrap();
+        let row = encoder.finish().unwrap();
+        assert_eq!(b"[1,2.5]", row.fields[0].as_ref().unwrap().as_ref());
+        assert_eq!(
+            &[0, 2, 0, 0, 0x3f, 0x80, 0, 0, 0x40, 0x20, 0, 0],
+            row.fields[1].as_ref().unwrap().as_ref()
+        );
+    }
+
+    #[test]
+    fn test_encode_binary_integers() {
+        // binary format is big-endian, network byte order
+        let schema = Arc::new(
+            [Type::INT2, Type::INT4, Type::INT4, Type::INT8, Type::OID]
+                .into_iter()
+                .map(|datatype| {
+                    FieldInfo::new("n".into(), None, None, datatype, FieldFormat::Binary)
+                })
+                .collect::<Vec<_>>(),
+        );
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&1i16).unwrap();
+        encoder.encode_field(&1i32).unwrap();
+        encoder.encode_field(&-2i32).unwrap();
+        encoder.encode_field(&0x0102_0304_0506_0708i64).unwrap();
+        encoder.encode_field(&0x0102_0304u32).unwrap();
+        let row = encoder.finish().unwrap();
+        let fields = row
+            .fields
+            .iter()
+            .map(|field| field.as_ref().unwrap().as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                [0, 1].as_ref(),
+                &[0, 0, 0, 1],
+                &[0xff, 0xff, 0xff, 0xfe],
+                &[1, 2, 3, 4, 5, 6, 7, 8],
+                &[1, 2, 3, 4],
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn test_type_size() {
+        // `typlen` of `pg_type` in postgres
+        assert_eq!(1, type_size(&Type::BOOL));
+        assert_eq!(4, type_size(&Type::INT4));
+        assert_eq!(8, type_size(&Type::TIMESTAMPTZ));
+        assert_eq!(16, type_size(&Type::UUID));
+        assert_eq!(64, type_size(&Type::NAME));
+        assert_eq!(-1, type_size(&Type::TEXT));
+        assert_eq!(-1, type_size(&Type::INT4_ARRAY));
+        assert_eq!(-2, type_size(&Type::CSTRING));
+
+        let field = FieldInfo::new("n".into(), None, None, Type::INT8, FieldFormat::Text)
+            .with_domain("public", "positive", 90003);
+        let description = FieldDescription::from(&field);
+        assert_eq!(8, description.type_size);
+        assert_eq!(-1, description.type_modifier);
+        let field = FieldInfo::new_with_type_oid("path".into(), None, None, "ltree", 90001);
+        assert_eq!(-1, FieldDescription::from(&field).type_size);
+    }
+
+    #[test]
+    fn test_encode_domain() {
+        // CREATE DOMAIN email AS text; CREATE DOMAIN billing.positive AS int4
+        let schema = Arc::new(vec![
+            FieldInfo::new("email".into(), None, None, Type::TEXT, FieldFormat::Text)
+                .with_domain("public", "email", 90002),
+            FieldInfo::new("n".into(), None, None, Type::INT4, FieldFormat::Binary)
+                .with_domain("billing", "positive", 90003),
+        ]);
+        let row_description = into_row_description(&schema);
+        assert_eq!(90002, row_description.fields[0].type_id);
+        assert_eq!(90003, row_description.fields[1].type_id);
+        assert_eq!(Kind::Domain(Type::INT4), *schema[1].datatype().kind());
+        assert_eq!("billing", schema[1].datatype().schema());
+
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&"a@example.com").unwrap();
+        encoder.encode_field(&7i32).unwrap();
+        let row = encoder.finish().unwrap();
+        assert_eq!(b"a@example.com", row.fields[0].as_ref().unwrap().as_ref());
+        assert_eq!(b"\0\0\0\x07", row.fields[1].as_ref().unwrap().as_ref());
+    }
+
+    #[tokio::test]
+    async fn test_blocking_row_stream() {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "id".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+
+        let row_schema = schema.clone();
+        let rows = blocking_row_stream(2, move |sender| {
+            for i in 0..10 {
+                let mut encoder = DataRowEncoder::new(row_schema.clone());
+                encoder.encode_field(&i)?;
+                assert!(sender.send(encoder.finish()));
+            }
+            Ok(())
+        });
+        let rows = rows.collect::<Vec<_>>().await;
+        assert_eq!(10, rows.len());
+        assert!(rows.iter().all(|r| r.is_ok()));
+
+        // zero buffer is taken as 1
+        let row_schema = schema.clone();
+        let rows = blocking_row_stream(0, move |sender| {
+            for _ in 0..3 {
+                assert!(sender.send(DataRowEncoder::new(row_schema.clone()).finish()));
+            }
+            Ok(())
+        });
+        assert_eq!(3, rows.collect::<Vec<_>>().await.len());
+
+        let rows = blocking_row_stream(2, |_| Err(PgWireError::UserNameRequired));
+        let rows = rows.collect::<Vec<_>>().await;
+        assert!(matches!(rows[..], [Err(PgWireError::UserNameRequired)]));
+
+        let rows = blocking_row_stream(2, |_| panic!("producer failed"));
+        let rows = rows.collect::<Vec<_>>().await;
+        assert!(matches!(rows[..], [Err(PgWireError::ApiError(_))]));
+
+        // producer stops once the stream is dropped
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let row_schema = schema.clone();
+        let mut rows = blocking_row_stream(1, move |sender| {
+            let mut sent = 0;
+            while sender.send(DataRowEncoder::new(row_schema.clone()).finish()) {
+                sent += 1;
+            }
+            done_tx.send(sent).unwrap();
+            Ok(())
+        });
+        assert!(rows.next().await.is_some());
+        drop(rows);
+        let sent = tokio::task::spawn_blocking(move || done_rx.recv().unwrap())
+            .await
+            .unwrap();
+        assert!(sent < 4);
+    }
+
+    #[tokio::test]
+    async fn test_encode_row_stream() {
+        let schema = Arc::new(vec![
+            FieldInfo::new("id".into(), None, None, Type::INT4, FieldFormat::Text),
+            FieldInfo::new("name".into(), None, None, Type::VARCHAR, FieldFormat::Text),
+        ]);
+
+        // rows of an async driver, a stream that is not `Unpin`
+        let rows = stream::iter(0..3).then(|i| async move {
+            tokio::task::yield_now().await;
+            if i < 2 {
+                Ok((i, format!("user{i}")))
+            } else {
+                Err(std::io::Error::other("connection reset"))
+            }
+        });
+        let rows = encode_row_stream(schema.clone(), rows, |encoder, (id, name)| {
+            encoder.encode_field(&id)?;
+            encoder.encode_field(&name)
+        });
+        let response = QueryResponse::new(schema, rows);
+
+        let rows = response.data_rows().collect::<Vec<_>>().await;
+        assert_eq!(3, rows.len());
+        let row = rows[1].as_ref().unwrap();
+        assert_eq!(2, row.fields.len());
+        assert_eq!(b"user1", row.fields[1].as_ref().unwrap().as_ref());
+        assert!(matches!(rows[2], Err(PgWireError::ApiError(_))));
+    }
+
+    #[test]
+    fn test_encode_arrays() {
+        let schema = Arc::new(vec![
+            FieldInfo::new(
+                "a".into(),
+                None,
+                None,
+                Type::INT4_ARRAY,
+                FieldFormat::Binary,
+            ),
+            FieldInfo::new(
+                "b".into(),
+                None,
+                None,
+                Type::INT4_ARRAY,
+                FieldFormat::Binary,
+            ),
+            FieldInfo::new("c".into(), None, None, Type::INT4_ARRAY, FieldFormat::Text),
+        ]);
+        let mut encoder = DataRowEncoder::new(schema);
+        encoder.encode_field(&Vec::<i32>::new()).unwrap();
+        encoder.encode_field(&vec![None, Some(1i32)]).unwrap();
+        encoder.encode_field(&vec![None, Some(1i32)]).unwrap();
+        let row = encoder.finish().unwrap();
+
+        // empty array has no dimension
+        let empty = row.fields[0].as_ref().unwrap();
+        assert_eq!(12, empty.len());
+        assert_eq!(0, (&empty[0..4]).get_i32());
+        assert_eq!(0, (&empty[4..8]).get_i32());
+        assert_eq!(Type::INT4.oid(), (&empty[8..12]).get_u32());
+
+        // ndim, has-nulls, element oid, dimension, then a NULL and a 4-byte
+        // element
+        let mut nulls = row.fields[1].clone().unwrap();
+        assert_eq!(1, nulls.get_i32());
+        assert_eq!(1, nulls.get_i32());
+        assert_eq!(Type::INT4.oid(), nulls.get_u32());
+        assert_eq!(2, nulls.get_i32());
+        assert_eq!(1, nulls.get_i32());
+        assert_eq!(-1, nulls.get_i32());
+        assert_eq!(4, nulls.get_i32());
+        assert_eq!(1, nulls.get_i32());
+        assert!(nulls.is_empty());
+
+        assert_eq!(b"{NULL,1}", row.fields[2].as_ref().unwrap().as_ref());
+    }
 }
@@ -0,0 +1,191 @@
+use super::results::{Response, Tag};
+use super::set::strip_keyword;
+use super::store::PortalStore;
+use super::{ClientInfo, ClientPortalStore, TransactionStatus, DEFAULT_NAME};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// A parsed statement that drops session state kept in `PortalStore`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResetStatement {
+    /// `DEALLOCATE [ PREPARE ] name`
+    Deallocate(String),
+    /// `DEALLOCATE [ PREPARE ] ALL`
+    DeallocateAll,
+    /// `DISCARD ALL`, used by connection poolers to reset a connection
+    /// between clients
+    DiscardAll,
+}
+
+impl ResetStatement {
+    /// Try to parse a `DEALLOCATE` or `DISCARD ALL` statement from query
+    /// string. Return `None` if the query is not one of them.
+    pub fn parse(query: &str) -> Option<ResetStatement> {
+        let query = query.trim().trim_end_matches(';').trim_end();
+        // append a space so keyword at the end can be stripped as well
+        let query = format!("{query} ");
+
+        if let Some(rest) = strip_keyword(&query, "DEALLOCATE") {
+            let rest = strip_keyword(rest, "PREPARE").unwrap_or(rest);
+            if let Some("") = strip_keyword(rest, "ALL") {
+                return Some(ResetStatement::DeallocateAll);
+            }
+            let name = rest.trim_end();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return None;
+            }
+            let name = name
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+                .map(|name| name.replace("\"\"", "\""))
+                .unwrap_or_else(|| name.to_lowercase());
+            Some(ResetStatement::Deallocate(name))
+        } else if let Some("") =
+            strip_keyword(&query, "DISCARD").and_then(|rest| strip_keyword(rest, "ALL"))
+        {
+            Some(ResetStatement::DiscardAll)
+        } else {
+            None
+        }
+    }
+}
+
+/// Apply the `DEALLOCATE` or `DISCARD ALL` statement to client's
+/// `PortalStore`.
+///
+/// Simple queries of these statements are passed by `SimpleQueryHandler` to
+/// its `on_reset`, which calls this by default, once
+/// `handle_reset_statements` is enabled. Call it from
+/// `ExtendedQueryHandler::do_query` to support them in extended query too.
+///
+/// `DEALLOCATE ALL` removes every prepared statement, and `DISCARD ALL`
+/// removes portals as well. Session parameters set by `SET` are kept, reset
+/// them in your handler if the server tracks any. As in postgres,
+/// deallocating a statement that doesn't exist is an error, and so is
+/// `DISCARD ALL` in a transaction block.
+pub fn on_reset<C>(client: &mut C, statement: &ResetStatement) -> PgWireResult<Response<'static>>
+where
+    C: ClientInfo + ClientPortalStore,
+    C::PortalStore: PortalStore,
+{
+    if *statement == ResetStatement::DiscardAll
+        && client.transaction_status() != TransactionStatus::Idle
+    {
+        return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "25001".to_owned(),
+            "DISCARD ALL cannot run inside a transaction block".to_owned(),
+        ))));
+    }
+
+    let store = client.portal_store();
+    let tag = match statement {
+        ResetStatement::Deallocate(name) => {
+            if name == DEFAULT_NAME || store.get_statement(name).is_none() {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "26000".to_owned(),
+                    format!("prepared statement \"{name}\" does not exist"),
+                ))));
+            }
+            store.rm_statement(name);
+            Tag::new("DEALLOCATE")
+        }
+        ResetStatement::DeallocateAll => {
+            store.clear_statements();
+            Tag::new("DEALLOCATE ALL")
+        }
+        ResetStatement::DiscardAll => {
+            store.clear_portals();
+            store.clear_statements();
+            Tag::new("DISCARD ALL")
+        }
+    };
+
+    Ok(Response::Execution(tag))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::api::portal::Portal;
+    use crate::api::stmt::StoredStatement;
+    use crate::api::DefaultClient;
+    use crate::messages::extendedquery::Bind;
+
+    #[test]
+    fn test_parse_reset() {
+        assert_eq!(
+            Some(ResetStatement::DeallocateAll),
+            ResetStatement::parse("DEALLOCATE ALL;")
+        );
+        assert_eq!(
+            Some(ResetStatement::DeallocateAll),
+            ResetStatement::parse("deallocate prepare all")
+        );
+        assert_eq!(
+            Some(ResetStatement::Deallocate("find_user".to_owned())),
+            ResetStatement::parse("DEALLOCATE Find_User")
+        );
+        assert_eq!(
+            Some(ResetStatement::Deallocate("S_1".to_owned())),
+            ResetStatement::parse("DEALLOCATE PREPARE \"S_1\"")
+        );
+        assert_eq!(
+            Some(ResetStatement::DiscardAll),
+            ResetStatement::parse("discard all")
+        );
+        assert_eq!(None, ResetStatement::parse("DEALLOCATE"));
+        assert_eq!(None, ResetStatement::parse("DISCARD PLANS"));
+        assert_eq!(None, ResetStatement::parse("SELECT 1"));
+    }
+
+    #[test]
+    fn test_on_reset() {
+        let mut client = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        let store = client.portal_store();
+        for name in ["s1", "s2"] {
+            let statement = StoredStatement::new(name.to_owned(), "SELECT 1".to_owned(), vec![]);
+            store.put_statement(Arc::new(statement));
+        }
+        let bind = Bind::new(
+            Some("p1".to_owned()),
+            Some("s1".to_owned()),
+            vec![],
+            vec![],
+            vec![],
+        );
+        let portal = Portal::try_new(&bind, store.get_statement("s1").unwrap()).unwrap();
+        store.put_portal(Arc::new(portal));
+
+        let deallocate = ResetStatement::Deallocate("s1".to_owned());
+        assert!(on_reset(&mut client, &deallocate).is_ok());
+        assert!(client.portal_store().get_statement("s1").is_none());
+        match on_reset(&mut client, &deallocate) {
+            Err(PgWireError::UserError(e)) => assert_eq!("26000", e.code),
+            _ => panic!("expect prepared statement does not exist error"),
+        }
+
+        match on_reset(&mut client, &ResetStatement::DeallocateAll) {
+            Ok(Response::Execution(tag)) => assert_eq!(Tag::new("DEALLOCATE ALL"), tag),
+            _ => panic!("expect DEALLOCATE ALL"),
+        }
+        assert!(client.portal_store().get_statement("s2").is_none());
+        assert!(client.portal_store().get_portal("p1").is_some());
+
+        client.set_transaction_status(TransactionStatus::Transaction);
+        match on_reset(&mut client, &ResetStatement::DiscardAll) {
+            Err(PgWireError::UserError(e)) => assert_eq!("25001", e.code),
+            _ => panic!("expect DISCARD ALL to be rejected in transaction"),
+        }
+        assert!(client.portal_store().get_portal("p1").is_some());
+
+        client.set_transaction_status(TransactionStatus::Idle);
+        match on_reset(&mut client, &ResetStatement::DiscardAll) {
+            Ok(Response::Execution(tag)) => assert_eq!(Tag::new("DISCARD ALL"), tag),
+            _ => panic!("expect DISCARD ALL"),
+        }
+        assert!(client.portal_store().get_portal("p1").is_none());
+    }
+}
@@ -0,0 +1,1223 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+
+use futures::{Sink, SinkExt};
+
+use super::results::{FieldFormat, FieldInfo, QueryResponse, Response, Tag};
+use super::{ClientInfo, TransactionStatus, Type, METADATA_INTEGER_DATETIMES, METADATA_USER};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::messages::startup::ParameterStatus;
+use crate::messages::PgWireBackendMessage;
+
+/// Runtime parameters known by default, with their canonical names.
+const DEFAULT_KNOWN_PARAMETERS: &[&str] = &[
+    "application_name",
+    "client_encoding",
+    "client_min_messages",
+    "DateStyle",
+    "default_transaction_deferrable",
+    "default_transaction_isolation",
+    "default_transaction_read_only",
+    "extra_float_digits",
+    "IntervalStyle",
+    "lock_timeout",
+    "search_path",
+    "session_authorization",
+    "standard_conforming_strings",
+    "statement_timeout",
+    "TimeZone",
+    "transaction_deferrable",
+    "transaction_isolation",
+    "transaction_read_only",
+];
+
+/// Values of parameters before any `SET`, as in postgres, except for
+/// `DateStyle` which matches `DefaultServerParameterProvider`.
+/// `session_authorization` defaults to the session user, and `transaction_*`
+/// parameters to their `default_transaction_*` counterparts.
+const DEFAULT_PARAMETER_VALUES: &[(&str, &str)] = &[
+    ("application_name", ""),
+    ("client_encoding", "UTF8"),
+    ("client_min_messages", "notice"),
+    ("DateStyle", "ISO YMD"),
+    ("default_transaction_deferrable", "off"),
+    ("default_transaction_isolation", "read committed"),
+    ("default_transaction_read_only", "off"),
+    ("extra_float_digits", "1"),
+    ("IntervalStyle", "postgres"),
+    ("lock_timeout", "0"),
+    ("search_path", "\"$user\", public"),
+    ("standard_conforming_strings", "on"),
+    ("statement_timeout", "0"),
+    ("TimeZone", "UTC"),
+];
+
+/// Parameters marked `GUC_REPORT` in postgres. Client is notified with
+/// `ParameterStatus` when they change, so drivers can track them.
+const DEFAULT_REPORTED_PARAMETERS: &[&str] = &[
+    "application_name",
+    "client_encoding",
+    "DateStyle",
+    "integer_datetimes",
+    "IntervalStyle",
+    "is_superuser",
+    "server_version",
+    "session_authorization",
+    "standard_conforming_strings",
+    "TimeZone",
+];
+
+/// Connection settings from startup message, which are not runtime
+/// parameters and cannot be `SET` whatever `UnknownParameterPolicy` is.
+const RESERVED_NAMES: &[&str] = &["database", "options", "replication", "user"];
+
+/// Values accepted for isolation levels, as in postgres.
+const ISOLATION_LEVELS: &[&str] = &[
+    "serializable",
+    "repeatable read",
+    "read committed",
+    "read uncommitted",
+];
+
+/// Parameters reported to client but fixed for the session.
+const READ_ONLY_PARAMETERS: &[&str] = &[
+    "in_hot_standby",
+    METADATA_INTEGER_DATETIMES,
+    "is_superuser",
+    "server_encoding",
+    "server_version",
+    "server_version_num",
+];
+
+/// How to respond `SET` for a parameter unknown to the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownParameterPolicy {
+    /// Reject with `42704 unrecognized configuration parameter`, as postgres
+    /// does.
+    #[default]
+    Error,
+    /// Accept the value and track it as if it's a known parameter.
+    Accept,
+}
+
+/// A parsed `SET` statement.
+#[non_exhaustive]
+#[derive(Debug, new, PartialEq, Eq)]
+pub struct SetStatement {
+    /// parameter name as provided by client
+    pub name: String,
+    /// value of the parameter, `None` for `DEFAULT`
+    pub value: Option<String>,
+    /// `SET LOCAL` affects current transaction only
+    pub local: bool,
+}
+
+impl SetStatement {
+    /// Try to parse a `SET` statement from query string.
+    ///
+    /// Supported forms are `SET [ SESSION | LOCAL ] name { TO | = } value`,
+    /// `SET [ SESSION | LOCAL ] TIME ZONE value` and `SET SESSION
+    /// AUTHORIZATION value`. Return `None` if the query is not a `SET`
+    /// statement of these forms.
+    pub fn parse(query: &str) -> Option<SetStatement> {
+        let query = query.trim().trim_end_matches(';').trim_end();
+        let rest = strip_keyword(query, "SET")?;
+
+        let (local, rest) = if let Some(rest) = strip_keyword(rest, "LOCAL") {
+            (true, rest)
+        } else if let Some(rest) = strip_keyword(rest, "SESSION") {
+            (false, rest)
+        } else {
+            (false, rest)
+        };
+
+        let (name, value) = if let Some(value) =
+            strip_keyword(rest, "TIME").and_then(|rest| strip_keyword(rest, "ZONE"))
+        {
+            ("TimeZone", value)
+        } else if let Some(value) = strip_keyword(rest, "AUTHORIZATION") {
+            ("session_authorization", value)
+        } else {
+            let name_end = rest
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(rest.len());
+            let (name, rest) = rest.split_at(name_end);
+            let rest = rest.trim_start();
+            let value = if let Some(value) = rest.strip_prefix('=') {
+                value.trim_start()
+            } else {
+                strip_keyword(rest, "TO")?
+            };
+            // quoted identifier
+            let name = name
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+                .unwrap_or(name);
+            (name, value)
+        };
+
+        if name.is_empty() || value.is_empty() {
+            return None;
+        }
+
+        let value = if value.eq_ignore_ascii_case("DEFAULT") {
+            None
+        } else {
+            Some(unquote(value))
+        };
+
+        Some(SetStatement {
+            name: name.to_owned(),
+            value,
+            local,
+        })
+    }
+}
+
+impl SetStatement {
+    /// Like `parse`, and also parse statements setting transaction
+    /// characteristics, which may set several parameters at once:
+    ///
+    /// - `SET SESSION CHARACTERISTICS AS TRANSACTION modes` sets
+    ///   `default_transaction_isolation`, `default_transaction_read_only` and
+    ///   `default_transaction_deferrable`
+    /// - `SET TRANSACTION modes` sets `transaction_isolation`,
+    ///   `transaction_read_only` and `transaction_deferrable`, as `LOCAL`
+    ///   statements
+    ///
+    /// where modes are `ISOLATION LEVEL level`, `READ ONLY`, `READ WRITE` or
+    /// `[NOT] DEFERRABLE`. Isolation levels are returned in lowercase, e.g.
+    /// `repeatable read`, and boolean modes as `on` or `off`.
+    pub fn parse_all(query: &str) -> Option<Vec<SetStatement>> {
+        let trimmed = query.trim().trim_end_matches(';').trim_end();
+        let rest = strip_keyword(trimmed, "SET")?;
+
+        let (prefix, local, modes) = if let Some(modes) = strip_keyword(rest, "SESSION")
+            .and_then(|rest| strip_keyword(rest, "CHARACTERISTICS"))
+            .and_then(|rest| strip_keyword(rest, "AS"))
+            .and_then(|rest| strip_keyword(rest, "TRANSACTION"))
+        {
+            ("default_transaction_", false, modes)
+        } else if let Some(modes) = strip_keyword(rest, "TRANSACTION") {
+            ("transaction_", true, modes)
+        } else {
+            return SetStatement::parse(query).map(|statement| vec![statement]);
+        };
+
+        let statements = parse_transaction_modes(modes)?
+            .into_iter()
+            .map(|(name, value)| {
+                SetStatement::new(format!("{prefix}{name}"), Some(value.to_owned()), local)
+            })
+            .collect();
+        Some(statements)
+    }
+}
+
+/// Parse transaction modes as parameter name suffix and value, return `None`
+/// if any mode is invalid.
+fn parse_transaction_modes(modes: &str) -> Option<Vec<(&'static str, &'static str)>> {
+    let words = modes
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|w| !w.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect::<Vec<String>>();
+    let words = words.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    let mut parsed = Vec::new();
+    let mut rest = &words[..];
+    while !rest.is_empty() {
+        let (mode, len) = match rest {
+            ["ISOLATION", "LEVEL", "SERIALIZABLE", ..] => (("isolation", "serializable"), 3),
+            ["ISOLATION", "LEVEL", "REPEATABLE", "READ", ..] => {
+                (("isolation", "repeatable read"), 4)
+            }
+            ["ISOLATION", "LEVEL", "READ", "COMMITTED", ..] => (("isolation", "read committed"), 4),
+            ["ISOLATION", "LEVEL", "READ", "UNCOMMITTED", ..] => {
+                (("isolation", "read uncommitted"), 4)
+            }
+            ["READ", "ONLY", ..] => (("read_only", "on"), 2),
+            ["READ", "WRITE", ..] => (("read_only", "off"), 2),
+            ["DEFERRABLE", ..] => (("deferrable", "on"), 1),
+            ["NOT", "DEFERRABLE", ..] => (("deferrable", "off"), 2),
+            _ => return None,
+        };
+        parsed.push(mode);
+        rest = &rest[len..];
+    }
+
+    if parsed.is_empty() {
+        None
+    } else {
+        Some(parsed)
+    }
+}
+
+/// Parse a `SHOW` statement, return the parameter name as provided by client.
+///
+/// `SHOW TIME ZONE` and `SHOW TRANSACTION ISOLATION LEVEL` are returned as
+/// `TimeZone` and `transaction_isolation`. `SHOW ALL` is not supported.
+fn parse_show(query: &str) -> Option<&str> {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let rest = strip_keyword(query, "SHOW")?;
+    if rest.eq_ignore_ascii_case("TIME ZONE") {
+        return Some("TimeZone");
+    }
+    if strip_keyword(rest, "TRANSACTION")
+        .and_then(|rest| strip_keyword(rest, "ISOLATION"))
+        .is_some_and(|rest| rest.eq_ignore_ascii_case("LEVEL"))
+    {
+        return Some("transaction_isolation");
+    }
+    if rest.is_empty() || rest.eq_ignore_ascii_case("ALL") || rest.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(rest)
+}
+
+/// Strip a case-insensitive keyword and following whitespaces from `s`.
+pub(crate) fn strip_keyword<'a>(s: &'a str, keyword: &str) -> Option<&'a str> {
+    let head = s.get(..keyword.len())?;
+    let rest = &s[keyword.len()..];
+    if head.eq_ignore_ascii_case(keyword) && rest.starts_with(char::is_whitespace) {
+        Some(rest.trim_start())
+    } else {
+        None
+    }
+}
+
+/// Remove single quotes from a literal value, unescaping `''`.
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].replace("''", "'")
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Default handler for `SET` statements.
+///
+/// Accepted values are stored in client metadata under the canonical name of
+/// the parameter. For parameters unknown to this handler, the behaviour is
+/// decided by `UnknownParameterPolicy`. Some drivers set proprietary
+/// parameters on connect, use `UnknownParameterPolicy::Accept` to stay
+/// compatible with them. Startup settings kept in metadata, like `user` and
+/// `database`, are never accepted, nor are read-only parameters like
+/// `server_version`.
+///
+/// `SET LOCAL` takes effect until the end of the current transaction, as told
+/// by `ClientInfo::transaction_status`, when `reset_local_parameters` restores
+/// the session values. Outside a transaction block it's ignored with a
+/// warning, as in postgres.
+///
+/// Changes of reported parameters, like `client_encoding` or `TimeZone`, are
+/// sent to client as `ParameterStatus`, as postgres does for `GUC_REPORT`
+/// variables.
+///
+/// `SET SESSION AUTHORIZATION` is refused, as this handler cannot check
+/// whether the session user is allowed to switch identity. Enable it with
+/// `set_allow_session_authorization` if your server does this check.
+#[derive(Debug)]
+pub struct DefaultSetHandler {
+    /// lowercased name to canonical name
+    known_parameters: HashMap<String, String>,
+    /// canonical names of parameters reported to client on change
+    reported_parameters: HashSet<String>,
+    /// canonical name to value before any `SET`
+    parameter_defaults: HashMap<String, String>,
+    /// canonical name to lowercased values accepted by `SET`
+    allowed_values: HashMap<String, Vec<String>>,
+    unknown_parameter_policy: UnknownParameterPolicy,
+    allow_session_authorization: bool,
+}
+
+impl Default for DefaultSetHandler {
+    fn default() -> Self {
+        DefaultSetHandler::new(UnknownParameterPolicy::default())
+    }
+}
+
+impl DefaultSetHandler {
+    pub fn new(unknown_parameter_policy: UnknownParameterPolicy) -> DefaultSetHandler {
+        let mut handler = DefaultSetHandler {
+            known_parameters: HashMap::new(),
+            reported_parameters: HashSet::new(),
+            parameter_defaults: HashMap::new(),
+            allowed_values: HashMap::new(),
+            unknown_parameter_policy,
+            allow_session_authorization: false,
+        };
+        for name in DEFAULT_KNOWN_PARAMETERS {
+            handler.add_known_parameter(name);
+        }
+        handler.reported_parameters = DEFAULT_REPORTED_PARAMETERS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        handler.parameter_defaults = DEFAULT_PARAMETER_VALUES
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        handler.set_allowed_values("default_transaction_isolation", ISOLATION_LEVELS);
+        handler.set_allowed_values("transaction_isolation", ISOLATION_LEVELS);
+        handler
+    }
+
+    /// Register a parameter name supported by your server.
+    pub fn add_known_parameter(&mut self, name: &str) {
+        self.known_parameters
+            .insert(name.to_lowercase(), name.to_owned());
+    }
+
+    /// Register a parameter supported by your server, and notify client with
+    /// `ParameterStatus` when it's changed.
+    pub fn add_reported_parameter(&mut self, name: &str) {
+        self.add_known_parameter(name);
+        self.reported_parameters.insert(name.to_owned());
+    }
+
+    /// Set how to respond `SET` for unknown parameters.
+    pub fn set_unknown_parameter_policy(&mut self, policy: UnknownParameterPolicy) {
+        self.unknown_parameter_policy = policy;
+    }
+
+    pub fn unknown_parameter_policy(&self) -> UnknownParameterPolicy {
+        self.unknown_parameter_policy
+    }
+
+    /// Set the value of a parameter before any `SET`, as reported to client
+    /// on `SET ... TO DEFAULT`. Use it when your server's defaults differ
+    /// from postgres, e.g. for `TimeZone`.
+    pub fn set_parameter_default(&mut self, name: &str, value: &str) {
+        self.add_known_parameter(name);
+        self.parameter_defaults
+            .insert(name.to_owned(), value.to_owned());
+    }
+
+    /// Register a parameter only accepting one of `values`, case insensitive,
+    /// e.g. `bytea_output` with `escape` and `hex` if your server encodes
+    /// bytea with `DataRowEncoder::with_bytea_output`. Other values are
+    /// rejected with `22023 invalid value for parameter`.
+    pub fn set_allowed_values(&mut self, name: &str, values: &[&str]) {
+        self.add_known_parameter(name);
+        self.allowed_values.insert(
+            name.to_owned(),
+            values.iter().map(|value| value.to_lowercase()).collect(),
+        );
+    }
+
+    /// Allow `SET SESSION AUTHORIZATION`. Only enable it when your server
+    /// restricts it to privileged users, as postgres does for superusers.
+    pub fn set_allow_session_authorization(&mut self, allow: bool) {
+        self.allow_session_authorization = allow;
+    }
+
+    /// Value of a parameter before any `SET`.
+    fn default_value<C: ClientInfo>(&self, client: &C, name: &str) -> Option<String> {
+        if name == "session_authorization" {
+            return client.metadata().get(METADATA_USER).cloned();
+        }
+        self.parameter_defaults.get(name).cloned()
+    }
+
+    /// Apply the `SET` statement to client metadata.
+    ///
+    /// `SET ... TO DEFAULT` removes the parameter from metadata. A new value of
+    /// reported parameter, or its default on reset, is fed to client as
+    /// `ParameterStatus`, and flushed with the rest of the query response.
+    pub async fn on_set<C>(
+        &self,
+        client: &mut C,
+        statement: &SetStatement,
+    ) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        // e.g. timestamps are always encoded as integers, as in postgres this
+        // is fixed at build time
+        if let Some(name) = READ_ONLY_PARAMETERS
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(&statement.name))
+        {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "55P02".to_owned(),
+                format!("parameter \"{name}\" cannot be changed"),
+            ))));
+        }
+
+        let lowercase = statement.name.to_lowercase();
+        let name = match self.known_parameters.get(&lowercase) {
+            Some(name) => name.clone(),
+            None if self.unknown_parameter_policy == UnknownParameterPolicy::Accept
+                && !RESERVED_NAMES.contains(&lowercase.as_str()) =>
+            {
+                lowercase
+            }
+            None => return Err(unrecognized_parameter_error(&statement.name)),
+        };
+        if name == "session_authorization" && !self.allow_session_authorization {
+            return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "42501".to_owned(),
+                "permission denied to set session authorization".to_owned(),
+            ))));
+        }
+
+        let value = statement
+            .value
+            .as_deref()
+            .map(|value| check_parameter_value(&name, value, self.allowed_values.get(&name)))
+            .transpose()?;
+        if statement.local {
+            if client.transaction_status() == TransactionStatus::Idle {
+                let command = if name.starts_with("transaction_") {
+                    "SET TRANSACTION"
+                } else {
+                    "SET LOCAL"
+                };
+                let warning = ErrorInfo::new(
+                    "WARNING".to_owned(),
+                    "25P01".to_owned(),
+                    format!("{command} can only be used in transaction blocks"),
+                );
+                client
+                    .feed(PgWireBackendMessage::NoticeResponse(warning.into()))
+                    .await?;
+                return Ok(Response::Execution(Tag::new("SET")));
+            }
+            let session_value = client.metadata().get(&name).cloned();
+            let report = self
+                .reported_parameters
+                .contains(&name)
+                .then(|| self.reported_value(client, &name, session_value.clone()));
+            local_parameters(client)?.save(&name, session_value, report);
+        } else {
+            let report = self.reported_value(client, &name, value.clone());
+            if let Some(local) = client
+                .extensions_mut()
+                .and_then(|extensions| extensions.get_mut::<LocalParameters>())
+            {
+                // the new session value takes effect once the transaction ends
+                local.update(&name, value.clone(), report);
+            }
+        }
+
+        let report = self.reported_value(client, &name, value.clone());
+        match value {
+            Some(value) => client.metadata_mut().insert(name.clone(), value),
+            None => client.metadata_mut().remove(&name),
+        };
+        if self.reported_parameters.contains(&name) {
+            client
+                .feed(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+                    name, report,
+                )))
+                .await?;
+        }
+
+        Ok(Response::Execution(Tag::new("SET")))
+    }
+
+    /// Value of a parameter as reported to client, its default if unset.
+    fn reported_value<C: ClientInfo>(
+        &self,
+        client: &C,
+        name: &str,
+        value: Option<String>,
+    ) -> String {
+        value
+            .or_else(|| self.default_value(client, name))
+            .unwrap_or_default()
+    }
+
+    /// Answer `SHOW` with the value of a parameter in client metadata.
+    ///
+    /// Only known and read-only parameters can be shown, and under
+    /// `UnknownParameterPolicy::Accept` those set by client as well. Startup
+    /// settings like `user` are never shown. Parameters not set in the session
+    /// are shown with their default, and `transaction_*` parameters fall back
+    /// to their `default_transaction_*` counterparts.
+    pub fn on_show<C>(&self, client: &C, name: &str) -> PgWireResult<Response<'static>>
+    where
+        C: ClientInfo,
+    {
+        let lowercase = name.to_lowercase();
+        let name = if let Some(name) = self.known_parameters.get(&lowercase) {
+            name.clone()
+        } else if let Some(name) = READ_ONLY_PARAMETERS
+            .iter()
+            .find(|read_only| read_only.eq_ignore_ascii_case(name))
+        {
+            name.to_string()
+        } else if self.unknown_parameter_policy == UnknownParameterPolicy::Accept
+            && !RESERVED_NAMES.contains(&lowercase.as_str())
+            && client.metadata().contains_key(&lowercase)
+        {
+            lowercase
+        } else {
+            return Err(unrecognized_parameter_error(name));
+        };
+
+        let lookup = |name: &str| {
+            client
+                .metadata()
+                .get(name)
+                .cloned()
+                .or_else(|| self.default_value(client, name))
+        };
+        let value = lookup(&name)
+            .or_else(|| {
+                name.strip_prefix("transaction_")
+                    .and_then(|suffix| lookup(&format!("default_transaction_{suffix}")))
+            })
+            .unwrap_or_default();
+
+        let field = FieldInfo::new(name, None, None, Type::TEXT, FieldFormat::Text);
+        QueryResponse::single_value(field, &value).map(|response| {
+            Response::Query(response.with_command_tag(Tag::new("SHOW").without_row_count()))
+        })
+    }
+
+    /// Handle `query` if it's a `SET` or `SHOW` statement supported by this
+    /// handler, return `None` otherwise.
+    ///
+    /// `SET` statements are parsed with `SetStatement::parse_all`, so
+    /// transaction characteristics set by ORMs on connect are accepted.
+    /// Call this ahead of your own query processing in
+    /// `SimpleQueryHandler::do_query`.
+    pub async fn on_query<C>(
+        &self,
+        client: &mut C,
+        query: &str,
+    ) -> Option<PgWireResult<Response<'static>>>
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        if let Some(statements) = SetStatement::parse_all(query) {
+            let mut response = Ok(Response::Execution(Tag::new("SET")));
+            for statement in &statements {
+                response = self.on_set(client, statement).await;
+                if response.is_err() {
+                    break;
+                }
+            }
+            return Some(response);
+        }
+        parse_show(query).map(|name| self.on_show(client, name))
+    }
+}
+
+fn unrecognized_parameter_error(name: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "42704".to_owned(),
+        format!("unrecognized configuration parameter \"{name}\""),
+    )))
+}
+
+/// Session values of parameters changed by `SET LOCAL` in current
+/// transaction, kept in client extensions until it ends.
+#[derive(Debug, Default)]
+struct LocalParameters {
+    /// canonical name, session value, and value to report when restored
+    saved: Vec<(String, Option<String>, Option<String>)>,
+}
+
+impl LocalParameters {
+    fn save(&mut self, name: &str, session_value: Option<String>, report: Option<String>) {
+        if !self.saved.iter().any(|(saved, _, _)| saved == name) {
+            self.saved.push((name.to_owned(), session_value, report));
+        }
+    }
+
+    fn update(&mut self, name: &str, session_value: Option<String>, report: String) {
+        if let Some(saved) = self.saved.iter_mut().find(|(saved, _, _)| saved == name) {
+            saved.1 = session_value;
+            if saved.2.is_some() {
+                saved.2 = Some(report);
+            }
+        }
+    }
+}
+
+fn local_parameters<C: ClientInfo>(client: &mut C) -> PgWireResult<&mut LocalParameters> {
+    let extensions = client.extensions_mut().ok_or_else(|| {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            "SET LOCAL is not supported".to_owned(),
+        )))
+    })?;
+    if extensions.get::<LocalParameters>().is_none() {
+        extensions.insert(LocalParameters::default());
+    }
+    Ok(extensions
+        .get_mut::<LocalParameters>()
+        .expect("inserted above"))
+}
+
+/// Restore the session values of parameters changed by `SET LOCAL`, once
+/// the transaction has ended, i.e. `ClientInfo::transaction_status` is back to
+/// `Idle`.
+///
+/// The default `on_query` and `on_sync` call it before `ReadyForQuery`, call
+/// it as well if you override them. Restored values of reported parameters
+/// are fed to client as `ParameterStatus`.
+pub async fn reset_local_parameters<C>(client: &mut C) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    if client.transaction_status() != TransactionStatus::Idle {
+        return Ok(());
+    }
+    let local = match client
+        .extensions_mut()
+        .and_then(|extensions| extensions.remove::<LocalParameters>())
+    {
+        Some(local) => local,
+        None => return Ok(()),
+    };
+    for (name, session_value, report) in local.saved {
+        let local_value = match session_value {
+            Some(value) => client.metadata_mut().insert(name.clone(), value),
+            None => client.metadata_mut().remove(&name),
+        };
+        if let Some(report) = report {
+            if local_value.as_ref() != Some(&report) {
+                client
+                    .feed(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+                        name, report,
+                    )))
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Validate value of parameters with a limited set of `allowed` values,
+/// return the value normalized.
+fn check_parameter_value(
+    name: &str,
+    value: &str,
+    allowed: Option<&Vec<String>>,
+) -> PgWireResult<String> {
+    let allowed = match allowed {
+        Some(allowed) => allowed,
+        None => return Ok(value.to_owned()),
+    };
+    let lowercase = value.to_lowercase();
+    if allowed.contains(&lowercase) {
+        return Ok(lowercase);
+    }
+
+    let mut info = ErrorInfo::new(
+        "ERROR".to_owned(),
+        "22023".to_owned(),
+        format!("invalid value for parameter \"{name}\": \"{value}\""),
+    );
+    info.hint = Some(format!("Available values: {}.", allowed.join(", ")));
+    Err(PgWireError::UserError(Box::new(info)))
+}
+
+/// Report a parameter changed by the server rather than by client's `SET`,
+/// e.g. `in_hot_standby` when a backend is promoted from standby to primary.
+///
+/// The value is stored in client metadata and fed to client as
+/// `ParameterStatus` if it differs from the last value stored, and flushed
+/// with the next response. Clients like libpq track these parameters, e.g.
+/// `target_session_attrs=read-write` checks `in_hot_standby`, so call this
+/// on each session when the role of your backend changes.
+pub async fn report_parameter<C>(client: &mut C, name: &str, value: &str) -> PgWireResult<()>
+where
+    C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+    C::Error: Debug,
+    PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+{
+    if client.metadata().get(name).map(String::as_str) == Some(value) {
+        return Ok(());
+    }
+    client
+        .metadata_mut()
+        .insert(name.to_owned(), value.to_owned());
+    client
+        .feed(PgWireBackendMessage::ParameterStatus(ParameterStatus::new(
+            name.to_owned(),
+            value.to_owned(),
+        )))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::api::testing::RecordingClient;
+
+    #[test]
+    fn test_parse_set() {
+        assert_eq!(
+            Some(SetStatement::new(
+                "search_path".to_owned(),
+                Some("public".to_owned()),
+                false
+            )),
+            SetStatement::parse("SET search_path = public;")
+        );
+        assert_eq!(
+            Some(SetStatement::new(
+                "application_name".to_owned(),
+                Some("it's me".to_owned()),
+                true
+            )),
+            SetStatement::parse("set local application_name to 'it''s me'")
+        );
+        assert_eq!(
+            Some(SetStatement::new("TimeZone".to_owned(), None, false)),
+            SetStatement::parse("SET SESSION TIME ZONE DEFAULT")
+        );
+        assert_eq!(None, SetStatement::parse("SELECT 1"));
+        assert_eq!(None, SetStatement::parse("SETTINGS"));
+        assert_eq!(
+            Some(SetStatement::new(
+                "session_authorization".to_owned(),
+                Some("tomcat".to_owned()),
+                false
+            )),
+            SetStatement::parse("SET SESSION AUTHORIZATION 'tomcat'")
+        );
+        assert_eq!(None, SetStatement::parse("SET foo"));
+    }
+
+    #[test]
+    fn test_parse_transaction_characteristics() {
+        assert_eq!(
+            Some(vec![SetStatement::new(
+                "default_transaction_isolation".to_owned(),
+                Some("serializable".to_owned()),
+                false
+            )]),
+            SetStatement::parse_all(
+                "SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL SERIALIZABLE"
+            )
+        );
+        assert_eq!(
+            Some(vec![
+                SetStatement::new(
+                    "transaction_isolation".to_owned(),
+                    Some("repeatable read".to_owned()),
+                    true
+                ),
+                SetStatement::new(
+                    "transaction_read_only".to_owned(),
+                    Some("on".to_owned()),
+                    true
+                ),
+                SetStatement::new(
+                    "transaction_deferrable".to_owned(),
+                    Some("off".to_owned()),
+                    true
+                ),
+            ]),
+            SetStatement::parse_all(
+                "set transaction isolation level repeatable read, read only not deferrable;"
+            )
+        );
+        assert_eq!(
+            Some(vec![SetStatement::new(
+                "search_path".to_owned(),
+                Some("public".to_owned()),
+                false
+            )]),
+            SetStatement::parse_all("SET search_path = public")
+        );
+        assert_eq!(
+            None,
+            SetStatement::parse_all("SET TRANSACTION ISOLATION LEVEL READ")
+        );
+        assert_eq!(
+            None,
+            SetStatement::parse_all("SET TRANSACTION SNAPSHOT '1'")
+        );
+        assert_eq!(None, SetStatement::parse_all("SET TRANSACTION"));
+    }
+
+    async fn show<C>(handler: &DefaultSetHandler, client: &mut C, query: &str) -> String
+    where
+        C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send,
+        C::Error: Debug,
+        PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+    {
+        match handler.on_query(client, query).await {
+            Some(Ok(Response::Query(response))) => {
+                let row = response.data_rows().next().await.unwrap().unwrap();
+                String::from_utf8(row.fields[0].as_ref().unwrap().to_vec()).unwrap()
+            }
+            _ => panic!("expect SHOW response"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_and_show_transaction_isolation() {
+        let mut client = RecordingClient::<String>::new();
+        let handler = DefaultSetHandler::default();
+
+        assert_eq!(
+            "read committed",
+            show(&handler, &mut client, "SHOW TRANSACTION ISOLATION LEVEL").await
+        );
+        assert_eq!(
+            "off",
+            show(&handler, &mut client, "show default_transaction_read_only").await
+        );
+
+        let set =
+            "SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ ONLY";
+        assert!(matches!(
+            handler.on_query(&mut client, set).await,
+            Some(Ok(Response::Execution(_)))
+        ));
+        assert_eq!(
+            "serializable",
+            show(&handler, &mut client, "SHOW default_transaction_isolation").await
+        );
+        assert_eq!(
+            "serializable",
+            show(&handler, &mut client, "SHOW transaction_isolation").await
+        );
+        assert_eq!(
+            "on",
+            show(&handler, &mut client, "SHOW transaction_read_only").await
+        );
+
+        let set = "SET TRANSACTION ISOLATION LEVEL READ COMMITTED";
+        client.transaction_status = TransactionStatus::Transaction;
+        assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        assert_eq!(
+            "read committed",
+            show(&handler, &mut client, "SHOW transaction_isolation").await
+        );
+        // SET TRANSACTION only lasts until the transaction ends
+        client.transaction_status = TransactionStatus::Idle;
+        reset_local_parameters(&mut client).await.unwrap();
+        assert_eq!(
+            "serializable",
+            show(&handler, &mut client, "SHOW transaction_isolation").await
+        );
+
+        let set = "SET default_transaction_isolation = 'Repeatable Read'";
+        assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        assert_eq!(
+            "repeatable read",
+            show(&handler, &mut client, "SHOW default_transaction_isolation").await
+        );
+        let set = "SET default_transaction_isolation = 'snapshot'";
+        match handler.on_query(&mut client, set).await {
+            Some(Err(PgWireError::UserError(e))) => assert_eq!("22023", e.code),
+            _ => panic!("expect invalid parameter value error"),
+        }
+
+        for set in [
+            "SET integer_datetimes = off",
+            "SET integer_datetimes TO DEFAULT",
+        ] {
+            match handler.on_query(&mut client, set).await {
+                Some(Err(PgWireError::UserError(e))) => assert_eq!("55P02", e.code),
+                _ => panic!("expect cannot be changed error"),
+            }
+        }
+
+        match handler.on_query(&mut client, "SHOW foo").await {
+            Some(Err(PgWireError::UserError(e))) => assert_eq!("42704", e.code),
+            _ => panic!("expect unrecognized configuration parameter error"),
+        }
+        assert!(handler.on_query(&mut client, "SHOW ALL").await.is_none());
+        assert!(handler.on_query(&mut client, "SELECT 1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_parameter_policy() {
+        let mut client = RecordingClient::<String>::new();
+        let unknown = SetStatement::parse("SET foo = 'bar'").unwrap();
+
+        let mut handler = DefaultSetHandler::default();
+        match handler.on_set(&mut client, &unknown).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("42704", e.code),
+            _ => panic!("expect unrecognized configuration parameter error"),
+        }
+
+        let known = SetStatement::parse("SET datestyle TO 'ISO, MDY'").unwrap();
+        assert!(handler.on_set(&mut client, &known).await.is_ok());
+        assert_eq!(
+            Some("ISO, MDY"),
+            client.metadata().get("DateStyle").map(String::as_str)
+        );
+
+        handler.set_allowed_values("bytea_output", &["escape", "hex"]);
+        let invalid = SetStatement::parse("SET bytea_output = 'base64'").unwrap();
+        match handler.on_set(&mut client, &invalid).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("22023", e.code),
+            _ => panic!("expect invalid parameter value error"),
+        }
+        let valid = SetStatement::parse("SET bytea_output = escape").unwrap();
+        assert!(handler.on_set(&mut client, &valid).await.is_ok());
+
+        handler.set_unknown_parameter_policy(UnknownParameterPolicy::Accept);
+        assert!(handler.on_set(&mut client, &unknown).await.is_ok());
+        assert_eq!(
+            Some("bar"),
+            client.metadata().get("foo").map(String::as_str)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_show() {
+        let mut client = RecordingClient::<String>::new();
+        client
+            .metadata_mut()
+            .insert(METADATA_USER.to_owned(), "tomcat".to_owned());
+        client
+            .metadata_mut()
+            .insert("server_version".to_owned(), "16.1".to_owned());
+        client
+            .metadata_mut()
+            .insert("foo".to_owned(), "bar".to_owned());
+        let mut handler = DefaultSetHandler::default();
+
+        // defaults of parameters not set
+        for (query, expected) in [
+            ("SHOW TimeZone", "UTC"),
+            ("SHOW application_name", ""),
+            ("SHOW search_path", "\"$user\", public"),
+            ("SHOW session_authorization", "tomcat"),
+            ("SHOW server_version", "16.1"),
+        ] {
+            assert_eq!(expected, show(&handler, &mut client, query).await);
+        }
+
+        for policy in [
+            UnknownParameterPolicy::Error,
+            UnknownParameterPolicy::Accept,
+        ] {
+            handler.set_unknown_parameter_policy(policy);
+            for query in ["SHOW user", "SHOW replication", "SHOW database"] {
+                match handler.on_query(&mut client, query).await {
+                    Some(Err(PgWireError::UserError(e))) => assert_eq!("42704", e.code),
+                    _ => panic!("expect unrecognized configuration parameter error"),
+                }
+            }
+        }
+        assert_eq!("bar", show(&handler, &mut client, "SHOW foo").await);
+    }
+
+    #[tokio::test]
+    async fn test_reserved_parameters() {
+        let mut client = RecordingClient::<String>::new();
+        client
+            .metadata_mut()
+            .insert("user".to_owned(), "tomcat".to_owned());
+        let handler = DefaultSetHandler::new(UnknownParameterPolicy::Accept);
+
+        for (set, code) in [
+            ("SET user = 'postgres'", "42704"),
+            ("SET \"database\" TO admin", "42704"),
+            ("SET replication = true", "42704"),
+            ("SET server_version = '99'", "55P02"),
+            ("SET is_superuser = on", "55P02"),
+        ] {
+            match handler.on_query(&mut client, set).await {
+                Some(Err(PgWireError::UserError(e))) => assert_eq!(code, e.code, "{set}"),
+                _ => panic!("expect error for {set}"),
+            }
+        }
+        assert_eq!(
+            Some("tomcat"),
+            client.metadata().get("user").map(String::as_str)
+        );
+        assert!(!client.metadata().contains_key("server_version"));
+    }
+
+    #[tokio::test]
+    async fn test_set_local() {
+        let mut client = RecordingClient::<String>::new();
+        let handler = DefaultSetHandler::default();
+        let search_path = |client: &RecordingClient| client.metadata.get("search_path").cloned();
+
+        // ignored with a warning outside of transaction block
+        let set = "SET LOCAL search_path = private";
+        assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        assert_eq!(None, search_path(&client));
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::NoticeResponse(notice)] => {
+                assert!(notice.fields.contains(&(b'C', "25P01".to_owned())));
+            }
+            messages => panic!("expect warning, got {messages:?}"),
+        }
+
+        let set = "SET search_path = public";
+        assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        client.set_transaction_status(TransactionStatus::Transaction);
+        for set in [
+            "SET LOCAL search_path = private",
+            "SET LOCAL application_name = 'batch'",
+        ] {
+            assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        }
+        assert_eq!(Some("private".to_owned()), search_path(&client));
+        assert_eq!(Some("batch"), client.application_name());
+        client.messages().unwrap();
+
+        // kept until the transaction ends
+        reset_local_parameters(&mut client).await.unwrap();
+        assert_eq!(Some("private".to_owned()), search_path(&client));
+        client.set_transaction_status(TransactionStatus::Idle);
+        reset_local_parameters(&mut client).await.unwrap();
+        assert_eq!(Some("public".to_owned()), search_path(&client));
+        assert_eq!(None, client.application_name());
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::ParameterStatus(status)] => {
+                assert_eq!("application_name", status.name);
+                assert_eq!("", status.value);
+            }
+            messages => panic!("expect ParameterStatus, got {messages:?}"),
+        }
+
+        // session value set in the transaction is kept after it
+        client.set_transaction_status(TransactionStatus::Transaction);
+        for set in ["SET LOCAL search_path = private", "SET search_path = audit"] {
+            assert!(handler.on_query(&mut client, set).await.unwrap().is_ok());
+        }
+        client.set_transaction_status(TransactionStatus::Idle);
+        reset_local_parameters(&mut client).await.unwrap();
+        assert_eq!(Some("audit".to_owned()), search_path(&client));
+    }
+
+    #[tokio::test]
+    async fn test_reported_parameters() {
+        let mut client = RecordingClient::<String>::new();
+        let mut handler = DefaultSetHandler::default();
+
+        let set = SetStatement::parse("SET client_encoding TO 'WIN1252'").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::ParameterStatus(status)] => {
+                assert_eq!("client_encoding", status.name);
+                assert_eq!("WIN1252", status.value);
+            }
+            sent => panic!("expect ParameterStatus, got {:?}", sent),
+        }
+
+        client.take_bytes();
+        let set = SetStatement::parse("SET application_name = 'billing-worker'").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        assert_eq!(Some("billing-worker"), client.application_name());
+        assert!(matches!(
+            &client.messages().unwrap()[..],
+            [PgWireBackendMessage::ParameterStatus(status)] if status.value == "billing-worker"
+        ));
+
+        // reset to default
+        client.take_bytes();
+        let set = SetStatement::parse("SET TIME ZONE 'Europe/Paris'").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        let set = SetStatement::parse("SET TIME ZONE DEFAULT").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        assert!(!client.metadata().contains_key("TimeZone"));
+        assert!(matches!(
+            &client.messages().unwrap()[..],
+            [_, PgWireBackendMessage::ParameterStatus(status)] if status.value == "UTC"
+        ));
+        client.take_bytes();
+        handler.set_parameter_default("TimeZone", "Asia/Tokyo");
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        assert!(matches!(
+            &client.messages().unwrap()[..],
+            [PgWireBackendMessage::ParameterStatus(status)] if status.value == "Asia/Tokyo"
+        ));
+
+        // not reported
+        client.take_bytes();
+        let set = SetStatement::parse("SET search_path = public").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        let set = SetStatement::parse("SET search_path TO DEFAULT").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        assert!(client.bytes().is_empty());
+
+        handler.add_reported_parameter("search_path");
+        let set = SetStatement::parse("SET search_path = public").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        assert_eq!(1, client.messages().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_session_authorization() {
+        let mut client = RecordingClient::<String>::new();
+        client
+            .metadata_mut()
+            .insert(METADATA_USER.to_owned(), "tomcat".to_owned());
+        let mut handler = DefaultSetHandler::default();
+
+        let set = SetStatement::parse("SET SESSION AUTHORIZATION 'postgres'").unwrap();
+        match handler.on_set(&mut client, &set).await {
+            Err(PgWireError::UserError(e)) => assert_eq!("42501", e.code),
+            _ => panic!("expect permission denied"),
+        }
+        assert!(client.bytes().is_empty());
+
+        handler.set_allow_session_authorization(true);
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        let set = SetStatement::parse("SET SESSION AUTHORIZATION DEFAULT").unwrap();
+        assert!(handler.on_set(&mut client, &set).await.is_ok());
+        match &client.messages().unwrap()[..] {
+            [PgWireBackendMessage::ParameterStatus(set), PgWireBackendMessage::ParameterStatus(reset)] =>
+            {
+                assert_eq!("postgres", set.value);
+                assert_eq!("tomcat", reset.value);
+            }
+            sent => panic!("expect ParameterStatus, got {:?}", sent),
+        }
+        assert_eq!(
+            Some("tomcat"),
+            client.metadata().get(METADATA_USER).map(String::as_str)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_parameter() {
+        let mut client = RecordingClient::<String>::new();
+
+        report_parameter(&mut client, "in_hot_standby", "on")
+            .await
+            .unwrap();
+        // unchanged value is not reported again
+        report_parameter(&mut client, "in_hot_standby", "on")
+            .await
+            .unwrap();
+        report_parameter(&mut client, "in_hot_standby", "off")
+            .await
+            .unwrap();
+
+        let values: Vec<String> = client
+            .messages()
+            .unwrap()
+            .into_iter()
+            .map(|m| match m {
+                PgWireBackendMessage::ParameterStatus(status) => {
+                    assert_eq!("in_hot_standby", status.name);
+                    status.value
+                }
+                _ => panic!("expect ParameterStatus"),
+            })
+            .collect();
+        assert_eq!(vec!["on", "off"], values);
+    }
+}
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use postgres_types::Type;
+use postgres_types::{Oid, Type};
 
-use crate::error::PgWireResult;
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
 use crate::messages::extendedquery::Parse;
 
 use super::DEFAULT_NAME;
@@ -28,9 +28,22 @@ impl<S> StoredStatement<S> {
         let types = parse
             .type_oids
             .iter()
-            .map(|oid| Type::from_oid(*oid).unwrap_or(Type::UNKNOWN))
-            .collect::<Vec<Type>>();
+            .map(|oid| {
+                // 0 is left unspecified by client
+                if *oid == 0 {
+                    return Ok(Type::UNKNOWN);
+                }
+                parser.lookup_type(*oid).ok_or_else(|| {
+                    PgWireError::UserError(Box::new(ErrorInfo::new(
+                        "ERROR".to_owned(),
+                        "42704".to_owned(),
+                        format!("type with OID {oid} does not exist"),
+                    )))
+                })
+            })
+            .collect::<PgWireResult<Vec<Type>>>()?;
         let statement = parser.parse_sql(&parse.query, &types).await?;
+        let types = parser.infer_parameter_types(&parse.query, &statement, &types)?;
         Ok(StoredStatement {
             id: parse
                 .name
@@ -49,6 +62,34 @@ pub trait QueryParser {
     type Statement;
 
     async fn parse_sql(&self, sql: &str, types: &[Type]) -> PgWireResult<Self::Statement>;
+
+    /// Type of a parameter OID declared by client in `Parse`, `None` if it's
+    /// unknown to the server.
+    ///
+    /// `Parse` with an unknown OID fails with `42704` error. The default
+    /// knows postgres builtin types, override it to accept custom types, or
+    /// to reject types the handler can't decode.
+    fn lookup_type(&self, oid: Oid) -> Option<Type> {
+        Type::from_oid(oid)
+    }
+
+    /// Types of all parameters of `statement`, parsed from `sql`.
+    ///
+    /// `types` are declared by client in `Parse`, `Type::UNKNOWN` for those
+    /// left unspecified, and may be fewer than parameters in `sql`. Clients
+    /// like JDBC leave them all unspecified and rely on `Describe` to learn
+    /// them, a parser can infer them from casts like `$1::uuid`, keeping the
+    /// declared ones. The result is stored in
+    /// `StoredStatement::parameter_types`, to be returned by `do_describe` in
+    /// `ParameterDescription`. The default returns `types` as is.
+    fn infer_parameter_types(
+        &self,
+        _sql: &str,
+        _statement: &Self::Statement,
+        types: &[Type],
+    ) -> PgWireResult<Vec<Type>> {
+        Ok(types.to_vec())
+    }
 }
 
 #[async_trait]
@@ -61,6 +102,19 @@ where
     async fn parse_sql(&self, sql: &str, types: &[Type]) -> PgWireResult<Self::Statement> {
         (**self).parse_sql(sql, types).await
     }
+
+    fn lookup_type(&self, oid: Oid) -> Option<Type> {
+        (**self).lookup_type(oid)
+    }
+
+    fn infer_parameter_types(
+        &self,
+        sql: &str,
+        statement: &Self::Statement,
+        types: &[Type],
+    ) -> PgWireResult<Vec<Type>> {
+        (**self).infer_parameter_types(sql, statement, types)
+    }
 }
 
 /// A demo parser implementation. Never use it in serious application.
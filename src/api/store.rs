@@ -13,11 +13,20 @@ pub trait PortalStore: Send + Sync {
 
     fn get_statement(&self, name: &str) -> Option<Arc<StoredStatement<Self::Statement>>>;
 
+    /// Remove all statements, for `DEALLOCATE ALL` and `DISCARD ALL`.
+    ///
+    /// Does nothing by default, for stores that don't enumerate their
+    /// statements.
+    fn clear_statements(&self) {}
+
     fn put_portal(&self, portal: Arc<Portal<Self::Statement>>);
 
     fn rm_portal(&self, name: &str);
 
     fn get_portal(&self, name: &str) -> Option<Arc<Portal<Self::Statement>>>;
+
+    /// Remove all portals, for `DISCARD ALL`. Does nothing by default.
+    fn clear_portals(&self) {}
 }
 
 #[derive(Debug, Default, new)]
@@ -46,6 +55,10 @@ impl<S: Clone + Send + Sync> PortalStore for MemPortalStore<S> {
         guard.get(name).cloned()
     }
 
+    fn clear_statements(&self) {
+        self.statements.write().unwrap().clear();
+    }
+
     fn put_portal(&self, portal: Arc<Portal<Self::Statement>>) {
         let mut guard = self.portals.write().unwrap();
         guard.insert(portal.name.to_owned(), portal);
@@ -60,4 +73,8 @@ impl<S: Clone + Send + Sync> PortalStore for MemPortalStore<S> {
         let guard = self.portals.read().unwrap();
         guard.get(name).cloned()
     }
+
+    fn clear_portals(&self) {
+        self.portals.write().unwrap().clear();
+    }
 }
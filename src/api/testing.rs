@@ -0,0 +1,328 @@
+//! Helpers to test handlers against the exact bytes sent on the wire.
+//!
+//! Serialized output can be compared byte for byte with traffic captured
+//! from a real postgres server, e.g. with wireshark, to pin protocol
+//! compatibility in golden tests.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use futures::{Sink, SinkExt};
+
+use super::copy::send_copy_in_response;
+use super::extensions::Extensions;
+use super::query::{send_execution_response, send_query_response};
+use super::results::{Response, RowLimit};
+use super::store::MemPortalStore;
+use super::{ClientInfo, ClientPortalStore, PgWireConnectionState, TransactionStatus};
+use crate::error::{PgWireError, PgWireResult};
+use crate::messages::response::EmptyQueryResponse;
+use crate::messages::PgWireBackendMessage;
+
+/// A client that serializes every message sent to it into a buffer, instead
+/// of a socket.
+///
+/// Pass it to `on_` prefixed handler methods, then assert on `bytes` or
+/// decoded `messages`.
+#[derive(Debug)]
+pub struct RecordingClient<S = String> {
+    pub is_secure: bool,
+    pub state: PgWireConnectionState,
+    pub transaction_status: TransactionStatus,
+    pub metadata: HashMap<String, String>,
+    pub portal_store: MemPortalStore<S>,
+    pub extensions: Extensions,
+    pub max_result_rows: Option<RowLimit>,
+    buf: BytesMut,
+    sent: Arc<AtomicUsize>,
+    flushes: usize,
+    closed: bool,
+}
+
+impl<S> Default for RecordingClient<S> {
+    fn default() -> Self {
+        RecordingClient {
+            is_secure: false,
+            state: PgWireConnectionState::ReadyForQuery,
+            transaction_status: TransactionStatus::Idle,
+            metadata: HashMap::new(),
+            portal_store: MemPortalStore::new(),
+            extensions: Extensions::new(),
+            max_result_rows: None,
+            buf: BytesMut::new(),
+            sent: Arc::new(AtomicUsize::new(0)),
+            flushes: 0,
+            closed: false,
+        }
+    }
+}
+
+impl<S> RecordingClient<S> {
+    /// Create a client in `ReadyForQuery` state.
+    pub fn new() -> RecordingClient<S> {
+        RecordingClient::default()
+    }
+
+    /// Bytes of all messages sent to this client since last `take_bytes`.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Take bytes sent to this client, leaving the buffer empty.
+    pub fn take_bytes(&mut self) -> BytesMut {
+        self.buf.split()
+    }
+
+    /// Number of messages sent to this client so far, shared so it can be
+    /// read while a handler runs, e.g. from a row stream to check rows are
+    /// sent as they are produced.
+    pub fn sent_count(&self) -> Arc<AtomicUsize> {
+        self.sent.clone()
+    }
+
+    /// Number of times this client was flushed.
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+
+    /// Test if the handler closed this client, e.g. after a `FATAL` error.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Decode and take messages sent to this client.
+    pub fn messages(&mut self) -> PgWireResult<Vec<PgWireBackendMessage>> {
+        let mut buf = self.take_bytes();
+        let mut messages = Vec::new();
+        while let Some(message) = PgWireBackendMessage::decode(&mut buf)? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
+
+impl<S> ClientInfo for RecordingClient<S> {
+    fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn is_secure(&self) -> bool {
+        self.is_secure
+    }
+
+    fn state(&self) -> PgWireConnectionState {
+        self.state
+    }
+
+    fn set_state(&mut self, new_state: PgWireConnectionState) {
+        self.state = new_state;
+    }
+
+    fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.transaction_status = status;
+    }
+
+    fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.metadata
+    }
+
+    fn max_result_rows(&self) -> Option<RowLimit> {
+        self.max_result_rows
+    }
+
+    fn extensions(&self) -> Option<&Extensions> {
+        Some(&self.extensions)
+    }
+
+    fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        Some(&mut self.extensions)
+    }
+}
+
+impl<S> ClientPortalStore for RecordingClient<S> {
+    type PortalStore = MemPortalStore<S>;
+
+    fn portal_store(&self) -> &Self::PortalStore {
+        &self.portal_store
+    }
+}
+
+impl<S: Unpin> Sink<PgWireBackendMessage> for RecordingClient<S> {
+    type Error = PgWireError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: PgWireBackendMessage) -> PgWireResult<()> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        item.encode(&mut self.buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        self.flushes += 1;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<PgWireResult<()>> {
+        self.closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Serialize messages as they are sent on the wire.
+pub fn encode_messages<'a, I>(messages: I) -> PgWireResult<BytesMut>
+where
+    I: IntoIterator<Item = &'a PgWireBackendMessage>,
+{
+    let mut buf = BytesMut::new();
+    for message in messages {
+        message.encode(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+/// Serialize a `Response` as sent for a statement of simple query.
+///
+/// `Response::Query` is sent as `RowDescription`, `DataRow`s and
+/// `CommandComplete`, the other variants as a single message.
+/// `ReadyForQuery` is not included.
+pub async fn encode_response(response: Response<'_>) -> PgWireResult<BytesMut> {
+    let mut client = RecordingClient::<String>::new();
+    match response {
+        Response::EmptyQuery => {
+            client
+                .send(PgWireBackendMessage::EmptyQueryResponse(EmptyQueryResponse))
+                .await?
+        }
+        Response::Query(results) => send_query_response(&mut client, results, true).await?,
+        Response::Execution(tag) => send_execution_response(&mut client, tag).await?,
+        Response::Error(e) => {
+            client
+                .send(PgWireBackendMessage::ErrorResponse((*e).into()))
+                .await?
+        }
+        Response::CopyIn(copy) => send_copy_in_response(&mut client, copy).await?,
+    }
+    Ok(client.take_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use futures::stream;
+
+    use super::*;
+    use crate::api::query::SimpleQueryHandler;
+    use crate::api::results::{DataRowEncoder, FieldFormat, FieldInfo, QueryResponse, Tag};
+    use crate::api::Type;
+    use crate::error::ErrorInfo;
+    use crate::messages::response::ReadyForQuery;
+    use crate::messages::simplequery::Query;
+
+    fn select_one() -> Response<'static> {
+        let schema = Arc::new(vec![FieldInfo::new(
+            "a".into(),
+            None,
+            None,
+            Type::INT4,
+            FieldFormat::Text,
+        )]);
+        let mut encoder = DataRowEncoder::new(schema.clone());
+        encoder.encode_field(&1i32).unwrap();
+        let rows = vec![encoder.finish()];
+        Response::Query(QueryResponse::new(schema, stream::iter(rows)))
+    }
+
+    #[tokio::test]
+    async fn test_encode_response() {
+        // as sent by postgres for `SELECT 1 AS a`
+        let expected = [
+            b"T\0\0\0\x1a\0\x01a\0\0\0\0\0\0\0\0\0\0\x17\0\x04\xff\xff\xff\xff\0\0".as_ref(),
+            b"D\0\0\0\x0b\0\x01\0\0\0\x011",
+            b"C\0\0\0\x0dSELECT 1\0",
+        ]
+        .concat();
+        assert_eq!(expected, encode_response(select_one()).await.unwrap());
+
+        assert_eq!(
+            b"C\0\0\0\x0fINSERT 0 1\0".as_ref(),
+            encode_response(Response::Execution(
+                Tag::new("INSERT").with_oid(0).with_rows(1)
+            ))
+            .await
+            .unwrap()
+        );
+        assert_eq!(
+            b"I\0\0\0\x04".as_ref(),
+            encode_response(Response::EmptyQuery).await.unwrap()
+        );
+
+        let error = ErrorInfo::new(
+            "ERROR".to_owned(),
+            "42601".to_owned(),
+            "syntax error".to_owned(),
+        );
+        assert_eq!(
+            b"E\0\0\0\x28SERROR\0VERROR\0C42601\0Msyntax error\0\0".as_ref(),
+            encode_response(Response::Error(Box::new(error)))
+                .await
+                .unwrap()
+        );
+
+        let ready = PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(b'I'));
+        assert_eq!(b"Z\0\0\0\x05I".as_ref(), encode_messages([&ready]).unwrap());
+    }
+
+    struct Handler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for Handler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: std::fmt::Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(vec![select_one()])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recording_client() {
+        let mut client = RecordingClient::<String>::new();
+        Handler
+            .on_query(&mut client, Query::new("SELECT 1 AS a".to_owned()))
+            .await
+            .unwrap();
+        let expected = [
+            encode_response(select_one()).await.unwrap().as_ref(),
+            b"Z\0\0\0\x05I",
+        ]
+        .concat();
+        assert_eq!(expected, client.bytes());
+
+        let messages = client.messages().unwrap();
+        assert_eq!(4, messages.len());
+        assert!(client.bytes().is_empty());
+    }
+}
@@ -1,6 +1,6 @@
-use std::io::{Error as IOError, ErrorKind};
+use std::io::Error as IOError;
 
-use postgres_types::Oid;
+use postgres_types::{Oid, Type};
 use thiserror::Error;
 
 use crate::messages::response::{ErrorResponse, NoticeResponse};
@@ -11,10 +11,14 @@ pub enum PgWireError {
     InvalidProtocolVersion(i32),
     #[error("Invalid message recevied, received {0}")]
     InvalidMessageType(u8),
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
     #[error("Invalid target type, received {0}")]
     InvalidTargetType(u8),
     #[error("Invalid startup message")]
     InvalidStartupMessage,
+    #[error("Startup packet too large, received {0} bytes")]
+    StartupPacketTooLarge(usize),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("Portal not found for name: {0:?}")]
@@ -31,10 +35,22 @@ pub enum PgWireError {
     FailedToParseParameter(Box<dyn std::error::Error + Send + Sync>),
     #[error("Failed to parse scram message: {0}")]
     InvalidScramMessage(String),
+    #[error("At least one SASL mechanism is required")]
+    NoSaslMechanism,
+    #[error("Unsupported SASL mechanism: {0}")]
+    UnsupportedSaslMechanism(String),
+    #[error("Certificate is required for SASL mechanism {0}")]
+    CertificateRequiredForChannelBinding(String),
     #[error("Certificate algorithm is not supported")]
     UnsupportedCertificateSignatureAlgorithm,
     #[error("Username is required")]
     UserNameRequired,
+    #[error("User not found: {0}")]
+    UserNotFound(String),
+    #[error("Cannot send {0} in current connection state")]
+    InvalidConnectionState(String),
+    #[error("Invalid sequence of backend messages: {0}")]
+    InvalidBackendMessageSequence(String),
 
     #[error(transparent)]
     ApiError(#[from] Box<dyn std::error::Error + 'static + Send + Sync>),
@@ -43,14 +59,48 @@ pub enum PgWireError {
     UserError(Box<ErrorInfo>),
 }
 
+impl PgWireError {
+    /// `0A000` error for a value of type `oid` the handler can't encode or
+    /// decode.
+    ///
+    /// Return it from a handler instead of panicking, e.g. in the fallback arm
+    /// of a `match` on `Type` of a parameter or column. Like any `UserError`,
+    /// it's sent to client as `ErrorResponse` and fails only the current
+    /// query, the connection is kept.
+    pub fn unsupported_type(oid: Oid) -> PgWireError {
+        let message = match Type::from_oid(oid) {
+            Some(ty) => format!("data type {} is not supported", ty.name()),
+            None => format!("data type with OID {oid} is not supported"),
+        };
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "0A000".to_owned(),
+            message,
+        )))
+    }
+}
+
 impl From<PgWireError> for IOError {
     fn from(e: PgWireError) -> Self {
-        IOError::new(ErrorKind::Other, e)
+        IOError::other(e)
     }
 }
 
 pub type PgWireResult<T> = Result<T, PgWireError>;
 
+/// `08P01` error of a frontend message violating the protocol.
+pub(crate) fn protocol_violation(message: String) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "08P01".to_owned(),
+        message,
+    )))
+}
+
+const NONLOCALIZED_SEVERITIES: [&str; 8] = [
+    "ERROR", "FATAL", "PANIC", "WARNING", "NOTICE", "DEBUG", "INFO", "LOG",
+];
+
 // Postgres error and notice message fields
 // This part of protocol is defined in
 // https://www.postgresql.org/docs/8.2/protocol-error-fields.html
@@ -102,9 +152,16 @@ pub struct ErrorInfo {
 
 impl ErrorInfo {
     fn into_fields(self) -> Vec<(u8, String)> {
-        let mut fields = Vec::with_capacity(11);
+        let mut fields = Vec::with_capacity(12);
 
+        // severity that is never localized, sent by postgres 9.6 and later
+        let nonlocalized_severity = NONLOCALIZED_SEVERITIES
+            .contains(&self.severity.as_str())
+            .then(|| self.severity.clone());
         fields.push((b'S', self.severity));
+        if let Some(value) = nonlocalized_severity {
+            fields.push((b'V', value));
+        }
         fields.push((b'C', self.code));
         fields.push((b'M', self.message));
         if let Some(value) = self.detail {
@@ -167,4 +224,20 @@ mod test {
         assert_eq!("Password authentication failed", error_info.message);
         assert!(error_info.file_name.is_none());
     }
+
+    #[test]
+    fn test_unsupported_type() {
+        for (oid, message) in [
+            (Type::TS_VECTOR.oid(), "data type tsvector is not supported"),
+            (123456, "data type with OID 123456 is not supported"),
+        ] {
+            match PgWireError::unsupported_type(oid) {
+                PgWireError::UserError(info) => {
+                    assert_eq!("0A000", info.code);
+                    assert_eq!(message, info.message);
+                }
+                _ => panic!("expect user error"),
+            }
+        }
+    }
 }
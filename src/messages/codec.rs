@@ -1,8 +1,8 @@
 use std::str;
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-use crate::error::PgWireResult;
+use crate::error::{PgWireError, PgWireResult};
 
 /// Get null-terminated string, returns None when empty cstring read.
 ///
@@ -18,8 +18,9 @@ pub(crate) fn get_cstring(buf: &mut BytesMut) -> Option<String> {
     }
 
     // i+1: include the '\0'
-    // move cursor to the end of cstring
-    let string_buf = buf.split_to(i + 1);
+    // move cursor to the end of cstring, an unterminated string takes the rest
+    // of the buffer
+    let string_buf = buf.split_to((i + 1).min(buf.remaining()));
 
     if i == 0 {
         None
@@ -64,6 +65,13 @@ where
     F: Fn(&mut BytesMut, usize) -> PgWireResult<T>,
 {
     if let Some(msg_len) = get_length(buf, offset) {
+        if !(4..=i32::MAX as usize).contains(&msg_len) {
+            return Err(PgWireError::InvalidMessage(format!(
+                "invalid message length {}",
+                msg_len as i32
+            )));
+        }
+
         if buf.remaining() >= msg_len + offset {
             buf.advance(offset + 4);
             return decode_fn(buf, msg_len).map(|r| Some(r));
@@ -73,6 +81,56 @@ where
     Ok(None)
 }
 
+/// Read an `i16` item count, and check the buffer has room for that many
+/// items of at least `item_len` bytes each.
+pub(crate) fn get_count(buf: &mut BytesMut, item_len: usize) -> PgWireResult<usize> {
+    let count = get_i16(buf)?;
+    if count < 0 || buf.remaining() < count as usize * item_len {
+        return Err(PgWireError::InvalidMessage(format!(
+            "invalid item count {count}"
+        )));
+    }
+    Ok(count as usize)
+}
+
+/// Read an `i16` if the buffer has one.
+pub(crate) fn get_i16(buf: &mut BytesMut) -> PgWireResult<i16> {
+    if buf.remaining() < 2 {
+        return Err(PgWireError::InvalidMessage(
+            "unexpected end of message".to_owned(),
+        ));
+    }
+    Ok(buf.get_i16())
+}
+
+/// Read an `i32` if the buffer has one.
+pub(crate) fn get_i32(buf: &mut BytesMut) -> PgWireResult<i32> {
+    if buf.remaining() < 4 {
+        return Err(PgWireError::InvalidMessage(
+            "unexpected end of message".to_owned(),
+        ));
+    }
+    Ok(buf.get_i32())
+}
+
+/// Read a length prefixed value of `DataRow` or `Bind`.
+///
+/// Length -1 is NULL and read as `None`, while length 0 is an empty value,
+/// e.g. `''` or an empty bytea, and read as `Some` of empty bytes. Other
+/// negative lengths and lengths beyond the buffer are invalid.
+pub(crate) fn get_nullable_bytes(buf: &mut BytesMut) -> PgWireResult<Option<Bytes>> {
+    let len = get_i32(buf)?;
+    if len == -1 {
+        Ok(None)
+    } else if len < 0 || buf.remaining() < len as usize {
+        Err(PgWireError::InvalidMessage(format!(
+            "invalid value length {len}"
+        )))
+    } else {
+        Ok(Some(buf.split_to(len as usize).freeze()))
+    }
+}
+
 // pub(crate) fn get_and_ensure_message_type(buf: &mut BytesMut, t: u8) -> PgWireResult<()> {
 //     let msg_type = buf[0];
 //     // ensure the type is corrent
@@ -84,5 +142,5 @@ where
 // }
 
 pub(crate) fn option_string_len(s: &Option<String>) -> usize {
-    1 + s.as_ref().map(|s| s.as_bytes().len()).unwrap_or(0)
+    1 + s.as_ref().map(|s| s.len()).unwrap_or(0)
 }
@@ -66,11 +66,11 @@ pub struct CopyFail {
 impl Message for CopyFail {
     #[inline]
     fn message_type() -> Option<u8> {
-        Some(MESSAGE_TYPE_BYTE_COPY_DONE)
+        Some(MESSAGE_TYPE_BYTE_COPY_FAIL)
     }
 
     fn message_length(&self) -> usize {
-        4 + self.message.as_bytes().len() + 1
+        4 + self.message.len() + 1
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
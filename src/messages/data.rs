@@ -44,7 +44,7 @@ impl Message for RowDescription {
             + self
                 .fields
                 .iter()
-                .map(|f| f.name.as_bytes().len() + 1 + 4 + 2 + 4 + 2 + 4 + 2)
+                .map(|f| f.name.len() + 1 + 4 + 2 + 4 + 2 + 4 + 2)
                 .sum::<usize>()
     }
 
@@ -170,17 +170,13 @@ impl Message for DataRow {
         Ok(())
     }
 
-    fn decode_body(buf: &mut BytesMut, _msg_len: usize) -> PgWireResult<Self> {
-        let field_count = buf.get_i16() as usize;
+    fn decode_body(buf: &mut BytesMut, msg_len: usize) -> PgWireResult<Self> {
+        let buf = &mut buf.split_to(msg_len - 4);
+        let field_count = codec::get_count(buf, 4)?;
 
         let mut fields = Vec::with_capacity(field_count);
         for _ in 0..field_count {
-            let field_len = buf.get_i32();
-            if field_len >= 0 {
-                fields.push(Some(buf.split_to(field_len as usize).freeze()));
-            } else {
-                fields.push(None);
-            }
+            fields.push(codec::get_nullable_bytes(buf)?);
         }
 
         Ok(DataRow { fields })
@@ -23,7 +23,7 @@ impl Message for Parse {
 
     fn message_length(&self) -> usize {
         4 + codec::option_string_len(&self.name) // name
-            + (1 + self.query.as_bytes().len()) // query
+            + (1 + self.query.len()) // query
             + (4 * self.type_oids.len()) // type oids
     }
 
@@ -209,32 +209,29 @@ impl Message for Bind {
         Ok(())
     }
 
-    fn decode_body(buf: &mut bytes::BytesMut, _: usize) -> PgWireResult<Self> {
+    fn decode_body(buf: &mut bytes::BytesMut, full_len: usize) -> PgWireResult<Self> {
+        // bind carries client controlled counts and lengths, never read past
+        // the message itself
+        let buf = &mut buf.split_to(full_len - 4);
+
         let portal_name = codec::get_cstring(buf);
         let statement_name = codec::get_cstring(buf);
 
-        let parameter_format_code_len = buf.get_i16();
-        let mut parameter_format_codes = Vec::with_capacity(parameter_format_code_len as usize);
+        let parameter_format_code_len = codec::get_count(buf, 2)?;
+        let mut parameter_format_codes = Vec::with_capacity(parameter_format_code_len);
 
         for _ in 0..parameter_format_code_len {
             parameter_format_codes.push(buf.get_i16());
         }
 
-        let parameter_len = buf.get_i16();
-        let mut parameters = Vec::with_capacity(parameter_len as usize);
+        let parameter_len = codec::get_count(buf, 4)?;
+        let mut parameters = Vec::with_capacity(parameter_len);
         for _ in 0..parameter_len {
-            let data_len = buf.get_i32();
-
-            if data_len >= 0 {
-                parameters.push(Some(buf.split_to(data_len as usize).freeze()));
-            } else {
-                parameters.push(None);
-            }
+            parameters.push(codec::get_nullable_bytes(buf)?);
         }
 
-        let result_column_format_code_len = buf.get_i16();
-        let mut result_column_format_codes =
-            Vec::with_capacity(result_column_format_code_len as usize);
+        let result_column_format_code_len = codec::get_count(buf, 2)?;
+        let mut result_column_format_codes = Vec::with_capacity(result_column_format_code_len);
         for _ in 0..result_column_format_code_len {
             result_column_format_codes.push(buf.get_i16());
         }
@@ -0,0 +1,121 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::codec;
+use super::Message;
+use crate::error::PgWireResult;
+
+/// Call of a function by its OID, the legacy fast-path interface used by
+/// libpq's large object API.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct FunctionCall {
+    pub function_oid: u32,
+    pub argument_format_codes: Vec<i16>,
+    // None for Null argument
+    pub arguments: Vec<Option<Bytes>>,
+    pub result_format_code: i16,
+}
+
+pub const MESSAGE_TYPE_BYTE_FUNCTION_CALL: u8 = b'F';
+
+impl Message for FunctionCall {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_FUNCTION_CALL)
+    }
+
+    fn message_length(&self) -> usize {
+        4 + 4 // function_oid
+            + 2 // argument_format_codes len
+            + (2 * self.argument_format_codes.len()) // argument_format_codes
+            + 2 // arguments len
+            + self.arguments.iter().map(|a| 4 + a.as_ref().map(|data| data.len()).unwrap_or(0)).sum::<usize>() // arguments
+            + 2 // result_format_code
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_u32(self.function_oid);
+
+        buf.put_i16(self.argument_format_codes.len() as i16);
+        for c in &self.argument_format_codes {
+            buf.put_i16(*c);
+        }
+
+        buf.put_i16(self.arguments.len() as i16);
+        for v in &self.arguments {
+            if let Some(v) = v {
+                buf.put_i32(v.len() as i32);
+                buf.put_slice(v.as_ref());
+            } else {
+                buf.put_i32(-1);
+            }
+        }
+
+        buf.put_i16(self.result_format_code);
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, full_len: usize) -> PgWireResult<Self> {
+        // never read past the message itself, like bind
+        let buf = &mut buf.split_to(full_len - 4);
+
+        let function_oid = codec::get_i32(buf)? as u32;
+
+        let argument_format_code_len = codec::get_count(buf, 2)?;
+        let mut argument_format_codes = Vec::with_capacity(argument_format_code_len);
+        for _ in 0..argument_format_code_len {
+            argument_format_codes.push(codec::get_i16(buf)?);
+        }
+
+        let argument_len = codec::get_count(buf, 4)?;
+        let mut arguments = Vec::with_capacity(argument_len);
+        for _ in 0..argument_len {
+            arguments.push(codec::get_nullable_bytes(buf)?);
+        }
+
+        let result_format_code = codec::get_i16(buf)?;
+
+        Ok(FunctionCall {
+            function_oid,
+            argument_format_codes,
+            arguments,
+            result_format_code,
+        })
+    }
+}
+
+/// Result of a `FunctionCall`, `None` for Null.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct FunctionCallResponse {
+    pub value: Option<Bytes>,
+}
+
+pub const MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE: u8 = b'V';
+
+impl Message for FunctionCallResponse {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE)
+    }
+
+    fn message_length(&self) -> usize {
+        4 + 4 + self.value.as_ref().map(|v| v.len()).unwrap_or(0)
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        if let Some(ref value) = self.value {
+            buf.put_i32(value.len() as i32);
+            buf.put_slice(value.as_ref());
+        } else {
+            buf.put_i32(-1);
+        }
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, full_len: usize) -> PgWireResult<Self> {
+        let buf = &mut buf.split_to(full_len - 4);
+        let value = codec::get_nullable_bytes(buf)?;
+        Ok(FunctionCallResponse { value })
+    }
+}
@@ -61,6 +61,8 @@ pub mod copy;
 pub mod data;
 /// Extended query messages, including request/response for parse, bind and etc.
 pub mod extendedquery;
+/// Function call messages
+pub mod function;
 /// General response messages
 pub mod response;
 /// Simple query messages, including descriptions
@@ -75,6 +77,8 @@ pub mod terminate;
 pub enum PgWireFrontendMessage {
     Startup(startup::Startup),
     SslRequest(startup::SslRequest),
+    GssEncRequest(startup::GssEncRequest),
+    CancelRequest(startup::CancelRequest),
     PasswordMessageFamily(startup::PasswordMessageFamily),
 
     Query(simplequery::Query),
@@ -92,6 +96,8 @@ pub enum PgWireFrontendMessage {
     CopyData(copy::CopyData),
     CopyFail(copy::CopyFail),
     CopyDone(copy::CopyDone),
+
+    FunctionCall(function::FunctionCall),
 }
 
 impl PgWireFrontendMessage {
@@ -112,6 +118,8 @@ impl PgWireFrontendMessage {
         match self {
             Self::Startup(msg) => msg.encode(buf),
             Self::SslRequest(msg) => msg.encode(buf),
+            Self::GssEncRequest(msg) => msg.encode(buf),
+            Self::CancelRequest(msg) => msg.encode(buf),
             Self::PasswordMessageFamily(msg) => msg.encode(buf),
 
             Self::Query(msg) => msg.encode(buf),
@@ -129,6 +137,8 @@ impl PgWireFrontendMessage {
             Self::CopyData(msg) => msg.encode(buf),
             Self::CopyFail(msg) => msg.encode(buf),
             Self::CopyDone(msg) => msg.encode(buf),
+
+            Self::FunctionCall(msg) => msg.encode(buf),
         }
     }
 
@@ -182,6 +192,10 @@ impl PgWireFrontendMessage {
                 copy::MESSAGE_TYPE_BYTE_COPY_DONE => {
                     copy::CopyDone::decode(buf).map(|v| v.map(Self::CopyDone))
                 }
+
+                function::MESSAGE_TYPE_BYTE_FUNCTION_CALL => {
+                    function::FunctionCall::decode(buf).map(|v| v.map(Self::FunctionCall))
+                }
                 _ => Err(PgWireError::InvalidMessageType(first_byte)),
             }
         } else {
@@ -197,6 +211,7 @@ pub enum PgWireBackendMessage {
     Authentication(startup::Authentication),
     ParameterStatus(startup::ParameterStatus),
     BackendKeyData(startup::BackendKeyData),
+    NegotiateProtocolVersion(startup::NegotiateProtocolVersion),
 
     // extended query
     ParseComplete(extendedquery::ParseComplete),
@@ -226,6 +241,9 @@ pub enum PgWireBackendMessage {
     CopyInResponse(copy::CopyInResponse),
     CopyOutResponse(copy::CopyOutResponse),
     CopyBothResponse(copy::CopyBothResponse),
+
+    // function call
+    FunctionCallResponse(function::FunctionCallResponse),
 }
 
 impl PgWireBackendMessage {
@@ -234,6 +252,7 @@ impl PgWireBackendMessage {
             Self::Authentication(msg) => msg.encode(buf),
             Self::ParameterStatus(msg) => msg.encode(buf),
             Self::BackendKeyData(msg) => msg.encode(buf),
+            Self::NegotiateProtocolVersion(msg) => msg.encode(buf),
 
             Self::ParseComplete(msg) => msg.encode(buf),
             Self::BindComplete(msg) => msg.encode(buf),
@@ -259,6 +278,8 @@ impl PgWireBackendMessage {
             Self::CopyInResponse(msg) => msg.encode(buf),
             Self::CopyOutResponse(msg) => msg.encode(buf),
             Self::CopyBothResponse(msg) => msg.encode(buf),
+
+            Self::FunctionCallResponse(msg) => msg.encode(buf),
         }
     }
 
@@ -275,6 +296,10 @@ impl PgWireBackendMessage {
                 startup::MESSAGE_TYPE_BYTE_BACKEND_KEY_DATA => {
                     startup::BackendKeyData::decode(buf).map(|v| v.map(Self::BackendKeyData))
                 }
+                startup::MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION => {
+                    startup::NegotiateProtocolVersion::decode(buf)
+                        .map(|v| v.map(Self::NegotiateProtocolVersion))
+                }
 
                 extendedquery::MESSAGE_TYPE_BYTE_PARSE_COMPLETE => {
                     extendedquery::ParseComplete::decode(buf).map(|v| v.map(Self::ParseComplete))
@@ -343,6 +368,11 @@ impl PgWireBackendMessage {
                 copy::MESSAGE_TYPE_BYTE_COPY_BOTH_RESPONSE => {
                     copy::CopyBothResponse::decode(buf).map(|v| v.map(Self::CopyBothResponse))
                 }
+
+                function::MESSAGE_TYPE_BYTE_FUNCTION_CALL_RESPONSE => {
+                    function::FunctionCallResponse::decode(buf)
+                        .map(|v| v.map(Self::FunctionCallResponse))
+                }
                 _ => Err(PgWireError::InvalidMessageType(first_byte)),
             }
         } else {
@@ -356,6 +386,7 @@ mod test {
     use super::copy::*;
     use super::data::*;
     use super::extendedquery::*;
+    use super::function::*;
     use super::response::*;
     use super::simplequery::*;
     use super::startup::*;
@@ -400,6 +431,11 @@ mod test {
 
         let md5pass = Authentication::MD5Password(vec![b'p', b's', b't', b'g']);
         roundtrip!(md5pass, Authentication);
+
+        let sasl_continue = Authentication::SASLContinue(Bytes::from("r=abc,s=def,i=4096"));
+        roundtrip!(sasl_continue, Authentication);
+        let sasl_final = Authentication::SASLFinal(Bytes::from("v=xyz"));
+        roundtrip!(sasl_final, Authentication);
     }
 
     #[test]
@@ -414,6 +450,12 @@ mod test {
         roundtrip!(pps, ParameterStatus);
     }
 
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let npv = NegotiateProtocolVersion::new(0, vec!["_pq_.report".to_owned()]);
+        roundtrip!(npv, NegotiateProtocolVersion);
+    }
+
     #[test]
     fn test_query() {
         let query = Query::new("SELECT 1".to_owned());
@@ -454,24 +496,26 @@ mod test {
     fn test_row_description() {
         let mut row_description = RowDescription::default();
 
-        let mut f1 = FieldDescription::default();
-        f1.name = "id".into();
-        f1.table_id = 1001;
-        f1.column_id = 10001;
-        f1.type_id = 1083;
-        f1.type_size = 4;
-        f1.type_modifier = -1;
-        f1.format_code = FORMAT_CODE_TEXT;
+        let f1 = FieldDescription {
+            name: "id".into(),
+            table_id: 1001,
+            column_id: 10001,
+            type_id: 1083,
+            type_size: 4,
+            type_modifier: -1,
+            format_code: FORMAT_CODE_TEXT,
+        };
         row_description.fields.push(f1);
 
-        let mut f2 = FieldDescription::default();
-        f2.name = "name".into();
-        f2.table_id = 1001;
-        f2.column_id = 10001;
-        f2.type_id = 1099;
-        f2.type_size = -1;
-        f2.type_modifier = -1;
-        f2.format_code = FORMAT_CODE_TEXT;
+        let f2 = FieldDescription {
+            name: "name".into(),
+            table_id: 1001,
+            column_id: 10001,
+            type_id: 1099,
+            type_size: -1,
+            type_modifier: -1,
+            format_code: FORMAT_CODE_TEXT,
+        };
         row_description.fields.push(f2);
 
         roundtrip!(row_description, RowDescription);
@@ -487,6 +531,34 @@ mod test {
         roundtrip!(row0, DataRow);
     }
 
+    #[test]
+    fn test_data_row_empty_and_null() {
+        // '' and NULL text, empty bytea and NULL bytea
+        let row = DataRow::new(vec![
+            Some(Bytes::new()),
+            None,
+            Some(Bytes::from_static(b"\\x")),
+            None,
+        ]);
+        let mut buffer = BytesMut::new();
+        row.encode(&mut buffer).unwrap();
+        assert_eq!(
+            &b"D\0\0\0\x18\0\x04\0\0\0\0\xff\xff\xff\xff\0\0\0\x02\\x\xff\xff\xff\xff"[..],
+            &buffer[..]
+        );
+        roundtrip!(row, DataRow);
+
+        let row = DataRow::new(vec![Some(Bytes::new()), None]);
+        roundtrip!(row, DataRow);
+
+        // -1 is the only length of NULL
+        let mut buffer = BytesMut::from(&b"D\0\0\0\x0a\0\x01\xff\xff\xff\xfe"[..]);
+        assert!(DataRow::decode(&mut buffer).is_err());
+        // value beyond the message
+        let mut buffer = BytesMut::from(&b"D\0\0\0\x0a\0\x01\0\0\0\x01Z\0\0\0\x05I"[..]);
+        assert!(DataRow::decode(&mut buffer).is_err());
+    }
+
     #[test]
     fn test_terminate() {
         let terminate = Terminate::new();
@@ -530,6 +602,83 @@ mod test {
         roundtrip!(bind, Bind);
     }
 
+    #[test]
+    fn test_function_call() {
+        // lo_write(fd, data) as sent by libpq
+        let call = FunctionCall::new(
+            955,
+            vec![1, 1],
+            vec![
+                Some(Bytes::from_static(&[0, 0, 0, 0])),
+                Some(Bytes::from_static(b"hello")),
+            ],
+            1,
+        );
+        roundtrip!(call, FunctionCall);
+
+        let call = FunctionCall::new(957, vec![], vec![None], 0);
+        roundtrip!(call, FunctionCall);
+
+        let response = FunctionCallResponse::new(Some(Bytes::from_static(&[0, 0, 0, 5])));
+        roundtrip!(response, FunctionCallResponse);
+
+        let response = FunctionCallResponse::new(None);
+        roundtrip!(response, FunctionCallResponse);
+    }
+
+    #[test]
+    fn test_bind_empty_and_null() {
+        // empty text and bytea parameters are not NULL
+        let bind = Bind::new(
+            None,
+            None,
+            vec![0, 1, 0, 1],
+            vec![Some(Bytes::new()), Some(Bytes::new()), None, None],
+            vec![],
+        );
+        let mut buffer = BytesMut::new();
+        bind.encode(&mut buffer).unwrap();
+        let decoded = Bind::decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(
+            vec![Some(Bytes::new()), Some(Bytes::new()), None, None],
+            decoded.parameters
+        );
+
+        let mut buffer = BytesMut::from(&b"B\0\0\0\x10\0\0\0\0\0\x01\xff\xff\xff\xfe\0\0"[..]);
+        assert!(Bind::decode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_bind_malformed() {
+        let bind = Bind::new(
+            None,
+            Some("s0".to_owned()),
+            vec![1],
+            vec![Some(Bytes::from_static(b"1234")), None],
+            vec![1],
+        );
+        let mut valid = BytesMut::new();
+        bind.encode(&mut valid).unwrap();
+
+        // every truncation of the body is rejected without reading further
+        for len in 5..valid.len() {
+            let mut buffer = BytesMut::from(&valid[..len]);
+            buffer[1..5].copy_from_slice(&(len as i32 - 1).to_be_bytes());
+            buffer.extend_from_slice(b"S\0\0\0\x04");
+            assert!(Bind::decode(&mut buffer).is_err());
+        }
+
+        // negative counts and lengths
+        let mut buffer = BytesMut::from(&b"B\0\0\0\x08\0\0\xff\xff"[..]);
+        assert!(Bind::decode(&mut buffer).is_err());
+        let mut buffer = BytesMut::from(&b"B\0\0\0\x0e\0\0\0\0\0\x01\x7f\xff\xff\xff"[..]);
+        assert!(Bind::decode(&mut buffer).is_err());
+        let mut buffer = BytesMut::from(&b"B\0\0\0\x02"[..]);
+        assert!(Bind::decode(&mut buffer).is_err());
+        let mut buffer = BytesMut::from(&b"B\xff\xff\xff\xff"[..]);
+        assert!(Bind::decode(&mut buffer).is_err());
+    }
+
     #[test]
     fn test_execute() {
         let exec = Execute::new(Some("find-user-by-id-0".to_owned()), 100);
@@ -542,6 +691,27 @@ mod test {
         roundtrip!(sslreq, SslRequest);
     }
 
+    #[test]
+    fn test_gssencrequest() {
+        let gssencreq = GssEncRequest::new();
+        roundtrip!(gssencreq, GssEncRequest);
+
+        let mut buf = BytesMut::new();
+        gssencreq.encode(&mut buf).unwrap();
+        assert!(SslRequest::decode(&mut buf).unwrap().is_none());
+
+        buf.advance(4);
+        assert!(GssEncRequest::decode_body(&mut buf, 8).is_ok());
+        let mut buf = BytesMut::from(&SslRequest::BODY_MAGIC_NUMBER.to_be_bytes()[..]);
+        assert!(GssEncRequest::decode_body(&mut buf, 8).is_err());
+    }
+
+    #[test]
+    fn test_cancel_request() {
+        let cancel = CancelRequest::new(1234, 5678);
+        roundtrip!(cancel, CancelRequest);
+    }
+
     #[test]
     fn test_sslresponse() {
         let sslaccept = SslResponse::Accept;
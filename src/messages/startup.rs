@@ -30,9 +30,18 @@ impl Default for Startup {
 
 impl Startup {
     const MINIMUM_STARTUP_MESSAGE_LEN: usize = 8;
+    /// Default limit of startup packet size, same as postgres.
+    pub const MAX_STARTUP_PACKET_LENGTH: usize = 10000;
 
+    /// Newest minor version of protocol 3 supported by the server.
+    pub const NEWEST_MINOR_VERSION: u16 = 0;
+    /// Prefix of protocol extension parameters in startup message.
+    pub const PROTOCOL_EXTENSION_PREFIX: &'static str = "_pq_.";
+
+    /// Clients of any 3.x version are accepted, newer minor versions are
+    /// negotiated down with `NegotiateProtocolVersion`.
     fn is_protocol_version_supported(version: i32) -> bool {
-        version == 196608
+        version >> 16 == 3
     }
 }
 
@@ -41,7 +50,7 @@ impl Message for Startup {
         let param_length = self
             .parameters
             .iter()
-            .map(|(k, v)| k.as_bytes().len() + v.as_bytes().len() + 2)
+            .map(|(k, v)| k.len() + v.len() + 2)
             .sum::<usize>();
         // length:4 + protocol_number:4 + param.len + nullbyte:1
         9 + param_length
@@ -194,11 +203,11 @@ impl Message for Authentication {
                 Authentication::SASL(methods)
             }
             11 => {
-                let data = buf.split_to(msg_len - 4).freeze();
+                let data = buf.split_to(msg_len - 8).freeze();
                 Authentication::SASLContinue(data)
             }
             12 => {
-                let data = buf.split_to(msg_len - 4).freeze();
+                let data = buf.split_to(msg_len - 8).freeze();
                 Authentication::SASLFinal(data)
             }
             _ => unreachable!(),
@@ -334,7 +343,7 @@ impl Message for Password {
     }
 
     fn message_length(&self) -> usize {
-        5 + self.password.as_bytes().len()
+        5 + self.password.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -343,7 +352,14 @@ impl Message for Password {
         Ok(())
     }
 
-    fn decode_body(buf: &mut BytesMut, _: usize) -> PgWireResult<Self> {
+    fn decode_body(buf: &mut BytesMut, full_len: usize) -> PgWireResult<Self> {
+        let buf = &mut buf.split_to(full_len - 4);
+        // like postgres, the body must be exactly one null-terminated string
+        if buf.iter().position(|b| *b == b'\0') != Some(buf.len().saturating_sub(1)) {
+            return Err(PgWireError::InvalidMessage(
+                "invalid password packet size".to_owned(),
+            ));
+        }
         let pass = codec::get_cstring(buf).unwrap_or_else(|| "".to_owned());
 
         Ok(Password::new(pass))
@@ -366,7 +382,7 @@ impl Message for ParameterStatus {
     }
 
     fn message_length(&self) -> usize {
-        4 + 2 + self.name.as_bytes().len() + self.value.as_bytes().len()
+        4 + 2 + self.name.len() + self.value.len()
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -420,6 +436,119 @@ impl Message for BackendKeyData {
     }
 }
 
+/// `NegotiateProtocolVersion` sent from backend after startup message, when
+/// client requested a newer minor protocol version or protocol extensions
+/// (`_pq_.` parameters) the server doesn't support. The connection continues
+/// with the returned minor version and without those options.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct NegotiateProtocolVersion {
+    pub newest_minor_version: i32,
+    pub unsupported_options: Vec<String>,
+}
+
+pub const MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION: u8 = b'v';
+
+impl Message for NegotiateProtocolVersion {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        Some(MESSAGE_TYPE_BYTE_NEGOTIATE_PROTOCOL_VERSION)
+    }
+
+    fn message_length(&self) -> usize {
+        12 + self
+            .unsupported_options
+            .iter()
+            .map(|o| o.len() + 1)
+            .sum::<usize>()
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(self.newest_minor_version);
+        buf.put_i32(self.unsupported_options.len() as i32);
+        for option in &self.unsupported_options {
+            codec::put_cstring(buf, option);
+        }
+
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, _: usize) -> PgWireResult<Self> {
+        let newest_minor_version = codec::get_i32(buf)?;
+        let count = codec::get_i32(buf)?;
+        // each option takes at least its null terminator
+        if count < 0 || buf.remaining() < count as usize {
+            return Err(PgWireError::InvalidMessage(format!(
+                "invalid item count {count}"
+            )));
+        }
+        let mut unsupported_options = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            unsupported_options.push(codec::get_cstring(buf).unwrap_or_default());
+        }
+
+        Ok(NegotiateProtocolVersion {
+            newest_minor_version,
+            unsupported_options,
+        })
+    }
+}
+
+/// `CancelRequest` sent from frontend on a new connection, to cancel the
+/// query running on the connection identified by `BackendKeyData`.
+///
+/// Like `SslRequest`, the packet has no message type. It contains a length(4),
+/// a magic number(4) and the pid and secret key from `BackendKeyData`.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct CancelRequest {
+    pub pid: i32,
+    pub secret_key: i32,
+}
+
+impl CancelRequest {
+    pub const BODY_MAGIC_NUMBER: i32 = 80877102;
+    pub const BODY_SIZE: usize = 16;
+}
+
+impl Message for CancelRequest {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    fn message_length(&self) -> usize {
+        Self::BODY_SIZE
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(Self::BODY_MAGIC_NUMBER);
+        buf.put_i32(self.pid);
+        buf.put_i32(self.secret_key);
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, _full_len: usize) -> PgWireResult<Self> {
+        // skip magic number
+        buf.advance(4);
+        let pid = buf.get_i32();
+        let secret_key = buf.get_i32();
+
+        Ok(CancelRequest { pid, secret_key })
+    }
+
+    /// Try to decode and check if the packet is a `CancelRequest`.
+    fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
+        if buf.remaining() >= Self::BODY_SIZE && (&buf[4..8]).get_i32() == Self::BODY_MAGIC_NUMBER {
+            buf.advance(4);
+            Self::decode_body(buf, Self::BODY_SIZE).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 /// `Sslrequest` sent from frontend to negotiate with backend to check if the
 /// backend supports secure connection. The packet has no message type and
 /// contains only a length(4) and an i32 value.
@@ -467,6 +596,59 @@ impl Message for SslRequest {
     }
 }
 
+/// `GssEncRequest` sent from frontend to negotiate GSSAPI encryption. Like
+/// `SslRequest`, the packet contains only a length(4) and an i32 value.
+///
+/// GSSAPI encryption is not supported, the backend always answers 'N' and the
+/// frontend may continue with `SslRequest` or `Startup` on the same
+/// connection.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, new)]
+pub struct GssEncRequest;
+
+impl GssEncRequest {
+    pub const BODY_MAGIC_NUMBER: i32 = 80877104;
+    pub const BODY_SIZE: usize = 8;
+}
+
+impl Message for GssEncRequest {
+    #[inline]
+    fn message_type() -> Option<u8> {
+        None
+    }
+
+    #[inline]
+    fn message_length(&self) -> usize {
+        Self::BODY_SIZE
+    }
+
+    fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
+        buf.put_i32(Self::BODY_MAGIC_NUMBER);
+        Ok(())
+    }
+
+    fn decode_body(buf: &mut BytesMut, _full_len: usize) -> PgWireResult<Self> {
+        let code = codec::get_i32(buf)?;
+        if code == Self::BODY_MAGIC_NUMBER {
+            Ok(GssEncRequest)
+        } else {
+            Err(PgWireError::InvalidMessage(format!(
+                "invalid GSSENCRequest code {code}"
+            )))
+        }
+    }
+
+    /// Try to decode and check if the packet is a `GssEncRequest`.
+    fn decode(buf: &mut BytesMut) -> PgWireResult<Option<Self>> {
+        if buf.remaining() >= 8 && (&buf[4..8]).get_i32() == Self::BODY_MAGIC_NUMBER {
+            buf.advance(8);
+            Ok(Some(GssEncRequest))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Debug, new)]
 pub struct SASLInitialResponse {
@@ -482,10 +664,7 @@ impl Message for SASLInitialResponse {
 
     #[inline]
     fn message_length(&self) -> usize {
-        4 + self.auth_method.as_bytes().len()
-            + 1
-            + 4
-            + self.data.as_ref().map(|b| b.len()).unwrap_or(0)
+        4 + self.auth_method.len() + 1 + 4 + self.data.as_ref().map(|b| b.len()).unwrap_or(0)
     }
 
     fn encode_body(&self, buf: &mut BytesMut) -> PgWireResult<()> {
@@ -501,12 +680,7 @@ impl Message for SASLInitialResponse {
 
     fn decode_body(buf: &mut BytesMut, _full_len: usize) -> PgWireResult<Self> {
         let auth_method = codec::get_cstring(buf).unwrap_or_else(|| "".to_owned());
-        let data_len = buf.get_i32();
-        let data = if data_len == -1 {
-            None
-        } else {
-            Some(buf.split_to(data_len as usize).freeze())
-        };
+        let data = codec::get_nullable_bytes(buf)?;
 
         Ok(SASLInitialResponse { auth_method, data })
     }
@@ -1,28 +1,55 @@
 use std::io::Error as IOError;
 use std::sync::Arc;
+use std::time::Duration;
 
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 use futures::future::poll_fn;
 use futures::{SinkExt, StreamExt};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio_rustls::TlsAcceptor;
 use tokio_util::codec::{Decoder, Encoder, Framed};
 
-use crate::api::auth::StartupHandler;
+pub use socket2::TcpKeepalive;
+
+use crate::api::admission::AdmissionControl;
+use crate::api::auth::{check_integer_datetimes, negotiate_protocol_version, StartupHandler};
+use crate::api::cancel::{self, query_canceled_error, CancellationToken};
+use crate::api::copy::unexpected_copy_message_error;
+use crate::api::extensions::Extensions;
 use crate::api::query::ExtendedQueryHandler;
 use crate::api::query::SimpleQueryHandler;
-use crate::api::{ClientInfo, ClientPortalStore, DefaultClient, PgWireConnectionState};
-use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+use crate::api::replication::{ReplicationMode, METADATA_REPLICATION};
+use crate::api::results::RowLimit;
+use crate::api::{
+    ClientInfo, ClientPortalStore, DefaultClient, PeerCred, PgWireConnectionState,
+    TransactionStatus,
+};
+use crate::error::{protocol_violation, ErrorInfo, PgWireError, PgWireResult};
 use crate::messages::response::ReadyForQuery;
 use crate::messages::response::{SslResponse, READY_STATUS_IDLE};
-use crate::messages::startup::{SslRequest, Startup};
+use crate::messages::startup::{CancelRequest, GssEncRequest, SslRequest, Startup};
 use crate::messages::{Message, PgWireBackendMessage, PgWireFrontendMessage};
 
 #[non_exhaustive]
 #[derive(Debug, new)]
 pub struct PgWireMessageServerCodec<S> {
     pub client_info: DefaultClient<S>,
+    /// Packets received before startup, which are not authenticated yet, are
+    /// rejected when larger than this.
+    #[new(value = "Startup::MAX_STARTUP_PACKET_LENGTH")]
+    pub max_startup_packet_size: usize,
+    /// Transaction status of the last `ReadyForQuery` sent, until next
+    /// message is received from client.
+    #[new(default)]
+    idle_transaction_status: Option<u8>,
+    /// Whether GSSAPI encryption was negotiated, i.e. a `GssEncRequest` was
+    /// refused or TLS is established. Like postgres, another `GssEncRequest`
+    /// is rejected then.
+    #[new(default)]
+    gss_enc_done: bool,
 }
 
 impl<S> Decoder for PgWireMessageServerCodec<S> {
@@ -30,12 +57,40 @@ impl<S> Decoder for PgWireMessageServerCodec<S> {
     type Error = PgWireError;
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let message = self.decode_message(src);
+        if let Ok(Some(_)) = message {
+            self.idle_transaction_status = None;
+        }
+        message
+    }
+}
+
+impl<S> PgWireMessageServerCodec<S> {
+    fn decode_message(
+        &mut self,
+        src: &mut bytes::BytesMut,
+    ) -> PgWireResult<Option<PgWireFrontendMessage>> {
         match self.client_info.state() {
             PgWireConnectionState::AwaitingStartup => {
+                if src.len() >= 4 {
+                    let len = (&src[..4]).get_i32() as usize;
+                    if len > self.max_startup_packet_size {
+                        return Err(PgWireError::StartupPacketTooLarge(len));
+                    }
+                }
+
                 if let Some(request) = SslRequest::decode(src)? {
                     return Ok(Some(PgWireFrontendMessage::SslRequest(request)));
                 }
 
+                if let Some(request) = GssEncRequest::decode(src)? {
+                    return Ok(Some(PgWireFrontendMessage::GssEncRequest(request)));
+                }
+
+                if let Some(request) = CancelRequest::decode(src)? {
+                    return Ok(Some(PgWireFrontendMessage::CancelRequest(request)));
+                }
+
                 if let Some(startup) = Startup::decode(src)? {
                     return Ok(Some(PgWireFrontendMessage::Startup(startup)));
                 }
@@ -55,6 +110,9 @@ impl<S> Encoder<PgWireBackendMessage> for PgWireMessageServerCodec<S> {
         item: PgWireBackendMessage,
         dst: &mut bytes::BytesMut,
     ) -> Result<(), Self::Error> {
+        if let PgWireBackendMessage::ReadyForQuery(ref ready) = item {
+            self.idle_transaction_status = Some(ready.status);
+        }
         item.encode(dst).map_err(Into::into)
     }
 }
@@ -76,6 +134,14 @@ impl<T, S> ClientInfo for Framed<T, PgWireMessageServerCodec<S>> {
         self.codec_mut().client_info.set_state(new_state);
     }
 
+    fn transaction_status(&self) -> TransactionStatus {
+        self.codec().client_info.transaction_status()
+    }
+
+    fn set_transaction_status(&mut self, status: TransactionStatus) {
+        self.codec_mut().client_info.set_transaction_status(status);
+    }
+
     fn metadata(&self) -> &std::collections::HashMap<String, String> {
         self.codec().client_info.metadata()
     }
@@ -83,6 +149,34 @@ impl<T, S> ClientInfo for Framed<T, PgWireMessageServerCodec<S>> {
     fn metadata_mut(&mut self) -> &mut std::collections::HashMap<String, String> {
         self.codec_mut().client_info.metadata_mut()
     }
+
+    fn backend_key(&self) -> Option<(i32, i32)> {
+        self.codec().client_info.backend_key()
+    }
+
+    fn cancel_token(&self) -> CancellationToken {
+        self.codec().client_info.cancel_token()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCred> {
+        self.codec().client_info.peer_credentials()
+    }
+
+    fn replication_mode(&self) -> Option<ReplicationMode> {
+        self.codec().client_info.replication_mode()
+    }
+
+    fn max_result_rows(&self) -> Option<RowLimit> {
+        self.codec().client_info.max_result_rows()
+    }
+
+    fn extensions(&self) -> Option<&Extensions> {
+        self.codec().client_info.extensions()
+    }
+
+    fn extensions_mut(&mut self) -> Option<&mut Extensions> {
+        self.codec_mut().client_info.extensions_mut()
+    }
 }
 
 impl<T, S> ClientPortalStore for Framed<T, PgWireMessageServerCodec<S>> {
@@ -93,22 +187,223 @@ impl<T, S> ClientPortalStore for Framed<T, PgWireMessageServerCodec<S>> {
     }
 }
 
-async fn process_message<S, A, Q, EQ>(
-    message: PgWireFrontendMessage,
+/// Connection level options of `process_socket_with_options`.
+#[non_exhaustive]
+#[derive(Debug, Clone, new)]
+pub struct SocketOptions {
+    /// Reject connections that don't negotiate TLS with `SslRequest`, like a
+    /// `hostssl` only `pg_hba.conf` in postgres.
+    ///
+    /// Plaintext clients receive a `FATAL` error instructing them to use
+    /// `sslmode=require` in response to their startup message. Cancel
+    /// requests are still accepted in plaintext as their clients never
+    /// negotiate TLS. Note that without a `TlsAcceptor`, all connections are
+    /// rejected.
+    pub require_tls: bool,
+    /// Limit of the startup packet size, 10000 bytes by default like
+    /// postgres.
+    ///
+    /// The startup packet is processed before authentication, larger packets
+    /// are rejected with a protocol violation error.
+    #[new(value = "Startup::MAX_STARTUP_PACKET_LENGTH")]
+    pub max_startup_packet_size: usize,
+    /// Checked on every query start, see `AdmissionControl`. All queries are
+    /// admitted by default.
+    #[new(default)]
+    pub admission_control: Option<Arc<dyn AdmissionControl>>,
+    /// Limit of extended query messages a client may pipeline before `Sync`,
+    /// `DEFAULT_MAX_PIPELINED_MESSAGES` by default.
+    ///
+    /// Messages are counted from the last `Sync` or simple `Query`, a `Flush`
+    /// does not reset the count. This stops a client from keeping one batch
+    /// open indefinitely. Once exceeded, the batch fails with a `08P01` error
+    /// and remaining messages are discarded until `Sync`.
+    #[new(value = "DEFAULT_MAX_PIPELINED_MESSAGES")]
+    pub max_pipelined_messages: usize,
+    /// Close connections idle in a transaction for longer than this, like
+    /// `idle_in_transaction_session_timeout` of postgres. Disabled by
+    /// default.
+    ///
+    /// A connection is idle in a transaction after `ReadyForQuery` with
+    /// status `T` or `E` is sent, until client sends next message. The status
+    /// is `ClientInfo::transaction_status`, which handlers update on `BEGIN`
+    /// and `COMMIT`. On timeout,
+    /// a `FATAL` error `25P03` is sent and the connection is closed, so locks
+    /// held by the transaction can be released by the handler when the
+    /// connection is dropped.
+    #[new(default)]
+    pub idle_in_transaction_session_timeout: Option<Duration>,
+    /// Cap on rows of every query response on the connection, a guardrail
+    /// for multi-tenant deployments. Unlimited by default.
+    ///
+    /// Exposed as `ClientInfo::max_result_rows` and enforced by
+    /// `send_query_response`, in addition to a lower
+    /// `QueryResponse::with_row_limit` set by handlers.
+    #[new(default)]
+    pub max_result_rows: Option<RowLimit>,
+}
+
+/// Default of `SocketOptions::max_pipelined_messages`, generous for batches
+/// of clients like JDBC while keeping a connection bounded.
+pub const DEFAULT_MAX_PIPELINED_MESSAGES: usize = 100_000;
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions::new(false)
+    }
+}
+
+impl SocketOptions {
+    pub fn with_require_tls(mut self, require_tls: bool) -> Self {
+        self.require_tls = require_tls;
+        self
+    }
+
+    pub fn with_max_startup_packet_size(mut self, max_startup_packet_size: usize) -> Self {
+        self.max_startup_packet_size = max_startup_packet_size;
+        self
+    }
+
+    pub fn with_admission_control(mut self, admission_control: Arc<dyn AdmissionControl>) -> Self {
+        self.admission_control = Some(admission_control);
+        self
+    }
+
+    pub fn with_max_pipelined_messages(mut self, max_pipelined_messages: usize) -> Self {
+        self.max_pipelined_messages = max_pipelined_messages;
+        self
+    }
+
+    pub fn with_idle_in_transaction_session_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_in_transaction_session_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_max_result_rows(mut self, max_result_rows: RowLimit) -> Self {
+        self.max_result_rows = Some(max_result_rows);
+        self
+    }
+}
+
+/// Error sent to plaintext clients when `SocketOptions::require_tls` is set.
+fn tls_required_error() -> ErrorInfo {
+    ErrorInfo::new(
+        "FATAL".to_owned(),
+        "28000".to_owned(),
+        "SSL connection is required by the server, use sslmode=require".to_owned(),
+    )
+}
+
+/// Error sent to clients repeating `GssEncRequest`, which postgres takes as
+/// a startup packet of unknown protocol version.
+fn duplicate_gss_enc_request_error() -> ErrorInfo {
+    let code = GssEncRequest::BODY_MAGIC_NUMBER;
+    ErrorInfo::new(
+        "FATAL".to_owned(),
+        "0A000".to_owned(),
+        format!(
+            "unsupported frontend protocol {}.{}: server supports 3.0 to 3.0",
+            code >> 16,
+            code & 0xffff
+        ),
+    )
+}
+
+/// Error sent before closing a connection idle in a transaction for longer
+/// than `SocketOptions::idle_in_transaction_session_timeout`.
+fn idle_in_transaction_timeout_error() -> ErrorInfo {
+    ErrorInfo::new(
+        "FATAL".to_owned(),
+        "25P03".to_owned(),
+        "terminating connection due to idle-in-transaction timeout".to_owned(),
+    )
+}
+
+/// Check `replication` parameter of startup message, return error to reject
+/// the connection.
+fn check_replication_startup(startup: &Startup, replication_supported: bool) -> Option<ErrorInfo> {
+    let value = startup.parameters.get(METADATA_REPLICATION)?;
+    match ReplicationMode::parse(value) {
+        Ok(Some(_)) if !replication_supported => Some(ErrorInfo::new(
+            "FATAL".to_owned(),
+            "0A000".to_owned(),
+            "replication connection is not supported by this server".to_owned(),
+        )),
+        Ok(_) => None,
+        Err(PgWireError::UserError(error_info)) => Some(*error_info),
+        Err(e) => Some(ErrorInfo::new(
+            "FATAL".to_owned(),
+            "XX000".to_owned(),
+            e.to_string(),
+        )),
+    }
+}
+
+async fn process_message<S, A, Q, EQ, R>(
+    mut message: PgWireFrontendMessage,
     socket: &mut Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
     authenticator: Arc<A>,
     query_handler: Arc<Q>,
     extended_query_handler: Arc<EQ>,
+    replication_handler: Option<Arc<R>>,
 ) -> PgWireResult<()>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
     A: StartupHandler,
     Q: SimpleQueryHandler,
     EQ: ExtendedQueryHandler,
+    R: SimpleQueryHandler,
 {
+    if let PgWireFrontendMessage::CancelRequest(request) = message {
+        // the connection is only used for cancelling, no response is sent
+        cancel::cancel(request.pid, request.secret_key);
+        return socket.close().await.map_err(Into::into);
+    }
+
     match socket.codec().client_info.state() {
         PgWireConnectionState::AwaitingStartup
         | PgWireConnectionState::AuthenticationInProgress => {
+            if let PgWireFrontendMessage::GssEncRequest(_) = message {
+                if socket.codec().gss_enc_done {
+                    socket
+                        .send(PgWireBackendMessage::ErrorResponse(
+                            duplicate_gss_enc_request_error().into(),
+                        ))
+                        .await?;
+                    return socket.close().await.map_err(Into::into);
+                }
+                socket.codec_mut().gss_enc_done = true;
+            }
+            if let PgWireFrontendMessage::SslRequest(_) | PgWireFrontendMessage::GssEncRequest(_) =
+                message
+            {
+                // encryption requests not handled before the message loop,
+                // e.g. on unix domain sockets, are refused
+                return socket
+                    .send(PgWireBackendMessage::SslResponse(SslResponse::Refuse))
+                    .await
+                    .map_err(Into::into);
+            }
+            if let PgWireFrontendMessage::Startup(ref mut startup) = message {
+                if let Some(error_info) =
+                    check_replication_startup(startup, replication_handler.is_some())
+                        .or_else(|| check_integer_datetimes(startup))
+                {
+                    socket
+                        .send(PgWireBackendMessage::ErrorResponse(error_info.into()))
+                        .await?;
+                    return socket.close().await.map_err(Into::into);
+                }
+                socket.codec_mut().client_info.replication_mode = startup
+                    .parameters
+                    .get(METADATA_REPLICATION)
+                    .and_then(|value| ReplicationMode::parse(value).ok().flatten());
+                if let Some(negotiation) = negotiate_protocol_version(startup) {
+                    socket
+                        .feed(PgWireBackendMessage::NegotiateProtocolVersion(negotiation))
+                        .await?;
+                }
+            }
             authenticator.on_startup(socket, message).await?;
         }
         // From Postgres docs:
@@ -122,12 +417,73 @@ where
                 socket.set_state(PgWireConnectionState::ReadyForQuery);
             }
         }
+        PgWireConnectionState::CopyInProgress(is_extended_query) => {
+            match replication_handler
+                .as_ref()
+                .filter(|_| socket.is_replication_connection())
+            {
+                Some(replication_handler) => {
+                    process_copy_message(message, socket, replication_handler, is_extended_query)
+                        .await?;
+                }
+                None => {
+                    process_copy_message(message, socket, &query_handler, is_extended_query)
+                        .await?;
+                }
+            }
+        }
         _ => {
+            // a cancel request received while idle doesn't affect next query
+            socket.codec().client_info.cancel_handle.reset();
+
+            if let Some(replication_handler) = replication_handler
+                .as_ref()
+                .filter(|_| socket.is_replication_connection())
+            {
+                // replication connections only speak simple query protocol
+                match message {
+                    PgWireFrontendMessage::Query(query) => {
+                        replication_handler.on_query(socket, query).await?;
+                    }
+                    // there is no extended query to sync or flush, answer them
+                    // as a simple query, for clients recovering from an error
+                    PgWireFrontendMessage::Sync(_) => {
+                        socket
+                            .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                                socket.transaction_status().ready_status(),
+                            )))
+                            .await?;
+                    }
+                    PgWireFrontendMessage::Flush(_) => {
+                        socket.flush().await?;
+                    }
+                    message if message.is_extended_query() => {
+                        return Err(protocol_violation(
+                            "extended query protocol not supported in a replication connection"
+                                .to_owned(),
+                        ));
+                    }
+                    PgWireFrontendMessage::FunctionCall(_) => {
+                        return Err(protocol_violation(
+                            "fastpath function calls not supported in a replication connection"
+                                .to_owned(),
+                        ));
+                    }
+                    // as in postgres, copy messages outside copy mode are
+                    // ignored, they are left over from a failed copy
+                    _ => {}
+                }
+                return Ok(());
+            }
+
             // query or query in progress
             match message {
                 PgWireFrontendMessage::Query(query) => {
                     query_handler.on_query(socket, query).await?;
                 }
+                PgWireFrontendMessage::FunctionCall(call) => {
+                    query_handler.on_function_call(socket, call).await?;
+                }
                 PgWireFrontendMessage::Parse(parse) => {
                     extended_query_handler.on_parse(socket, parse).await?;
                 }
@@ -153,6 +509,52 @@ where
     Ok(())
 }
 
+/// Pass a message received in copy mode to `handler`, and leave copy mode on
+/// `CopyDone`, `CopyFail` or an error.
+async fn process_copy_message<S, ST, H>(
+    message: PgWireFrontendMessage,
+    socket: &mut Framed<S, PgWireMessageServerCodec<ST>>,
+    handler: &Arc<H>,
+    is_extended_query: bool,
+) -> PgWireResult<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    ST: Send + Sync,
+    H: SimpleQueryHandler,
+{
+    // Flush and Sync are ignored during copy as in postgres, for
+    // clients sending them without noticing the command was a COPY
+    let done = match message {
+        PgWireFrontendMessage::CopyData(_) | PgWireFrontendMessage::CopyDone(_)
+            if socket.cancel_token().is_cancelled() =>
+        {
+            Err(query_canceled_error())
+        }
+        PgWireFrontendMessage::CopyData(data) => {
+            handler.on_copy_data(socket, data).await.map(|_| false)
+        }
+        PgWireFrontendMessage::CopyDone(done) => {
+            handler.on_copy_done(socket, done).await.map(|_| true)
+        }
+        PgWireFrontendMessage::CopyFail(fail) => Err(handler.on_copy_fail(socket, fail).await),
+        PgWireFrontendMessage::Flush(_) | PgWireFrontendMessage::Sync(_) => Ok(false),
+        message => Err(unexpected_copy_message_error(&message)),
+    };
+    if !matches!(done, Ok(false)) {
+        // copy data still in flight after an error is dropped by the
+        // message loop, as copy messages are ignored outside copy mode
+        socket.set_state(PgWireConnectionState::ReadyForQuery);
+    }
+    if done? && !is_extended_query {
+        socket
+            .send(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
+                socket.transaction_status().ready_status(),
+            )))
+            .await?;
+    }
+    Ok(())
+}
+
 async fn process_error<S, ST>(
     socket: &mut Framed<S, PgWireMessageServerCodec<ST>>,
     error: PgWireError,
@@ -173,6 +575,28 @@ where
                 .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
                 .await?;
         }
+        // client's statement cache may be out of sync, fail only the query
+        PgWireError::StatementNotFound(name) => {
+            let message = if name.is_empty() {
+                "unnamed prepared statement does not exist".to_owned()
+            } else {
+                format!("prepared statement \"{name}\" does not exist")
+            };
+            let error_info = ErrorInfo::new("ERROR".to_owned(), "26000".to_owned(), message);
+            socket
+                .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
+                .await?;
+        }
+        PgWireError::PortalNotFound(name) => {
+            let error_info = ErrorInfo::new(
+                "ERROR".to_owned(),
+                "34000".to_owned(),
+                format!("portal \"{name}\" does not exist"),
+            );
+            socket
+                .feed(PgWireBackendMessage::ErrorResponse(error_info.into()))
+                .await?;
+        }
         _ => {
             // Internal error
             let error_info =
@@ -184,12 +608,16 @@ where
         }
     }
 
+    // as in postgres, an error aborts the current transaction block
+    if socket.transaction_status() == TransactionStatus::Transaction {
+        socket.set_transaction_status(TransactionStatus::Error);
+    }
     if wait_for_sync {
         socket.set_state(PgWireConnectionState::AwaitingSync);
     } else {
         socket
             .feed(PgWireBackendMessage::ReadyForQuery(ReadyForQuery::new(
-                READY_STATUS_IDLE,
+                socket.transaction_status().ready_status(),
             )))
             .await?;
     }
@@ -198,41 +626,115 @@ where
     Ok(())
 }
 
-async fn is_sslrequest_pending(tcp_socket: &TcpStream) -> Result<bool, IOError> {
+/// Respond to a message that cannot be decoded, and close the connection.
+async fn process_decode_error<S, ST>(
+    socket: &mut Framed<S, PgWireMessageServerCodec<ST>>,
+    error: PgWireError,
+) -> Result<(), IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+{
+    let error_info = match error {
+        PgWireError::InvalidProtocolVersion(version) => {
+            let (major, minor) = (version >> 16, version & 0xffff);
+            // Like postgres, refuse clients of other protocol versions, e.g.
+            // 2.0, with a clear error rather than misparsing their startup
+            // packet. Unknown special request codes (major version 1234) are
+            // dropped silently.
+            (major != 1234).then(|| {
+                ErrorInfo::new(
+                    "FATAL".to_owned(),
+                    "0A000".to_owned(),
+                    format!(
+                        "unsupported frontend protocol {major}.{minor}: server supports 3.0 to 3.0"
+                    ),
+                )
+            })
+        }
+        PgWireError::StartupPacketTooLarge(_) => Some(ErrorInfo::new(
+            "FATAL".to_owned(),
+            "08P01".to_owned(),
+            "invalid length of startup packet".to_owned(),
+        )),
+        _ => None,
+    };
+    if let Some(error_info) = error_info {
+        socket
+            .send(PgWireBackendMessage::ErrorResponse(error_info.into()))
+            .await?;
+    }
+    socket.close().await
+}
+
+/// Peek the first packet of a connection, return it if it's a `SslRequest` or
+/// `GssEncRequest`.
+async fn peek_encryption_request(
+    tcp_socket: &TcpStream,
+) -> Result<Option<PgWireFrontendMessage>, IOError> {
     let mut buf = [0u8; SslRequest::BODY_SIZE];
     let mut buf = ReadBuf::new(&mut buf);
     while buf.filled().len() < SslRequest::BODY_SIZE {
         if poll_fn(|cx| tcp_socket.poll_peek(cx, &mut buf)).await? == 0 {
             // the tcp_stream has ended
-            return Ok(false);
+            return Ok(None);
         }
     }
 
     let mut buf = BytesMut::from(buf.filled());
-    if let Ok(Some(_)) = SslRequest::decode(&mut buf) {
-        return Ok(true);
+    if let Ok(Some(request)) = SslRequest::decode(&mut buf) {
+        return Ok(Some(PgWireFrontendMessage::SslRequest(request)));
+    }
+    if let Ok(Some(request)) = GssEncRequest::decode(&mut buf) {
+        return Ok(Some(PgWireFrontendMessage::GssEncRequest(request)));
     }
-    Ok(false)
+    Ok(None)
 }
 
 async fn peek_for_sslrequest<ST>(
     socket: &mut Framed<TcpStream, PgWireMessageServerCodec<ST>>,
     ssl_supported: bool,
 ) -> Result<bool, IOError> {
-    let mut ssl = false;
-    if is_sslrequest_pending(socket.get_ref()).await? {
-        // consume request
-        socket.next().await;
-
-        let response = if ssl_supported {
-            ssl = true;
-            PgWireBackendMessage::SslResponse(SslResponse::Accept)
-        } else {
-            PgWireBackendMessage::SslResponse(SslResponse::Refuse)
-        };
-        socket.send(response).await?;
+    loop {
+        match peek_encryption_request(socket.get_ref()).await? {
+            // a repeated request is left to the message loop, which rejects it
+            Some(PgWireFrontendMessage::GssEncRequest(_)) if !socket.codec().gss_enc_done => {
+                // consume request, GSSAPI encryption is not supported and the
+                // client may try SSL next
+                socket.next().await;
+                socket.codec_mut().gss_enc_done = true;
+                socket
+                    .send(PgWireBackendMessage::SslResponse(SslResponse::Refuse))
+                    .await?;
+            }
+            Some(PgWireFrontendMessage::SslRequest(_)) => {
+                // consume request
+                socket.next().await;
+
+                let response = if ssl_supported {
+                    SslResponse::Accept
+                } else {
+                    SslResponse::Refuse
+                };
+                socket
+                    .send(PgWireBackendMessage::SslResponse(response))
+                    .await?;
+                return Ok(ssl_supported);
+            }
+            _ => return Ok(false),
+        }
     }
-    Ok(ssl)
+}
+
+/// Enable TCP keepalive on an accepted client socket, so dead clients of
+/// long-idle connections, e.g. behind NAT or load balancers, are detected and
+/// their connections reaped.
+///
+/// The postgres protocol has no application-level ping for idle sessions,
+/// only replication connections exchange keepalive messages. Call this before
+/// passing the socket to `process_socket`. Probe time, interval and retries
+/// are configured on `TcpKeepalive` where the platform supports them.
+pub fn set_tcp_keepalive(tcp_socket: &TcpStream, keepalive: &TcpKeepalive) -> Result<(), IOError> {
+    socket2::SockRef::from(tcp_socket).set_tcp_keepalive(keepalive)
 }
 
 pub async fn process_socket<A, Q, EQ>(
@@ -246,32 +748,90 @@ where
     A: StartupHandler,
     Q: SimpleQueryHandler,
     EQ: ExtendedQueryHandler,
+{
+    process_socket_with_replication(
+        tcp_socket,
+        tls_acceptor,
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        None::<Arc<Q>>,
+    )
+    .await
+}
+
+/// Like `process_socket`, but also accepts replication connections.
+///
+/// Connections with `replication` startup parameter are rejected by
+/// `process_socket`. Here, once authenticated, `Query` messages of such
+/// connections are routed to `replication_handler` instead of
+/// `query_handler`, and extended query messages are refused as postgres does.
+/// Use `ClientInfo::replication_mode` to tell physical from logical
+/// replication, and `ReplicationQueryHandler` to dispatch replication
+/// commands to a `ReplicationCommandHandler`.
+pub async fn process_socket_with_replication<A, Q, EQ, R>(
+    tcp_socket: TcpStream,
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    replication_handler: Option<Arc<R>>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: SimpleQueryHandler,
+{
+    process_socket_with_options(
+        tcp_socket,
+        tls_acceptor,
+        SocketOptions::default(),
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        replication_handler,
+    )
+    .await
+}
+
+/// Like `process_socket_with_replication`, with connection level
+/// `SocketOptions`, e.g. to require TLS.
+pub async fn process_socket_with_options<A, Q, EQ, R>(
+    tcp_socket: TcpStream,
+    tls_acceptor: Option<Arc<TlsAcceptor>>,
+    options: SocketOptions,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    replication_handler: Option<Arc<R>>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: SimpleQueryHandler,
 {
     let addr = tcp_socket.peer_addr()?;
     tcp_socket.set_nodelay(true)?;
 
     let client_info = DefaultClient::new(addr, false);
-    let mut tcp_socket = Framed::new(tcp_socket, PgWireMessageServerCodec::new(client_info));
+    let mut codec = PgWireMessageServerCodec::new(client_info);
+    codec.max_startup_packet_size = options.max_startup_packet_size;
+    let mut tcp_socket = Framed::new(tcp_socket, codec);
     let ssl = peek_for_sslrequest(&mut tcp_socket, tls_acceptor.is_some()).await?;
 
     if !ssl {
         // use an already configured socket.
-        let mut socket = tcp_socket;
-
-        while let Some(Ok(msg)) = socket.next().await {
-            let is_extended_query = msg.is_extended_query();
-            if let Err(e) = process_message(
-                msg,
-                &mut socket,
-                startup_handler.clone(),
-                query_handler.clone(),
-                extended_query_handler.clone(),
-            )
-            .await
-            {
-                process_error(&mut socket, e, is_extended_query).await?;
-            }
-        }
+        process_framed_socket(
+            tcp_socket,
+            options,
+            startup_handler,
+            query_handler,
+            extended_query_handler,
+            replication_handler,
+        )
+        .await
     } else {
         // mention the use of ssl
         let client_info = DefaultClient::new(addr, true);
@@ -280,23 +840,1630 @@ where
             .unwrap()
             .accept(tcp_socket.into_inner())
             .await?;
-        let mut socket = Framed::new(ssl_socket, PgWireMessageServerCodec::new(client_info));
+        let mut codec = PgWireMessageServerCodec::new(client_info);
+        codec.max_startup_packet_size = options.max_startup_packet_size;
+        codec.gss_enc_done = true;
+        let socket = Framed::new(ssl_socket, codec);
+        process_framed_socket(
+            socket,
+            options.with_require_tls(false),
+            startup_handler,
+            query_handler,
+            extended_query_handler,
+            replication_handler,
+        )
+        .await
+    }
+}
+
+/// Process a client connected to a unix domain socket.
+///
+/// Like postgres, TLS is not available on unix domain sockets and
+/// `SslRequest` is always refused. OS credentials of the peer process are
+/// exposed as `ClientInfo::peer_credentials`. As there is no IP address,
+/// `ClientInfo::socket_addr` reports the unspecified address `0.0.0.0:0`.
+///
+/// `options` apply as in `process_socket_with_options`, except
+/// `require_tls`, which is ignored like `hostssl` entries of `pg_hba.conf`
+/// don't match unix domain sockets.
+#[cfg(unix)]
+pub async fn process_unix_socket<A, Q, EQ, R>(
+    unix_socket: UnixStream,
+    options: SocketOptions,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    replication_handler: Option<Arc<R>>,
+) -> Result<(), IOError>
+where
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: SimpleQueryHandler,
+{
+    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 0));
+    let mut client_info = DefaultClient::new(addr, false);
+    client_info.peer_credentials = unix_socket
+        .peer_cred()
+        .ok()
+        .map(|cred| PeerCred::new(cred.uid(), cred.gid(), cred.pid()));
+    let mut codec = PgWireMessageServerCodec::new(client_info);
+    codec.max_startup_packet_size = options.max_startup_packet_size;
+    let socket = Framed::new(unix_socket, codec);
+    process_framed_socket(
+        socket,
+        options.with_require_tls(false),
+        startup_handler,
+        query_handler,
+        extended_query_handler,
+        replication_handler,
+    )
+    .await
+}
+
+/// Ask `admission_control` whether a query started by `message` may run.
+fn check_admission<S, ST>(
+    socket: &Framed<S, PgWireMessageServerCodec<ST>>,
+    message: &PgWireFrontendMessage,
+    admission_control: Option<&dyn AdmissionControl>,
+) -> PgWireResult<()> {
+    let client_info = &socket.codec().client_info;
+    match (admission_control, message, client_info.state()) {
+        (
+            Some(admission_control),
+            PgWireFrontendMessage::Query(_)
+            | PgWireFrontendMessage::Execute(_)
+            | PgWireFrontendMessage::FunctionCall(_),
+            PgWireConnectionState::ReadyForQuery | PgWireConnectionState::QueryInProgress,
+        ) => admission_control.admit(client_info),
+        _ => Ok(()),
+    }
+}
+
+/// Count extended query messages received since last `Sync`.
+#[derive(Debug)]
+struct PipelineLimit {
+    max: usize,
+    queued: usize,
+}
+
+impl PipelineLimit {
+    /// Return `08P01` error if `message` exceeds the limit of the batch it
+    /// belongs to.
+    fn check(
+        &mut self,
+        message: &PgWireFrontendMessage,
+        state: PgWireConnectionState,
+    ) -> PgWireResult<()> {
+        match (message, state) {
+            (PgWireFrontendMessage::Sync(_) | PgWireFrontendMessage::Query(_), _) => {
+                self.queued = 0;
+            }
+            (
+                PgWireFrontendMessage::Flush(_),
+                PgWireConnectionState::ReadyForQuery | PgWireConnectionState::QueryInProgress,
+            ) => {}
+            (
+                message,
+                PgWireConnectionState::ReadyForQuery | PgWireConnectionState::QueryInProgress,
+            ) if message.is_extended_query() => {
+                self.queued += 1;
+                if self.queued > self.max {
+                    self.queued = 0;
+                    return Err(protocol_violation(format!(
+                        "too many extended query messages before Sync, the limit is {}",
+                        self.max
+                    )));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Whether an error processing `message` waits for `Sync`, as `message`
+/// belongs to extended query, or to a copy started by extended query.
+fn is_extended_query(message: &PgWireFrontendMessage, state: PgWireConnectionState) -> bool {
+    match state {
+        PgWireConnectionState::CopyInProgress(is_extended_query) => is_extended_query,
+        _ => message.is_extended_query(),
+    }
+}
+
+/// Run the message loop on a connection after TLS negotiation.
+async fn process_framed_socket<S, A, Q, EQ, R>(
+    mut socket: Framed<S, PgWireMessageServerCodec<EQ::Statement>>,
+    options: SocketOptions,
+    startup_handler: Arc<A>,
+    query_handler: Arc<Q>,
+    extended_query_handler: Arc<EQ>,
+    replication_handler: Option<Arc<R>>,
+) -> Result<(), IOError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + Sync,
+    A: StartupHandler,
+    Q: SimpleQueryHandler,
+    EQ: ExtendedQueryHandler,
+    R: SimpleQueryHandler,
+{
+    socket.codec_mut().client_info.max_result_rows = options.max_result_rows;
+    let client_info = &socket.codec().client_info;
+    let _registration =
+        cancel::register(client_info.backend_key, client_info.cancel_handle.clone());
+
+    let mut pipeline_limit = PipelineLimit {
+        max: options.max_pipelined_messages,
+        queued: 0,
+    };
+    loop {
+        let idle_in_transaction = socket
+            .codec()
+            .idle_transaction_status
+            .is_some_and(|status| status != READY_STATUS_IDLE);
+        let idle_in_transaction_timeout = options
+            .idle_in_transaction_session_timeout
+            .filter(|_| idle_in_transaction);
+        let msg = if let Some(timeout) = idle_in_transaction_timeout {
+            match tokio::time::timeout(timeout, socket.next()).await {
+                Ok(msg) => msg,
+                Err(_) => {
+                    socket
+                        .send(PgWireBackendMessage::ErrorResponse(
+                            idle_in_transaction_timeout_error().into(),
+                        ))
+                        .await?;
+                    return socket.close().await;
+                }
+            }
+        } else {
+            socket.next().await
+        };
+        let msg = match msg {
+            Some(msg) => msg,
+            None => break,
+        };
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => return process_decode_error(&mut socket, e).await,
+        };
+        if options.require_tls && !matches!(msg, PgWireFrontendMessage::CancelRequest(_)) {
+            socket
+                .send(PgWireBackendMessage::ErrorResponse(
+                    tls_required_error().into(),
+                ))
+                .await?;
+            return socket.close().await;
+        }
+        let is_extended_query = is_extended_query(&msg, socket.state());
+        if let Err(e) = pipeline_limit
+            .check(&msg, socket.state())
+            .and_then(|_| check_admission(&socket, &msg, options.admission_control.as_deref()))
+        {
+            process_error(&mut socket, e, is_extended_query).await?;
+            continue;
+        }
+        if let Err(e) = process_message(
+            msg,
+            &mut socket,
+            startup_handler.clone(),
+            query_handler.clone(),
+            extended_query_handler.clone(),
+            replication_handler.clone(),
+        )
+        .await
+        {
+            process_error(&mut socket, e, is_extended_query).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+    use bytes::{BufMut, Bytes};
+    use chrono::{NaiveDate, NaiveDateTime};
+    use futures::{stream, Sink};
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio_postgres::error::SqlState;
+
+    use super::*;
+    use crate::api::admission::too_many_queries_error;
+    use crate::api::copy::copy_tag;
+    use crate::api::portal::Portal;
+    use crate::api::query::{
+        send_execution_response, PlaceholderExtendedQueryHandler, StatementOrPortal,
+    };
+    use crate::api::results::{
+        CopyResponse, DataRowEncoder, DescribeResponse, FieldFormat, FieldInfo, QueryResponse,
+        Response, Tag,
+    };
+    use crate::api::stmt::{NoopQueryParser, StoredStatement};
+    use crate::api::store::PortalStore;
+    use crate::api::{ClientPortalStore, Type};
+    use crate::messages::copy::{CopyData, CopyDone, CopyFail};
+    use crate::messages::extendedquery::{Bind, Execute, Flush, Parse, Sync as PgSync};
+    use crate::messages::function::FunctionCall;
+    use crate::messages::simplequery::Query;
+    use crate::types::{Interval, Json, Numeric};
+
+    struct TestHandler;
+
+    fn test_error() -> PgWireError {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "XX000".to_owned(),
+            "boom".to_owned(),
+        )))
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for TestHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            if query.starts_with("COPY") {
+                return Ok(vec![Response::CopyIn(CopyResponse::new(
+                    FieldFormat::Text,
+                    1,
+                ))]);
+            }
+            Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                "ERROR".to_owned(),
+                "0A000".to_owned(),
+                format!("query not supported by test handler: {query}"),
+            ))))
+        }
+
+        fn handle_reset_statements(&self) -> bool {
+            true
+        }
+
+        async fn on_copy_data<C>(&self, _client: &mut C, _copy_data: CopyData) -> PgWireResult<()>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(())
+        }
 
-        while let Some(Ok(msg)) = socket.next().await {
-            let is_extended_query = msg.is_extended_query();
+        async fn on_copy_done<C>(&self, client: &mut C, _copy_done: CopyDone) -> PgWireResult<()>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            send_execution_response(client, copy_tag(0)).await
+        }
+    }
+
+    struct TestReplicationHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for TestReplicationHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            if query.starts_with("COPY") {
+                return Ok(vec![Response::CopyIn(CopyResponse::new(
+                    FieldFormat::Text,
+                    1,
+                ))]);
+            }
+            Ok(vec![Response::Execution(Tag::new(query))])
+        }
+    }
+
+    #[async_trait]
+    impl ExtendedQueryHandler for TestHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser::new())
+        }
+
+        async fn check_statement<C>(
+            &self,
+            _client: &C,
+            statement: &StoredStatement<Self::Statement>,
+        ) -> PgWireResult<()>
+        where
+            C: ClientInfo + Send + Sync,
+        {
+            if statement.statement.starts_with("DROP") {
+                return Err(PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "42501".to_owned(),
+                    "permission denied".to_owned(),
+                ))));
+            }
+            Ok(())
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "id".into(),
+                None,
+                None,
+                Type::INT4,
+                FieldFormat::Text,
+            )]);
+            let mut rows = Vec::new();
+            for i in 0..2 {
+                let mut encoder = DataRowEncoder::new(schema.clone());
+                encoder.encode_field(&i)?;
+                rows.push(encoder.finish());
+            }
+
+            match portal.statement.statement.as_str() {
+                "COPY" => Ok(Response::CopyIn(CopyResponse::new(FieldFormat::Text, 1))),
+                "error before rows" => Err(test_error()),
+                "error response" => Ok(Response::Error(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "XX000".to_owned(),
+                    "boom".to_owned(),
+                )))),
+                "error mid rows" => {
+                    rows.push(Err(test_error()));
+                    Ok(Response::Query(QueryResponse::new(
+                        schema,
+                        stream::iter(rows),
+                    )))
+                }
+                _ => Ok(Response::Query(QueryResponse::new(
+                    schema,
+                    stream::iter(rows),
+                ))),
+            }
+        }
+
+        async fn do_describe<C>(
+            &self,
+            _client: &mut C,
+            _target: StatementOrPortal<'_, Self::Statement>,
+        ) -> PgWireResult<DescribeResponse>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(DescribeResponse::no_data())
+        }
+    }
+
+    /// Stores lines of `COPY ... FROM STDIN`
+    #[derive(Default)]
+    struct CopyHandler {
+        data: std::sync::Mutex<Vec<u8>>,
+        lines: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for CopyHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(vec![Response::Execution(Tag::new("SELECT").with_rows(0))])
+        }
+
+        async fn on_copy_data<C>(&self, _client: &mut C, copy_data: CopyData) -> PgWireResult<()>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            self.data.lock().unwrap().extend_from_slice(&copy_data.data);
+            Ok(())
+        }
+
+        async fn on_copy_done<C>(&self, client: &mut C, _copy_done: CopyDone) -> PgWireResult<()>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let data = std::mem::take(&mut *self.data.lock().unwrap());
+            let lines = String::from_utf8(data).unwrap();
+            let count = lines.lines().count();
+            self.lines
+                .lock()
+                .unwrap()
+                .extend(lines.lines().map(str::to_owned));
+            send_execution_response(client, copy_tag(count as u64)).await
+        }
+
+        async fn on_copy_fail<C>(&self, _client: &mut C, copy_fail: CopyFail) -> PgWireError
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            self.data.lock().unwrap().clear();
+            crate::api::copy::copy_failed_error(&copy_fail)
+        }
+    }
+
+    #[async_trait]
+    impl ExtendedQueryHandler for CopyHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser::new())
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(Response::CopyIn(CopyResponse::new(FieldFormat::Text, 2)))
+        }
+
+        async fn do_describe<C>(
+            &self,
+            _client: &mut C,
+            target: StatementOrPortal<'_, Self::Statement>,
+        ) -> PgWireResult<DescribeResponse>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            match target {
+                StatementOrPortal::Statement(_) => Ok(DescribeResponse::new(Some(vec![]), vec![])),
+                StatementOrPortal::Portal(_) => Ok(DescribeResponse::no_data()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_in_with_tokio_postgres() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler = Arc::new(CopyHandler::default());
+        let server_handler = handler.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket(
+                socket,
+                None,
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                server_handler.clone(),
+                server_handler,
+            )
+            .await
+        });
+
+        let (client, connection) = tokio_postgres::connect(
+            &format!("host=127.0.0.1 port={} user=test", addr.port()),
+            tokio_postgres::NoTls,
+        )
+        .await
+        .unwrap();
+        tokio::spawn(connection);
+
+        let sink = client.copy_in("COPY t FROM STDIN").await.unwrap();
+        futures::pin_mut!(sink);
+        // a row split over messages
+        sink.send(Bytes::from_static(b"1\ta\n2\t")).await.unwrap();
+        sink.send(Bytes::from_static(b"b\n")).await.unwrap();
+        assert_eq!(2, sink.finish().await.unwrap());
+        assert_eq!(vec!["1\ta", "2\tb"], *handler.lines.lock().unwrap());
+
+        // aborted by client with CopyFail, once the sink is dropped
+        {
+            let sink = client.copy_in("COPY t FROM STDIN").await.unwrap();
+            futures::pin_mut!(sink);
+            sink.send(Bytes::from_static(b"3\tc\n")).await.unwrap();
+        }
+        client.simple_query("SELECT").await.unwrap();
+        assert_eq!(2, handler.lines.lock().unwrap().len());
+        assert!(handler.data.lock().unwrap().is_empty());
+    }
+
+    /// Serves a single column of `datatype`, with one row written by `encode`
+    struct ColumnHandler {
+        datatype: Type,
+        encode: fn(&mut DataRowEncoder) -> PgWireResult<()>,
+    }
+
+    impl ColumnHandler {
+        fn response<'a>(&self, format: FieldFormat) -> PgWireResult<Response<'a>> {
+            let schema = Arc::new(vec![FieldInfo::new(
+                "data".into(),
+                None,
+                None,
+                self.datatype.clone(),
+                format,
+            )]);
+            let mut encoder = DataRowEncoder::new(schema.clone());
+            (self.encode)(&mut encoder)?;
+            Ok(Response::Query(QueryResponse::new(
+                schema,
+                stream::iter(vec![encoder.finish()]),
+            )))
+        }
+    }
+
+    #[async_trait]
+    impl SimpleQueryHandler for ColumnHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            _query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            Ok(vec![self.response(FieldFormat::Text)?])
+        }
+    }
+
+    #[async_trait]
+    impl ExtendedQueryHandler for ColumnHandler {
+        type Statement = String;
+        type QueryParser = NoopQueryParser;
+
+        fn query_parser(&self) -> Arc<Self::QueryParser> {
+            Arc::new(NoopQueryParser::new())
+        }
+
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            _client: &mut C,
+            portal: &'a Portal<Self::Statement>,
+            _max_rows: usize,
+        ) -> PgWireResult<Response<'a>>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            self.response(portal.result_column_format.format_for(0))
+        }
+
+        async fn do_describe<C>(
+            &self,
+            _client: &mut C,
+            target: StatementOrPortal<'_, Self::Statement>,
+        ) -> PgWireResult<DescribeResponse>
+        where
+            C: ClientInfo + ClientPortalStore + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::PortalStore: PortalStore<Statement = Self::Statement>,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            let format = match target {
+                StatementOrPortal::Portal(portal) => portal.result_column_format.format_for(0),
+                StatementOrPortal::Statement(_) => FieldFormat::Text,
+            };
+            Ok(DescribeResponse::new(
+                Some(vec![]),
+                vec![FieldInfo::new(
+                    "data".into(),
+                    None,
+                    None,
+                    self.datatype.clone(),
+                    format,
+                )],
+            ))
+        }
+    }
+
+    /// Serve `handler` on a local port and connect to it with tokio-postgres
+    async fn connect_column_handler(handler: ColumnHandler) -> tokio_postgres::Client {
+        connect_column_handler_with_options(handler, SocketOptions::default()).await
+    }
+
+    async fn connect_column_handler_with_options(
+        handler: ColumnHandler,
+        options: SocketOptions,
+    ) -> tokio_postgres::Client {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let handler = Arc::new(handler);
+            process_socket_with_options(
+                socket,
+                None,
+                options,
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                handler.clone(),
+                handler,
+                None::<Arc<ColumnHandler>>,
+            )
+            .await
+        });
+
+        let (client, connection) = tokio_postgres::connect(
+            &format!("host=127.0.0.1 port={} user=test", addr.port()),
+            tokio_postgres::NoTls,
+        )
+        .await
+        .unwrap();
+        tokio::spawn(connection);
+        client
+    }
+
+    /// First row of a simple query
+    async fn simple_query_row(
+        client: &tokio_postgres::Client,
+        query: &str,
+    ) -> tokio_postgres::SimpleQueryRow {
+        client
+            .simple_query(query)
+            .await
+            .unwrap()
+            .into_iter()
+            .find_map(|m| match m {
+                tokio_postgres::SimpleQueryMessage::Row(row) => Some(row),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    const BYTEA_VALUE: &[u8] = &[0, 1, 0x5c, 0x78, 0xff];
+
+    #[tokio::test]
+    async fn test_bytea_with_tokio_postgres() {
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::BYTEA,
+            encode: |encoder| encoder.encode_field(&BYTEA_VALUE),
+        })
+        .await;
+
+        // tokio-postgres requests results in binary format
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(BYTEA_VALUE, rows[0].get::<_, Vec<u8>>(0));
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("\\x00015c78ff"), row.get(0));
+    }
+
+    #[tokio::test]
+    async fn test_binary_integers_with_tokio_postgres() {
+        // byte patterns that decode to a different value in little-endian
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::INT4,
+            encode: |encoder| encoder.encode_field(&0x0102_0304i32),
+        })
+        .await;
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(0x0102_0304, rows[0].get::<_, i32>(0));
+
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::INT8,
+            encode: |encoder| encoder.encode_field(&-0x0102_0304_0506_0708i64),
+        })
+        .await;
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(-0x0102_0304_0506_0708, rows[0].get::<_, i64>(0));
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("-72623859790382856"), row.get(0));
+    }
+
+    #[tokio::test]
+    async fn test_admission_control() {
+        let overloaded = Arc::new(AtomicBool::new(true));
+        let admission = {
+            let overloaded = overloaded.clone();
+            move |_: &dyn ClientInfo| {
+                if overloaded.load(Ordering::Relaxed) {
+                    Err(too_many_queries_error())
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        let client = connect_column_handler_with_options(
+            ColumnHandler {
+                datatype: Type::INT4,
+                encode: |encoder| encoder.encode_field(&7i32),
+            },
+            SocketOptions::default().with_admission_control(Arc::new(admission)),
+        )
+        .await;
+
+        let error = client.simple_query("SELECT data").await.unwrap_err();
+        assert_eq!(Some(&SqlState::TOO_MANY_CONNECTIONS), error.code());
+        let error = client.query("SELECT data", &[]).await.unwrap_err();
+        assert_eq!(Some(&SqlState::TOO_MANY_CONNECTIONS), error.code());
+
+        // the connection is kept for retry
+        overloaded.store(false, Ordering::Relaxed);
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(7, rows[0].get::<_, i32>(0));
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("7"), row.get(0));
+    }
+
+    /// Handler keeping transaction status of `BEGIN` and `COMMIT`
+    struct TransactionHandler;
+
+    #[async_trait]
+    impl SimpleQueryHandler for TransactionHandler {
+        async fn do_query<'a, 'b: 'a, C>(
+            &'b self,
+            client: &mut C,
+            query: &'a str,
+        ) -> PgWireResult<Vec<Response<'a>>>
+        where
+            C: ClientInfo + Sink<PgWireBackendMessage> + Unpin + Send + Sync,
+            C::Error: Debug,
+            PgWireError: From<<C as Sink<PgWireBackendMessage>>::Error>,
+        {
+            match query {
+                "BEGIN" => client.set_transaction_status(TransactionStatus::Transaction),
+                "COMMIT" => client.set_transaction_status(TransactionStatus::Idle),
+                "SELECT" => {}
+                _ => return Err(test_error()),
+            }
+            Ok(vec![Response::Execution(Tag::new(query))])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idle_in_transaction_session_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            process_socket_with_options(
+                socket,
+                None,
+                SocketOptions::default()
+                    .with_idle_in_transaction_session_timeout(Duration::from_millis(100)),
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                Arc::new(TransactionHandler),
+                Arc::new(PlaceholderExtendedQueryHandler),
+                None::<Arc<TransactionHandler>>,
+            )
+            .await
+        });
+
+        let (client, connection) = tokio_postgres::connect(
+            &format!("host=127.0.0.1 port={} user=test", addr.port()),
+            tokio_postgres::NoTls,
+        )
+        .await
+        .unwrap();
+        let connection = tokio::spawn(connection);
+
+        // idle outside of transaction is not limited
+        client.batch_execute("SELECT").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        client.batch_execute("BEGIN").await.unwrap();
+        client.batch_execute("COMMIT").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        client.batch_execute("BEGIN").await.unwrap();
+        // a failed transaction is still open
+        assert!(client.batch_execute("ROLLBACK").await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(client.is_closed());
+        let error = connection.await.unwrap().unwrap_err();
+        assert_eq!(
+            Some(&SqlState::IDLE_IN_TRANSACTION_SESSION_TIMEOUT),
+            error.code()
+        );
+    }
+
+    /// `jsonb` read in binary format, checking the version byte
+    #[derive(Debug)]
+    struct JsonbText(String);
+
+    impl<'a> postgres_types::FromSql<'a> for JsonbText {
+        fn from_sql(
+            _ty: &Type,
+            raw: &'a [u8],
+        ) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+            match raw.split_first() {
+                Some((1, text)) => Ok(JsonbText(std::str::from_utf8(text)?.to_owned())),
+                _ => Err("unsupported jsonb version".into()),
+            }
+        }
+
+        postgres_types::accepts!(JSONB);
+    }
+
+    #[tokio::test]
+    async fn test_jsonb_array_with_tokio_postgres() {
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::JSONB_ARRAY,
+            encode: |encoder| {
+                encoder.encode_field(&vec![
+                    Some(Json(r#"{"a": [1, {"b": null}]}"#)),
+                    None,
+                    Some(Json(r#""c,d""#)),
+                ])
+            },
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        let docs = rows[0].get::<_, Vec<Option<JsonbText>>>(0);
+        let docs = docs
+            .iter()
+            .map(|doc| doc.as_ref().map(|doc| doc.0.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![Some(r#"{"a": [1, {"b": null}]}"#), None, Some(r#""c,d""#)],
+            docs
+        );
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(
+            Some(r#"{"{\"a\": [1, {\"b\": null}]}",NULL,"\"c,d\""}"#),
+            row.get(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bool_array_with_tokio_postgres() {
+        // fixed width elements, each prefixed by length 1
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::BOOL_ARRAY,
+            encode: |encoder| encoder.encode_field(&vec![Some(true), None, Some(false)]),
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(
+            vec![Some(true), None, Some(false)],
+            rows[0].get::<_, Vec<Option<bool>>>(0)
+        );
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("{true,NULL,false}"), row.get(0));
+    }
+
+    #[tokio::test]
+    async fn test_numeric_array_with_tokio_postgres() {
+        // variable width elements
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::NUMERIC_ARRAY,
+            encode: |encoder| {
+                encoder.encode_field(&vec![
+                    Some(Numeric::Finite("12345.678".to_owned())),
+                    None,
+                    Some(Numeric::Finite("-0.00010".to_owned())),
+                    Some(Numeric::NaN),
+                    Some(Numeric::Finite("0".to_owned())),
+                ])
+            },
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(
+            vec![
+                Some(Numeric::Finite("12345.678".to_owned())),
+                None,
+                Some(Numeric::Finite("-0.00010".to_owned())),
+                Some(Numeric::NaN),
+                Some(Numeric::Finite("0".to_owned())),
+            ],
+            rows[0].get::<_, Vec<Option<Numeric>>>(0)
+        );
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("{12345.678,NULL,-0.00010,NaN,0}"), row.get(0));
+    }
+
+    fn timestamps() -> Vec<Option<NaiveDateTime>> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        vec![
+            date.and_hms_micro_opt(3, 4, 5, 123456),
+            None,
+            NaiveDate::from_ymd_opt(1999, 12, 31)
+                .unwrap()
+                .and_hms_opt(23, 59, 59),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_array_with_tokio_postgres() {
+        // fixed width elements of 8 bytes
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::TIMESTAMP_ARRAY,
+            encode: |encoder| encoder.encode_field(&timestamps()),
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(
+            timestamps(),
+            rows[0].get::<_, Vec<Option<NaiveDateTime>>>(0)
+        );
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(
+            Some(r#"{"2024-01-02 03:04:05.123456",NULL,"1999-12-31 23:59:59.000000"}"#),
+            row.get(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interval_array_with_tokio_postgres() {
+        // fixed width elements of 16 bytes
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::INTERVAL_ARRAY,
+            encode: |encoder| {
+                encoder.encode_field(&vec![
+                    Some(Interval::new(0, 1, 3_600_000_000)),
+                    None,
+                    Some(Interval::new(-14, 0, -1_500_000)),
+                ])
+            },
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        assert_eq!(
+            vec![
+                Some(Interval::new(0, 1, 3_600_000_000)),
+                None,
+                Some(Interval::new(-14, 0, -1_500_000)),
+            ],
+            rows[0].get::<_, Vec<Option<Interval>>>(0)
+        );
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(
+            Some(r#"{"1 day 01:00:00",NULL,"-1 years -2 mons -00:00:01.5"}"#),
+            row.get(0)
+        );
+    }
+
+    #[test]
+    fn test_pipeline_limit() {
+        let mut limit = PipelineLimit { max: 3, queued: 0 };
+        let ready = PgWireConnectionState::ReadyForQuery;
+        let mut check = |message, state| limit.check(&message, state).is_ok();
+
+        let parse =
+            || PgWireFrontendMessage::Parse(Parse::new(None, "SELECT 1".to_owned(), vec![]));
+        let flush = || PgWireFrontendMessage::Flush(Flush::new());
+        let sync = || PgWireFrontendMessage::Sync(PgSync::new());
+        assert!(check(parse(), ready));
+        assert!(check(flush(), ready));
+        assert!(check(parse(), ready));
+        assert!(check(parse(), ready));
+        assert!(!check(parse(), ready));
+        // discarded while waiting for Sync
+        assert!(check(parse(), PgWireConnectionState::AwaitingSync));
+        assert!(check(sync(), PgWireConnectionState::AwaitingSync));
+
+        for _ in 0..3 {
+            assert!(check(parse(), ready));
+        }
+        assert!(check(sync(), ready));
+        assert!(check(parse(), ready));
+    }
+
+    #[tokio::test]
+    async fn test_timestamptz_micros_with_tokio_postgres() {
+        // nanoseconds are truncated, not rounded, in both formats
+        let client = connect_column_handler(ColumnHandler {
+            datatype: Type::TIMESTAMPTZ,
+            encode: |encoder| {
+                let datetime = chrono::NaiveDate::from_ymd_opt(2023, 3, 5)
+                    .unwrap()
+                    .and_hms_nano_opt(12, 30, 0, 123_456_789)
+                    .unwrap()
+                    .and_utc();
+                encoder.encode_field(&datetime)
+            },
+        })
+        .await;
+
+        let rows = client.query("SELECT data", &[]).await.unwrap();
+        let datetime = rows[0].get::<_, chrono::DateTime<chrono::Utc>>(0);
+        assert_eq!("2023-03-05T12:30:00.123456+00:00", datetime.to_rfc3339());
+
+        let row = simple_query_row(&client, "SELECT data").await;
+        assert_eq!(Some("2023-03-05 12:30:00.123456+00"), row.get(0));
+    }
+
+    /// Run messages through the server loop, return message types sent back
+    async fn run_messages(messages: Vec<PgWireFrontendMessage>) -> Vec<char> {
+        let mut client_info = DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false);
+        client_info.set_state(PgWireConnectionState::ReadyForQuery);
+        run_messages_with_replication(client_info, messages, None::<Arc<TestHandler>>).await
+    }
+
+    async fn run_messages_with_replication<R: SimpleQueryHandler>(
+        client_info: DefaultClient<String>,
+        messages: Vec<PgWireFrontendMessage>,
+        replication_handler: Option<Arc<R>>,
+    ) -> Vec<char> {
+        let (client, server) = duplex(65536);
+        let mut socket = Framed::new(server, PgWireMessageServerCodec::new(client_info));
+
+        let handler = Arc::new(TestHandler);
+        for message in messages {
+            let is_extended_query = is_extended_query(&message, socket.state());
             if let Err(e) = process_message(
-                msg,
+                message,
                 &mut socket,
-                startup_handler.clone(),
-                query_handler.clone(),
-                extended_query_handler.clone(),
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                handler.clone(),
+                handler.clone(),
+                replication_handler.clone(),
             )
             .await
             {
-                process_error(&mut socket, e, is_extended_query).await?;
+                process_error(&mut socket, e, is_extended_query)
+                    .await
+                    .unwrap();
             }
         }
+        socket.flush().await.unwrap();
+        drop(socket);
+
+        let mut received = Vec::new();
+        let mut client = client;
+        client.read_to_end(&mut received).await.unwrap();
+        let mut received = BytesMut::from(&received[..]);
+
+        let mut types = Vec::new();
+        while let Some(message) = PgWireBackendMessage::decode(&mut received).unwrap() {
+            let mut buf = BytesMut::new();
+            message.encode(&mut buf).unwrap();
+            types.push(buf[0] as char);
+        }
+        types
     }
 
-    Ok(())
+    fn execute_messages(query: &str) -> Vec<PgWireFrontendMessage> {
+        vec![
+            PgWireFrontendMessage::Parse(Parse::new(None, query.to_owned(), vec![])),
+            PgWireFrontendMessage::Bind(Bind::new(None, None, vec![], vec![], vec![])),
+            PgWireFrontendMessage::Execute(Execute::new(None, 0)),
+            // discarded after error
+            PgWireFrontendMessage::Execute(Execute::new(None, 0)),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_execute_error() {
+        for query in ["error before rows", "error response"] {
+            let mut messages = execute_messages(query);
+            // connection is usable after sync
+            messages.extend(execute_messages("ok"));
+            assert_eq!(
+                vec!['1', '2', 'E', 'Z', '1', '2', 'D', 'D', 'C', 'D', 'D', 'C', 'Z'],
+                run_messages(messages).await
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_error_mid_rows() {
+        let mut messages = execute_messages("error mid rows");
+        messages.extend(execute_messages("ok"));
+        assert_eq!(
+            vec!['1', '2', 'D', 'D', 'E', 'Z', '1', '2', 'D', 'D', 'C', 'D', 'D', 'C', 'Z'],
+            run_messages(messages).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discard_all() {
+        let messages = vec![
+            PgWireFrontendMessage::Parse(Parse::new(
+                Some("s1".to_owned()),
+                "SELECT 1".to_owned(),
+                vec![],
+            )),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            PgWireFrontendMessage::Query(Query::new("DISCARD ALL".to_owned())),
+            // statement is gone
+            PgWireFrontendMessage::Bind(Bind::new(
+                None,
+                Some("s1".to_owned()),
+                vec![],
+                vec![],
+                vec![],
+            )),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ];
+        assert_eq!(
+            vec!['1', 'Z', 'C', 'Z', 'E', 'Z'],
+            run_messages(messages).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_in() {
+        let copy_data = || PgWireFrontendMessage::CopyData(CopyData::new("1\n".into()));
+        let query = || PgWireFrontendMessage::Query(Query::new("COPY t FROM STDIN".to_owned()));
+        let messages = vec![
+            // stray copy messages are ignored outside copy
+            copy_data(),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+            query(),
+            copy_data(),
+            // ignored during copy
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            copy_data(),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+            query(),
+            copy_data(),
+            PgWireFrontendMessage::CopyFail(CopyFail::new("aborted".to_owned())),
+            // dropped after the error
+            copy_data(),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+            query(),
+            query(),
+        ];
+        assert_eq!(
+            vec!['G', 'C', 'Z', 'G', 'E', 'Z', 'G', 'E', 'Z'],
+            run_messages(messages).await
+        );
+
+        // started by extended query, waits for Sync after the copy
+        let execute = || {
+            let mut messages = execute_messages("COPY");
+            messages.truncate(3);
+            messages
+        };
+        let mut messages = execute();
+        messages.extend([
+            // sent by some clients with Execute
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            copy_data(),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ]);
+        messages.extend(execute());
+        messages.extend([
+            PgWireFrontendMessage::CopyFail(CopyFail::new("aborted".to_owned())),
+            copy_data(),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ]);
+        assert_eq!(
+            vec!['1', '2', 'G', 'C', 'Z', '1', '2', 'G', 'E', 'Z'],
+            run_messages(messages).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_statement_and_portal_not_found() {
+        let mut messages = vec![
+            PgWireFrontendMessage::Bind(Bind::new(
+                None,
+                Some("S_1".to_owned()),
+                vec![],
+                vec![],
+                vec![],
+            )),
+            PgWireFrontendMessage::Execute(Execute::new(None, 0)),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            PgWireFrontendMessage::Execute(Execute::new(Some("C_1".to_owned()), 0)),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+        ];
+        // connection is usable after sync
+        messages.extend(execute_messages("ok"));
+        assert_eq!(
+            vec!['E', 'Z', 'E', 'Z', '1', '2', 'D', 'D', 'C', 'D', 'D', 'C', 'Z'],
+            run_messages(messages).await
+        );
+
+        for (error, code, message) in [
+            (
+                PgWireError::StatementNotFound("S_1".to_owned()),
+                "26000",
+                "prepared statement \"S_1\" does not exist",
+            ),
+            (
+                PgWireError::StatementNotFound("".to_owned()),
+                "26000",
+                "unnamed prepared statement does not exist",
+            ),
+            (
+                PgWireError::PortalNotFound("C_1".to_owned()),
+                "34000",
+                "portal \"C_1\" does not exist",
+            ),
+        ] {
+            let (mut client, server) = duplex(65536);
+            let client_info =
+                DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+            let mut socket = Framed::new(server, PgWireMessageServerCodec::new(client_info));
+            process_error(&mut socket, error, true).await.unwrap();
+            socket.flush().await.unwrap();
+            assert!(matches!(
+                socket.state(),
+                PgWireConnectionState::AwaitingSync
+            ));
+            drop(socket);
+
+            let mut received = Vec::new();
+            client.read_to_end(&mut received).await.unwrap();
+            match PgWireBackendMessage::decode(&mut BytesMut::from(&received[..])) {
+                Ok(Some(PgWireBackendMessage::ErrorResponse(response))) => {
+                    assert!(response.fields.contains(&(b'S', "ERROR".to_owned())));
+                    assert!(response.fields.contains(&(b'C', code.to_owned())));
+                    assert!(response.fields.contains(&(b'M', message.to_owned())));
+                }
+                _ => panic!("expect error response"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_statement() {
+        let mut messages = execute_messages("DROP TABLE users");
+        messages.extend(execute_messages("ok"));
+        // rejected statement is not stored, Bind and Execute are discarded
+        assert_eq!(
+            vec!['E', 'Z', '1', '2', 'D', 'D', 'C', 'D', 'D', 'C', 'Z'],
+            run_messages(messages).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replication_startup() {
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert(METADATA_REPLICATION.to_owned(), "true".to_owned());
+        let client_info = DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false);
+        assert_eq!(
+            vec!['E'],
+            run_messages_with_replication(
+                client_info,
+                vec![PgWireFrontendMessage::Startup(startup)],
+                None::<Arc<TestReplicationHandler>>,
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_protocol_version() {
+        let mut startup = Startup::new();
+        startup.protocol_number_minor = 2;
+        startup
+            .parameters
+            .insert("_pq_.report".to_owned(), "on".to_owned());
+        startup
+            .parameters
+            .insert("user".to_owned(), "tomcat".to_owned());
+
+        let mut packet = BytesMut::new();
+        startup.encode(&mut packet).unwrap();
+        let client_info = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        let mut codec = PgWireMessageServerCodec::new(client_info);
+        let message = codec.decode(&mut packet).unwrap().unwrap();
+
+        let client_info = DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false);
+        let types = run_messages_with_replication(
+            client_info,
+            vec![message],
+            None::<Arc<TestReplicationHandler>>,
+        )
+        .await;
+        // negotiation comes before AuthenticationOk
+        assert_eq!(['v', 'R'], types[..2]);
+        assert_eq!(Some(&'Z'), types.last());
+
+        let mut startup = Startup::new();
+        assert!(negotiate_protocol_version(&mut startup).is_none());
+        startup
+            .parameters
+            .insert("_pq_.report".to_owned(), "on".to_owned());
+        let negotiation = negotiate_protocol_version(&mut startup).unwrap();
+        assert_eq!(0, negotiation.newest_minor_version);
+        assert_eq!(
+            vec!["_pq_.report".to_owned()],
+            negotiation.unsupported_options
+        );
+        assert!(startup.parameters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replication_routing() {
+        let mut client_info = DefaultClient::new("127.0.0.1:5432".parse().unwrap(), false);
+        client_info.set_state(PgWireConnectionState::ReadyForQuery);
+        client_info.replication_mode = Some(ReplicationMode::Logical);
+
+        let messages = vec![
+            PgWireFrontendMessage::Query(Query::new("IDENTIFY_SYSTEM".to_owned())),
+            PgWireFrontendMessage::Parse(Parse::new(None, "SELECT 1".to_owned(), vec![])),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            PgWireFrontendMessage::Sync(PgSync::new()),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+            PgWireFrontendMessage::FunctionCall(FunctionCall::new(1, vec![], vec![], 0)),
+            // copy data goes to replication handler, which doesn't take it
+            PgWireFrontendMessage::Query(Query::new("COPY t FROM STDIN".to_owned())),
+            PgWireFrontendMessage::CopyData(CopyData::new(Bytes::from("1\n"))),
+            PgWireFrontendMessage::CopyDone(CopyDone::new()),
+        ];
+        assert_eq!(
+            vec!['C', 'Z', 'E', 'Z', 'Z', 'E', 'Z', 'G', 'E', 'Z'],
+            run_messages_with_replication(
+                client_info,
+                messages,
+                Some(Arc::new(TestReplicationHandler)),
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_protocol_version() {
+        // protocol 2.0 startup packet, with fixed size fields unlike 3.0
+        let mut packet = BytesMut::new();
+        packet.put_i32(296);
+        packet.put_i32(2 << 16);
+        packet.put_slice(&[0u8; 288]);
+
+        let (mut client, server) = duplex(65536);
+        client.write_all(&packet).await.unwrap();
+        let client_info = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        let mut socket = Framed::new(server, PgWireMessageServerCodec::new(client_info));
+
+        let error = socket.next().await.unwrap().unwrap_err();
+        assert!(matches!(error, PgWireError::InvalidProtocolVersion(131072)));
+        process_decode_error(&mut socket, error).await.unwrap();
+        drop(socket);
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        let mut received = BytesMut::from(&received[..]);
+        match PgWireBackendMessage::decode(&mut received).unwrap() {
+            Some(PgWireBackendMessage::ErrorResponse(error)) => {
+                assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                assert!(error.fields.contains(&(b'C', "0A000".to_owned())));
+                assert!(error.fields.contains(&(
+                    b'M',
+                    "unsupported frontend protocol 2.0: server supports 3.0 to 3.0".to_owned()
+                )));
+            }
+            _ => panic!("expect ErrorResponse"),
+        }
+        assert!(received.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_require_tls() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let handler = Arc::new(TestHandler);
+            process_socket_with_options(
+                socket,
+                None,
+                SocketOptions::default().with_require_tls(true),
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                handler.clone(),
+                handler,
+                None::<Arc<TestHandler>>,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        // GSSAPI encryption and SSL are both refused without a TlsAcceptor
+        for request in [
+            PgWireFrontendMessage::GssEncRequest(GssEncRequest::new()),
+            PgWireFrontendMessage::SslRequest(SslRequest::new()),
+        ] {
+            let mut buf = BytesMut::new();
+            request.encode(&mut buf).unwrap();
+            client.write_all(&buf).await.unwrap();
+            assert_eq!(SslResponse::BYTE_REFUSE, client.read_u8().await.unwrap());
+        }
+
+        let mut buf = BytesMut::new();
+        Startup::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        let mut received = BytesMut::from(&received[..]);
+        match PgWireBackendMessage::decode(&mut received).unwrap() {
+            Some(PgWireBackendMessage::ErrorResponse(error)) => {
+                assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                assert!(error.fields.contains(&(b'C', "28000".to_owned())));
+            }
+            _ => panic!("expect ErrorResponse"),
+        }
+        assert!(received.is_empty());
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_gss_enc_request() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let handler = Arc::new(TestHandler);
+            process_socket(
+                socket,
+                None,
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                handler.clone(),
+                handler,
+            )
+            .await
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = BytesMut::new();
+        GssEncRequest::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(SslResponse::BYTE_REFUSE, client.read_u8().await.unwrap());
+
+        // only one request is allowed
+        client.write_all(&buf).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        let mut received = BytesMut::from(&received[..]);
+        match PgWireBackendMessage::decode(&mut received).unwrap() {
+            Some(PgWireBackendMessage::ErrorResponse(error)) => {
+                assert!(error.fields.contains(&(b'S', "FATAL".to_owned())));
+                assert!(error.fields.contains(&(b'C', "0A000".to_owned())));
+            }
+            _ => panic!("expect ErrorResponse"),
+        }
+        assert!(received.is_empty());
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let credentials = server.peer_cred().unwrap();
+        assert_eq!(Some(std::process::id() as i32), credentials.pid());
+
+        let server = tokio::spawn(async move {
+            let handler = Arc::new(TestHandler);
+            process_unix_socket(
+                server,
+                // doesn't apply to unix domain sockets
+                SocketOptions::default().with_require_tls(true),
+                Arc::new(crate::api::auth::noop::NoopStartupHandler),
+                handler.clone(),
+                handler,
+                None::<Arc<TestHandler>>,
+            )
+            .await
+        });
+
+        let mut buf = BytesMut::new();
+        SslRequest::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        assert_eq!(SslResponse::BYTE_REFUSE, client.read_u8().await.unwrap());
+
+        let mut buf = BytesMut::new();
+        Startup::new().encode(&mut buf).unwrap();
+        client.write_all(&buf).await.unwrap();
+        // AuthenticationOk
+        assert_eq!(b'R', client.read_u8().await.unwrap());
+        drop(client);
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_peer_credentials() {
+        let mut client_info =
+            DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        assert_eq!(None, client_info.peer_credentials());
+        client_info.peer_credentials = Some(PeerCred::new(1000, 1000, Some(42)));
+        let socket = Framed::new(duplex(64).0, PgWireMessageServerCodec::new(client_info));
+        assert_eq!(Some(1000), socket.peer_credentials().map(|cred| cred.uid));
+    }
+
+    #[tokio::test]
+    async fn test_startup_packet_too_large() {
+        let mut startup = Startup::new();
+        startup
+            .parameters
+            .insert("application_name".to_owned(), "x".repeat(200));
+        let mut packet = BytesMut::new();
+        startup.encode(&mut packet).unwrap();
+
+        let (mut client, server) = duplex(65536);
+        client.write_all(&packet).await.unwrap();
+        let client_info = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        let mut codec = PgWireMessageServerCodec::new(client_info);
+        codec.max_startup_packet_size = 100;
+        let mut socket = Framed::new(server, codec);
+
+        let error = socket.next().await.unwrap().unwrap_err();
+        assert!(matches!(error, PgWireError::StartupPacketTooLarge(len) if len == packet.len()));
+        process_decode_error(&mut socket, error).await.unwrap();
+        drop(socket);
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        let mut received = BytesMut::from(&received[..]);
+        match PgWireBackendMessage::decode(&mut received).unwrap() {
+            Some(PgWireBackendMessage::ErrorResponse(error)) => {
+                assert!(error.fields.contains(&(b'C', "08P01".to_owned())));
+            }
+            _ => panic!("expect ErrorResponse"),
+        }
+
+        // accepted within the default limit
+        let client_info = DefaultClient::<String>::new("127.0.0.1:5432".parse().unwrap(), false);
+        let mut codec = PgWireMessageServerCodec::new(client_info);
+        assert!(matches!(
+            codec.decode(&mut packet).unwrap(),
+            Some(PgWireFrontendMessage::Startup(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_tcp_keepalive() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+
+        let keepalive = TcpKeepalive::new().with_time(std::time::Duration::from_secs(60));
+        set_tcp_keepalive(&socket, &keepalive).unwrap();
+        assert!(socket2::SockRef::from(&socket).keepalive().unwrap());
+    }
 }
@@ -0,0 +1,166 @@
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Parse text input of a one-dimensional array, like `{1,NULL,"a b"}`, into
+/// its elements, as postgres does.
+///
+/// Elements may be double quoted, with `\` escaping any character in or out
+/// of quotes. Whitespace around elements is skipped. An unquoted `NULL`, in
+/// any case, is `None`, while `"NULL"` is the string. A dimension decoration
+/// like `[0:2]=` is accepted and must match the number of elements. Nested
+/// arrays are not supported. Return `22P02` error on invalid input.
+pub(crate) fn parse_array(input: &str) -> PgWireResult<Vec<Option<String>>> {
+    let malformed = || {
+        PgWireError::UserError(Box::new(ErrorInfo::new(
+            "ERROR".to_owned(),
+            "22P02".to_owned(),
+            format!("malformed array literal: \"{input}\""),
+        )))
+    };
+
+    let mut s = input.trim_start();
+    let mut expected_len = None;
+    if let Some(rest) = s.strip_prefix('[') {
+        let (dim, rest) = rest.split_once(']').ok_or_else(malformed)?;
+        let (lower, upper) = dim.split_once(':').unwrap_or(("1", dim));
+        let lower: i32 = lower.trim().parse().map_err(|_| malformed())?;
+        let upper: i32 = upper.trim().parse().map_err(|_| malformed())?;
+        if upper < lower {
+            return Err(malformed());
+        }
+        expected_len = Some((upper as i64 - lower as i64 + 1) as usize);
+        s = rest.trim_start().strip_prefix('=').ok_or_else(malformed)?;
+    }
+
+    let mut chars = s
+        .trim_start()
+        .strip_prefix('{')
+        .ok_or_else(malformed)?
+        .chars();
+    let mut elements = Vec::new();
+    loop {
+        let mut value = String::new();
+        // length of value without trailing whitespace of unquoted element
+        let mut len = 0;
+        let mut in_quotes = false;
+        let mut quoted = false;
+        let mut escaped = false;
+        let delimiter = loop {
+            let c = chars.next().ok_or_else(malformed)?;
+            if in_quotes {
+                match c {
+                    '"' => in_quotes = false,
+                    '\\' => value.push(chars.next().ok_or_else(malformed)?),
+                    _ => value.push(c),
+                }
+                continue;
+            }
+            match c {
+                ',' | '}' => break c,
+                c if c.is_whitespace() => {
+                    if len > 0 && !quoted {
+                        value.push(c);
+                    }
+                }
+                // only whitespace is allowed after a quoted element
+                _ if quoted => return Err(malformed()),
+                '"' if len == 0 => {
+                    in_quotes = true;
+                    quoted = true;
+                }
+                '"' | '{' => return Err(malformed()),
+                '\\' => {
+                    value.push(chars.next().ok_or_else(malformed)?);
+                    escaped = true;
+                    len = value.len();
+                }
+                _ => {
+                    value.push(c);
+                    len = value.len();
+                }
+            }
+        };
+
+        if quoted {
+            elements.push(Some(value));
+        } else if len == 0 {
+            // `{}` is empty array, while an empty unquoted element is invalid
+            if delimiter == '}' && elements.is_empty() {
+                break;
+            }
+            return Err(malformed());
+        } else {
+            value.truncate(len);
+            if !escaped && value.eq_ignore_ascii_case("NULL") {
+                elements.push(None);
+            } else {
+                elements.push(Some(value));
+            }
+        }
+
+        if delimiter == '}' {
+            break;
+        }
+    }
+
+    if !chars.as_str().trim().is_empty() || expected_len.is_some_and(|l| l != elements.len()) {
+        return Err(malformed());
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_parse(expected: &[Option<&str>], input: &str) {
+        let actual = parse_array(input).unwrap();
+        assert_eq!(
+            expected,
+            actual.iter().map(Option::as_deref).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_array() {
+        assert_parse(&[], "{}");
+        assert_parse(&[], " { } ");
+        assert_parse(&[Some("1"), None, Some("3")], "{ 1 , NULL, 3 }");
+        assert_parse(&[Some("1"), Some("2")], "[0:1]={1,2}");
+        assert_parse(&[Some("1"), Some("2")], "[2]={1,2}");
+        // output of postgres
+        assert_parse(
+            &[Some("a b"), Some("c d"), Some("NULL"), None],
+            r#"{ "a b" , c d ,"NULL", null}"#,
+        );
+        assert_parse(
+            &[Some("a "), Some(""), Some(" x"), None, Some("NULL")],
+            r#"{a\  , "" , \ x,NuLl,\NULL}"#,
+        );
+        assert_parse(&[Some(r#"a"b\c"#), Some("{}")], r#"{"a\"b\\c","{}"}"#);
+
+        for input in [
+            "",
+            "1,2",
+            "{1,2",
+            "{1,,2}",
+            "{1,}",
+            "{,}",
+            "{1,2} x",
+            "{{1}}",
+            r#"{"a"b}"#,
+            r#"{a"b"}"#,
+            r#"{"a" b}"#,
+            r#"{"}"#,
+            r#"{a\"#,
+            "[1:2]={1,2,3}",
+            "[1:0]={}",
+            "[1:2]{1,2}",
+            "[-2147483648:2147483647]={}",
+        ] {
+            match parse_array(input) {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code, "{input}"),
+                _ => panic!("expect error for {input}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use bytes::{BufMut, BytesMut};
+use postgres_types::{FromSql, IsNull, ToSql, Type};
+
+/// Text output format of `bytea`, the `bytea_output` runtime parameter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteaOutput {
+    /// `\x` followed by hex digits, e.g. `\x00ff`, the default since postgres
+    /// 9.0
+    #[default]
+    Hex,
+    /// Printable ASCII as is, backslash doubled and other bytes as octal
+    /// escapes, e.g. `\000\377`
+    Escape,
+}
+
+impl ByteaOutput {
+    /// Parse value of `bytea_output` parameter, case insensitive. Return
+    /// `None` for values other than `hex` and `escape`.
+    pub fn parse(value: &str) -> Option<ByteaOutput> {
+        if value.eq_ignore_ascii_case("hex") {
+            Some(ByteaOutput::Hex)
+        } else if value.eq_ignore_ascii_case("escape") {
+            Some(ByteaOutput::Escape)
+        } else {
+            None
+        }
+    }
+
+    /// Encode `bytes` in text format of this output style.
+    pub fn encode(&self, bytes: &[u8], out: &mut BytesMut) {
+        match self {
+            ByteaOutput::Hex => {
+                out.put_slice(b"\\x");
+                out.put_slice(hex::encode(bytes).as_bytes());
+            }
+            ByteaOutput::Escape => {
+                for b in bytes {
+                    match b {
+                        b'\\' => out.put_slice(b"\\\\"),
+                        0x20..=0x7e => out.put_u8(*b),
+                        _ => out.put_slice(format!("\\{b:03o}").as_bytes()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Encode `value` of `BYTEA` or `BYTEA[]` in text format of this output
+    /// style, from the raw bytes of its binary format.
+    ///
+    /// Return `None` for hex style, other types, or values not accepted as
+    /// bytea like text in hex format, which are left to `ToSqlText`.
+    pub(crate) fn encode_value<T: ToSql + Sized>(
+        &self,
+        value: &T,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Option<IsNull> {
+        if *self == ByteaOutput::Hex
+            || (*ty != Type::BYTEA && *ty != Type::BYTEA_ARRAY)
+            || !T::accepts(ty)
+        {
+            return None;
+        }
+        let mut raw = BytesMut::new();
+        if let IsNull::Yes = value.to_sql(ty, &mut raw).ok()? {
+            return Some(IsNull::Yes);
+        }
+
+        if *ty == Type::BYTEA {
+            self.encode(&raw, out);
+        } else {
+            // only one dimensional arrays are decoded
+            let elements = Vec::<Option<Vec<u8>>>::from_sql(ty, &raw).ok()?;
+            out.put_u8(b'{');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    out.put_u8(b',');
+                }
+                match element {
+                    Some(bytes) => self.encode_array_element(bytes, out),
+                    None => out.put_slice(b"NULL"),
+                }
+            }
+            out.put_u8(b'}');
+        }
+        Some(IsNull::No)
+    }
+
+    /// Encode an element of `BYTEA[]`, quoted as in postgres array output when
+    /// it's empty or contains special characters.
+    fn encode_array_element(&self, bytes: &[u8], out: &mut BytesMut) {
+        let mut element = BytesMut::new();
+        self.encode(bytes, &mut element);
+        let quote = element.is_empty()
+            || element.eq_ignore_ascii_case(b"NULL")
+            || element
+                .iter()
+                .any(|b| b"{},\"\\".contains(b) || b.is_ascii_whitespace());
+        if !quote {
+            out.put_slice(&element);
+            return;
+        }
+        out.put_u8(b'"');
+        for b in element {
+            if b == b'"' || b == b'\\' {
+                out.put_u8(b'\\');
+            }
+            out.put_u8(b);
+        }
+        out.put_u8(b'"');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bytea_output() {
+        assert_eq!(Some(ByteaOutput::Hex), ByteaOutput::parse("Hex"));
+        assert_eq!(Some(ByteaOutput::Escape), ByteaOutput::parse("escape"));
+        assert!(ByteaOutput::parse("base64").is_none());
+
+        let bytes = b"\x00\x01\\A\xff~\x7f ";
+        let mut buf = BytesMut::new();
+        ByteaOutput::Escape.encode(bytes, &mut buf);
+        assert_eq!(b"\\000\\001\\\\A\\377~\\177 ", buf.as_ref());
+
+        let mut buf = BytesMut::new();
+        ByteaOutput::Hex.encode(bytes, &mut buf);
+        assert_eq!(b"\\x00015c41ff7e7f20", buf.as_ref());
+    }
+
+    #[test]
+    fn test_encode_value() {
+        let mut buf = BytesMut::new();
+        let value: &[u8] = b"\x00A\\";
+        assert!(matches!(
+            ByteaOutput::Escape.encode_value(&value, &Type::BYTEA, &mut buf),
+            Some(IsNull::No)
+        ));
+        assert_eq!(b"\\000A\\\\", buf.as_ref());
+        assert!(ByteaOutput::Hex
+            .encode_value(&value, &Type::BYTEA, &mut buf)
+            .is_none());
+        assert!(ByteaOutput::Escape
+            .encode_value(&"\\x00", &Type::BYTEA, &mut buf)
+            .is_none());
+
+        // output of postgres
+        let mut buf = BytesMut::new();
+        let value = vec![
+            Some(b"\x00A".to_vec()),
+            None,
+            Some(b"ab".to_vec()),
+            Some(vec![]),
+        ];
+        ByteaOutput::Escape.encode_value(&value, &Type::BYTEA_ARRAY, &mut buf);
+        assert_eq!(b"{\"\\\\000A\",NULL,ab,\"\"}", buf.as_ref());
+    }
+}
@@ -0,0 +1,175 @@
+use std::error::Error;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{to_sql_checked, IsNull, Oid, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Value of postgres `aclitem`, an access privilege entry found in
+/// `relacl`/`nspacl` columns of catalog tables.
+///
+/// It's rendered as `grantee=privileges/grantor`, for example
+/// `postgres=arwdDxt/postgres`. An empty grantee stands for `PUBLIC`. Postgres
+/// has no binary format for `aclitem`, so it's only supported in text format.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
+pub struct AclItem {
+    /// role name of the grantee, empty for `PUBLIC`
+    pub grantee: String,
+    /// privilege letters like `arwdDxt`, each optionally followed by `*`
+    /// for grant option
+    pub privileges: String,
+    /// role name of the grantor
+    pub grantor: String,
+}
+
+/// Write role name like postgres' `putid`: quoted, with `"` doubled, when
+/// it has any character other than ASCII letters, digits and `_`.
+fn put_role_name(out: &mut BytesMut, name: &str) {
+    let plain = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        out.put_slice(name.as_bytes());
+    } else {
+        out.put_u8(b'"');
+        out.put_slice(name.replace('"', "\"\"").as_bytes());
+        out.put_u8(b'"');
+    }
+}
+
+impl ToSqlText for AclItem {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        put_role_name(out, &self.grantee);
+        out.put_u8(b'=');
+        out.put_slice(self.privileges.as_bytes());
+        out.put_u8(b'/');
+        put_role_name(out, &self.grantor);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for AclItem {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        _out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        Err("aclitem has no binary format".into())
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        *ty == Type::ACLITEM
+    }
+
+    to_sql_checked!();
+}
+
+/// Value of OID alias types `regclass`, `regproc` and `regtype`.
+///
+/// On the wire these are OIDs in binary format, but names in text format, for
+/// example `'mytable'::regclass` renders as `mytable`. Resolving the name is up
+/// to the handler, so both are provided here. `name` is written as is, quote it
+/// if it's not a plain identifier.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
+pub struct RegOid {
+    pub oid: Oid,
+    pub name: String,
+}
+
+impl ToSqlText for RegOid {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.name.as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for RegOid {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_u32(self.oid);
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        matches!(*ty, Type::REGCLASS | Type::REGPROC | Type::REGTYPE)
+    }
+
+    to_sql_checked!();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_aclitem_text() {
+        let items = vec![
+            AclItem::new(
+                "postgres".to_owned(),
+                "arwdDxt".to_owned(),
+                "postgres".to_owned(),
+            ),
+            AclItem::new("".to_owned(), "r*".to_owned(), "postgres".to_owned()),
+            AclItem::new(
+                "Data Team".to_owned(),
+                "r".to_owned(),
+                "postgres".to_owned(),
+            ),
+        ];
+
+        let mut buf = BytesMut::new();
+        items[0].to_sql_text(&Type::ACLITEM, &mut buf).unwrap();
+        assert_eq!("postgres=arwdDxt/postgres", String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        items.to_sql_text(&Type::ACLITEM_ARRAY, &mut buf).unwrap();
+        assert_eq!(
+            r#"{postgres=arwdDxt/postgres,=r*/postgres,"\"Data Team\"=r/postgres"}"#,
+            String::from_utf8_lossy(&buf)
+        );
+
+        assert!(items[0].to_sql(&Type::ACLITEM, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_aclitem_role_quoting() {
+        for (grantee, expected) in [
+            ("Admin", "Admin=r/postgres"),
+            ("app_1", "app_1=r/postgres"),
+            ("a=b", r#""a=b"=r/postgres"#),
+            ("a/b", r#""a/b"=r/postgres"#),
+            (r#"say "hi""#, r#""say ""hi"""=r/postgres"#),
+            ("café", r#""café"=r/postgres"#),
+        ] {
+            let item = AclItem::new(grantee.to_owned(), "r".to_owned(), "postgres".to_owned());
+            let mut buf = BytesMut::new();
+            item.to_sql_text(&Type::ACLITEM, &mut buf).unwrap();
+            assert_eq!(expected, String::from_utf8_lossy(&buf));
+        }
+    }
+
+    #[test]
+    fn test_regoid() {
+        let value = RegOid::new(16384, "mytable".to_owned());
+
+        let mut buf = BytesMut::new();
+        value.to_sql_text(&Type::REGCLASS, &mut buf).unwrap();
+        assert_eq!("mytable", String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        value.to_sql_checked(&Type::REGCLASS, &mut buf).unwrap();
+        assert_eq!(&16384u32.to_be_bytes()[..], &buf[..]);
+
+        assert!(RegOid::accepts(&Type::REGTYPE));
+        assert!(!RegOid::accepts(&Type::OID));
+    }
+}
@@ -0,0 +1,194 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Field order for ambiguous dates like `01/02/2023`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateOrder {
+    /// month/day/year, as in `12/31/2023`
+    #[default]
+    Mdy,
+    /// day/month/year, as in `31/12/2023`
+    Dmy,
+    /// year/month/day, as in `2023/12/31`
+    Ymd,
+}
+
+/// Input interpretation of the `DateStyle` runtime parameter.
+///
+/// `DateStyle` has an output style (`ISO`, `Postgres`, `SQL` or `German`) and
+/// a field order (`MDY`, `DMY` or `YMD`), e.g. `ISO, MDY`. Only the order
+/// matters when parsing text input. ISO dates like `2023-12-31` are accepted
+/// regardless of the order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, new)]
+pub struct DateStyle {
+    pub order: DateOrder,
+}
+
+impl DateStyle {
+    /// Parse value of `DateStyle` parameter, e.g. `ISO, DMY`.
+    ///
+    /// As in postgres, `SQL`, `Postgres` and `German` styles imply `MDY`,
+    /// `MDY` and `DMY` order respectively unless an order is given. Return
+    /// `None` for unknown keywords.
+    pub fn parse(value: &str) -> Option<DateStyle> {
+        let mut style_order = None;
+        let mut order = None;
+        for keyword in value.split(',').map(str::trim) {
+            match keyword.to_ascii_uppercase().as_str() {
+                "ISO" | "SQL" | "POSTGRES" => {}
+                "GERMAN" => style_order = Some(DateOrder::Dmy),
+                "MDY" | "US" | "NONEUROPEAN" => order = Some(DateOrder::Mdy),
+                "DMY" | "EURO" | "EUROPEAN" => order = Some(DateOrder::Dmy),
+                "YMD" => order = Some(DateOrder::Ymd),
+                _ => return None,
+            }
+        }
+
+        Some(DateStyle {
+            order: order.or(style_order).unwrap_or_default(),
+        })
+    }
+
+    /// Parse text input of `date` type.
+    ///
+    /// Accept `-`, `/` or `.` as separator. A field with more than 2 digits is
+    /// taken as year, so `2023/12/31` is unambiguous. Two-digit years are
+    /// mapped to 1970-2069 as postgres does. Return `22007` error on invalid
+    /// input.
+    pub fn parse_date(&self, input: &str) -> PgWireResult<NaiveDate> {
+        self.try_parse_date(input.trim())
+            .ok_or_else(|| invalid_datetime("date", input))
+    }
+
+    /// Parse text input of `timestamp` type, a date followed by time of day,
+    /// separated by a space or `T`. Return `22007` error on invalid input.
+    pub fn parse_timestamp(&self, input: &str) -> PgWireResult<NaiveDateTime> {
+        let input_trimmed = input.trim();
+        let (date, time) = match input_trimmed.split_once([' ', 'T']) {
+            Some((date, time)) => (date, Some(time.trim())),
+            None => (input_trimmed, None),
+        };
+
+        let date = self.try_parse_date(date);
+        let time = match time {
+            Some(time) => NaiveTime::parse_from_str(time, "%H:%M:%S%.f")
+                .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+                .ok(),
+            None => Some(NaiveTime::MIN),
+        };
+
+        date.zip(time)
+            .map(|(date, time)| date.and_time(time))
+            .ok_or_else(|| invalid_datetime("timestamp", input))
+    }
+
+    fn try_parse_date(&self, input: &str) -> Option<NaiveDate> {
+        let fields = input.split(['-', '/', '.']).collect::<Vec<_>>();
+        let [a, b, c] = fields[..] else {
+            return None;
+        };
+        if [a, b, c]
+            .iter()
+            .any(|f| f.is_empty() || !f.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return None;
+        }
+
+        let (year, month, day) = if a.len() > 2 {
+            (a, b, c)
+        } else {
+            match self.order {
+                DateOrder::Mdy => (c, a, b),
+                DateOrder::Dmy => (c, b, a),
+                DateOrder::Ymd => (a, b, c),
+            }
+        };
+
+        let mut year = year.parse::<i32>().ok()?;
+        if year < 100 && fields.iter().all(|f| f.len() <= 2) {
+            year += if year < 70 { 2000 } else { 1900 };
+        }
+        NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)
+    }
+}
+
+fn invalid_datetime(type_name: &str, input: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "22007".to_owned(),
+        format!("invalid input syntax for type {type_name}: \"{input}\""),
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_datestyle() {
+        assert_eq!(
+            Some(DateStyle::new(DateOrder::Mdy)),
+            DateStyle::parse("ISO, MDY")
+        );
+        assert_eq!(
+            Some(DateStyle::new(DateOrder::Dmy)),
+            DateStyle::parse("German")
+        );
+        assert_eq!(
+            Some(DateStyle::new(DateOrder::Ymd)),
+            DateStyle::parse("sql, ymd")
+        );
+        assert_eq!(None, DateStyle::parse("ISO, XYZ"));
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let mdy = DateStyle::new(DateOrder::Mdy);
+        let dmy = DateStyle::new(DateOrder::Dmy);
+        let expected = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+
+        assert_eq!(expected, mdy.parse_date("2023-12-31").unwrap());
+        assert_eq!(expected, dmy.parse_date("2023-12-31").unwrap());
+        assert_eq!(expected, mdy.parse_date("12/31/2023").unwrap());
+        assert_eq!(expected, dmy.parse_date("31/12/2023").unwrap());
+        assert_eq!(expected, dmy.parse_date("31.12.23").unwrap());
+
+        // ambiguous
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+            mdy.parse_date("01/02/2023").unwrap()
+        );
+        assert_eq!(
+            NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(),
+            dmy.parse_date("01/02/2023").unwrap()
+        );
+
+        match mdy.parse_date("31/12/2023") {
+            Err(PgWireError::UserError(e)) => assert_eq!("22007", e.code),
+            _ => panic!("expect invalid_datetime_format error"),
+        }
+        assert!(mdy.parse_date("2023-12").is_err());
+        assert!(mdy.parse_date("today").is_err());
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let dmy = DateStyle::new(DateOrder::Dmy);
+        let date = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+
+        assert_eq!(
+            date.and_hms_micro_opt(23, 59, 58, 123456).unwrap(),
+            dmy.parse_timestamp("31/12/2023 23:59:58.123456").unwrap()
+        );
+        assert_eq!(
+            date.and_hms_opt(8, 30, 0).unwrap(),
+            dmy.parse_timestamp("2023-12-31T08:30").unwrap()
+        );
+        assert_eq!(
+            date.and_hms_opt(0, 0, 0).unwrap(),
+            dmy.parse_timestamp("31/12/2023").unwrap()
+        );
+        assert!(dmy.parse_timestamp("31/12/2023 25:00").is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::error::Error;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Value of postgres `point`, `(x,y)` in text format.
+///
+/// Geometric types are made of `float8` coordinates. In binary format, a point
+/// is 16 bytes: `x` then `y`, and the other types are sequences of points.
+#[derive(Debug, new, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Value of postgres `lseg`, `[(x1,y1),(x2,y2)]` in text format.
+#[derive(Debug, new, Clone, Copy, PartialEq)]
+pub struct LineSegment {
+    pub start: Point,
+    pub end: Point,
+}
+
+/// Value of postgres `box`, `(x1,y1),(x2,y2)` in text format.
+///
+/// Postgres always stores the upper right corner as `high` and the lower left
+/// corner as `low`. Values are sent as is, make sure the corners are in this
+/// order.
+#[derive(Debug, new, Clone, Copy, PartialEq)]
+pub struct GeoBox {
+    pub high: Point,
+    pub low: Point,
+}
+
+/// Value of postgres `path`, `((x1,y1),...)` in text format when closed, or
+/// `[(x1,y1),...]` when open.
+#[derive(Debug, new, Clone, PartialEq)]
+pub struct Path {
+    pub closed: bool,
+    pub points: Vec<Point>,
+}
+
+/// Value of postgres `polygon`, `((x1,y1),...)` in text format.
+#[derive(Debug, new, Clone, PartialEq)]
+pub struct Polygon {
+    pub points: Vec<Point>,
+}
+
+/// Value of postgres `circle`, `<(x,y),r>` in text format.
+#[derive(Debug, new, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: Point,
+    pub radius: f64,
+}
+
+/// Write a coordinate as postgres' `float8` output does.
+fn put_float(out: &mut BytesMut, value: f64) {
+    if value.is_infinite() {
+        let s = if value > 0.0 { "Infinity" } else { "-Infinity" };
+        out.put_slice(s.as_bytes());
+    } else {
+        out.put_slice(value.to_string().as_bytes());
+    }
+}
+
+fn put_point_text(out: &mut BytesMut, point: &Point) {
+    out.put_u8(b'(');
+    put_float(out, point.x);
+    out.put_u8(b',');
+    put_float(out, point.y);
+    out.put_u8(b')');
+}
+
+fn put_points_text(out: &mut BytesMut, points: &[Point]) {
+    for (i, point) in points.iter().enumerate() {
+        if i > 0 {
+            out.put_u8(b',');
+        }
+        put_point_text(out, point);
+    }
+}
+
+fn put_point(out: &mut BytesMut, point: &Point) {
+    out.put_f64(point.x);
+    out.put_f64(point.y);
+}
+
+fn put_points(out: &mut BytesMut, points: &[Point]) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let npts = i32::try_from(points.len()).map_err(|_| "too many points")?;
+    out.put_i32(npts);
+    for point in points {
+        put_point(out, point);
+    }
+    Ok(())
+}
+
+impl ToSql for Point {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        put_point(out, self);
+        Ok(IsNull::No)
+    }
+
+    accepts!(POINT);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Point {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        put_point_text(out, self);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for LineSegment {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        put_point(out, &self.start);
+        put_point(out, &self.end);
+        Ok(IsNull::No)
+    }
+
+    accepts!(LSEG);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for LineSegment {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_u8(b'[');
+        put_points_text(out, &[self.start, self.end]);
+        out.put_u8(b']');
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for GeoBox {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        put_point(out, &self.high);
+        put_point(out, &self.low);
+        Ok(IsNull::No)
+    }
+
+    accepts!(BOX);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for GeoBox {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        put_points_text(out, &[self.high, self.low]);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for Path {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_u8(self.closed as u8);
+        put_points(out, &self.points)?;
+        Ok(IsNull::No)
+    }
+
+    accepts!(PATH);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Path {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let (open, close) = if self.closed {
+            (b'(', b')')
+        } else {
+            (b'[', b']')
+        };
+        out.put_u8(open);
+        put_points_text(out, &self.points);
+        out.put_u8(close);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for Polygon {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        put_points(out, &self.points)?;
+        Ok(IsNull::No)
+    }
+
+    accepts!(POLYGON);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Polygon {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_u8(b'(');
+        put_points_text(out, &self.points);
+        out.put_u8(b')');
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for Circle {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        put_point(out, &self.center);
+        out.put_f64(self.radius);
+        Ok(IsNull::No)
+    }
+
+    accepts!(CIRCLE);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Circle {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_u8(b'<');
+        put_point_text(out, &self.center);
+        out.put_u8(b',');
+        put_float(out, self.radius);
+        out.put_u8(b'>');
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Buf;
+
+    use super::*;
+
+    fn text<T: ToSqlText>(value: &T, ty: &Type) -> String {
+        let mut buf = BytesMut::new();
+        value.to_sql_text(ty, &mut buf).unwrap();
+        String::from_utf8(buf.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_geo_text() {
+        let origin = Point::new(0.0, 0.0);
+        let point = Point::new(1.5, -2.0);
+        assert_eq!("(1.5,-2)", text(&point, &Type::POINT));
+        assert_eq!(
+            "(Infinity,-Infinity)",
+            text(&Point::new(f64::INFINITY, f64::NEG_INFINITY), &Type::POINT)
+        );
+        assert_eq!(
+            "[(0,0),(1.5,-2)]",
+            text(&LineSegment::new(origin, point), &Type::LSEG)
+        );
+        assert_eq!(
+            "(2,2),(0,0)",
+            text(&GeoBox::new(Point::new(2.0, 2.0), origin), &Type::BOX)
+        );
+        assert_eq!(
+            "((0,0),(1.5,-2))",
+            text(&Path::new(true, vec![origin, point]), &Type::PATH)
+        );
+        assert_eq!(
+            "[(0,0),(1.5,-2)]",
+            text(&Path::new(false, vec![origin, point]), &Type::PATH)
+        );
+        assert_eq!(
+            "((0,0),(1.5,-2))",
+            text(&Polygon::new(vec![origin, point]), &Type::POLYGON)
+        );
+        assert_eq!(
+            "<(1.5,-2),3>",
+            text(&Circle::new(point, 3.0), &Type::CIRCLE)
+        );
+    }
+
+    #[test]
+    fn test_geo_binary() {
+        let point = Point::new(1.5, -2.0);
+
+        let mut buf = BytesMut::new();
+        point.to_sql(&Type::POINT, &mut buf).unwrap();
+        assert_eq!(16, buf.len());
+        assert_eq!(1.5, buf.get_f64());
+        assert_eq!(-2.0, buf.get_f64());
+
+        let mut buf = BytesMut::new();
+        GeoBox::new(point, Point::new(0.0, -3.0))
+            .to_sql(&Type::BOX, &mut buf)
+            .unwrap();
+        assert_eq!(32, buf.len());
+        assert_eq!(-3.0, (&buf[24..32]).get_f64());
+
+        let mut buf = BytesMut::new();
+        Circle::new(point, 3.0)
+            .to_sql(&Type::CIRCLE, &mut buf)
+            .unwrap();
+        assert_eq!(24, buf.len());
+        assert_eq!(3.0, (&buf[16..24]).get_f64());
+
+        let mut buf = BytesMut::new();
+        Path::new(true, vec![point, point])
+            .to_sql(&Type::PATH, &mut buf)
+            .unwrap();
+        assert_eq!(1 + 4 + 32, buf.len());
+        assert_eq!(1, buf.get_u8());
+        assert_eq!(2, buf.get_i32());
+
+        let mut buf = BytesMut::new();
+        Polygon::new(vec![point; 3])
+            .to_sql(&Type::POLYGON, &mut buf)
+            .unwrap();
+        assert_eq!(4 + 48, buf.len());
+        assert_eq!(3, buf.get_i32());
+
+        assert!(point.to_sql_checked(&Type::BOX, &mut buf).is_err());
+    }
+}
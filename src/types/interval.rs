@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt::Write;
+
+use bytes::{Buf, BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const MICROS_PER_HOUR: i64 = 3600 * MICROS_PER_SECOND;
+
+/// Value of postgres `interval`.
+///
+/// Months, days and time are kept apart like postgres does, as neither a
+/// month nor a day has a fixed length. In binary format, `interval` is 16
+/// bytes: 8 bytes of microseconds, then 4 bytes of days and 4 bytes of
+/// months.
+#[derive(Debug, new, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interval {
+    pub months: i32,
+    pub days: i32,
+    pub microseconds: i64,
+}
+
+impl ToSql for Interval {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i64(self.microseconds);
+        out.put_i32(self.days);
+        out.put_i32(self.months);
+        Ok(IsNull::No)
+    }
+
+    accepts!(INTERVAL);
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Interval {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 16 {
+            return Err("invalid message length: interval requires 16 bytes".into());
+        }
+        let microseconds = raw.get_i64();
+        let days = raw.get_i32();
+        let months = raw.get_i32();
+        Ok(Interval {
+            months,
+            days,
+            microseconds,
+        })
+    }
+
+    accepts!(INTERVAL);
+}
+
+impl ToSqlText for Interval {
+    /// Encode in the default `postgres` style of `IntervalStyle`, e.g.
+    /// `1 year 2 mons -3 days +04:05:06.789`.
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        let mut text = String::new();
+        // once a field is negative, following positive fields are signed
+        let mut negative_before = false;
+        for (value, unit) in [
+            (self.months / 12, "year"),
+            (self.months % 12, "mon"),
+            (self.days, "day"),
+        ] {
+            if value == 0 {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            let sign = if negative_before && value > 0 {
+                "+"
+            } else {
+                ""
+            };
+            let plural = if value != 1 { "s" } else { "" };
+            write!(text, "{sign}{value} {unit}{plural}")?;
+            negative_before |= value < 0;
+        }
+
+        if text.is_empty() || self.microseconds != 0 {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            let micros = self.microseconds.unsigned_abs();
+            let sign = if self.microseconds < 0 {
+                "-"
+            } else if negative_before {
+                "+"
+            } else {
+                ""
+            };
+            let hours = micros / MICROS_PER_HOUR as u64;
+            let minutes = micros / 60_000_000 % 60;
+            let seconds = micros / MICROS_PER_SECOND as u64 % 60;
+            write!(text, "{sign}{hours:02}:{minutes:02}:{seconds:02}")?;
+            let fraction = micros % MICROS_PER_SECOND as u64;
+            if fraction != 0 {
+                let fraction = format!(".{fraction:06}");
+                text.push_str(fraction.trim_end_matches('0'));
+            }
+        }
+
+        out.put_slice(text.as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interval_binary() {
+        let value = Interval::new(14, 3, 14_706_789_000);
+        let mut buf = BytesMut::new();
+        value.to_sql(&Type::INTERVAL, &mut buf).unwrap();
+
+        assert_eq!(16, buf.len());
+        assert_eq!(14_706_789_000, (&buf[0..8]).get_i64());
+        assert_eq!(3, (&buf[8..12]).get_i32());
+        assert_eq!(14, (&buf[12..16]).get_i32());
+        assert_eq!(value, Interval::from_sql(&Type::INTERVAL, &buf).unwrap());
+
+        assert!(Interval::from_sql(&Type::INTERVAL, &buf[..12]).is_err());
+    }
+
+    #[test]
+    fn test_interval_text() {
+        // output of postgres
+        for (months, days, microseconds, expected) in [
+            (0, 0, 0, "00:00:00"),
+            (12, 0, 0, "1 year"),
+            (-12, 0, 0, "-1 years"),
+            (14, 3, 14_706_789_000, "1 year 2 mons 3 days 04:05:06.789"),
+            (-10, 0, 0, "-10 mons"),
+            (1, -1, 0, "1 mon -1 days"),
+            (0, -1, 3_600_000_000, "-1 days +01:00:00"),
+            (0, 0, -3_723_500_000, "-01:02:03.5"),
+            (0, 0, 90_000_000_000, "25:00:00"),
+            (13, 0, 0, "1 year 1 mon"),
+            (0, 2, 1, "2 days 00:00:00.000001"),
+            (-1, 0, -3_600_000_000, "-1 mons -01:00:00"),
+            (-1, 0, 3_600_000_000, "-1 mons +01:00:00"),
+            (0, 0, 100_000, "00:00:00.1"),
+            (-27, 0, 0, "-2 years -3 mons"),
+            (
+                i32::MAX,
+                i32::MAX,
+                i64::MAX,
+                "178956970 years 7 mons 2147483647 days 2562047788:00:54.775807",
+            ),
+        ] {
+            let mut buf = BytesMut::new();
+            Interval::new(months, days, microseconds)
+                .to_sql_text(&Type::INTERVAL, &mut buf)
+                .unwrap();
+            assert_eq!(expected, String::from_utf8_lossy(&buf));
+        }
+    }
+}
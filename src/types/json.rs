@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::fmt::{Display, Write};
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Version of `jsonb` binary format, the only one postgres knows.
+const JSONB_VERSION: u8 = 1;
+
+/// JSON document, for `json` and `jsonb` fields and their arrays.
+///
+/// The document is written with its `Display` implementation straight into
+/// the row buffer. Serialized text like `&str` or `String` is copied as is,
+/// while a value formatting itself as JSON (`serde_json::Value`, or a
+/// `Display` wrapper calling a serializer) is serialized in place without an
+/// intermediate `String`. The output is not validated.
+///
+/// Binary format of `jsonb` is the text prefixed by a version byte, which
+/// every element of a `jsonb[]` array carries as well. Encode the array as a
+/// `Vec<Json<T>>` or slice of `Json<T>`:
+///
+/// ```
+/// use bytes::BytesMut;
+/// use pgwire::types::Json;
+/// use postgres_types::{ToSql, Type};
+///
+/// let docs = vec![Json(r#"{"a": 1}"#), Json("[]")];
+/// let mut buf = BytesMut::new();
+/// docs.to_sql(&Type::JSONB_ARRAY, &mut buf).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+impl<T: Display + std::fmt::Debug> ToSql for Json<T> {
+    fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        if *ty == Type::JSONB {
+            out.put_u8(JSONB_VERSION);
+        }
+        write!(out, "{}", self.0)?;
+        Ok(IsNull::No)
+    }
+
+    accepts!(JSON, JSONB);
+
+    to_sql_checked!();
+}
+
+impl<T: Display + std::fmt::Debug> ToSqlText for Json<T> {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        write!(out, "{}", self.0)?;
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A nested document written piece by piece
+    #[derive(Debug)]
+    struct Nested(usize);
+
+    impl Display for Nested {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self.0 {
+                0 => f.write_str("null"),
+                n => write!(f, "{{\"depth\": {}, \"child\": {}}}", n, Nested(n - 1)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let mut buf = BytesMut::new();
+        Json("{}").to_sql(&Type::JSONB, &mut buf).unwrap();
+        assert_eq!(b"\x01{}", buf.as_ref());
+
+        let mut buf = BytesMut::new();
+        Json("{}").to_sql(&Type::JSON, &mut buf).unwrap();
+        assert_eq!(b"{}", buf.as_ref());
+
+        // every element carries the version byte
+        let mut buf = BytesMut::new();
+        vec![Json("1"), Json("[]")]
+            .to_sql(&Type::JSONB_ARRAY, &mut buf)
+            .unwrap();
+        let expected: &[u8] = b"\0\0\0\x01\0\0\0\0\0\0\x0e\xda\0\0\0\x02\0\0\0\x01\
+            \0\0\0\x02\x011\0\0\0\x03\x01[]";
+        assert_eq!(expected, buf.as_ref());
+
+        let mut buf = BytesMut::new();
+        vec![Some(Json(r#"{"a": "b c"}"#.to_owned())), None]
+            .to_sql_text(&Type::JSONB_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!(
+            r#"{"{\"a\": \"b c\"}",NULL}"#,
+            String::from_utf8_lossy(&buf)
+        );
+
+        let mut buf = BytesMut::new();
+        Json(Nested(2)).to_sql(&Type::JSONB, &mut buf).unwrap();
+        assert_eq!(
+            &b"\x01{\"depth\": 2, \"child\": {\"depth\": 1, \"child\": null}}"[..],
+            buf.as_ref()
+        );
+    }
+}
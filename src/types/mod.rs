@@ -3,8 +3,36 @@ use std::{error::Error, fmt};
 
 use bytes::{BufMut, BytesMut};
 use chrono::offset::Utc;
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
-use postgres_types::{IsNull, Type, WrongType};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use postgres_types::{to_sql_checked, IsNull, Kind, ToSql, Type, WasNull, WrongType};
+
+mod array;
+mod bytea;
+mod catalog;
+mod datestyle;
+mod geo;
+mod interval;
+mod json;
+mod money;
+mod numeric;
+mod pg_lsn;
+mod timetz;
+mod tsearch;
+mod vector;
+
+pub(crate) use array::parse_array;
+pub use bytea::ByteaOutput;
+pub use catalog::{AclItem, RegOid};
+pub use datestyle::{DateOrder, DateStyle};
+pub use geo::{Circle, GeoBox, LineSegment, Path, Point, Polygon};
+pub use interval::Interval;
+pub use json::Json;
+pub use money::Money;
+pub use numeric::Numeric;
+pub use pg_lsn::PgLsn;
+pub use timetz::TimeTz;
+pub use tsearch::{TsLexeme, TsPosition, TsQuery, TsQueryLexeme, TsVector, TsWeight};
+pub use vector::Vector;
 
 pub trait ToSqlText: fmt::Debug {
     /// Converts value to text format of Postgres type.
@@ -20,7 +48,7 @@ pub trait ToSqlText: fmt::Debug {
         Self: Sized;
 }
 
-impl<'a, T> ToSqlText for &'a T
+impl<T> ToSqlText for &T
 where
     T: ToSqlText,
 {
@@ -56,7 +84,7 @@ impl ToSqlText for String {
     }
 }
 
-impl<'a> ToSqlText for &'a str {
+impl ToSqlText for &str {
     fn to_sql_text(
         &self,
         _ty: &Type,
@@ -82,7 +110,6 @@ macro_rules! impl_to_sql_text {
     };
 }
 
-impl_to_sql_text!(i8);
 impl_to_sql_text!(i16);
 impl_to_sql_text!(i32);
 impl_to_sql_text!(i64);
@@ -92,13 +119,39 @@ impl_to_sql_text!(f64);
 impl_to_sql_text!(bool);
 impl_to_sql_text!(char);
 
+/// `"char"`, the single byte internal type, is the byte as is, or empty for
+/// zero, with non-ASCII bytes in octal escapes like `\310`.
+impl ToSqlText for i8 {
+    fn to_sql_text(
+        &self,
+        ty: &Type,
+        w: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        if *ty == Type::CHAR {
+            match *self as u8 {
+                0 => {}
+                b @ 1..=0x7f => w.put_u8(b),
+                b => w.put_slice(format!("\\{b:03o}").as_bytes()),
+            }
+        } else {
+            w.put_slice(self.to_string().as_bytes());
+        }
+        Ok(IsNull::No)
+    }
+}
+
+/// `bytea` in hex format, `\x` followed by hex digits. The binary format,
+/// from `ToSql`, is the raw bytes.
+///
+/// `DataRowEncoder::with_bytea_output` encodes it in escape format instead,
+/// for sessions with `bytea_output` set to `escape`.
 impl ToSqlText for &[u8] {
     fn to_sql_text(
         &self,
         _ty: &Type,
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
-        out.put_slice(hex::encode(self).as_bytes());
+        ByteaOutput::Hex.encode(self, out);
         Ok(IsNull::No)
     }
 }
@@ -123,6 +176,33 @@ impl<const N: usize> ToSqlText for [u8; N] {
     }
 }
 
+/// 2000-01-01 00:00:00, epoch of `timestamp` and `timestamptz` in postgres
+fn pg_epoch() -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+}
+
+/// Offset to truncate `timestamp` to whole microseconds as binary format
+/// does, toward the postgres epoch.
+///
+/// Binary format of `timestamp` and `timestamptz` is microseconds since
+/// 2000-01-01, which `ToSql` of chrono types and `SystemTime` truncates
+/// toward zero: sub-microsecond digits are dropped after 2000, but rounded up
+/// to the next microsecond before. Text format follows the same rule, so a
+/// value reads the same in both formats.
+fn micros_truncation(timestamp: NaiveDateTime) -> chrono::Duration {
+    let nanos = i64::from(timestamp.nanosecond() % 1000);
+    if nanos == 0 {
+        chrono::Duration::zero()
+    } else if timestamp < pg_epoch() {
+        chrono::Duration::nanoseconds(1000 - nanos)
+    } else {
+        chrono::Duration::nanoseconds(-nanos)
+    }
+}
+
 impl ToSqlText for SystemTime {
     fn to_sql_text(
         &self,
@@ -130,6 +210,7 @@ impl ToSqlText for SystemTime {
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
         let datetime: DateTime<Utc> = DateTime::<Utc>::from(*self);
+        let datetime = datetime + micros_truncation(datetime.naive_utc());
         let fmt = datetime.format("%Y-%m-%d %H:%M:%S%.6f").to_string();
         out.put_slice(fmt.as_bytes());
         Ok(IsNull::No)
@@ -153,7 +234,11 @@ where
             Type::TIMETZ => "%H:%M:%S%.6f%:::z",
             _ => Err(Box::new(WrongType::new::<DateTime<Tz>>(ty.clone())))?,
         };
-        out.put_slice(self.format(fmt).to_string().as_bytes());
+        let mut datetime = self.clone();
+        if matches!(*ty, Type::TIMESTAMP | Type::TIMESTAMPTZ) {
+            datetime += micros_truncation(self.naive_utc());
+        }
+        out.put_slice(datetime.format(fmt).to_string().as_bytes());
         Ok(IsNull::No)
     }
 }
@@ -170,7 +255,11 @@ impl ToSqlText for NaiveDateTime {
             Type::TIME => "%H:%M:%S%.6f",
             _ => Err(Box::new(WrongType::new::<NaiveDateTime>(ty.clone())))?,
         };
-        out.put_slice(self.format(fmt).to_string().as_bytes());
+        let mut datetime = *self;
+        if *ty == Type::TIMESTAMP {
+            datetime += micros_truncation(*self);
+        }
+        out.put_slice(datetime.format(fmt).to_string().as_bytes());
         Ok(IsNull::No)
     }
 }
@@ -206,20 +295,59 @@ impl ToSqlText for NaiveTime {
     }
 }
 
+/// Write an element of array in postgres array literal syntax.
+///
+/// The element is double-quoted when it's empty, equals to `NULL` or contains
+/// any of delimiter, braces, quotes, backslash or whitespace.
+fn put_array_element(out: &mut BytesMut, element: &[u8]) {
+    let needs_quote = element.is_empty()
+        || element.eq_ignore_ascii_case(b"NULL")
+        || element
+            .iter()
+            .any(|b| matches!(b, b'{' | b'}' | b',' | b'"' | b'\\') || b.is_ascii_whitespace());
+
+    if needs_quote {
+        out.put_u8(b'"');
+        for b in element {
+            if matches!(b, b'"' | b'\\') {
+                out.put_u8(b'\\');
+            }
+            out.put_u8(*b);
+        }
+        out.put_u8(b'"');
+    } else {
+        out.put_slice(element);
+    }
+}
+
 impl<T: ToSqlText> ToSqlText for &[T] {
+    /// Encode slice as postgres array literal, like `{1,2,NULL}`.
+    ///
+    /// Elements are encoded with element type of `ty` if it's an array type.
     fn to_sql_text(
         &self,
         ty: &Type,
         out: &mut BytesMut,
     ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
-        out.put_slice(b"[");
+        let element_type = match ty.kind() {
+            Kind::Array(element_type) => element_type,
+            _ => ty,
+        };
+
+        let mut element = BytesMut::new();
+        out.put_slice(b"{");
         for (i, val) in self.iter().enumerate() {
             if i > 0 {
                 out.put_slice(b",");
             }
-            val.to_sql_text(ty, out)?;
+            if let IsNull::Yes = val.to_sql_text(element_type, &mut element)? {
+                out.put_slice(b"NULL");
+            } else {
+                put_array_element(out, &element);
+            }
+            element.clear();
         }
-        out.put_slice(b"]");
+        out.put_slice(b"}");
         Ok(IsNull::No)
     }
 }
@@ -244,6 +372,187 @@ impl<T: ToSqlText, const N: usize> ToSqlText for [T; N] {
     }
 }
 
+/// Raw text representation of a value.
+///
+/// This is for serving types that have no rust implementation, like `ltree`,
+/// `citext` or `jsonpath`, without per-type code. The bytes are written as is
+/// for any data type, but only in text format. Use it with a `FieldInfo` of
+/// the custom type, created by `FieldInfo::new_with_type_oid`.
+#[derive(Debug, new, Clone, Copy, PartialEq, Eq)]
+pub struct RawText<'a>(pub &'a [u8]);
+
+impl ToSqlText for RawText<'_> {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.0);
+        Ok(IsNull::No)
+    }
+}
+
+impl ToSql for RawText<'_> {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        _out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        Err(format!("binary format is not supported for raw text of type {ty}").into())
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+pub trait FromSqlText: Sized {
+    /// Converts value from text format of Postgres type.
+    ///
+    /// This trait is modelled after `FromSql` from postgres-types, which is
+    /// for binary decoding.
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>>;
+
+    /// Value for a `NULL` element of an array, an error by default as for
+    /// `FromSql`.
+    fn from_sql_null(_ty: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Err(Box::new(WasNull))
+    }
+}
+
+impl<T: FromSqlText> FromSqlText for Option<T> {
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        T::from_sql_text(ty, input).map(Some)
+    }
+
+    fn from_sql_null(_ty: &Type) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(None)
+    }
+}
+
+impl FromSqlText for String {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(std::str::from_utf8(input)?.to_owned())
+    }
+}
+
+macro_rules! impl_from_sql_text {
+    ($t:ty) => {
+        impl FromSqlText for $t {
+            fn from_sql_text(
+                _ty: &Type,
+                input: &[u8],
+            ) -> Result<Self, Box<dyn Error + Sync + Send>> {
+                Ok(std::str::from_utf8(input)?.trim().parse()?)
+            }
+        }
+    };
+}
+
+impl_from_sql_text!(i16);
+impl_from_sql_text!(i32);
+impl_from_sql_text!(i64);
+impl_from_sql_text!(u32);
+impl_from_sql_text!(f32);
+impl_from_sql_text!(f64);
+#[cfg(feature = "with-uuid-1")]
+impl_from_sql_text!(uuid::Uuid);
+
+/// `bool` is accepted as `t`, `true`, `yes`, `on`, `1` and their negatives
+/// in any case, as postgres does.
+impl FromSqlText for bool {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let input = std::str::from_utf8(input)?.trim().to_ascii_lowercase();
+        match input.as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => Ok(true),
+            "f" | "false" | "n" | "no" | "off" | "0" => Ok(false),
+            _ => Err(format!("invalid input syntax for type boolean: \"{input}\"").into()),
+        }
+    }
+}
+
+/// `bytea` in hex format like `\\x0a0b`, or in escape format with `\\`
+/// and octal escapes like `\\012`.
+impl FromSqlText for Vec<u8> {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if let Some(hex) = input.strip_prefix(b"\\x") {
+            let hex = hex
+                .iter()
+                .filter(|b| !b.is_ascii_whitespace())
+                .copied()
+                .collect::<Vec<_>>();
+            if hex.len() % 2 != 0 {
+                return Err("invalid hexadecimal data: odd number of digits".into());
+            }
+            return hex
+                .chunks(2)
+                .map(|pair| {
+                    std::str::from_utf8(pair)
+                        .ok()
+                        .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                        .ok_or_else(|| "invalid hexadecimal digit".into())
+                })
+                .collect();
+        }
+
+        let mut out = Vec::with_capacity(input.len());
+        let mut bytes = input.iter();
+        while let Some(&b) = bytes.next() {
+            if b != b'\\' {
+                out.push(b);
+                continue;
+            }
+            let rest = bytes.as_slice();
+            if rest.first() == Some(&b'\\') {
+                out.push(b'\\');
+                bytes.next();
+            } else if rest.len() >= 3 && rest[..3].iter().all(|d| (b'0'..=b'7').contains(d)) {
+                let value = rest[..3]
+                    .iter()
+                    .fold(0u16, |acc, d| acc * 8 + (d - b'0') as u16);
+                out.push(u8::try_from(value).map_err(|_| "invalid input syntax for type bytea")?);
+                bytes.nth(2);
+            } else {
+                return Err("invalid input syntax for type bytea".into());
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromSqlText for NaiveDate {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(DateStyle::default().parse_date(std::str::from_utf8(input)?)?)
+    }
+}
+
+impl FromSqlText for NaiveDateTime {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(DateStyle::default().parse_timestamp(std::str::from_utf8(input)?)?)
+    }
+}
+
+/// One-dimensional array like `{1,NULL,3}`, see `parse_array`. `NULL`
+/// elements are decoded with `from_sql_null`, so they are only accepted
+/// with `Option` elements.
+impl<T: FromSqlText> FromSqlText for Vec<T> {
+    fn from_sql_text(ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let element_type = match ty.kind() {
+            Kind::Array(element_type) => element_type,
+            _ => ty,
+        };
+        parse_array(std::str::from_utf8(input)?)?
+            .into_iter()
+            .map(|element| match element {
+                Some(element) => T::from_sql_text(element_type, element.as_bytes()),
+                None => T::from_sql_null(element_type),
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -266,4 +575,138 @@ mod test {
         // format: 2023-02-01 22:31:49.479895+08
         assert_eq!(29, String::from_utf8_lossy(buf.freeze().as_ref()).len());
     }
+
+    #[test]
+    fn test_timestamp_micros_truncation() {
+        let text = |value: &dyn Fn(&mut BytesMut) -> Result<IsNull, _>| {
+            let mut buf = BytesMut::new();
+            value(&mut buf).unwrap();
+            String::from_utf8(buf.to_vec()).unwrap()
+        };
+        let binary = |value: &NaiveDateTime| {
+            let mut buf = BytesMut::new();
+            value.to_sql(&Type::TIMESTAMP, &mut buf).unwrap();
+            i64::from_be_bytes(buf[..].try_into().unwrap())
+        };
+
+        for (year, nanos, expected_text, expected_binary) in [
+            (
+                2023,
+                123_456_999,
+                "2023-03-05 12:00:00.123456",
+                731_332_800_123_456,
+            ),
+            // before 2000, truncated toward 2000-01-01 as binary format is
+            (
+                1999,
+                999_999_500,
+                "1999-03-05 12:00:01.000000",
+                -26_049_599_000_000,
+            ),
+        ] {
+            let datetime = NaiveDate::from_ymd_opt(year, 3, 5)
+                .unwrap()
+                .and_hms_nano_opt(12, 0, 0, nanos)
+                .unwrap();
+            assert_eq!(expected_binary, binary(&datetime));
+            assert_eq!(
+                expected_text,
+                text(&|buf| datetime.to_sql_text(&Type::TIMESTAMP, buf))
+            );
+            let utc = DateTime::<Utc>::from_naive_utc_and_offset(datetime, Utc);
+            assert_eq!(
+                format!("{expected_text}+00"),
+                text(&|buf| utc.to_sql_text(&Type::TIMESTAMPTZ, buf))
+            );
+            assert_eq!(
+                expected_text,
+                text(&|buf| SystemTime::from(utc).to_sql_text(&Type::TIMESTAMPTZ, buf))
+            );
+        }
+    }
+
+    #[test]
+    fn test_raw_text() {
+        let jsonpath = Type::new(
+            "jsonpath".to_owned(),
+            4072,
+            postgres_types::Kind::Simple,
+            "pg_catalog".to_owned(),
+        );
+        let mut buf = BytesMut::new();
+        RawText(b"$.a[*] ? (@ > 1)")
+            .to_sql_text(&jsonpath, &mut buf)
+            .unwrap();
+        assert_eq!(b"$.a[*] ? (@ > 1)", buf.as_ref());
+
+        assert!(RawText(b"1").to_sql(&jsonpath, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_bytea_text() {
+        let mut buf = BytesMut::new();
+        vec![0u8, 1, 0xff]
+            .to_sql_text(&Type::BYTEA, &mut buf)
+            .unwrap();
+        assert_eq!(b"\\x0001ff", buf.as_ref());
+
+        let mut buf = BytesMut::new();
+        vec![0u8, 1, 0xff].to_sql(&Type::BYTEA, &mut buf).unwrap();
+        assert_eq!(&[0u8, 1, 0xff], buf.as_ref());
+    }
+
+    #[test]
+    fn test_array_text() {
+        let mut buf = BytesMut::new();
+        vec![1, 2, 3]
+            .to_sql_text(&Type::INT4_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!("{1,2,3}", String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        vec!["pg_catalog", "public"]
+            .to_sql_text(&Type::NAME_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!("{pg_catalog,public}", String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        vec![
+            Some("a b"),
+            None,
+            Some(""),
+            Some("null"),
+            Some("say \"hi\""),
+        ]
+        .to_sql_text(&Type::TEXT_ARRAY, &mut buf)
+        .unwrap();
+        assert_eq!(
+            r#"{"a b",NULL,"","null","say \"hi\""}"#,
+            String::from_utf8_lossy(&buf)
+        );
+
+        let mut buf = BytesMut::new();
+        Vec::<i32>::new()
+            .to_sql_text(&Type::INT4_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!("{}", String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        vec![NaiveDate::from_ymd_opt(2023, 3, 5).unwrap()]
+            .to_sql_text(&Type::DATE_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!("{2023-03-05}", String::from_utf8_lossy(&buf));
+    }
+
+    #[test]
+    fn test_char_text() {
+        let mut buf = BytesMut::new();
+        vec![b'a' as i8, b' ' as i8, b'\\' as i8, -56, 0]
+            .to_sql_text(&Type::CHAR_ARRAY, &mut buf)
+            .unwrap();
+        assert_eq!(r#"{a," ","\\","\\310",""}"#, String::from_utf8_lossy(&buf));
+
+        let mut buf = BytesMut::new();
+        (-56i8).to_sql_text(&Type::INT4, &mut buf).unwrap();
+        assert_eq!("-56", String::from_utf8_lossy(&buf));
+    }
 }
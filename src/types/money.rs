@@ -0,0 +1,233 @@
+use std::error::Error;
+use std::fmt;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::{FromSqlText, ToSqlText};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Fractional digits of `money`, as of `lc_monetary` `C` and most locales.
+const FRAC_DIGITS: u32 = 2;
+
+/// Value of postgres `money`, in cents.
+///
+/// Binary format is the amount as `int8` of cents. Text format depends on
+/// `lc_monetary` in postgres, this type always uses the `C` locale: `$` as
+/// currency symbol, `,` for grouping and `.` as decimal point, e.g.
+/// `-$1,234.56`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(pub i64);
+
+fn invalid_money(input: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "22P02".to_owned(),
+        format!("invalid input syntax for type money: \"{input}\""),
+    )))
+}
+
+fn money_out_of_range(input: &str) -> PgWireError {
+    PgWireError::UserError(Box::new(ErrorInfo::new(
+        "ERROR".to_owned(),
+        "22003".to_owned(),
+        format!("value \"{input}\" is out of range for type money"),
+    )))
+}
+
+impl Money {
+    /// Parse text input of `money` as postgres does in `C` locale.
+    ///
+    /// The currency symbol is optional, before or after the sign, and
+    /// grouping separators are ignored, so `$1,234.50`, `1234.5` and
+    /// `-$12.34` are all accepted. A negative amount may also be written as
+    /// `(12.34)` or `12.34-`. Digits beyond cents are rounded half away from
+    /// zero. Return `22P02` error on invalid input, `22003` if the amount
+    /// doesn't fit.
+    pub fn parse(input: &str) -> PgWireResult<Money> {
+        let mut s = input.trim_start();
+        s = s.strip_prefix('$').unwrap_or(s).trim_start();
+        let mut negative = false;
+        if let Some(rest) = s.strip_prefix('(').or_else(|| s.strip_prefix('-')) {
+            negative = true;
+            s = rest;
+        } else if let Some(rest) = s.strip_prefix('+') {
+            s = rest;
+        }
+        s = s.trim_start();
+        s = s.strip_prefix('$').unwrap_or(s).trim_start();
+
+        // accumulate as negative, to reach i64::MIN
+        let mut value: i64 = 0;
+        let mut decimals = None;
+        let mut round_up = false;
+        let mut rest = s;
+        for (i, c) in s.char_indices() {
+            match c {
+                '0'..='9' if decimals.map_or(true, |d| d < FRAC_DIGITS) => {
+                    value = value
+                        .checked_mul(10)
+                        .and_then(|v| v.checked_sub(c.to_digit(10).unwrap() as i64))
+                        .ok_or_else(|| money_out_of_range(input))?;
+                    decimals = decimals.map(|d| d + 1);
+                }
+                '0'..='9' => {
+                    // the first extra digit decides rounding, the others are
+                    // skipped
+                    if decimals == Some(FRAC_DIGITS) {
+                        round_up = c >= '5';
+                        decimals = Some(FRAC_DIGITS + 1);
+                    }
+                }
+                '.' if decimals.is_none() => decimals = Some(0),
+                ',' if decimals.is_none() => {}
+                _ => {
+                    rest = &s[i..];
+                    break;
+                }
+            }
+            rest = &s[i + c.len_utf8()..];
+        }
+
+        let scale = FRAC_DIGITS - decimals.unwrap_or(0).min(FRAC_DIGITS);
+        value = value
+            .checked_mul(10i64.pow(scale))
+            .and_then(|v| if round_up { v.checked_sub(1) } else { Some(v) })
+            .ok_or_else(|| money_out_of_range(input))?;
+
+        for c in rest.chars() {
+            match c {
+                ')' | '-' => negative = true,
+                '$' => {}
+                c if c.is_whitespace() => {}
+                _ => return Err(invalid_money(input)),
+            }
+        }
+
+        if negative {
+            Ok(Money(value))
+        } else {
+            value
+                .checked_neg()
+                .map(Money)
+                .ok_or_else(|| money_out_of_range(input))
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cents = self.0.unsigned_abs();
+        let unit = 10u64.pow(FRAC_DIGITS);
+        let integer = (cents / unit).to_string();
+
+        if self.0 < 0 {
+            f.write_str("-")?;
+        }
+        f.write_str("$")?;
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{digit}")?;
+        }
+        write!(f, ".{:02}", cents % unit)
+    }
+}
+
+impl ToSql for Money {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i64(self.0);
+        Ok(IsNull::No)
+    }
+
+    accepts!(MONEY);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Money {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSqlText for Money {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(Money::parse(std::str::from_utf8(input)?)?)
+    }
+}
+
+impl<'a> FromSql<'a> for Money {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let raw: [u8; 8] = raw.try_into().map_err(|_| "invalid message length")?;
+        Ok(Money(i64::from_be_bytes(raw)))
+    }
+
+    accepts!(MONEY);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(input: &str) -> i64 {
+        Money::parse(input).unwrap().0
+    }
+
+    #[test]
+    fn test_money_parse() {
+        assert_eq!(123450, parse("$1,234.5"));
+        assert_eq!(1234, parse("12.34"));
+        assert_eq!(1200, parse(" 12 "));
+        assert_eq!(1200, parse("12 $"));
+        assert_eq!(50, parse(".5"));
+        assert_eq!(0, parse("$"));
+        assert_eq!(-1234, parse("-$12.34"));
+        assert_eq!(-1234, parse("$-12.34"));
+        assert_eq!(-1234, parse("(12.34)"));
+        assert_eq!(-1234, parse("($12.34)"));
+        assert_eq!(-500, parse("5-"));
+        assert_eq!(500, parse("+$5"));
+        // rounded half away from zero on the first extra digit
+        assert_eq!(1235, parse("12.345"));
+        assert_eq!(1235, parse("12.3456"));
+        assert_eq!(-1235, parse("-12.345"));
+        assert_eq!(1234, parse("12.3449"));
+        assert_eq!(i64::MAX, parse("92233720368547758.07"));
+        assert_eq!(i64::MIN, parse("-92233720368547758.08"));
+
+        for (input, code) in [
+            ("abc", "22P02"),
+            ("1.2.3", "22P02"),
+            ("USD 3", "22P02"),
+            ("92233720368547758.08", "22003"),
+        ] {
+            match Money::parse(input) {
+                Err(PgWireError::UserError(e)) => assert_eq!(code, e.code, "{input}"),
+                _ => panic!("expect error for {input}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_money_encode() {
+        assert_eq!("$1,234,567.89", Money(123456789).to_string());
+        assert_eq!("-$12.35", Money(-1235).to_string());
+        assert_eq!("$0.05", Money(5).to_string());
+        assert_eq!("-$92,233,720,368,547,758.08", Money(i64::MIN).to_string());
+
+        let mut buf = BytesMut::new();
+        Money(-1234).to_sql(&Type::MONEY, &mut buf).unwrap();
+        assert_eq!(Money(-1234), Money::from_sql(&Type::MONEY, &buf).unwrap());
+        assert!(Money::from_sql(&Type::MONEY, &buf[..4]).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::{FromSqlText, ToSqlText};
+
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+const NUMERIC_NAN: u16 = 0xC000;
+const NUMERIC_PINF: u16 = 0xD000;
+const NUMERIC_NINF: u16 = 0xF000;
+/// Largest display scale of binary format.
+const NUMERIC_DSCALE_MAX: usize = 0x3FFF;
+/// Decimal digits in each base 10000 digit of binary format.
+const DEC_DIGITS: usize = 4;
+
+/// Value of postgres `numeric`, including the special values `NaN`,
+/// `Infinity` and `-Infinity` (postgres 14+).
+///
+/// A finite value is kept in plain decimal notation, like `-12.340`, and
+/// encoded in text format normalized as postgres does, e.g. `+007.50` as
+/// `7.50`. Its scale, the number of fractional digits, is preserved.
+/// Exponent notation is not supported. Parse with `FromStr` to validate a
+/// value early, otherwise invalid values fail to encode.
+///
+/// In binary format, `numeric` is a header of number of digits, weight, sign
+/// and display scale, followed by base 10000 digits. Special values have no
+/// digits and are told by their sign field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Numeric {
+    NaN,
+    Infinity,
+    NegativeInfinity,
+    Finite(String),
+}
+
+/// Decimal parts of a finite numeric.
+struct Decimal<'a> {
+    negative: bool,
+    /// without leading zeros
+    integer: &'a str,
+    fraction: &'a str,
+}
+
+fn parse_decimal(s: &str) -> Result<Decimal<'_>, Box<dyn Error + Sync + Send>> {
+    let invalid = || format!("invalid input syntax for type numeric: \"{s}\"");
+
+    let (negative, unsigned) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer.bytes().all(|b| b.is_ascii_digit())
+        || !fraction.bytes().all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid().into());
+    }
+    if fraction.len() > NUMERIC_DSCALE_MAX {
+        return Err("numeric scale out of range".into());
+    }
+
+    let integer = integer.trim_start_matches('0');
+    // zero is always positive
+    let negative = negative && (!integer.is_empty() || fraction.bytes().any(|b| b != b'0'));
+    Ok(Decimal {
+        negative,
+        integer,
+        fraction,
+    })
+}
+
+fn base10000_digits(digits: &[u8]) -> impl Iterator<Item = i16> + '_ {
+    digits.chunks(DEC_DIGITS).map(|chunk| {
+        chunk
+            .iter()
+            .chain(std::iter::repeat(&b'0'))
+            .take(DEC_DIGITS)
+            .fold(0i16, |acc, d| acc * 10 + (d - b'0') as i16)
+    })
+}
+
+impl Numeric {
+    fn special_text(&self) -> Option<&'static str> {
+        match self {
+            Self::NaN => Some("NaN"),
+            Self::Infinity => Some("Infinity"),
+            Self::NegativeInfinity => Some("-Infinity"),
+            Self::Finite(_) => None,
+        }
+    }
+}
+
+impl FromStr for Numeric {
+    type Err = Box<dyn Error + Sync + Send>;
+
+    /// Parse a numeric literal, special values are case insensitive like in
+    /// postgres, e.g. `nan`, `inf` or `-Infinity`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "nan" => Ok(Self::NaN),
+            "infinity" | "+infinity" | "inf" | "+inf" => Ok(Self::Infinity),
+            "-infinity" | "-inf" => Ok(Self::NegativeInfinity),
+            _ => {
+                parse_decimal(s)?;
+                Ok(Self::Finite(s.to_owned()))
+            }
+        }
+    }
+}
+
+impl From<f64> for Numeric {
+    fn from(value: f64) -> Self {
+        if value.is_nan() {
+            Self::NaN
+        } else if value.is_infinite() {
+            if value > 0.0 {
+                Self::Infinity
+            } else {
+                Self::NegativeInfinity
+            }
+        } else {
+            // never in exponent notation
+            Self::Finite(value.to_string())
+        }
+    }
+}
+
+impl fmt::Display for Numeric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(text) = self.special_text() {
+            return f.write_str(text);
+        }
+        let Self::Finite(s) = self else {
+            unreachable!()
+        };
+        let decimal = parse_decimal(s).map_err(|_| fmt::Error)?;
+        if decimal.negative {
+            f.write_str("-")?;
+        }
+        f.write_str(if decimal.integer.is_empty() {
+            "0"
+        } else {
+            decimal.integer
+        })?;
+        if !decimal.fraction.is_empty() {
+            write!(f, ".{}", decimal.fraction)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToSql for Numeric {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        let s = match self {
+            Self::NaN | Self::Infinity | Self::NegativeInfinity => {
+                let sign = match self {
+                    Self::NaN => NUMERIC_NAN,
+                    Self::Infinity => NUMERIC_PINF,
+                    _ => NUMERIC_NINF,
+                };
+                out.put_i16(0);
+                out.put_i16(0);
+                out.put_u16(sign);
+                out.put_u16(0);
+                return Ok(IsNull::No);
+            }
+            Self::Finite(s) => s,
+        };
+        let decimal = parse_decimal(s)?;
+
+        // align the integer part to base 10000 digits from the decimal point
+        let padding = (DEC_DIGITS - decimal.integer.len() % DEC_DIGITS) % DEC_DIGITS;
+        let integer = "0".repeat(padding) + decimal.integer;
+        let integer_digits = integer.len() / DEC_DIGITS;
+        let mut digits = base10000_digits(integer.as_bytes())
+            .chain(base10000_digits(decimal.fraction.as_bytes()))
+            .collect::<Vec<i16>>();
+        let mut weight = integer_digits as i32 - 1;
+
+        // strip leading and trailing zeros as postgres does
+        let leading = digits.iter().take_while(|d| **d == 0).count();
+        digits.drain(..leading);
+        weight -= leading as i32;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        if digits.is_empty() {
+            weight = 0;
+        }
+
+        let ndigits = i16::try_from(digits.len()).map_err(|_| "numeric value out of range")?;
+        let weight = i16::try_from(weight).map_err(|_| "numeric value out of range")?;
+        out.put_i16(ndigits);
+        out.put_i16(weight);
+        out.put_u16(if decimal.negative {
+            NUMERIC_NEG
+        } else {
+            NUMERIC_POS
+        });
+        out.put_u16(decimal.fraction.len() as u16);
+        for digit in digits {
+            out.put_i16(digit);
+        }
+        Ok(IsNull::No)
+    }
+
+    accepts!(NUMERIC);
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for Numeric {
+    /// Decode binary format, e.g. of a bound parameter. The display scale is
+    /// preserved, `1.50` is decoded as `1.50`.
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let invalid = || "invalid numeric binary value";
+        let field = |i: usize| -> Result<u16, Box<dyn Error + Sync + Send>> {
+            let bytes = raw.get(i * 2..i * 2 + 2).ok_or_else(invalid)?;
+            Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let ndigits = field(0)? as usize;
+        let weight = field(1)? as i16 as i32;
+        let sign = field(2)?;
+        let dscale = field(3)? as usize;
+        if raw.len() != (4 + ndigits) * 2 {
+            return Err(invalid().into());
+        }
+        let digits = (0..ndigits)
+            .map(|i| field(4 + i))
+            .collect::<Result<Vec<u16>, _>>()?;
+        if digits.iter().any(|d| *d >= 10000) {
+            return Err(invalid().into());
+        }
+
+        let negative = match sign {
+            NUMERIC_NAN => return Ok(Self::NaN),
+            NUMERIC_PINF => return Ok(Self::Infinity),
+            NUMERIC_NINF => return Ok(Self::NegativeInfinity),
+            NUMERIC_POS => false,
+            NUMERIC_NEG => true,
+            _ => return Err(invalid().into()),
+        };
+        // base 10000 digit at `weight` position, counted from the first
+        let digit = |weight_pos: i32| -> u16 {
+            usize::try_from(weight - weight_pos)
+                .ok()
+                .and_then(|i| digits.get(i).copied())
+                .unwrap_or(0)
+        };
+
+        let mut s = String::new();
+        if negative {
+            s.push('-');
+        }
+        if weight < 0 {
+            s.push('0');
+        } else {
+            write!(s, "{}", digit(weight))?;
+            for pos in (0..weight).rev() {
+                write!(s, "{:04}", digit(pos))?;
+            }
+        }
+        if dscale > 0 {
+            let mut fraction = String::new();
+            let mut pos = -1;
+            while fraction.len() < dscale {
+                write!(fraction, "{:04}", digit(pos))?;
+                pos -= 1;
+            }
+            fraction.truncate(dscale);
+            s.push('.');
+            s.push_str(&fraction);
+        }
+        Ok(Self::Finite(s))
+    }
+
+    accepts!(NUMERIC);
+}
+
+impl ToSqlText for Numeric {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        if let Self::Finite(s) = self {
+            // report invalid values with their error
+            parse_decimal(s)?;
+        }
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSqlText for Numeric {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        std::str::from_utf8(input)?.parse()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn binary(value: &str) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        value
+            .parse::<Numeric>()
+            .unwrap()
+            .to_sql(&Type::NUMERIC, &mut buf)
+            .unwrap();
+        buf.to_vec()
+    }
+
+    fn text(value: &Numeric) -> String {
+        let mut buf = BytesMut::new();
+        value.to_sql_text(&Type::NUMERIC, &mut buf).unwrap();
+        String::from_utf8(buf.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_numeric_special_values() {
+        assert_eq!(vec![0, 0, 0, 0, 0xc0, 0, 0, 0], binary("NaN"));
+        assert_eq!(vec![0, 0, 0, 0, 0xd0, 0, 0, 0], binary("Infinity"));
+        assert_eq!(vec![0, 0, 0, 0, 0xf0, 0, 0, 0], binary("-inf"));
+
+        assert_eq!("NaN", text(&Numeric::from(f64::NAN)));
+        assert_eq!("Infinity", text(&Numeric::from(f64::INFINITY)));
+        assert_eq!("-Infinity", text(&Numeric::from(f64::NEG_INFINITY)));
+    }
+
+    #[test]
+    fn test_numeric_from_sql() {
+        for value in [
+            "-12345.67000",
+            "0.0001",
+            "10000",
+            "0.00",
+            "1",
+            "123456789.123456789",
+            "NaN",
+            "-Infinity",
+        ] {
+            let decoded = Numeric::from_sql(&Type::NUMERIC, &binary(value)).unwrap();
+            assert_eq!(value, text(&decoded));
+        }
+        // as sent by postgres for 0.5::numeric(10,4)
+        let raw = [0, 1, 0xff, 0xff, 0, 0, 0, 4, 0x13, 0x88];
+        assert_eq!(
+            Numeric::Finite("0.5000".to_owned()),
+            Numeric::from_sql(&Type::NUMERIC, &raw).unwrap()
+        );
+
+        assert!(Numeric::from_sql(&Type::NUMERIC, &raw[..9]).is_err());
+        // digit out of range
+        let raw = [0, 1, 0, 0, 0, 0, 0, 0, 0x27, 0x10];
+        assert!(Numeric::from_sql(&Type::NUMERIC, &raw).is_err());
+    }
+
+    #[test]
+    fn test_numeric_finite() {
+        // ndigits 3, weight 1, negative, dscale 5: 1 2345 6700
+        assert_eq!(
+            vec![0, 3, 0, 1, 0x40, 0, 0, 5, 0, 1, 0x09, 0x29, 0x1a, 0x2c],
+            binary("-12345.67000")
+        );
+        // 0.0001 is a single digit of weight -1
+        assert_eq!(vec![0, 1, 0xff, 0xff, 0, 0, 0, 4, 0, 1], binary("0.0001"));
+        // trailing zero digits are stripped
+        assert_eq!(vec![0, 1, 0, 1, 0, 0, 0, 0, 0, 1], binary("10000"));
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 2], binary("-0.00"));
+
+        assert_eq!("7.50", text(&"+007.50".parse().unwrap()));
+        assert_eq!("0.00", text(&"-0.00".parse().unwrap()));
+        assert_eq!("-0.5", text(&Numeric::from(-0.5)));
+
+        assert!("1e5".parse::<Numeric>().is_err());
+        assert!(".".parse::<Numeric>().is_err());
+        let mut buf = BytesMut::new();
+        assert!(Numeric::Finite("abc".to_owned())
+            .to_sql(&Type::NUMERIC, &mut buf)
+            .is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::{FromSqlText, ToSqlText};
+use crate::error::{ErrorInfo, PgWireError, PgWireResult};
+
+/// Max hex digits of each half of `pg_lsn` text.
+const MAX_COMPONENT_DIGITS: usize = 8;
+
+/// Value of postgres `pg_lsn`, a position in write-ahead log.
+///
+/// Binary format is the position as a big-endian `int8`. Text format is the
+/// high and low 32 bits in uppercase hex, separated by `/`, e.g.
+/// `16/B374D848`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PgLsn(pub u64);
+
+impl PgLsn {
+    /// Parse text input of `pg_lsn` as postgres does.
+    ///
+    /// Each half has 1 to 8 hex digits in either case, and no whitespace is
+    /// allowed. Return `22P02` error on invalid input.
+    pub fn parse(input: &str) -> PgWireResult<PgLsn> {
+        let parse_component = |s: &str| {
+            if (1..=MAX_COMPONENT_DIGITS).contains(&s.len())
+                && s.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                u32::from_str_radix(s, 16).ok()
+            } else {
+                None
+            }
+        };
+
+        input
+            .split_once('/')
+            .and_then(|(high, low)| Some((parse_component(high)?, parse_component(low)?)))
+            .map(|(high, low)| PgLsn((high as u64) << 32 | low as u64))
+            .ok_or_else(|| {
+                PgWireError::UserError(Box::new(ErrorInfo::new(
+                    "ERROR".to_owned(),
+                    "22P02".to_owned(),
+                    format!("invalid input syntax for type pg_lsn: \"{input}\""),
+                )))
+            })
+    }
+}
+
+impl fmt::Display for PgLsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xffff_ffff)
+    }
+}
+
+impl ToSql for PgLsn {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_u64(self.0);
+        Ok(IsNull::No)
+    }
+
+    accepts!(PG_LSN);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for PgLsn {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.to_string().as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSqlText for PgLsn {
+    fn from_sql_text(_ty: &Type, input: &[u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(PgLsn::parse(std::str::from_utf8(input)?)?)
+    }
+}
+
+impl<'a> FromSql<'a> for PgLsn {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        let raw: [u8; 8] = raw.try_into().map_err(|_| "invalid message length")?;
+        Ok(PgLsn(u64::from_be_bytes(raw)))
+    }
+
+    accepts!(PG_LSN);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pg_lsn_parse() {
+        // output of postgres
+        for (value, text) in [
+            (0, "0/0"),
+            (0x16_b374_d848, "16/B374D848"),
+            (u64::MAX, "FFFFFFFF/FFFFFFFF"),
+        ] {
+            assert_eq!(text, PgLsn(value).to_string());
+            assert_eq!(PgLsn(value), PgLsn::parse(text).unwrap());
+        }
+        assert_eq!(PgLsn(0x16_b374_d848), PgLsn::parse("016/b374d848").unwrap());
+
+        for input in [
+            "",
+            " 0/0",
+            "0/0 ",
+            "000000001/0",
+            "0x1/0",
+            "1/",
+            "/1",
+            "1",
+            "1/2/3",
+            "+1/0",
+        ] {
+            match PgLsn::parse(input) {
+                Err(PgWireError::UserError(e)) => assert_eq!("22P02", e.code, "{input}"),
+                _ => panic!("expect error for {input}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_pg_lsn_encode() {
+        let mut buf = BytesMut::new();
+        PgLsn(0x16_b374_d848)
+            .to_sql(&Type::PG_LSN, &mut buf)
+            .unwrap();
+        assert_eq!(&[0, 0, 0, 0x16, 0xb3, 0x74, 0xd8, 0x48], &buf[..]);
+        assert_eq!(
+            PgLsn(0x16_b374_d848),
+            PgLsn::from_sql(&Type::PG_LSN, &buf).unwrap()
+        );
+        assert!(PgLsn::from_sql(&Type::PG_LSN, &buf[..4]).is_err());
+
+        let mut buf = BytesMut::new();
+        PgLsn(0x16_b374_d848)
+            .to_sql_text(&Type::PG_LSN, &mut buf)
+            .unwrap();
+        assert_eq!(b"16/B374D848", &buf[..]);
+    }
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+
+use bytes::{Buf, BufMut, BytesMut};
+use chrono::{FixedOffset, NaiveTime, Timelike};
+use postgres_types::{accepts, to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+
+/// Value of postgres `timetz`, a time of day with its UTC offset.
+///
+/// In binary format, `timetz` is 12 bytes: 8 bytes of microseconds since
+/// midnight, followed by 4 bytes of the zone offset in seconds *west* of UTC.
+/// That is the negation of the usual offset, `+08:00` is stored as `-28800`.
+#[derive(Debug, new, Clone, Copy, PartialEq, Eq)]
+pub struct TimeTz {
+    pub time: NaiveTime,
+    pub offset: FixedOffset,
+}
+
+fn time_to_micros(time: &NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64 * MICROS_PER_SECOND + (time.nanosecond() / 1000) as i64
+}
+
+fn micros_to_time(micros: i64) -> Option<NaiveTime> {
+    let secs = micros.div_euclid(MICROS_PER_SECOND);
+    let nanos = micros.rem_euclid(MICROS_PER_SECOND) * 1000;
+    NaiveTime::from_num_seconds_from_midnight_opt(secs.try_into().ok()?, nanos as u32)
+}
+
+impl ToSql for TimeTz {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        out.put_i64(time_to_micros(&self.time));
+        // postgres stores seconds west of UTC
+        out.put_i32(-self.offset.local_minus_utc());
+        Ok(IsNull::No)
+    }
+
+    accepts!(TIMETZ);
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for TimeTz {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() != 12 {
+            return Err("invalid message length: timetz requires 12 bytes".into());
+        }
+        let micros = raw.get_i64();
+        let zone = raw.get_i32();
+
+        let time = micros_to_time(micros).ok_or("timetz out of range")?;
+        let offset = FixedOffset::east_opt(-zone).ok_or("timetz offset out of range")?;
+        Ok(TimeTz { time, offset })
+    }
+
+    accepts!(TIMETZ);
+}
+
+impl ToSqlText for TimeTz {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        out.put_slice(self.time.format("%H:%M:%S%.6f").to_string().as_bytes());
+
+        // format offset as postgres does: `+08`, `+05:30` or `-03:30:15`
+        let offset = self.offset.local_minus_utc();
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        let (hours, minutes, seconds) = (offset / 3600, offset / 60 % 60, offset % 60);
+        let mut zone = format!("{sign}{hours:02}");
+        if minutes != 0 || seconds != 0 {
+            zone.push_str(&format!(":{minutes:02}"));
+        }
+        if seconds != 0 {
+            zone.push_str(&format!(":{seconds:02}"));
+        }
+        out.put_slice(zone.as_bytes());
+
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timetz_binary() {
+        let value = TimeTz::new(
+            NaiveTime::from_hms_micro_opt(1, 2, 3, 456789).unwrap(),
+            FixedOffset::east_opt(8 * 3600).unwrap(),
+        );
+        let mut buf = BytesMut::new();
+        value.to_sql(&Type::TIMETZ, &mut buf).unwrap();
+
+        assert_eq!(12, buf.len());
+        assert_eq!(3_723_456_789, (&buf[0..8]).get_i64());
+        assert_eq!(-28800, (&buf[8..12]).get_i32());
+        assert_eq!(value, TimeTz::from_sql(&Type::TIMETZ, &buf).unwrap());
+
+        let value = TimeTz::new(
+            NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+            FixedOffset::west_opt(3 * 3600 + 30 * 60).unwrap(),
+        );
+        let mut buf = BytesMut::new();
+        value.to_sql(&Type::TIMETZ, &mut buf).unwrap();
+        assert_eq!(12600, (&buf[8..12]).get_i32());
+        assert_eq!(value, TimeTz::from_sql(&Type::TIMETZ, &buf).unwrap());
+
+        assert!(TimeTz::from_sql(&Type::TIMETZ, &buf[..8]).is_err());
+    }
+
+    #[test]
+    fn test_time_binary() {
+        let value = NaiveTime::from_hms_micro_opt(1, 2, 3, 456789).unwrap();
+        let mut buf = BytesMut::new();
+        value.to_sql(&Type::TIME, &mut buf).unwrap();
+
+        assert_eq!(8, buf.len());
+        assert_eq!(3_723_456_789, (&buf[..]).get_i64());
+    }
+
+    #[test]
+    fn test_timetz_text() {
+        let time = NaiveTime::from_hms_opt(1, 2, 3).unwrap();
+        for (offset, expected) in [
+            (8 * 3600, "01:02:03.000000+08"),
+            (5 * 3600 + 30 * 60, "01:02:03.000000+05:30"),
+            (-(3 * 3600 + 30 * 60 + 15), "01:02:03.000000-03:30:15"),
+            (0, "01:02:03.000000+00"),
+        ] {
+            let value = TimeTz::new(time, FixedOffset::east_opt(offset).unwrap());
+            let mut buf = BytesMut::new();
+            value.to_sql_text(&Type::TIMETZ, &mut buf).unwrap();
+            assert_eq!(expected, String::from_utf8_lossy(&buf));
+        }
+    }
+}
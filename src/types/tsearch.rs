@@ -0,0 +1,482 @@
+use std::error::Error;
+
+use bytes::{BufMut, BytesMut};
+use postgres_types::{accepts, to_sql_checked, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Largest lexeme position postgres stores, larger positions are clamped on
+/// input.
+const MAX_POSITION: u16 = (1 << 14) - 1;
+/// Largest number of positions of a lexeme.
+const MAX_POSITIONS: usize = 256;
+
+const QI_VAL: u8 = 1;
+const QI_OPR: u8 = 2;
+
+const OP_NOT: u8 = 1;
+const OP_AND: u8 = 2;
+const OP_OR: u8 = 3;
+const OP_PHRASE: u8 = 4;
+
+/// Weight label of a lexeme position, `D` is the default and omitted in text
+/// format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TsWeight {
+    A,
+    B,
+    C,
+    #[default]
+    D,
+}
+
+impl TsWeight {
+    /// Value stored in the top 2 bits of a tsvector position.
+    fn position_bits(&self) -> u16 {
+        match self {
+            Self::A => 3,
+            Self::B => 2,
+            Self::C => 1,
+            Self::D => 0,
+        }
+    }
+
+    /// Bit of the weight mask of a tsquery operand.
+    fn mask_bit(&self) -> u8 {
+        1 << self.position_bits()
+    }
+
+    fn label(&self) -> u8 {
+        match self {
+            Self::A => b'A',
+            Self::B => b'B',
+            Self::C => b'C',
+            Self::D => b'D',
+        }
+    }
+}
+
+/// Position of a lexeme in the document, from 1 to 16383.
+#[derive(Debug, new, Clone, Copy, PartialEq, Eq)]
+pub struct TsPosition {
+    pub position: u16,
+    #[new(default)]
+    pub weight: TsWeight,
+}
+
+impl TsPosition {
+    pub fn with_weight(mut self, weight: TsWeight) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+/// A normalized word of a `tsvector` and its positions, which can be empty.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
+pub struct TsLexeme {
+    pub word: String,
+    pub positions: Vec<TsPosition>,
+}
+
+/// Value of postgres `tsvector`, `'cat':1 'dog':2A` in text format.
+///
+/// Postgres keeps lexemes sorted and unique, and positions of a lexeme in
+/// ascending order. Values are sent as is, make sure they are in this order.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
+pub struct TsVector {
+    pub lexemes: Vec<TsLexeme>,
+}
+
+/// Operand of a `tsquery`, `'cat':*AB` in text format.
+#[derive(Debug, new, Clone, PartialEq, Eq)]
+pub struct TsQueryLexeme {
+    pub word: String,
+    /// Weights the lexeme is restricted to, empty to match all of them.
+    #[new(default)]
+    pub weights: Vec<TsWeight>,
+    /// Match words starting with the lexeme.
+    #[new(default)]
+    pub prefix: bool,
+}
+
+impl TsQueryLexeme {
+    pub fn with_weights(mut self, weights: Vec<TsWeight>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: bool) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    fn weight_mask(&self) -> u8 {
+        self.weights.iter().fold(0, |mask, w| mask | w.mask_bit())
+    }
+}
+
+/// Value of postgres `tsquery`, `'cat' & ( 'dog' | !'rat' )` in text format.
+///
+/// In binary format, the operator tree is sent in prefix form with the right
+/// operand of binary operators before the left one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TsQuery {
+    Lexeme(TsQueryLexeme),
+    Not(Box<TsQuery>),
+    And(Box<TsQuery>, Box<TsQuery>),
+    Or(Box<TsQuery>, Box<TsQuery>),
+    /// Followed by operator `<N>`, where `N` is the distance between the
+    /// operands, written as `<->` when it's 1.
+    Phrase(Box<TsQuery>, Box<TsQuery>, u16),
+}
+
+impl TsQuery {
+    pub fn lexeme(word: impl Into<String>) -> Self {
+        Self::Lexeme(TsQueryLexeme::new(word.into()))
+    }
+
+    pub fn negate(query: TsQuery) -> Self {
+        Self::Not(Box::new(query))
+    }
+
+    pub fn and(left: TsQuery, right: TsQuery) -> Self {
+        Self::And(Box::new(left), Box::new(right))
+    }
+
+    pub fn or(left: TsQuery, right: TsQuery) -> Self {
+        Self::Or(Box::new(left), Box::new(right))
+    }
+
+    pub fn phrase(left: TsQuery, right: TsQuery, distance: u16) -> Self {
+        Self::Phrase(Box::new(left), Box::new(right), distance)
+    }
+
+    /// Operator priority used by postgres to decide parentheses.
+    fn priority(&self) -> u8 {
+        match self {
+            Self::Lexeme(_) => u8::MAX,
+            Self::Not(_) => 4,
+            Self::Phrase(..) => 3,
+            Self::And(..) => 2,
+            Self::Or(..) => 1,
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            Self::Lexeme(_) => 1,
+            Self::Not(query) => 1 + query.count(),
+            Self::And(left, right) | Self::Or(left, right) | Self::Phrase(left, right, _) => {
+                1 + left.count() + right.count()
+            }
+        }
+    }
+}
+
+/// Write a lexeme quoted, doubling quotes and backslashes as postgres does.
+fn put_lexeme_text(out: &mut BytesMut, word: &str) {
+    out.put_u8(b'\'');
+    for b in word.bytes() {
+        if b == b'\'' || b == b'\\' {
+            out.put_u8(b);
+        }
+        out.put_u8(b);
+    }
+    out.put_u8(b'\'');
+}
+
+fn check_word(word: &str) -> Result<(), Box<dyn Error + Sync + Send>> {
+    if word.is_empty() || word.contains('\0') {
+        Err("lexeme must be non-empty and must not contain null bytes".into())
+    } else {
+        Ok(())
+    }
+}
+
+fn check_positions(positions: &[TsPosition]) -> Result<(), Box<dyn Error + Sync + Send>> {
+    if positions.len() > MAX_POSITIONS {
+        return Err("too many positions of a tsvector lexeme".into());
+    }
+    if positions
+        .iter()
+        .any(|p| p.position == 0 || p.position > MAX_POSITION)
+    {
+        return Err("tsvector position out of range".into());
+    }
+    Ok(())
+}
+
+impl ToSql for TsVector {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        let size = i32::try_from(self.lexemes.len()).map_err(|_| "too many lexemes")?;
+        out.put_i32(size);
+        for lexeme in &self.lexemes {
+            check_word(&lexeme.word)?;
+            check_positions(&lexeme.positions)?;
+
+            out.put_slice(lexeme.word.as_bytes());
+            out.put_u8(b'\0');
+            out.put_u16(lexeme.positions.len() as u16);
+            for p in &lexeme.positions {
+                out.put_u16(p.weight.position_bits() << 14 | p.position);
+            }
+        }
+        Ok(IsNull::No)
+    }
+
+    accepts!(TS_VECTOR);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for TsVector {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        for (i, lexeme) in self.lexemes.iter().enumerate() {
+            check_word(&lexeme.word)?;
+            check_positions(&lexeme.positions)?;
+
+            if i > 0 {
+                out.put_u8(b' ');
+            }
+            put_lexeme_text(out, &lexeme.word);
+            for (j, p) in lexeme.positions.iter().enumerate() {
+                out.put_u8(if j == 0 { b':' } else { b',' });
+                out.put_slice(p.position.to_string().as_bytes());
+                if p.weight != TsWeight::D {
+                    out.put_u8(p.weight.label());
+                }
+            }
+        }
+        Ok(IsNull::No)
+    }
+}
+
+fn put_query(out: &mut BytesMut, query: &TsQuery) -> Result<(), Box<dyn Error + Sync + Send>> {
+    match query {
+        TsQuery::Lexeme(lexeme) => {
+            check_word(&lexeme.word)?;
+            out.put_u8(QI_VAL);
+            out.put_u8(lexeme.weight_mask());
+            out.put_u8(lexeme.prefix as u8);
+            out.put_slice(lexeme.word.as_bytes());
+            out.put_u8(b'\0');
+        }
+        TsQuery::Not(query) => {
+            out.put_u8(QI_OPR);
+            out.put_u8(OP_NOT);
+            put_query(out, query)?;
+        }
+        TsQuery::And(left, right) | TsQuery::Or(left, right) | TsQuery::Phrase(left, right, _) => {
+            out.put_u8(QI_OPR);
+            match query {
+                TsQuery::And(..) => out.put_u8(OP_AND),
+                TsQuery::Or(..) => out.put_u8(OP_OR),
+                TsQuery::Phrase(_, _, distance) => {
+                    if *distance > MAX_POSITION {
+                        return Err("tsquery phrase distance out of range".into());
+                    }
+                    out.put_u8(OP_PHRASE);
+                    out.put_i16(*distance as i16);
+                }
+                _ => unreachable!(),
+            }
+            put_query(out, right)?;
+            put_query(out, left)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a query in infix form, mirroring `infix()` of postgres' tsquery.c.
+fn put_query_text(
+    out: &mut BytesMut,
+    query: &TsQuery,
+    parent_priority: u8,
+    right_phrase_operand: bool,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let priority = query.priority();
+    match query {
+        TsQuery::Lexeme(lexeme) => {
+            check_word(&lexeme.word)?;
+            put_lexeme_text(out, &lexeme.word);
+            if lexeme.prefix || !lexeme.weights.is_empty() {
+                out.put_u8(b':');
+                if lexeme.prefix {
+                    out.put_u8(b'*');
+                }
+                let mask = lexeme.weight_mask();
+                for w in [TsWeight::A, TsWeight::B, TsWeight::C, TsWeight::D] {
+                    if mask & w.mask_bit() != 0 {
+                        out.put_u8(w.label());
+                    }
+                }
+            }
+        }
+        TsQuery::Not(operand) => {
+            out.put_u8(b'!');
+            put_query_text(out, operand, priority, false)?;
+        }
+        TsQuery::And(left, right) | TsQuery::Or(left, right) | TsQuery::Phrase(left, right, _) => {
+            let is_phrase = matches!(query, TsQuery::Phrase(..));
+            let parenthesis = priority < parent_priority || (is_phrase && right_phrase_operand);
+            if parenthesis {
+                out.put_slice(b"( ");
+            }
+            put_query_text(out, left, priority, false)?;
+            match query {
+                TsQuery::And(..) => out.put_slice(b" & "),
+                TsQuery::Or(..) => out.put_slice(b" | "),
+                TsQuery::Phrase(_, _, 1) => out.put_slice(b" <-> "),
+                TsQuery::Phrase(_, _, distance) => {
+                    out.put_slice(format!(" <{distance}> ").as_bytes())
+                }
+                _ => unreachable!(),
+            }
+            put_query_text(out, right, priority, is_phrase)?;
+            if parenthesis {
+                out.put_slice(b" )");
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ToSql for TsQuery {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        let size = i32::try_from(self.count()).map_err(|_| "tsquery is too large")?;
+        out.put_i32(size);
+        put_query(out, self)?;
+        Ok(IsNull::No)
+    }
+
+    accepts!(TSQUERY);
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for TsQuery {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        put_query_text(out, self, 0, false)?;
+        Ok(IsNull::No)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Buf;
+
+    use super::*;
+
+    fn text<T: ToSqlText>(value: &T, ty: &Type) -> String {
+        let mut buf = BytesMut::new();
+        value.to_sql_text(ty, &mut buf).unwrap();
+        String::from_utf8(buf.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_tsvector() {
+        let vector = TsVector::new(vec![
+            TsLexeme::new("cat".to_owned(), vec![TsPosition::new(1)]),
+            TsLexeme::new(
+                "dog".to_owned(),
+                vec![
+                    TsPosition::new(2).with_weight(TsWeight::A),
+                    TsPosition::new(3),
+                ],
+            ),
+            TsLexeme::new("it's".to_owned(), vec![]),
+        ]);
+        assert_eq!(
+            "'cat':1 'dog':2A,3 'it''s'",
+            text(&vector, &Type::TS_VECTOR)
+        );
+
+        let mut buf = BytesMut::new();
+        vector.to_sql(&Type::TS_VECTOR, &mut buf).unwrap();
+        assert_eq!(3, buf.get_i32());
+        assert_eq!(b"cat\0", &buf[..4]);
+        buf.advance(4);
+        assert_eq!(1, buf.get_u16());
+        assert_eq!(1, buf.get_u16());
+        buf.advance(4);
+        assert_eq!(2, buf.get_u16());
+        assert_eq!(0xc002, buf.get_u16());
+        assert_eq!(3, buf.get_u16());
+        assert_eq!(b"it's\0", &buf[..5]);
+        buf.advance(5);
+        assert_eq!(0, buf.get_u16());
+        assert!(buf.is_empty());
+
+        let invalid = TsVector::new(vec![TsLexeme::new(
+            "cat".to_owned(),
+            vec![TsPosition::new(16384)],
+        )]);
+        assert!(invalid.to_sql(&Type::TS_VECTOR, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_tsquery() {
+        let query = TsQuery::and(
+            TsQuery::lexeme("cat"),
+            TsQuery::or(
+                TsQuery::Lexeme(
+                    TsQueryLexeme::new("dog".to_owned())
+                        .with_prefix(true)
+                        .with_weights(vec![TsWeight::B, TsWeight::A]),
+                ),
+                TsQuery::negate(TsQuery::lexeme("rat")),
+            ),
+        );
+        assert_eq!(
+            "'cat' & ( 'dog':*AB | !'rat' )",
+            text(&query, &Type::TSQUERY)
+        );
+
+        let phrase = TsQuery::phrase(
+            TsQuery::lexeme("a"),
+            TsQuery::phrase(TsQuery::lexeme("b"), TsQuery::lexeme("c"), 2),
+            1,
+        );
+        assert_eq!("'a' <-> ( 'b' <2> 'c' )", text(&phrase, &Type::TSQUERY));
+        assert_eq!(
+            "!( 'a' | 'b' )",
+            text(
+                &TsQuery::negate(TsQuery::or(TsQuery::lexeme("a"), TsQuery::lexeme("b"))),
+                &Type::TSQUERY
+            )
+        );
+
+        let mut buf = BytesMut::new();
+        query.to_sql(&Type::TSQUERY, &mut buf).unwrap();
+        assert_eq!(6, buf.get_i32());
+        // & then its right operand |
+        assert_eq!(&[QI_OPR, OP_AND, QI_OPR, OP_OR], &buf[..4]);
+        buf.advance(4);
+        // right operand of | comes first
+        assert_eq!(&[QI_OPR, OP_NOT, QI_VAL, 0, 0], &buf[..5]);
+        buf.advance(5);
+        assert_eq!(b"rat\0", &buf[..4]);
+        buf.advance(4);
+        assert_eq!(&[QI_VAL, 0b1100, 1], &buf[..3]);
+        buf.advance(3);
+        assert_eq!(b"dog\0", &buf[..4]);
+        buf.advance(4);
+        assert_eq!(&[QI_VAL, 0, 0], &buf[..3]);
+        buf.advance(3);
+        assert_eq!(b"cat\0", &buf[..]);
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt::Write;
+
+use bytes::{Buf, BufMut, BytesMut};
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+use super::ToSqlText;
+
+/// Max dimensions of `vector`, identical to pgvector.
+const MAX_DIM: usize = 16000;
+
+/// Value of pgvector's `vector`, an embedding of `float4`s.
+///
+/// As an extension type, `vector` has no fixed OID. Look it up from your
+/// catalog, e.g. `SELECT oid FROM pg_type WHERE typname = 'vector'` on
+/// postgres, and declare the column with `FieldInfo::new_with_type_oid`,
+/// which accepts binary format with `FieldInfo::with_format`. This type
+/// accepts any type named `vector`.
+///
+/// Binary format is `int2` dimensions, `int2` reserved as 0, then each
+/// element as big-endian `float4`. Text format is like `[1,2.5,3]`.
+///
+/// Like pgvector, 1 to 16000 finite elements are allowed, other vectors fail
+/// to encode or decode.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Vector(pub Vec<f32>);
+
+/// Validate a vector as pgvector does, on dimensions and finite elements.
+fn check_vector(values: &[f32]) -> Result<(), Box<dyn Error + Sync + Send>> {
+    if values.is_empty() {
+        return Err("vector must have at least 1 dimension".into());
+    }
+    if values.len() > MAX_DIM {
+        return Err(format!("vector cannot have more than {MAX_DIM} dimensions").into());
+    }
+    for v in values {
+        if v.is_nan() {
+            return Err("NaN not allowed in vector".into());
+        }
+        if v.is_infinite() {
+            return Err("infinite value not allowed in vector".into());
+        }
+    }
+    Ok(())
+}
+
+impl ToSql for Vector {
+    fn to_sql(&self, _ty: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+    where
+        Self: Sized,
+    {
+        check_vector(&self.0)?;
+        out.put_i16(self.0.len() as i16);
+        out.put_i16(0);
+        for v in &self.0 {
+            out.put_f32(*v);
+        }
+        Ok(IsNull::No)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "vector"
+    }
+
+    to_sql_checked!();
+}
+
+impl ToSqlText for Vector {
+    fn to_sql_text(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        check_vector(&self.0)?;
+        let mut text = String::from("[");
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                text.push(',');
+            }
+            write!(text, "{v}")?;
+        }
+        text.push(']');
+        out.put_slice(text.as_bytes());
+        Ok(IsNull::No)
+    }
+}
+
+impl<'a> FromSql<'a> for Vector {
+    fn from_sql(_ty: &Type, mut raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        if raw.len() < 4 {
+            return Err("invalid message length: vector header requires 4 bytes".into());
+        }
+        let dim = raw.get_i16();
+        let _unused = raw.get_i16();
+        if dim <= 0 || raw.len() != dim as usize * 4 {
+            return Err(format!("invalid message length for vector of {dim} dimensions").into());
+        }
+        let mut values = Vec::with_capacity(dim as usize);
+        for _ in 0..dim {
+            values.push(raw.get_f32());
+        }
+        check_vector(&values)?;
+        Ok(Vector(values))
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "vector"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use postgres_types::Kind;
+
+    use super::*;
+
+    fn vector_type() -> Type {
+        Type::new(
+            "vector".to_owned(),
+            16385,
+            Kind::Simple,
+            "public".to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_vector_binary() {
+        let value = Vector(vec![1.0, -2.5, 0.1]);
+        let mut buf = BytesMut::new();
+        value.to_sql_checked(&vector_type(), &mut buf).unwrap();
+
+        assert_eq!(&[0, 3, 0, 0], &buf[..4]);
+        assert_eq!(1.0, (&buf[4..8]).get_f32());
+        assert_eq!(value, Vector::from_sql(&vector_type(), &buf).unwrap());
+        assert!(Vector::from_sql(&vector_type(), &buf[..8]).is_err());
+        assert!(Vector::from_sql(&vector_type(), &buf[..2]).is_err());
+
+        assert!(value.to_sql_checked(&Type::FLOAT4_ARRAY, &mut buf).is_err());
+        assert!(Vector(vec![]).to_sql(&vector_type(), &mut buf).is_err());
+        assert!(Vector(vec![0.0; MAX_DIM + 1])
+            .to_sql(&vector_type(), &mut buf)
+            .is_err());
+
+        for v in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let value = Vector(vec![1.0, v]);
+            let mut buf = BytesMut::new();
+            assert!(value.to_sql(&vector_type(), &mut buf).is_err());
+            assert!(value.to_sql_text(&vector_type(), &mut buf).is_err());
+
+            let mut buf = BytesMut::new();
+            buf.put_i16(2);
+            buf.put_i16(0);
+            buf.put_f32(1.0);
+            buf.put_f32(v);
+            assert!(Vector::from_sql(&vector_type(), &buf).is_err());
+        }
+    }
+
+    #[test]
+    fn test_vector_text() {
+        // output of pgvector
+        for (value, expected) in [
+            (vec![1.0, 2.0, 3.0], "[1,2,3]"),
+            (vec![-0.5, 0.1, 1234.5], "[-0.5,0.1,1234.5]"),
+        ] {
+            let mut buf = BytesMut::new();
+            Vector(value).to_sql_text(&vector_type(), &mut buf).unwrap();
+            assert_eq!(expected, String::from_utf8_lossy(&buf));
+        }
+    }
+}